@@ -0,0 +1,194 @@
+use super::diff::diff_lines;
+use super::display::pretty_print;
+use super::git;
+use super::manifests::{ApixManifest, PROJECT_DIR};
+use super::paths::lock_manifests;
+use super::validators::validate_manifest;
+use anyhow::Result;
+use dialoguer::{theme::ColorfulTheme, Confirm};
+use std::collections::HashMap;
+use std::path::Path;
+
+// split a multi-document yaml on `---` lines, documents holding only comments are dropped
+// a leading `# <file>.yaml` comment, written by `ctl export bundle`, is not part of the document
+pub fn split_documents(content: &str) -> Vec<String> {
+  let mut documents = vec![String::new()];
+  for line in content.lines() {
+    if line == "---" || line.starts_with("--- ") {
+      documents.push(String::new());
+    } else if let Some(document) = documents.last_mut() {
+      let is_source_comment =
+        document.is_empty() && line.starts_with("# ") && (line.ends_with(".yaml") || line.ends_with(".yml"));
+      if !is_source_comment {
+        document.push_str(line);
+        document.push('\n');
+      }
+    }
+  }
+  documents
+    .into_iter()
+    .filter(|document| {
+      document
+        .lines()
+        .any(|line| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+    })
+    .collect()
+}
+
+// new project file of a resource, named after it, and its kind when another resource already has this name
+// targets planned earlier in the same run count as taken, as dry runs don't write them
+fn new_target(name: &str, kind: &str, planned: &HashMap<(String, String), String>) -> String {
+  let taken = |target: &Path| target.exists() || planned.values().any(|planned| Path::new(planned) == target);
+  let target = Path::new(PROJECT_DIR).join(format!("{}.yaml", name));
+  let target = if taken(&target) {
+    Path::new(PROJECT_DIR).join(format!("{}-{}.yaml", name, kind))
+  } else {
+    target
+  };
+  target.to_string_lossy().to_string()
+}
+
+// file receiving an applied manifest, the existing resource with the same kind and name, the target planned for it
+// earlier in this run, or a new project file
+// the applied file is skipped, as manifests are also searched in current directory
+fn apply_target(
+  file: &str,
+  manifest: &ApixManifest,
+  planned: &mut HashMap<(String, String), String>,
+) -> Result<String> {
+  let source = Path::new(file).canonicalize()?;
+  let kind = manifest.kind().to_string().to_lowercase();
+  let existing = ApixManifest::find_manifests_by_kind(&kind)?
+    .filter(|(path, _)| path.canonicalize().ok().as_ref() != Some(&source))
+    .find(|(_, existing)| existing.name() == manifest.name())
+    .and_then(|(path, _)| path.to_str().map(str::to_string));
+  let key = (kind, manifest.name().to_string());
+  let target = existing
+    .or_else(|| planned.get(&key).cloned())
+    .unwrap_or_else(|| new_target(&key.1, &key.0, planned));
+  planned.insert(key, target.clone());
+  Ok(target)
+}
+
+// how existing resources are replaced when applying several manifests at once
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConflictMode {
+  Replace,
+  Ask,
+}
+
+fn confirm_replace(kind: &str, name: &str, target: &str) -> Result<bool> {
+  Ok(
+    Confirm::with_theme(&ColorfulTheme::default())
+      .with_prompt(format!("Replace existing {} {} in {}?", kind, name, target))
+      .default(false)
+      .interact()?,
+  )
+}
+
+// apply one manifest, returns the file written
+fn apply_document(
+  file: &str,
+  content: &str,
+  dry_run: bool,
+  conflicts: ConflictMode,
+  theme: &str,
+  enable_color: bool,
+  planned: &mut HashMap<(String, String), String>,
+) -> Result<Option<String>> {
+  let manifest = validate_manifest(content)?;
+  let target = apply_target(file, &manifest, planned)?;
+  let current = std::fs::read_to_string(&target).unwrap_or_default();
+  let changes = diff_lines(&current, content, &target, file);
+  if changes.is_empty() {
+    println!("{} unchanged", target);
+    return Ok(None);
+  }
+  let kind = manifest.kind().to_string().to_lowercase();
+  if !current.is_empty() && !dry_run && conflicts == ConflictMode::Ask {
+    pretty_print(changes.clone(), theme, "diff", enable_color)?;
+    if !confirm_replace(&kind, manifest.name(), &target)? {
+      println!("Skipped {} {}", kind, manifest.name());
+      return Ok(None);
+    }
+  }
+  match (current.is_empty(), dry_run) {
+    (true, true) => println!("Would create {} {} in {}", kind, manifest.name(), target),
+    (true, false) => println!("Created {} {} in {}", kind, manifest.name(), target),
+    (false, true) => println!("Would replace {} {} in {}", kind, manifest.name(), target),
+    (false, false) => println!("Replaced {} {} in {}", kind, manifest.name(), target),
+  }
+  if current.is_empty() || dry_run || conflicts == ConflictMode::Replace {
+    pretty_print(changes, theme, "diff", enable_color)?;
+  }
+  if dry_run {
+    return Ok(None);
+  }
+  if let Some(parent) = Path::new(&target).parent() {
+    std::fs::create_dir_all(parent)?;
+  }
+  std::fs::write(&target, content).map_err(|e| anyhow::anyhow!("Failed to write {}\ncause: {}", target, e))?;
+  Ok(Some(target))
+}
+
+// apply a manifest file into current project, showing changes of the replaced resource
+// multi-document files (like bundles) are split into a file per resource, replacing existing resources is asked
+// unless `yes` is set or stdin is not a terminal
+pub fn handle_apply(file: &str, dry_run: bool, yes: bool, theme: &str, enable_color: bool) -> Result<()> {
+  let content = std::fs::read_to_string(file).map_err(|e| anyhow::anyhow!("Could not read {}\ncause: {}", file, e))?;
+  let documents = split_documents(&content);
+  let _lock = match dry_run {
+    true => None,
+    false => Some(lock_manifests()?),
+  };
+  let conflicts = match documents.len() > 1 && !yes && atty::is(atty::Stream::Stdin) {
+    true => ConflictMode::Ask,
+    false => ConflictMode::Replace,
+  };
+  let mut planned = HashMap::new();
+  let targets = match documents.as_slice() {
+    [] => return Err(anyhow::anyhow!("No manifest found in {}", file)),
+    // a single manifest is written as is, keeping its leading comments and separator
+    [_] => vec![apply_document(
+      file,
+      &content,
+      dry_run,
+      conflicts,
+      theme,
+      enable_color,
+      &mut planned,
+    )?],
+    documents => documents
+      .iter()
+      .map(|document| apply_document(file, document, dry_run, conflicts, theme, enable_color, &mut planned))
+      .collect::<Result<Vec<_>>>()?,
+  };
+  let targets = targets.into_iter().flatten().collect::<Vec<_>>();
+  if !targets.is_empty() {
+    git::auto_commit("apply", &targets)?;
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_split_documents() {
+    let bundle = "---\n# apix/demo.yaml\n# demo api\nkind: Api\n---\n# only a comment\n--- \nkind: Request\n";
+    assert_eq!(
+      split_documents(bundle),
+      vec!["# demo api\nkind: Api\n".to_string(), "kind: Request\n".to_string()]
+    );
+    assert_eq!(split_documents("kind: Request\n"), vec!["kind: Request\n".to_string()]);
+  }
+
+  #[test]
+  fn test_new_target_skips_planned_targets() {
+    let mut planned = HashMap::new();
+    assert_eq!(new_target("demo", "api", &planned), "apix/demo.yaml");
+    planned.insert(("api".to_string(), "demo".to_string()), "apix/demo.yaml".to_string());
+    assert_eq!(new_target("demo", "request", &planned), "apix/demo-request.yaml");
+  }
+}
@@ -0,0 +1,263 @@
+use crate::manifests::{ApixArtifact, ApixArtifactSet, ApixKind, ApixManifest};
+use crate::progress_component::MultiDownloadProgressComponent;
+use crate::requests::{cached_download_client, RequestOptions};
+use anyhow::Result;
+use futures::{stream, StreamExt};
+use indicatif::ProgressBar;
+use reqwest::header::USER_AGENT;
+use reqwest::Client;
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use tokio::io::AsyncWriteExt;
+
+static APP_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"),);
+
+static DEFAULT_CONCURRENCY: usize = 4;
+
+#[derive(Debug, PartialEq)]
+pub enum ArtifactStatus {
+  Downloaded,
+  // destination already matches the checksum
+  UpToDate,
+}
+
+// expected hex digest of a `sha256:<hex digest>` checksum
+fn parse_checksum(checksum: &str) -> Result<String> {
+  match checksum.split_once(':') {
+    Some(("sha256", digest)) if digest.len() == 64 && digest.chars().all(|c| c.is_ascii_hexdigit()) => {
+      Ok(digest.to_lowercase())
+    }
+    _ => Err(anyhow::anyhow!(
+      "Bad checksum \"{}\", expected sha256:<hex digest>",
+      checksum
+    )),
+  }
+}
+
+fn file_digest(path: &Path) -> Result<String> {
+  let mut file = std::fs::File::open(path)?;
+  let mut hasher = Sha256::new();
+  std::io::copy(&mut file, &mut hasher)?;
+  Ok(format!("{:x}", hasher.finalize()))
+}
+
+// stream an artifact into `<destination>.part`, moved to its destination once its checksum is verified
+pub async fn download_artifact(client: &Client, artifact: &ApixArtifact, bar: &ProgressBar) -> Result<ArtifactStatus> {
+  let expected = artifact.checksum.as_deref().map(parse_checksum).transpose()?;
+  let destination = Path::new(&artifact.destination);
+  if let Some(expected) = &expected {
+    if destination.is_file() && &file_digest(destination)? == expected {
+      bar.finish_with_message(format!("{} up to date", artifact.destination));
+      return Ok(ArtifactStatus::UpToDate);
+    }
+  }
+  if let Some(parent) = destination.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+    tokio::fs::create_dir_all(parent).await?;
+  }
+  let response = client
+    .get(&artifact.url)
+    .header(USER_AGENT, APP_USER_AGENT)
+    .send()
+    .await?
+    .error_for_status()
+    .map_err(|e| anyhow::anyhow!("Failed to download {}\ncause: {}", artifact.url, e))?;
+  bar.set_length(response.content_length().unwrap_or(0));
+  let part = format!("{}.part", artifact.destination);
+  let mut file = tokio::fs::File::create(&part)
+    .await
+    .map_err(|e| anyhow::anyhow!("Could not create {}\ncause: {}", part, e))?;
+  let mut hasher = Sha256::new();
+  let mut chunks = response.bytes_stream();
+  while let Some(chunk) = chunks.next().await {
+    let chunk = chunk?;
+    hasher.update(&chunk);
+    file.write_all(&chunk).await?;
+    bar.inc(chunk.len() as u64);
+  }
+  file.flush().await?;
+  drop(file);
+  let digest = format!("{:x}", hasher.finalize());
+  if let Some(expected) = expected {
+    if digest != expected {
+      tokio::fs::remove_file(&part).await?;
+      return Err(anyhow::anyhow!(
+        "Checksum mismatch for {}, expected sha256:{} but got sha256:{}",
+        artifact.destination,
+        expected,
+        digest
+      ));
+    }
+  }
+  tokio::fs::rename(&part, destination).await?;
+  bar.finish_with_message(format!("{} downloaded", artifact.destination));
+  Ok(ArtifactStatus::Downloaded)
+}
+
+// download every artifact of the set, failed ones don't stop the others
+// proxy, timeouts, trust store and certificate pins of options are used
+pub async fn download_artifacts(
+  set: &ApixArtifactSet,
+  concurrency: Option<usize>,
+  options: &RequestOptions<'_>,
+) -> Vec<Result<ArtifactStatus>> {
+  let concurrency = concurrency.or(set.concurrency).unwrap_or(DEFAULT_CONCURRENCY).max(1);
+  let destinations = set
+    .artifacts
+    .iter()
+    .map(|artifact| artifact.destination.clone())
+    .collect::<Vec<_>>();
+  let component = MultiDownloadProgressComponent::new(&destinations, options.progress);
+  let client = cached_download_client(options);
+  let results = match &client {
+    Ok(client) => {
+      let component = &component;
+      stream::iter(set.artifacts.iter().enumerate())
+        .map(|(index, artifact)| async move { download_artifact(client, artifact, component.bar(index)).await })
+        .buffered(concurrency)
+        .collect::<Vec<_>>()
+        .await
+    }
+    Err(e) => set.artifacts.iter().map(|_| Err(anyhow::anyhow!("{:#}", e))).collect(),
+  };
+  if let Err(e) = component.join().await {
+    eprintln!("Failed to display download progress\ncause: {}", e);
+  }
+  results
+}
+
+// download the artifact set of a manifest file, or of a project resource by name
+pub async fn handle_download(name: &str, concurrency: Option<usize>, options: RequestOptions<'_>) -> Result<()> {
+  let manifest = if Path::new(name).is_file() {
+    ApixManifest::from_file(Path::new(name))?
+  } else {
+    ApixManifest::find_manifest("artifactset", name)
+      .map(|(_, manifest)| manifest)
+      .ok_or_else(|| anyhow::anyhow!("No resource of type artifactset where found with name {}", name))?
+  };
+  let set = match manifest.kind() {
+    ApixKind::ArtifactSet(set) => set,
+    _ => return Err(anyhow::anyhow!("Resource {} is not an artifact set", name)),
+  };
+  let results = download_artifacts(set, concurrency, &options).await;
+  let mut failed = 0;
+  for (artifact, result) in set.artifacts.iter().zip(&results) {
+    match result {
+      Ok(ArtifactStatus::Downloaded) => println!("✔ {}", artifact.destination),
+      Ok(ArtifactStatus::UpToDate) => println!("✔ {} (up to date)", artifact.destination),
+      Err(e) => {
+        failed += 1;
+        println!("✘ {}: {}", artifact.destination, e);
+      }
+    }
+  }
+  match failed {
+    0 => Ok(()),
+    failed => Err(anyhow::anyhow!(
+      "{} of {} artifacts failed to download",
+      failed,
+      results.len()
+    )),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tokio::io::AsyncReadExt;
+
+  #[test]
+  fn test_parse_checksum() {
+    let digest = "5891B5B522D5DF086D0FF0B110FBD9D21BB4FC7163AF34D08286A2E846F6BE03";
+    assert_eq!(
+      parse_checksum(&format!("sha256:{}", digest)).unwrap(),
+      digest.to_lowercase()
+    );
+    assert!(parse_checksum("sha256:abc").is_err());
+    assert!(parse_checksum(&format!("md5:{}", digest)).is_err());
+  }
+
+  #[tokio::test]
+  async fn test_download_artifacts() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/artifact", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+      loop {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut buffer = [0u8; 1024];
+        let _ = socket.read(&mut buffer).await.unwrap();
+        socket
+          .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 5\r\nconnection: close\r\n\r\nhello")
+          .await
+          .unwrap();
+      }
+    });
+    let directory = std::env::temp_dir().join(format!("apix-artifacts-{}", uuid::Uuid::new_v4()));
+    let destination = |name: &str| directory.join(name).to_string_lossy().to_string();
+    let set = ApixArtifactSet {
+      concurrency: Some(2),
+      artifacts: vec![
+        ApixArtifact {
+          url: url.clone(),
+          destination: destination("hello.txt"),
+          checksum: Some("sha256:2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824".to_string()),
+        },
+        ApixArtifact {
+          url: url.clone(),
+          destination: destination("bad.txt"),
+          checksum: Some(format!("sha256:{}", "0".repeat(64))),
+        },
+      ],
+    };
+    let options = RequestOptions::default();
+    let results = download_artifacts(&set, None, &options).await;
+    assert_eq!(results[0].as_ref().unwrap(), &ArtifactStatus::Downloaded);
+    assert_eq!(std::fs::read_to_string(destination("hello.txt")).unwrap(), "hello");
+    assert!(results[1].is_err());
+    assert!(!Path::new(&destination("bad.txt")).exists());
+    assert!(!Path::new(&destination("bad.txt.part")).exists());
+    let results = download_artifacts(&set, None, &options).await;
+    assert_eq!(results[0].as_ref().unwrap(), &ArtifactStatus::UpToDate);
+    std::fs::remove_dir_all(directory).unwrap();
+  }
+
+  // test artifacts are downloaded through the configured proxy
+  #[tokio::test]
+  async fn test_download_artifacts_proxy() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+      let (mut socket, _) = listener.accept().await.unwrap();
+      let mut buffer = [0u8; 1024];
+      let read = socket.read(&mut buffer).await.unwrap();
+      // proxies receive absolute urls
+      let body = match String::from_utf8_lossy(&buffer[..read]).starts_with("GET http://artifacts.invalid/hello ") {
+        true => "hello",
+        false => "wrong",
+      };
+      let response = format!(
+        "HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+        body.len(),
+        body
+      );
+      socket.write_all(response.as_bytes()).await.unwrap();
+    });
+    let directory = std::env::temp_dir().join(format!("apix-artifacts-{}", uuid::Uuid::new_v4()));
+    let destination = directory.join("hello.txt").to_string_lossy().to_string();
+    let set = ApixArtifactSet {
+      concurrency: None,
+      artifacts: vec![ApixArtifact {
+        url: "http://artifacts.invalid/hello".to_string(),
+        destination: destination.clone(),
+        checksum: None,
+      }],
+    };
+    let options = RequestOptions {
+      proxy_url: Some(proxy),
+      ..Default::default()
+    };
+    let results = download_artifacts(&set, None, &options).await;
+    assert_eq!(results[0].as_ref().unwrap(), &ArtifactStatus::Downloaded);
+    assert_eq!(std::fs::read_to_string(&destination).unwrap(), "hello");
+    std::fs::remove_dir_all(directory).unwrap();
+  }
+}
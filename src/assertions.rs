@@ -0,0 +1,474 @@
+use anyhow::Result;
+use regex::Regex;
+use serde_json::Value;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathSegment {
+  Key(String),
+  Index(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+  Length,
+  Lower,
+  Upper,
+  Trim,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+  Equal,
+  NotEqual,
+  Greater,
+  GreaterOrEqual,
+  Lower,
+  LowerOrEqual,
+  Contains,
+  NotContains,
+  Matches,
+  Exists,
+}
+
+// assertion on a response, of the form `<path> [| <filter>]* <operator> [<json value>]`
+// example: `status == 200`, `headers["content-type"] contains "json"`, `body.items | length >= 1`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assertion {
+  source: String,
+  pub path: Vec<PathSegment>,
+  pub filters: Vec<Filter>,
+  pub operator: Operator,
+  pub expected: Value,
+}
+
+struct Parser<'a> {
+  input: &'a str,
+  position: usize,
+}
+
+impl<'a> Parser<'a> {
+  fn rest(&self) -> &'a str {
+    &self.input[self.position..]
+  }
+
+  fn skip_whitespaces(&mut self) {
+    self.position = self.input.len() - self.rest().trim_start().len();
+  }
+
+  fn eat(&mut self, token: &str) -> bool {
+    if self.rest().starts_with(token) {
+      self.position += token.len();
+      true
+    } else {
+      false
+    }
+  }
+
+  fn ident(&mut self) -> Option<&'a str> {
+    let rest = self.rest();
+    let len = rest
+      .char_indices()
+      .find(|(index, c)| !(c.is_alphanumeric() || *c == '_' || (*index > 0 && *c == '-')))
+      .map(|(index, _)| index)
+      .unwrap_or(rest.len());
+    if len == 0 || rest.starts_with(|c: char| c.is_ascii_digit()) {
+      return None;
+    }
+    self.position += len;
+    Some(&rest[..len])
+  }
+
+  // bracket accessor content, either a quoted key or an index
+  fn bracket(&mut self) -> Result<PathSegment> {
+    let rest = self.rest();
+    // quoted keys can contain ']', so the closing bracket is searched after their closing quote
+    let key_start = rest.len() - rest.trim_start().len();
+    let key_end = match rest[key_start..].strip_prefix('"') {
+      Some(key) => {
+        let mut escaped = false;
+        let quote = key
+          .char_indices()
+          .find(|(_, char)| {
+            let closing = *char == '"' && !escaped;
+            escaped = *char == '\\' && !escaped;
+            closing
+          })
+          .ok_or_else(|| anyhow::anyhow!("missing closing quote in '{}'", self.input))?;
+        key_start + 1 + quote.0 + 1
+      }
+      None => key_start,
+    };
+    let end = rest[key_end..]
+      .find(']')
+      .map(|end| key_end + end)
+      .ok_or_else(|| anyhow::anyhow!("missing ']' in '{}'", self.input))?;
+    let content = rest[..end].trim();
+    self.position += end + 1;
+    if let Ok(index) = content.parse::<usize>() {
+      return Ok(PathSegment::Index(index));
+    }
+    match serde_json::from_str::<Value>(content) {
+      Ok(Value::String(key)) => Ok(PathSegment::Key(key)),
+      _ => Err(anyhow::anyhow!(
+        "bad accessor [{}] in '{}', expected an index or a quoted key",
+        content,
+        self.input
+      )),
+    }
+  }
+
+  fn path(&mut self) -> Result<Vec<PathSegment>> {
+    let root = self
+      .ident()
+      .ok_or_else(|| anyhow::anyhow!("expected status, headers or body in '{}'", self.input))?;
+    let mut path = vec![PathSegment::Key(root.to_string())];
+    loop {
+      if self.eat(".") {
+        let key = self
+          .ident()
+          .ok_or_else(|| anyhow::anyhow!("expected a key after '.' in '{}'", self.input))?;
+        path.push(PathSegment::Key(key.to_string()));
+      } else if self.eat("[") {
+        path.push(self.bracket()?);
+      } else {
+        // header names are case insensitive, and received lowercased
+        if let [PathSegment::Key(root), PathSegment::Key(name), ..] = path.as_mut_slice() {
+          if root == "headers" {
+            *name = name.to_lowercase();
+          }
+        }
+        return Ok(path);
+      }
+    }
+  }
+
+  fn filters(&mut self) -> Result<Vec<Filter>> {
+    let mut filters = Vec::new();
+    loop {
+      self.skip_whitespaces();
+      if !self.eat("|") {
+        return Ok(filters);
+      }
+      self.skip_whitespaces();
+      filters.push(match self.ident() {
+        Some("length") => Filter::Length,
+        Some("lower") => Filter::Lower,
+        Some("upper") => Filter::Upper,
+        Some("trim") => Filter::Trim,
+        other => {
+          return Err(anyhow::anyhow!(
+            "unknown filter '{}' in '{}', expected length, lower, upper or trim",
+            other.unwrap_or_default(),
+            self.input
+          ))
+        }
+      });
+    }
+  }
+
+  fn operator(&mut self) -> Result<Operator> {
+    self.skip_whitespaces();
+    let symbols = [
+      ("==", Operator::Equal),
+      ("!=", Operator::NotEqual),
+      (">=", Operator::GreaterOrEqual),
+      ("<=", Operator::LowerOrEqual),
+      (">", Operator::Greater),
+      ("<", Operator::Lower),
+    ];
+    for (symbol, operator) in symbols {
+      if self.eat(symbol) {
+        return Ok(operator);
+      }
+    }
+    match self.ident() {
+      Some("contains") => Ok(Operator::Contains),
+      Some("matches") => Ok(Operator::Matches),
+      Some("exists") => Ok(Operator::Exists),
+      Some("not") => {
+        self.skip_whitespaces();
+        match self.ident() {
+          Some("contains") => Ok(Operator::NotContains),
+          _ => Err(anyhow::anyhow!("expected 'not contains' in '{}'", self.input)),
+        }
+      }
+      _ => Err(anyhow::anyhow!(
+        "expected an operator (==, !=, >, >=, <, <=, contains, not contains, matches, exists) in '{}'",
+        self.input
+      )),
+    }
+  }
+}
+
+impl FromStr for Assertion {
+  type Err = anyhow::Error;
+
+  fn from_str(source: &str) -> Result<Self, Self::Err> {
+    let mut parser = Parser {
+      input: source.trim(),
+      position: 0,
+    };
+    let path = parser.path()?;
+    let filters = parser.filters()?;
+    let operator = parser.operator()?;
+    let rest = parser.rest().trim();
+    let expected = match (operator, rest) {
+      (Operator::Exists, "") => Value::Null,
+      (Operator::Exists, _) => {
+        return Err(anyhow::anyhow!("unexpected value after 'exists' in '{}'", source));
+      }
+      (_, rest) => serde_json::from_str(rest).map_err(|_| {
+        anyhow::anyhow!(
+          "bad value '{}' in '{}', expected a json value (strings must be quoted)",
+          rest,
+          source
+        )
+      })?,
+    };
+    if operator == Operator::Matches {
+      let pattern = expected
+        .as_str()
+        .ok_or_else(|| anyhow::anyhow!("'matches' expects a quoted regex in '{}'", source))?;
+      Regex::new(pattern)?;
+    }
+    Ok(Self {
+      source: source.trim().to_string(),
+      path,
+      filters,
+      operator,
+      expected,
+    })
+  }
+}
+
+fn resolve<'a>(value: &'a Value, path: &[PathSegment]) -> Option<&'a Value> {
+  path.iter().try_fold(value, |value, segment| match segment {
+    PathSegment::Key(key) => value.get(key.as_str()),
+    PathSegment::Index(index) => value.get(*index),
+  })
+}
+
+fn apply_filter(filter: Filter, value: Value) -> Result<Value, String> {
+  match (filter, value) {
+    (Filter::Length, Value::String(string)) => Ok(Value::from(string.chars().count())),
+    (Filter::Length, Value::Array(array)) => Ok(Value::from(array.len())),
+    (Filter::Length, Value::Object(object)) => Ok(Value::from(object.len())),
+    (Filter::Lower, Value::String(string)) => Ok(Value::String(string.to_lowercase())),
+    (Filter::Upper, Value::String(string)) => Ok(Value::String(string.to_uppercase())),
+    (Filter::Trim, Value::String(string)) => Ok(Value::String(string.trim().to_string())),
+    (filter, value) => Err(format!("filter {:?} can't be applied to {}", filter, value)),
+  }
+}
+
+fn compare(actual: &Value, expected: &Value) -> Option<std::cmp::Ordering> {
+  match (actual, expected) {
+    (Value::Number(actual), Value::Number(expected)) => actual.as_f64()?.partial_cmp(&expected.as_f64()?),
+    (Value::String(actual), Value::String(expected)) => Some(actual.cmp(expected)),
+    _ => None,
+  }
+}
+
+fn contains(actual: &Value, expected: &Value) -> Result<bool, String> {
+  match (actual, expected) {
+    (Value::String(actual), Value::String(expected)) => Ok(actual.contains(expected.as_str())),
+    (Value::Array(actual), expected) => Ok(
+      actual
+        .iter()
+        .any(|item| item == expected || compare(item, expected) == Some(std::cmp::Ordering::Equal)),
+    ),
+    (Value::Object(actual), Value::String(expected)) => Ok(actual.contains_key(expected)),
+    (actual, expected) => Err(format!("can't check if {} contains {}", actual, expected)),
+  }
+}
+
+impl Assertion {
+  pub fn source(&self) -> &str {
+    &self.source
+  }
+
+  // evaluate the assertion against a json representation of a response, error describes the failure
+  pub fn evaluate(&self, response: &Value) -> Result<(), String> {
+    let actual = resolve(response, &self.path);
+    if self.operator == Operator::Exists {
+      return actual.map(|_| ()).ok_or_else(|| "value does not exist".to_string());
+    }
+    let mut actual = actual.cloned().ok_or_else(|| "value does not exist".to_string())?;
+    for filter in &self.filters {
+      actual = apply_filter(*filter, actual)?;
+    }
+    let expected = &self.expected;
+    let ordering = compare(&actual, expected);
+    let success = match self.operator {
+      Operator::Equal => actual == *expected || ordering == Some(std::cmp::Ordering::Equal),
+      Operator::NotEqual => !(actual == *expected || ordering == Some(std::cmp::Ordering::Equal)),
+      Operator::Greater | Operator::GreaterOrEqual | Operator::Lower | Operator::LowerOrEqual => {
+        let ordering = ordering.ok_or_else(|| format!("can't compare {} with {}", actual, expected))?;
+        match self.operator {
+          Operator::Greater => ordering.is_gt(),
+          Operator::GreaterOrEqual => ordering.is_ge(),
+          Operator::Lower => ordering.is_lt(),
+          _ => ordering.is_le(),
+        }
+      }
+      Operator::Contains => contains(&actual, expected)?,
+      Operator::NotContains => !contains(&actual, expected)?,
+      Operator::Matches => match (&actual, expected) {
+        (Value::String(actual), Value::String(pattern)) => {
+          Regex::new(pattern).map_err(|err| err.to_string())?.is_match(actual)
+        }
+        _ => return Err(format!("can't match {} against a regex", actual)),
+      },
+      Operator::Exists => true,
+    };
+    if success {
+      Ok(())
+    } else {
+      Err(format!("actual value is {}", actual))
+    }
+  }
+}
+
+// value captured from a response, of the form `<path> [| <filter>]*`
+// example: `body.access_token`, `headers["x-request-id"]`, `body.items | length`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Capture {
+  source: String,
+  pub path: Vec<PathSegment>,
+  pub filters: Vec<Filter>,
+}
+
+impl FromStr for Capture {
+  type Err = anyhow::Error;
+
+  fn from_str(source: &str) -> Result<Self, Self::Err> {
+    let mut parser = Parser {
+      input: source.trim(),
+      position: 0,
+    };
+    let path = parser.path()?;
+    let filters = parser.filters()?;
+    if !parser.rest().trim().is_empty() {
+      return Err(anyhow::anyhow!("unexpected '{}' in '{}'", parser.rest().trim(), source));
+    }
+    Ok(Self {
+      source: source.trim().to_string(),
+      path,
+      filters,
+    })
+  }
+}
+
+impl Capture {
+  // extract the value from a json representation of a response
+  pub fn evaluate(&self, response: &Value) -> Result<Value> {
+    let mut value = resolve(response, &self.path)
+      .cloned()
+      .ok_or_else(|| anyhow::anyhow!("'{}' does not exist in response", self.source))?;
+    for filter in &self.filters {
+      value = apply_filter(*filter, value).map_err(|e| anyhow::anyhow!("'{}': {}", self.source, e))?;
+    }
+    Ok(value)
+  }
+}
+
+// evaluate all assertions, printing a line per assertion, and fail if any of them failed
+pub fn check_assertions(assertions: &[Assertion], response: &Value) -> Result<()> {
+  let mut failed = 0;
+  for assertion in assertions {
+    match assertion.evaluate(response) {
+      Ok(()) => eprintln!("✔ {}", assertion.source()),
+      Err(cause) => {
+        failed += 1;
+        eprintln!("✘ {} ({})", assertion.source(), cause);
+      }
+    }
+  }
+  match failed {
+    0 => Ok(()),
+    failed => Err(anyhow::anyhow!("{} of {} assertions failed", failed, assertions.len())),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+  use test_case::test_case;
+
+  fn response() -> Value {
+    json!({
+      "status": 200,
+      "headers": { "content-type": "application/json; charset=utf-8" },
+      "body": {
+        "items": [{ "name": "Apix" }, { "name": "Rust" }],
+        "token": "abc",
+        "tags": ["a", "b"],
+        "a]b": 1,
+        "say \"]\"": 2
+      }
+    })
+  }
+
+  #[test_case("status == 200" => true ; "status equal")]
+  #[test_case("status != 200" => false ; "status not equal")]
+  #[test_case("status >= 200" => true ; "status greater or equal")]
+  #[test_case("status < 300" => true ; "status lower")]
+  #[test_case("status > 200" => false ; "status greater")]
+  #[test_case("headers[\"content-type\"] contains \"json\"" => true)]
+  #[test_case("headers[\"content-type\"] not contains \"xml\"" => true)]
+  #[test_case("headers[\"Content-Type\"] contains \"json\"" => true ; "header name case")]
+  #[test_case("body[\"a]b\"] == 1" => true ; "bracket in key")]
+  #[test_case("body[ \"say \\\"]\\\"\" ] == 2" => true ; "escaped quote in key")]
+  #[test_case("body.items | length >= 1" => true)]
+  #[test_case("body.items[1].name | lower == \"rust\"" => true)]
+  #[test_case("body.items[0].name matches \"^A\"" => true)]
+  #[test_case("body.tags contains \"b\"" => true)]
+  #[test_case("body contains \"token\"" => true)]
+  #[test_case("body.token exists" => true)]
+  #[test_case("body.missing exists" => false)]
+  #[test_case("body.missing == 1" => false)]
+  #[test_case("body.token == \"abd\"" => false)]
+  fn test_evaluate(assertion: &str) -> bool {
+    Assertion::from_str(assertion).unwrap().evaluate(&response()).is_ok()
+  }
+
+  #[test_case("status = 200")]
+  #[test_case("status == ok")]
+  #[test_case("body | size == 1")]
+  #[test_case("body[items] == 1")]
+  #[test_case("body exists 1")]
+  #[test_case("body[\"a] == 1")]
+  #[test_case("body matches \"(\"")]
+  fn test_parse_errors(assertion: &str) {
+    assert!(Assertion::from_str(assertion).is_err());
+  }
+
+  #[test]
+  fn test_check_assertions() {
+    let assertions = ["status == 200", "body.token == \"abc\""]
+      .iter()
+      .map(|assertion| Assertion::from_str(assertion).unwrap())
+      .collect::<Vec<_>>();
+    assert!(check_assertions(&assertions, &response()).is_ok());
+    let assertions = vec![Assertion::from_str("status == 404").unwrap()];
+    assert!(check_assertions(&assertions, &response()).is_err());
+  }
+
+  #[test_case("body.token" => json!("abc") ; "body key")]
+  #[test_case("headers[\"content-type\"]" => json!("application/json; charset=utf-8") ; "header")]
+  #[test_case("body.items[1].name | upper" => json!("RUST") ; "filtered")]
+  #[test_case("body.tags | length" => json!(2) ; "length")]
+  fn test_capture(capture: &str) -> Value {
+    Capture::from_str(capture).unwrap().evaluate(&response()).unwrap()
+  }
+
+  #[test]
+  fn test_capture_errors() {
+    assert!(Capture::from_str("body.token == 1").is_err());
+    assert!(Capture::from_str("body.missing")
+      .unwrap()
+      .evaluate(&response())
+      .is_err());
+  }
+}
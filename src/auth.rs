@@ -0,0 +1,162 @@
+use super::dialog::Dialog;
+use super::manifests::{ApiKeyLocation, ApixAuth, ApixParameter};
+use super::tokens::{get_token_blocking, TokenRequest};
+use anyhow::Result;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION};
+use serde_json::{json, Value};
+use std::str::FromStr;
+
+// rendered credential value, prompted when missing or rendered empty
+fn resolve(
+  name: &str,
+  value: Option<&String>,
+  password: bool,
+  render: &mut impl FnMut(&str, &str) -> Result<String>,
+) -> Result<String> {
+  if let Some(value) = value {
+    let rendered = render(name, value)?;
+    if !rendered.is_empty() {
+      return Ok(rendered);
+    }
+  }
+  if !atty::is(atty::Stream::Stdin) {
+    return Err(anyhow::anyhow!("Missing auth {}, set it in api manifest", name));
+  }
+  let parameter = ApixParameter::new(
+    format!("auth {}", name),
+    true,
+    password,
+    None,
+    Some(json!({ "type": "string" })),
+  );
+  match parameter.ask()? {
+    Value::String(value) => Ok(value),
+    value => Ok(value.to_string()),
+  }
+}
+
+fn render_option(
+  name: &str,
+  value: Option<&String>,
+  render: &mut impl FnMut(&str, &str) -> Result<String>,
+) -> Result<Option<String>> {
+  Ok(
+    value
+      .map(|value| render(name, value))
+      .transpose()?
+      .filter(|value| !value.is_empty()),
+  )
+}
+
+// add credentials to request headers or queries, explicit request values are kept
+// render is called with the credential name and its template
+pub fn apply_auth(
+  auth: &ApixAuth,
+  headers: &mut HeaderMap,
+  queries: &mut Vec<(String, String)>,
+  mut render: impl FnMut(&str, &str) -> Result<String>,
+) -> Result<()> {
+  if let ApixAuth::ApiKey {
+    name,
+    value,
+    location: ApiKeyLocation::Query,
+  } = auth
+  {
+    if !queries.iter().any(|(key, _)| key == name) {
+      let value = resolve(name, value.as_ref(), true, &mut render)?;
+      queries.push((name.clone(), value));
+    }
+    return Ok(());
+  }
+  let header = match auth {
+    ApixAuth::ApiKey { name, .. } => HeaderName::from_str(name)?,
+    _ => AUTHORIZATION,
+  };
+  if headers.contains_key(&header) {
+    return Ok(());
+  }
+  let value = match auth {
+    ApixAuth::Bearer { token } => format!("Bearer {}", resolve("token", token.as_ref(), true, &mut render)?),
+    ApixAuth::Basic { username, password } => {
+      let username = resolve("username", username.as_ref(), false, &mut render)?;
+      let password = resolve("password", password.as_ref(), true, &mut render)?;
+      format!("Basic {}", base64::encode(format!("{}:{}", username, password)))
+    }
+    ApixAuth::ApiKey { name, value, .. } => resolve(name, value.as_ref(), true, &mut render)?,
+    ApixAuth::OAuth2 {
+      issuer,
+      audience,
+      scope,
+      client_id,
+      client_secret,
+    } => {
+      let request = TokenRequest::new(
+        &render("issuer", issuer)?,
+        render_option("audience", audience.as_ref(), &mut render)?.as_deref(),
+        render_option("scope", scope.as_ref(), &mut render)?.as_deref(),
+        render_option("clientId", client_id.as_ref(), &mut render)?.as_deref(),
+        render_option("clientSecret", client_secret.as_ref(), &mut render)?.as_deref(),
+      );
+      format!("Bearer {}", get_token_blocking(request)?)
+    }
+  };
+  headers.insert(header, HeaderValue::from_str(&value)?);
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn apply(auth: &str, headers: &mut HeaderMap, queries: &mut Vec<(String, String)>) {
+    let auth: ApixAuth = serde_yaml::from_str(auth).unwrap();
+    apply_auth(&auth, headers, queries, |_, value| {
+      Ok(value.replace("{{ env.TOKEN }}", "secret"))
+    })
+    .unwrap();
+  }
+
+  #[test]
+  fn test_apply_bearer_auth() {
+    let mut headers = HeaderMap::new();
+    apply(
+      "type: bearer\ntoken: \"{{ env.TOKEN }}\"",
+      &mut headers,
+      &mut Vec::new(),
+    );
+    assert_eq!(headers.get(AUTHORIZATION).unwrap(), "Bearer secret");
+  }
+
+  #[test]
+  fn test_apply_basic_auth() {
+    let mut headers = HeaderMap::new();
+    apply(
+      "type: basic\nusername: user\npassword: pass",
+      &mut headers,
+      &mut Vec::new(),
+    );
+    assert_eq!(headers.get(AUTHORIZATION).unwrap(), "Basic dXNlcjpwYXNz");
+  }
+
+  #[test]
+  fn test_apply_apikey_auth() {
+    let mut headers = HeaderMap::new();
+    let mut queries = Vec::new();
+    apply("type: apikey\nname: x-api-key\nvalue: key", &mut headers, &mut queries);
+    apply(
+      "type: apikey\nname: key\nvalue: key\nin: query",
+      &mut headers,
+      &mut queries,
+    );
+    assert_eq!(headers.get("x-api-key").unwrap(), "key");
+    assert_eq!(queries, vec![("key".to_string(), "key".to_string())]);
+  }
+
+  #[test]
+  fn test_explicit_auth_is_kept() {
+    let mut headers = HeaderMap::new();
+    headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer explicit"));
+    apply("type: bearer\ntoken: other", &mut headers, &mut Vec::new());
+    assert_eq!(headers.get(AUTHORIZATION).unwrap(), "Bearer explicit");
+  }
+}
@@ -0,0 +1,185 @@
+use crate::manifests::ApixLoadProfile;
+use crate::report::RepeatStats;
+use anyhow::Result;
+use comfy_table::{ContentArrangement, Table};
+use futures::stream::{FuturesUnordered, StreamExt};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+// stage duration like `500ms`, `30s`, `2m` or `1h`
+pub fn parse_stage_duration(value: &str) -> Result<Duration> {
+  static RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(\d+)\s*(ms|s|m|h)$").unwrap());
+  let captures = RE.captures(value.trim()).ok_or_else(|| {
+    anyhow::anyhow!(
+      "Bad stage duration: \"{}\", should be a number followed by ms, s, m or h",
+      value
+    )
+  })?;
+  let amount = captures[1].parse::<u64>()?;
+  Ok(match &captures[2] {
+    "ms" => Duration::from_millis(amount),
+    "s" => Duration::from_secs(amount),
+    "m" => Duration::from_secs(amount * 60),
+    _ => Duration::from_secs(amount * 3600),
+  })
+}
+
+// send offsets of requests during a stage whose rate goes linearly from `from` to `to` requests per second,
+// request `i` is sent when the number of requests expected since the stage start reaches `i`
+fn schedule(from: f64, to: f64, duration: Duration) -> Vec<Duration> {
+  let total = duration.as_secs_f64();
+  if total <= 0.0 {
+    return vec![];
+  }
+  // expected requests after t seconds: from * t + (to - from) * t² / 2 * total
+  let acceleration = (to - from) / (2.0 * total);
+  let count = (from * total + acceleration * total * total).round().max(0.0) as usize;
+  (0..count)
+    .map(|index| {
+      let index = index as f64;
+      let seconds = if acceleration.abs() < f64::EPSILON {
+        index / from
+      } else {
+        (-from + (from * from + 4.0 * acceleration * index).max(0.0).sqrt()) / (2.0 * acceleration)
+      };
+      Duration::from_secs_f64(seconds.clamp(0.0, total))
+    })
+    .collect()
+}
+
+pub struct StageReport {
+  pub label: String,
+  pub stats: RepeatStats,
+}
+
+// run stages one after the other, requests of a stage are sent concurrently at their scheduled offset
+// `send` returns the success of a request, latencies are measured from the actual send time
+pub async fn run_profile<F, Fut>(profile: &ApixLoadProfile, send: F) -> Result<Vec<StageReport>>
+where
+  F: Fn() -> Fut,
+  Fut: Future<Output = bool>,
+{
+  let mut reports = Vec::new();
+  let mut previous = 0.0;
+  for stage in &profile.stages {
+    let duration = parse_stage_duration(&stage.duration)?;
+    let from = stage.from.unwrap_or(previous);
+    previous = stage.to;
+    let label = format!("{} → {} rps over {}", from, stage.to, stage.duration);
+    eprintln!("stage {}", label);
+    let start = Instant::now();
+    let mut requests = schedule(from, stage.to, duration)
+      .into_iter()
+      .map(|offset| {
+        let send = &send;
+        async move {
+          tokio::time::sleep_until((start + offset).into()).await;
+          let sent = Instant::now();
+          let success = send().await;
+          (sent.elapsed(), success)
+        }
+      })
+      .collect::<FuturesUnordered<_>>();
+    let mut stats = RepeatStats::default();
+    while let Some((latency, success)) = requests.next().await {
+      stats.record(latency, success);
+    }
+    reports.push(StageReport { label, stats });
+  }
+  Ok(reports)
+}
+
+// latency and error breakdown per stage
+pub fn print_stage_reports(reports: &[StageReport]) {
+  let mut table = Table::new();
+  table
+    .load_preset("││──├─┼┤│─┼├┤┬┴╭╮╰╯")
+    .set_content_arrangement(ContentArrangement::Dynamic)
+    .set_header(["Stage", "Requests", "Errors", "Min", "Avg", "P95", "Max"]);
+  for report in reports {
+    let latencies = report
+      .stats
+      .latencies()
+      .map(|latencies| latencies.map(|latency| format!("{}ms", latency.as_millis())))
+      .unwrap_or_default();
+    let mut row = vec![
+      report.label.clone(),
+      report.stats.count().to_string(),
+      report.stats.failed().to_string(),
+    ];
+    row.extend(latencies);
+    table.add_row(row);
+  }
+  eprintln!("{table}");
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::manifests::ApixLoadStage;
+  use test_case::test_case;
+
+  #[test_case("500ms" => 500)]
+  #[test_case("30s" => 30_000)]
+  #[test_case("2m" => 120_000)]
+  #[test_case("1h" => 3_600_000)]
+  fn test_parse_stage_duration(value: &str) -> u128 {
+    parse_stage_duration(value).unwrap().as_millis()
+  }
+
+  #[test]
+  fn test_parse_bad_stage_duration() {
+    assert!(parse_stage_duration("2 minutes").is_err());
+  }
+
+  #[test]
+  fn test_schedule_constant_rate() {
+    let offsets = schedule(10.0, 10.0, Duration::from_secs(1));
+    assert_eq!(offsets.len(), 10);
+    assert_eq!(offsets[0], Duration::ZERO);
+    assert_eq!(offsets[5].as_millis(), 500);
+  }
+
+  #[test]
+  fn test_schedule_ramp_up() {
+    let offsets = schedule(0.0, 10.0, Duration::from_secs(2));
+    assert_eq!(offsets.len(), 10);
+    assert!(offsets.windows(2).all(|pair| pair[0] <= pair[1]));
+    // half of the requests are sent during the last 30% of the stage
+    assert!(offsets[5].as_secs_f64() > 1.4);
+  }
+
+  #[test]
+  fn test_schedule_ramp_down() {
+    let offsets = schedule(10.0, 0.0, Duration::from_secs(2));
+    assert_eq!(offsets.len(), 10);
+    assert!(offsets[5].as_secs_f64() < 0.6);
+    assert!(offsets.iter().all(|offset| offset.as_secs_f64() <= 2.0));
+  }
+
+  #[tokio::test]
+  async fn test_run_profile() {
+    let profile = ApixLoadProfile {
+      stages: vec![
+        ApixLoadStage {
+          duration: "100ms".to_string(),
+          from: Some(100.0),
+          to: 100.0,
+        },
+        ApixLoadStage {
+          duration: "100ms".to_string(),
+          from: None,
+          to: 300.0,
+        },
+      ],
+    };
+    let reports = run_profile(&profile, || async { true }).await.unwrap();
+    assert_eq!(reports.len(), 2);
+    assert_eq!(reports[0].stats.count(), 10);
+    assert_eq!(reports[1].label, "100 → 300 rps over 100ms");
+    assert_eq!(reports[1].stats.count(), 20);
+    assert_eq!(reports[1].stats.failed(), 0);
+  }
+}
@@ -1,24 +1,43 @@
+use super::certificates::parse_pins;
+use super::decode::BodyDecoder;
+use super::http_utils::{parse_size, parse_timeout, ContinueAt};
+use super::logging::LOG_LEVELS;
 use super::match_params::RequestParam;
-use super::validators::{validate_param, validate_url};
+use super::validators::{
+  validate_concurrency, validate_date, validate_header, validate_param, validate_range, validate_repeat, validate_url,
+  validate_url_template,
+};
 use clap::{crate_authors, crate_version, App, AppSettings, Arg, ValueHint};
 use clap_complete::Shell;
 use once_cell::sync::Lazy;
 
 pub fn build_request_args() -> impl Iterator<Item = &'static Arg<'static>> {
-  static ARGS: Lazy<[Arg<'static>; 17]> = Lazy::new(|| {
+  static ARGS: Lazy<[Arg<'static>; 28]> = Lazy::new(|| {
     [
       Arg::new("url")
-        .help("url to request, can be a 'Tera' template")
+        .help("url to request, can be a 'Tera' template, several urls send the same request to each of them")
         .required(true)
+        .multiple_values(true)
         .value_hint(ValueHint::Url)
-        .validator(validate_url),
+        .validator(validate_url_template),
+      Arg::new("parallel")
+        .long("parallel")
+        .help("send request to all urls concurrently, responses are labelled with their url")
+        .conflicts_with("output-file"),
+      Arg::new("repeat")
+        .long("repeat")
+        .help("send request n times sequentially, then print latency statistics")
+        .takes_value(true)
+        .value_name("n")
+        .validator(validate_repeat)
+        .conflicts_with("parallel"),
       Arg::new("header")
         .short('H')
         .long("header")
-        .help("set header name:value to send with request")
+        .help("set header name:value to send with request, 'name:' removes a default header and 'name;' sends it empty")
         .multiple_occurrences(true)
         .takes_value(true)
-        .validator(|param| validate_param(param, RequestParam::Header)),
+        .validator(validate_header),
       Arg::new("cookie")
         .short('c')
         .long("cookie")
@@ -29,7 +48,7 @@ pub fn build_request_args() -> impl Iterator<Item = &'static Arg<'static>> {
       Arg::new("query")
         .short('q')
         .long("query")
-        .help("set query name:value to send with request")
+        .help("set query name:value to send with request, overrides url query pairs of the same name")
         .multiple_occurrences(true)
         .takes_value(true)
         .validator(|param| validate_param(param, RequestParam::Query)),
@@ -42,17 +61,49 @@ pub fn build_request_args() -> impl Iterator<Item = &'static Arg<'static>> {
       Arg::new("file")
         .short('f')
         .long("file")
-        .help("set body from file to send with request, can be a 'Tera' template")
+        .help("set body from file to send with request, can be a 'Tera' template, `-` streams body from stdin")
         .takes_value(true)
         .conflicts_with("body")
         .value_hint(ValueHint::FilePath),
+      Arg::new("json")
+        .long("json")
+        .help("send body as json, sets Content-Type and Accept headers for json")
+        .conflicts_with("form"),
+      Arg::new("form")
+        .long("form")
+        .help("send body as url encoded form, a json object body is encoded, sets Content-Type header for forms")
+        .conflicts_with("json"),
       Arg::new("param")
         .short('p')
         .long("param")
-        .help("set parameter name:value for 'Tera' template rendering")
+        .help("set parameter name:value for 'Tera' template rendering, name:@file reads the value from a file (json if .json)")
         .multiple_occurrences(true)
         .takes_value(true)
         .validator(|param| validate_param(param, RequestParam::Param)),
+      Arg::new("if-none-match")
+        .long("if-none-match")
+        .help("only get response if its etag differs, sets If-None-Match header")
+        .takes_value(true),
+      Arg::new("if-modified-since")
+        .long("if-modified-since")
+        .help(
+          "only get response if modified since date, can be an HTTP date, YYYY-MM-DD or relative like '2 hours ago'",
+        )
+        .takes_value(true)
+        .validator(validate_date),
+      Arg::new("range")
+        .long("range")
+        .help("only get byte ranges of response, sets Range header (eg: 0-1023, 1024- or -512)")
+        .takes_value(true)
+        .validator(validate_range),
+      Arg::new("continue-at")
+        .long("continue-at")
+        .value_name("offset|auto")
+        .help("resume download into output file at byte offset, auto continues after bytes already downloaded")
+        .takes_value(true)
+        .requires("output-file")
+        .conflicts_with_all(&["range", "parallel"])
+        .validator(|value| value.parse::<ContinueAt>().map(|_| ())),
       Arg::new("proxy")
         .help("set proxy url to use for request")
         .short('x')
@@ -78,7 +129,18 @@ pub fn build_request_args() -> impl Iterator<Item = &'static Arg<'static>> {
       Arg::new("timeout")
         .help("set request timeout in seconds")
         .long("timeout")
-        .takes_value(true),
+        .takes_value(true)
+        .validator(|value| parse_timeout(value).map(|_| ())),
+      Arg::new("connect-timeout")
+        .help("set connection timeout in seconds, including dns resolution and tls handshake")
+        .long("connect-timeout")
+        .takes_value(true)
+        .validator(|value| parse_timeout(value).map(|_| ())),
+      Arg::new("read-timeout")
+        .help("fail when the server sends nothing for this many seconds, while waiting for the response or its body")
+        .long("read-timeout")
+        .takes_value(true)
+        .validator(|value| parse_timeout(value).map(|_| ())),
       Arg::new("user-agent")
         .help("set user agent to send with request")
         .long("user-agent")
@@ -96,29 +158,72 @@ pub fn build_request_args() -> impl Iterator<Item = &'static Arg<'static>> {
       Arg::new("insecure")
         .help("allow insecure connections when using https")
         .long("insecure"),
+      Arg::new("session")
+        .help("reuse and update headers and cookies of a named session stored in .apix/sessions")
+        .long("session")
+        .takes_value(true),
     ]
   });
   ARGS.iter()
 }
 
 pub fn build_exec_args() -> impl Iterator<Item = &'static Arg<'static>> {
-  static EXEC_ARGS: Lazy<[Arg<'static>; 6]> = Lazy::new(|| {
+  static EXEC_ARGS: Lazy<[Arg<'static>; 15]> = Lazy::new(|| {
     [
       Arg::new("name").help("name of the request to execute").index(1),
       Arg::new("file")
-        .help("Execute a manifest file request directly")
+        .help("Execute manifest file requests directly, accepts glob patterns")
         .short('f')
         .long("file")
+        .multiple_occurrences(true)
         .takes_value(true)
         .value_hint(ValueHint::FilePath)
         .conflicts_with("name"),
+      Arg::new("concurrency")
+        .help("Set max number of manifest files executed at the same time")
+        .long("concurrency")
+        .takes_value(true)
+        .default_value("1")
+        .validator(validate_concurrency),
+      Arg::new("report-json")
+        .help("Write the run summary of executed manifest files as json")
+        .long("report-json")
+        .takes_value(true)
+        .value_hint(ValueHint::FilePath),
+      Arg::new("metrics-file")
+        .help("Write request metrics of executed manifest files in prometheus format")
+        .long("metrics-file")
+        .takes_value(true)
+        .value_hint(ValueHint::FilePath),
+      Arg::new("pushgateway")
+        .help("Push request metrics of executed manifest files to a prometheus pushgateway")
+        .long("pushgateway")
+        .takes_value(true)
+        .value_hint(ValueHint::Url)
+        .validator(validate_url),
       Arg::new("param")
-        .help("Set a parameter for the request")
+        .help("Set a parameter for the request, name:@file reads the value from a file (json if .json)")
         .short('p')
         .long("param")
         .multiple_occurrences(true)
         .takes_value(true)
         .validator(|param| validate_param(param, RequestParam::Param)),
+      Arg::new("interactive")
+        .help("Ask for every declared parameter, not only required ones, given parameters are proposed as defaults")
+        .short('i')
+        .long("interactive"),
+      Arg::new("repeat")
+        .help("Execute the request n times sequentially, then print latency statistics")
+        .long("repeat")
+        .takes_value(true)
+        .value_name("n")
+        .validator(validate_repeat),
+      Arg::new("continue-on-error")
+        .help("Execute remaining manifest files after a failure, failures are still reported")
+        .long("continue-on-error"),
+      Arg::new("soft")
+        .help("Warn instead of failing when a request is slower than its expected max duration")
+        .long("soft"),
       Arg::new("proxy")
         .help("set proxy to use for request")
         .short('x')
@@ -132,13 +237,127 @@ pub fn build_exec_args() -> impl Iterator<Item = &'static Arg<'static>> {
         .help("set proxy password to use for request")
         .long("proxy-password")
         .takes_value(true),
+      Arg::new("export-env")
+        .help("write values exported by the request to a dotenv file")
+        .long("export-env")
+        .takes_value(true)
+        .value_name("file"),
     ]
   });
   EXEC_ARGS.iter()
 }
 
+pub fn build_bench_args() -> impl Iterator<Item = &'static Arg<'static>> {
+  static BENCH_ARGS: Lazy<[Arg<'static>; 3]> = Lazy::new(|| {
+    [
+      Arg::new("name").help("name of the request to bench").index(1),
+      Arg::new("file")
+        .help("Bench a manifest file request directly")
+        .short('f')
+        .long("file")
+        .takes_value(true)
+        .value_hint(ValueHint::FilePath)
+        .conflicts_with("name"),
+      Arg::new("param")
+        .help("set parameter name:value for 'Tera' template rendering")
+        .short('p')
+        .long("param")
+        .multiple_occurrences(true)
+        .takes_value(true)
+        .validator(|param| validate_param(param, RequestParam::Param)),
+    ]
+  });
+  BENCH_ARGS.iter()
+}
+
+pub fn build_diff_args() -> impl Iterator<Item = &'static Arg<'static>> {
+  static DIFF_ARGS: Lazy<[Arg<'static>; 10]> = Lazy::new(|| {
+    [
+      Arg::new("name").help("name of the request to compare").index(1),
+      Arg::new("file")
+        .help("Compare a manifest file request directly")
+        .short('f')
+        .long("file")
+        .takes_value(true)
+        .value_hint(ValueHint::FilePath)
+        .conflicts_with("name"),
+      Arg::new("param")
+        .help("Set a parameter for both requests")
+        .short('p')
+        .long("param")
+        .multiple_occurrences(true)
+        .takes_value(true)
+        .validator(|param| validate_param(param, RequestParam::Param)),
+      Arg::new("left")
+        .help("Base url replacing the request url origin for the first request")
+        .long("left")
+        .takes_value(true)
+        .value_hint(ValueHint::Url)
+        .validator(validate_url),
+      Arg::new("right")
+        .help("Base url replacing the request url origin for the second request")
+        .long("right")
+        .takes_value(true)
+        .value_hint(ValueHint::Url)
+        .validator(validate_url),
+      Arg::new("left-param")
+        .help("Set a parameter for the first request only")
+        .long("left-param")
+        .multiple_occurrences(true)
+        .takes_value(true)
+        .validator(|param| validate_param(param, RequestParam::Param)),
+      Arg::new("right-param")
+        .help("Set a parameter for the second request only")
+        .long("right-param")
+        .multiple_occurrences(true)
+        .takes_value(true)
+        .validator(|param| validate_param(param, RequestParam::Param)),
+      Arg::new("proxy")
+        .help("set proxy to use for requests")
+        .short('x')
+        .long("proxy")
+        .takes_value(true),
+      Arg::new("proxy-login")
+        .help("set proxy login to use for requests")
+        .long("proxy-login")
+        .takes_value(true),
+      Arg::new("proxy-password")
+        .help("set proxy password to use for requests")
+        .long("proxy-password")
+        .takes_value(true),
+    ]
+  });
+  DIFF_ARGS.iter()
+}
+
+pub fn build_history_filter_args() -> impl Iterator<Item = &'static Arg<'static>> {
+  static HISTORY_FILTER_ARGS: Lazy<[Arg<'static>; 4]> = Lazy::new(|| {
+    [
+      Arg::new("url-contains")
+        .help("only keep requests with an url containing this text")
+        .long("url-contains")
+        .takes_value(true),
+      Arg::new("status")
+        .help("only keep requests answered with this status")
+        .long("status")
+        .takes_value(true)
+        .validator(|status| status.parse::<u16>()),
+      Arg::new("request-id")
+        .help("only keep the request sent with this generated request id")
+        .long("request-id")
+        .takes_value(true),
+      Arg::new("limit")
+        .help("only keep the latest requests")
+        .long("limit")
+        .takes_value(true)
+        .validator(|limit| limit.parse::<usize>()),
+    ]
+  });
+  HISTORY_FILTER_ARGS.iter()
+}
+
 pub fn build_create_request_args() -> impl Iterator<Item = &'static Arg<'static>> {
-  static CREATE_ARGS: Lazy<[Arg<'static>; 10]> = Lazy::new(|| {
+  static CREATE_ARGS: Lazy<[Arg<'static>; 11]> = Lazy::new(|| {
     [
       Arg::new("name").help("name of request to create").index(1),
       Arg::new("method")
@@ -195,11 +414,55 @@ pub fn build_create_request_args() -> impl Iterator<Item = &'static Arg<'static>
         .help("allow insecure connections when using https")
         .short('i')
         .long("insecure"),
+      Arg::new("edit")
+        .short('e')
+        .long("edit")
+        .help("open the created manifest with current terminal EDITOR, then validate it"),
     ]
   });
   CREATE_ARGS.iter()
 }
 
+pub fn build_export_args() -> impl Iterator<Item = &'static Arg<'static>> {
+  static EXPORT_ARGS: Lazy<[Arg<'static>; 3]> = Lazy::new(|| {
+    [
+      Arg::new("name")
+        .help("name of the request to export")
+        .required_unless_present("all")
+        .index(1),
+      Arg::new("all")
+        .help("export every request of the project")
+        .long("all")
+        .conflicts_with("name"),
+      Arg::new("output-file")
+        .help("write export to a file instead of stdout")
+        .short('o')
+        .long("output-file")
+        .takes_value(true)
+        .value_hint(ValueHint::FilePath),
+    ]
+  });
+  EXPORT_ARGS.iter()
+}
+
+pub fn build_copy_args() -> impl Iterator<Item = &'static Arg<'static>> {
+  static COPY_ARGS: Lazy<[Arg<'static>; 3]> = Lazy::new(|| {
+    [
+      Arg::new("resource")
+        .help("resource type")
+        .possible_values(["request", "story", "templatelibrary"])
+        .required(true)
+        .index(1),
+      Arg::new("name").help("name of apix resource").required(true).index(2),
+      Arg::new("new-name")
+        .help("new name of apix resource")
+        .required(true)
+        .index(3),
+    ]
+  });
+  COPY_ARGS.iter()
+}
+
 pub fn build_cli() -> App<'static> {
   App::new("apix")
     .setting(AppSettings::SubcommandRequiredElseHelp)
@@ -218,14 +481,213 @@ pub fn build_cli() -> App<'static> {
         .takes_value(true)
         .value_hint(ValueHint::FilePath)
         .global(true),
+      Arg::new("tee")
+        .help("also display response body when saving it to output file")
+        .long("tee")
+        .requires("output-file")
+        .global(true),
+      Arg::new("content-digest")
+        .help("send the sha-256 digest of request bodies in a Content-Digest header, or in a legacy Digest header")
+        .long("content-digest")
+        .takes_value(true)
+        .min_values(0)
+        .require_equals(true)
+        .default_missing_value("content-digest")
+        .possible_values(["content-digest", "digest"])
+        .global(true),
+      Arg::new("idempotency-key")
+        .help("send an Idempotency-Key header with this value, or with a new uuid for each run when `auto` or no value is given")
+        .long("idempotency-key")
+        .takes_value(true)
+        .min_values(0)
+        .require_equals(true)
+        .default_missing_value("auto")
+        .global(true),
+      Arg::new("cache")
+        .help("cache GET responses following HTTP caching rules (RFC 9111), verbose mode explains cache decisions")
+        .long("cache")
+        .takes_value(true)
+        .possible_values(["rfc"])
+        .global(true),
+      Arg::new("ipv4")
+        .help("only resolve and connect to IPv4 addresses")
+        .short('4')
+        .long("ipv4")
+        .conflicts_with("ipv6")
+        .global(true),
+      Arg::new("ipv6")
+        .help("only resolve and connect to IPv6 addresses")
+        .short('6')
+        .long("ipv6")
+        .conflicts_with("ipv4")
+        .global(true),
+      Arg::new("fresh-dns")
+        .help("resolve host names again for each request, connections are never reused")
+        .long("fresh-dns")
+        .global(true),
+      Arg::new("tlsv1.0")
+        .help("use tls version 1.0 or greater")
+        .long("tlsv1.0")
+        .conflicts_with_all(&["tlsv1.1", "tlsv1.2", "tlsv1.3"])
+        .global(true),
+      Arg::new("tlsv1.1")
+        .help("use tls version 1.1 or greater")
+        .long("tlsv1.1")
+        .conflicts_with_all(&["tlsv1.0", "tlsv1.2", "tlsv1.3"])
+        .global(true),
+      Arg::new("tlsv1.2")
+        .help("use tls version 1.2 or greater")
+        .long("tlsv1.2")
+        .conflicts_with_all(&["tlsv1.0", "tlsv1.1", "tlsv1.3"])
+        .global(true),
+      Arg::new("tlsv1.3")
+        .help("use tls version 1.3 or greater, not supported by every platform tls library")
+        .long("tlsv1.3")
+        .conflicts_with_all(&["tlsv1.0", "tlsv1.1", "tlsv1.2"])
+        .global(true),
+      Arg::new("tls-max")
+        .help("maximum tls version to use")
+        .long("tls-max")
+        .value_name("version")
+        .takes_value(true)
+        .possible_values(["1.0", "1.1", "1.2", "1.3"])
+        .global(true),
+      Arg::new("show-cert")
+        .help("print the certificate chain of https servers, with its verification result")
+        .long("show-cert")
+        .global(true),
+      Arg::new("trust-store")
+        .help("root certificates trusted by https requests: system store, or Mozilla store bundled with apix")
+        .long("trust-store")
+        .takes_value(true)
+        .possible_values(["system", "bundled"])
+        .global(true),
+      Arg::new("pin")
+        .help("fail when no certificate of the https server chain has one of these public key hashes: sha256//BASE64, separated by ;")
+        .long("pin")
+        .value_name("pins")
+        .takes_value(true)
+        .multiple_occurrences(true)
+        .validator(|value| parse_pins(value).map(|_| ()))
+        .global(true),
+      Arg::new("max-body-size")
+        .help("fail when a response body read in memory is larger than this size, like 50MB")
+        .long("max-body-size")
+        .value_name("size")
+        .takes_value(true)
+        .validator(|value| parse_size(value).map(|_| ()))
+        .global(true),
+      Arg::new("no-progress")
+        .help("never display progress bars, they are already hidden when stderr is not a terminal")
+        .long("no-progress")
+        .global(true),
+      Arg::new("traceparent")
+        .help("send a W3C traceparent header with each request")
+        .long("traceparent")
+        .global(true),
+      Arg::new("otlp-endpoint")
+        .help("export request spans to an OpenTelemetry collector using OTLP/HTTP")
+        .long("otlp-endpoint")
+        .takes_value(true)
+        .value_hint(ValueHint::Url)
+        .validator(validate_url)
+        .global(true),
+      Arg::new("log-file")
+        .help("append structured json logs of requests, retries and template rendering to a file")
+        .long("log-file")
+        .takes_value(true)
+        .value_hint(ValueHint::FilePath)
+        .global(true),
+      Arg::new("log-level")
+        .help("minimum level of events written to the log file")
+        .long("log-level")
+        .takes_value(true)
+        .possible_values(LOG_LEVELS)
+        .requires("log-file")
+        .global(true),
+      Arg::new("trace")
+        .help("print connection events of the http stack to stderr: dns resolution, connection, tls, http frames")
+        .long("trace")
+        .global(true),
+      Arg::new("style")
+        .help("comma separated decorations of pretty printed outputs: plain, full, grid, header, numbers")
+        .long("style")
+        .takes_value(true)
+        .global(true),
+      Arg::new("wrap")
+        .help("wrapping mode of pretty printed outputs")
+        .long("wrap")
+        .takes_value(true)
+        .possible_values(["auto", "character", "never"])
+        .global(true),
+      Arg::new("syntax")
+        .help("force the language used to highlight response bodies, ignoring response content type (eg: json, xml)")
+        .long("syntax")
+        .takes_value(true)
+        .global(true),
+      Arg::new("output")
+        .help("output format of response bodies, csv bodies are shown as a table on terminals by default, shell prints exports of executed requests")
+        .long("output")
+        .takes_value(true)
+        .possible_values(["table", "text", "shell"])
+        .global(true),
+      Arg::new("decode")
+        .help("decode binary bodies to json: msgpack or proto:<schema.proto>:<Message>, msgpack is decoded by default")
+        .long("decode")
+        .value_name("decoder")
+        .takes_value(true)
+        .validator(|value| value.parse::<BodyDecoder>().map(|_| ()))
+        .global(true),
+      Arg::new("raw")
+        .help("write response bodies exactly as received, without formatting nor highlighting")
+        .long("raw")
+        .conflicts_with_all(&["output", "decode", "syntax"])
+        .global(true),
+      Arg::new("fail")
+        .help("exit with an error on http errors (4xx, 5xx), hiding the response body")
+        .long("fail")
+        .global(true),
+      Arg::new("fail-with-body")
+        .help("exit with an error on http errors (4xx, 5xx), still printing the response body")
+        .long("fail-with-body")
+        .conflicts_with("fail")
+        .global(true),
+      Arg::new("tab-width")
+        .help("width of tabs in pretty printed outputs")
+        .long("tab-width")
+        .takes_value(true)
+        .global(true),
     ])
     .subcommands([
-      App::new("completions").about("generate shell completions").arg(
-        Arg::new("shell")
-          .help("shell to target for completions")
-          .possible_values(Shell::possible_values())
-          .required(true),
-      ),
+      App::new("completions")
+        .about("generate shell completions")
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .arg(
+          Arg::new("shell")
+            .help("shell to target for completions")
+            .possible_values(Shell::possible_values())
+            .required(true),
+        )
+        .subcommand(
+          App::new("install")
+            .about("install completions where your shell loads them")
+            .arg(
+              Arg::new("shell")
+                .help("shell to install completions for, detected from $SHELL by default")
+                .long("shell")
+                .takes_value(true)
+                .possible_values(Shell::possible_values()),
+            ),
+        ),
+      App::new("complete")
+        .setting(AppSettings::Hidden)
+        .about("list dynamic completion candidates")
+        .arg(
+          Arg::new("kind")
+            .help("kind of candidates to list")
+            .possible_values(["request", "story", "config"])
+            .required(true),
+        ),
       App::new("config")
         .setting(AppSettings::SubcommandRequiredElseHelp)
         .about("configuration settings")
@@ -240,6 +702,9 @@ pub fn build_cli() -> App<'static> {
               .help("value to set configuration value to")
               .required(true)
               .index(2),
+            Arg::new("dry-run")
+              .long("dry-run")
+              .help("only show changes, without saving configuration"),
           ]),
           App::new("get").about("get a configuration value").arg(
             Arg::new("name")
@@ -252,8 +717,112 @@ pub fn build_cli() -> App<'static> {
               .required(true),
           ),
         ]),
-      App::new("init").about("initialise a new API context in the current directory by using git"),
-      App::new("history").about("show history of requests sent (require project)"),
+      App::new("init")
+        .about("initialise a new API context in the current directory by using git")
+        .arg(
+          Arg::new("no-git")
+            .long("no-git")
+            .help("only create apix project files, without initialising or committing to git"),
+        )
+        .arg(
+          Arg::new("template")
+            .long("template")
+            .takes_value(true)
+            .value_name("git-url")
+            .help("initialise project from a starter template git repository, `{{ project.name }}` is replaced by current directory name"),
+        ),
+      App::new("tokens")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .about("OIDC access tokens, cached in user state directory and refreshed when expired")
+        .subcommands([
+          App::new("get")
+            .about("print an access token, use `{{ auth_token(issuer=\"...\") }}` in templates")
+            .args([
+              Arg::new("issuer")
+                .help("OIDC issuer url, its token endpoint is found with discovery")
+                .required(true)
+                .value_hint(ValueHint::Url),
+              Arg::new("audience")
+                .long("audience")
+                .takes_value(true)
+                .help("audience of the token"),
+              Arg::new("scope").long("scope").takes_value(true).help("scope of the token"),
+              Arg::new("client-id")
+                .long("client-id")
+                .takes_value(true)
+                .help("client id, defaults to `oidc-client-id` configuration value"),
+              Arg::new("client-secret")
+                .long("client-secret")
+                .takes_value(true)
+                .help("client secret, defaults to `oidc-client-secret` configuration value"),
+            ]),
+          App::new("list").about("list cached tokens"),
+          App::new("clear").about("remove all cached tokens"),
+        ]),
+      App::new("cookies")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .about("move session cookies from and to curl cookie jars (Netscape format)")
+        .subcommands([
+          App::new("export").about("export cookies of a session to a cookie jar").args([
+            Arg::new("file")
+              .help("cookie jar file to write, `-` writes to stdout")
+              .required(true)
+              .value_hint(ValueHint::FilePath),
+            Arg::new("session")
+              .help("session to export cookies from")
+              .long("session")
+              .takes_value(true)
+              .required(true),
+            Arg::new("domain")
+              .help("only export cookies sent to this domain")
+              .long("domain")
+              .takes_value(true),
+          ]),
+          App::new("import").about("import cookies of a cookie jar into a session").args([
+            Arg::new("file")
+              .help("cookie jar file to read, `-` reads from stdin")
+              .required(true)
+              .value_hint(ValueHint::FilePath),
+            Arg::new("session")
+              .help("session to import cookies into, created if missing")
+              .long("session")
+              .takes_value(true)
+              .required(true),
+            Arg::new("domain")
+              .help("only import cookies sent to this domain")
+              .long("domain")
+              .takes_value(true),
+          ]),
+        ]),
+      App::new("self-update").about("update apix to the latest release").arg(
+        Arg::new("check")
+          .long("check")
+          .help("only check if a new release is available, without installing it"),
+      ),
+      App::new("history")
+        .about("show history of requests sent (require project)")
+        .subcommands([
+          App::new("list")
+            .about("list requests sent, oldest first")
+            .args(build_history_filter_args()),
+          App::new("export")
+            .about("export requests sent as yaml or http archive (har)")
+            .args(build_history_filter_args())
+            .args([
+              Arg::new("format")
+                .help("export format")
+                .long("format")
+                .takes_value(true)
+                .possible_values(["yaml", "har"])
+                .default_value("yaml"),
+              Arg::new("output-file")
+                .help("write export to a file instead of stdout")
+                .short('o')
+                .long("output-file")
+                .takes_value(true)
+                .value_hint(ValueHint::FilePath),
+            ]),
+        ]),
       App::new("get").about("get an http resource").args(build_request_args()),
       App::new("head")
         .about("get an http resource header")
@@ -271,14 +840,89 @@ pub fn build_cli() -> App<'static> {
         .about("patch an http resource")
         .args(build_request_args()),
       App::new("exec")
-        .about("execute a request from the current API context")
+        .about("execute a request or a story from the current API context")
         .args(build_exec_args()),
+      App::new("bench")
+        .about("run the staged load profile of a request and report latencies per stage")
+        .args(build_bench_args()),
+      App::new("diff")
+        .about("execute a request against two environments and compare responses")
+        .args(build_diff_args()),
+      App::new("download")
+        .about("download the files of an artifact set, checking their checksums")
+        .args([
+          Arg::new("name")
+            .help("name of the artifact set, or its manifest file")
+            .required(true)
+            .value_hint(ValueHint::FilePath),
+          Arg::new("concurrency")
+            .help("number of files downloaded at the same time, overrides the artifact set concurrency")
+            .long("concurrency")
+            .takes_value(true)
+            .validator(validate_concurrency),
+          Arg::new("proxy")
+            .help("set proxy to use for downloads")
+            .short('x')
+            .long("proxy")
+            .takes_value(true),
+          Arg::new("proxy-login")
+            .help("set proxy login to use for downloads")
+            .long("proxy-login")
+            .takes_value(true),
+          Arg::new("proxy-password")
+            .help("set proxy password to use for downloads")
+            .long("proxy-password")
+            .takes_value(true),
+        ]),
+      App::new("mock")
+        .about("serve examples of the OpenAPI description an api was imported from")
+        .args([
+          Arg::new("api")
+            .help("name of the api to mock")
+            .long("api")
+            .takes_value(true)
+            .required(true),
+          Arg::new("listen")
+            .help("address to listen on")
+            .long("listen")
+            .takes_value(true)
+            .default_value("127.0.0.1:8080"),
+        ]),
       App::new("ctl")
         .setting(AppSettings::SubcommandRequiredElseHelp)
         .about("apix control interface for handling multiple APIs")
         .subcommands([
           App::new("switch").about("switch API context"),
-          App::new("apply").about("apply an apix manifest into current project"),
+          App::new("context")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .about("manage values saved in project context, `.apix/context.yaml`")
+            .subcommands([
+              App::new("get")
+                .about("print saved context values")
+                .arg(Arg::new("name").help("name of the value to print, all values when missing")),
+              App::new("set").about("save a context value").args([
+                Arg::new("name").help("name of the value").required(true),
+                Arg::new("value").help("value to save").required(true),
+              ]),
+              App::new("delete")
+                .about("remove a saved context value")
+                .arg(Arg::new("name").help("name of the value").required(true)),
+            ]),
+          App::new("apply")
+            .about("apply an apix manifest into current project")
+            .args([
+              Arg::new("file")
+                .help("manifest file to apply")
+                .required(true)
+                .value_hint(ValueHint::FilePath),
+              Arg::new("dry-run")
+                .long("dry-run")
+                .help("only show changes, without applying the manifest"),
+              Arg::new("yes")
+                .short('y')
+                .long("yes")
+                .help("replace existing resources of a multi-document file without asking"),
+            ]),
           App::new("create")
             .setting(AppSettings::SubcommandRequiredElseHelp)
             .about("create a new apix manifest")
@@ -307,14 +951,53 @@ pub fn build_cli() -> App<'static> {
             ]),
           App::new("get").about("get information about an apix resource").args([
             Arg::new("resource")
-              .possible_values(["resource", "context", "story", "request"])
+              .possible_values(["api", "request", "story", "templatelibrary", "artifactset"])
               .index(1),
             Arg::new("name").help("name of apix resource to edit").index(2),
+            Arg::new("sort-by")
+              .help("sort listed resources by creation date or name")
+              .long("sort-by")
+              .takes_value(true)
+              .possible_values(["created-at", "name"]),
+            Arg::new("created-since")
+              .help("only list resources created since a date or a duration, like 7d")
+              .long("created-since")
+              .takes_value(true)
+              .validator(validate_date),
+            Arg::new("created-by")
+              .help("only list resources created by this user")
+              .long("created-by")
+              .takes_value(true),
           ]),
+          App::new("graph")
+            .about("show steps of a story, its needs and values flowing between steps")
+            .args([
+              Arg::new("story").help("name of the story").required(true).index(1),
+              Arg::new("format")
+                .help("graphviz dot document or ascii tree")
+                .long("format")
+                .takes_value(true)
+                .possible_values(["dot", "tree"])
+                .default_value("dot"),
+            ]),
+          App::new("lint")
+            .about("check manifests for hardcoded secrets, missing schemas, unknown or unused parameters")
+            .arg(
+              Arg::new("file")
+                .help("manifest files or glob patterns to check, defaults to every project manifest")
+                .multiple_values(true)
+                .value_hint(ValueHint::FilePath),
+            ),
+          App::new("copy")
+            .about("duplicate a resource under a new name, in a new file")
+            .args(build_copy_args()),
+          App::new("rename")
+            .about("rename a resource and its file, fixing template library references")
+            .args(build_copy_args()),
           App::new("delete").about("delete an existing named resource").args([
             Arg::new("resource")
               .help("resource type to delete")
-              .possible_values(["resource", "context", "story", "request"])
+              .possible_values(["api", "request", "story", "templatelibrary", "artifactset"])
               .required(true)
               .index(1),
             Arg::new("name")
@@ -322,13 +1005,54 @@ pub fn build_cli() -> App<'static> {
               .required(true)
               .index(2),
           ]),
+          App::new("export")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .about("export requests to other http clients formats, or the whole project as a bundle")
+            .subcommands([
+              App::new("http")
+                .about("export requests as a REST Client .http/.rest file")
+                .args(build_export_args()),
+              App::new("hurl")
+                .about("export requests, assertions and exports as a hurl file")
+                .args(build_export_args()),
+              App::new("bundle")
+                .about("export every manifest of the project as one multi-document yaml file")
+                .arg(
+                  Arg::new("file")
+                    .help("bundle file to write, `-` or none writes to stdout")
+                    .value_hint(ValueHint::FilePath),
+                ),
+            ]),
           App::new("import")
-            .about("import an OpenAPI description file in yaml or json")
-            .arg(
+            .about("import an OpenAPI description file in yaml or json, a hurl file, or a project bundle")
+            .setting(AppSettings::SubcommandsNegateReqs)
+            .subcommand(
+              App::new("bundle")
+                .about("import a bundle exported by `ctl export bundle`, as a file per resource")
+                .args([
+                  Arg::new("file")
+                    .help("bundle file to import")
+                    .required(true)
+                    .value_hint(ValueHint::FilePath),
+                  Arg::new("dry-run")
+                    .long("dry-run")
+                    .help("only show changes, without importing the bundle"),
+                  Arg::new("yes")
+                    .short('y')
+                    .long("yes")
+                    .help("replace existing resources without asking"),
+                ]),
+            )
+            .args([
               Arg::new("url")
-                .help("Filename or URL to openApi description to import")
+                .help("Filename or URL to openApi description or hurl file to import")
                 .required(true),
-            ),
+              Arg::new("format")
+                .help("format of the imported file, hurl for files with a .hurl extension by default")
+                .long("format")
+                .takes_value(true)
+                .possible_values(["openapi", "hurl"]),
+            ]),
         ]),
     ])
 }
@@ -0,0 +1,458 @@
+use crate::paths::{cache_dir, restrict_dir, write_atomic, PRIVATE_FILE_MODE};
+use anyhow::Result;
+use chrono::DateTime;
+use indexmap::IndexMap;
+use reqwest::header::{
+  HeaderMap, HeaderName, HeaderValue, AGE, CACHE_CONTROL, DATE, ETAG, EXPIRES, IF_MODIFIED_SINCE, IF_NONE_MATCH,
+  LAST_MODIFIED, PRAGMA, VARY,
+};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+// request headers holding credentials, only a hash of them is stored when responses vary on them
+static CREDENTIAL_HEADERS: [&str; 5] = [
+  "authorization",
+  "proxy-authorization",
+  "cookie",
+  "x-api-key",
+  "x-auth-token",
+];
+
+// status codes that are cacheable by default (RFC 9110 section 15.1)
+static HEURISTICALLY_CACHEABLE: [u16; 11] = [200, 203, 204, 300, 301, 308, 404, 405, 410, 414, 501];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CacheMode {
+  // private cache following RFC 9111 freshness and validation rules
+  Rfc,
+}
+
+impl FromStr for CacheMode {
+  type Err = anyhow::Error;
+
+  fn from_str(value: &str) -> Result<Self> {
+    match value {
+      "rfc" => Ok(CacheMode::Rfc),
+      _ => Err(anyhow::anyhow!("Bad cache mode: \"{}\", should be rfc", value)),
+    }
+  }
+}
+
+#[derive(Debug, Default)]
+struct CacheControl {
+  directives: IndexMap<String, Option<String>>,
+}
+
+impl CacheControl {
+  fn parse(headers: &HeaderMap) -> Self {
+    let directives = headers
+      .get_all(CACHE_CONTROL)
+      .iter()
+      .filter_map(|value| value.to_str().ok())
+      .flat_map(|value| value.split(','))
+      .filter_map(|directive| {
+        let mut parts = directive.splitn(2, '=');
+        let name = parts.next()?.trim().to_lowercase();
+        let value = parts.next().map(|value| value.trim().trim_matches('"').to_string());
+        (!name.is_empty()).then_some((name, value))
+      })
+      .collect();
+    Self { directives }
+  }
+
+  fn has(&self, name: &str) -> bool {
+    self.directives.contains_key(name)
+  }
+
+  fn seconds(&self, name: &str) -> Option<i64> {
+    self
+      .directives
+      .get(name)
+      .and_then(|value| value.as_ref())
+      .and_then(|value| value.parse().ok())
+  }
+}
+
+fn header_date(headers: &HeaderMap, name: HeaderName) -> Option<i64> {
+  headers
+    .get(name)
+    .and_then(|value| value.to_str().ok())
+    .and_then(|value| DateTime::parse_from_rfc2822(value).ok())
+    .map(|date| date.timestamp())
+}
+
+fn header_values(headers: &HeaderMap, name: &str) -> Option<String> {
+  let values = headers
+    .get_all(name)
+    .iter()
+    .filter_map(|value| value.to_str().ok())
+    .collect::<Vec<_>>();
+  (!values.is_empty()).then(|| values.join(", "))
+}
+
+// request header value selected by the response Vary header, as stored in cache entries
+fn vary_value(request_headers: &HeaderMap, name: &str) -> Option<String> {
+  let value = header_values(request_headers, name)?;
+  match CREDENTIAL_HEADERS.contains(&name) {
+    true => Some(format!("sha256:{:x}", Sha256::digest(value.as_bytes()))),
+    false => Some(value),
+  }
+}
+
+// request header names selected by the response Vary header
+fn vary_names(headers: &HeaderMap) -> Vec<String> {
+  headers
+    .get_all(VARY)
+    .iter()
+    .filter_map(|value| value.to_str().ok())
+    .flat_map(|value| value.split(','))
+    .map(|name| name.trim().to_lowercase())
+    .filter(|name| !name.is_empty())
+    .collect()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CacheEntry {
+  status: u16,
+  headers: Vec<(String, String)>,
+  pub body: String,
+  // request header values selected by response Vary header, when response was stored, credentials are hashed
+  vary: Vec<(String, Option<String>)>,
+  // unix timestamp of response reception
+  stored_at: i64,
+}
+
+impl CacheEntry {
+  pub fn new(status: StatusCode, headers: &HeaderMap, body: String, request_headers: &HeaderMap, now: i64) -> Self {
+    let vary = vary_names(headers)
+      .into_iter()
+      .map(|name| {
+        let value = vary_value(request_headers, &name);
+        (name, value)
+      })
+      .collect();
+    Self {
+      status: status.as_u16(),
+      headers: headers
+        .iter()
+        .filter_map(|(key, value)| value.to_str().ok().map(|value| (key.to_string(), value.to_string())))
+        .collect(),
+      body,
+      vary,
+      stored_at: now,
+    }
+  }
+
+  pub fn status(&self) -> StatusCode {
+    StatusCode::from_u16(self.status).unwrap_or(StatusCode::OK)
+  }
+
+  pub fn headers(&self) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    for (key, value) in &self.headers {
+      if let (Ok(key), Ok(value)) = (HeaderName::from_str(key), HeaderValue::from_str(value)) {
+        headers.append(key, value);
+      }
+    }
+    headers
+  }
+
+  // stored headers with an updated Age header
+  pub fn headers_with_age(&self, now: i64) -> HeaderMap {
+    let mut headers = self.headers();
+    headers.insert(AGE, HeaderValue::from(self.current_age(now)));
+    headers
+  }
+
+  // explicit max-age, then Expires, then 10% of time since Last-Modified (RFC 9111 section 4.2.1)
+  fn freshness_lifetime(&self) -> i64 {
+    let headers = self.headers();
+    let cache_control = CacheControl::parse(&headers);
+    if let Some(max_age) = cache_control.seconds("max-age") {
+      return max_age;
+    }
+    let date = header_date(&headers, DATE).unwrap_or(self.stored_at);
+    if headers.contains_key(EXPIRES) {
+      return header_date(&headers, EXPIRES)
+        .map(|expires| (expires - date).max(0))
+        .unwrap_or(0);
+    }
+    header_date(&headers, LAST_MODIFIED)
+      .map(|last_modified| ((date - last_modified) / 10).max(0))
+      .unwrap_or(0)
+  }
+
+  // age received from upstream caches plus time spent in this cache
+  fn current_age(&self, now: i64) -> i64 {
+    let headers = self.headers();
+    let age = headers
+      .get(AGE)
+      .and_then(|value| value.to_str().ok())
+      .and_then(|value| value.parse::<i64>().ok())
+      .unwrap_or(0);
+    age + (now - self.stored_at).max(0)
+  }
+
+  fn matches_vary(&self, request_headers: &HeaderMap) -> bool {
+    self
+      .vary
+      .iter()
+      .all(|(name, value)| &vary_value(request_headers, name) == value)
+  }
+
+  // validators of stored response to send with a conditional request
+  pub fn conditional_headers(&self) -> HeaderMap {
+    let headers = self.headers();
+    let mut conditional = HeaderMap::new();
+    if let Some(etag) = headers.get(ETAG) {
+      conditional.insert(IF_NONE_MATCH, etag.clone());
+    }
+    if let Some(last_modified) = headers.get(LAST_MODIFIED) {
+      conditional.insert(IF_MODIFIED_SINCE, last_modified.clone());
+    }
+    conditional
+  }
+
+  // update stored headers with those of a 304 Not Modified response
+  pub fn refresh(&mut self, not_modified_headers: &HeaderMap, now: i64) {
+    let mut headers = self.headers();
+    for key in not_modified_headers.keys() {
+      headers.remove(key);
+    }
+    for (key, value) in not_modified_headers {
+      headers.append(key.clone(), value.clone());
+    }
+    self.headers = headers
+      .iter()
+      .filter_map(|(key, value)| value.to_str().ok().map(|value| (key.to_string(), value.to_string())))
+      .collect();
+    self.stored_at = now;
+  }
+}
+
+#[derive(Debug)]
+pub enum CacheLookup {
+  // stored response can be used without contacting the server, with its age and freshness lifetime
+  Fresh(CacheEntry, i64, i64),
+  // stored response must be validated by the server first
+  Stale(CacheEntry, String),
+  Miss(String),
+}
+
+pub fn lookup(entry: Option<CacheEntry>, request_headers: &HeaderMap, now: i64) -> CacheLookup {
+  let entry = match entry {
+    Some(entry) => entry,
+    None => return CacheLookup::Miss("no stored response".to_string()),
+  };
+  if !entry.matches_vary(request_headers) {
+    return CacheLookup::Miss("stored response varies on different request headers".to_string());
+  }
+  let request_cache_control = CacheControl::parse(request_headers);
+  let pragma_no_cache =
+    header_values(request_headers, PRAGMA.as_str()).is_some_and(|pragma| pragma.contains("no-cache"));
+  if request_cache_control.has("no-cache") || request_cache_control.seconds("max-age") == Some(0) || pragma_no_cache {
+    return CacheLookup::Stale(entry, "request asks for validation".to_string());
+  }
+  if CacheControl::parse(&entry.headers()).has("no-cache") {
+    return CacheLookup::Stale(entry, "stored response requires validation".to_string());
+  }
+  let age = entry.current_age(now);
+  let lifetime = entry.freshness_lifetime();
+  if age < lifetime {
+    CacheLookup::Fresh(entry, age, lifetime)
+  } else {
+    CacheLookup::Stale(
+      entry,
+      format!("stored response is stale, age {}s for a lifetime of {}s", age, lifetime),
+    )
+  }
+}
+
+// check whether a response may be stored, giving the reason when it may not
+pub fn storable(status: StatusCode, request_headers: &HeaderMap, response_headers: &HeaderMap) -> Result<(), String> {
+  if CacheControl::parse(request_headers).has("no-store") {
+    return Err("request has no-store directive".to_string());
+  }
+  let cache_control = CacheControl::parse(response_headers);
+  if cache_control.has("no-store") {
+    return Err("response has no-store directive".to_string());
+  }
+  if vary_names(response_headers).iter().any(|name| name == "*") {
+    return Err("response varies on everything".to_string());
+  }
+  let explicit = cache_control.has("max-age") || cache_control.has("public") || response_headers.contains_key(EXPIRES);
+  if !explicit && !HEURISTICALLY_CACHEABLE.contains(&status.as_u16()) {
+    return Err(format!("status {} is not cacheable by default", status.as_u16()));
+  }
+  Ok(())
+}
+
+// responses are stored in user cache directory, one file per method and url
+// they may hold cookies and credential hashes, so only the user can read them
+pub struct HttpCache {
+  dir: PathBuf,
+}
+
+impl HttpCache {
+  pub fn open() -> Result<Self> {
+    let dir = cache_dir("responses")?;
+    restrict_dir(&dir)?;
+    Ok(Self { dir })
+  }
+
+  fn path(&self, method: &str, url: &str) -> PathBuf {
+    let key = Sha256::digest(format!("{} {}", method.to_uppercase(), url).as_bytes());
+    self.dir.join(format!("{:x}.json", key))
+  }
+
+  pub fn load(&self, method: &str, url: &str) -> Option<CacheEntry> {
+    let content = std::fs::read_to_string(self.path(method, url)).ok()?;
+    serde_json::from_str(&content).ok()
+  }
+
+  pub fn store(&self, method: &str, url: &str, entry: &CacheEntry) -> Result<()> {
+    let path = self.path(method, url);
+    write_atomic(
+      &path,
+      serde_json::to_string(entry)?.as_bytes(),
+      false,
+      Some(PRIVATE_FILE_MODE),
+    )
+    .map_err(|e| anyhow::anyhow!("Failed to store response in cache {:?}\ncause: {}", &path, e))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use test_case::test_case;
+
+  fn headers(values: &[(&'static str, &'static str)]) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    for (key, value) in values {
+      headers.append(*key, HeaderValue::from_static(value));
+    }
+    headers
+  }
+
+  fn entry(
+    response_headers: &[(&'static str, &'static str)],
+    request_headers: &[(&'static str, &'static str)],
+  ) -> CacheEntry {
+    CacheEntry::new(
+      StatusCode::OK,
+      &headers(response_headers),
+      "body".to_string(),
+      &headers(request_headers),
+      1000,
+    )
+  }
+
+  #[test_case(&[("cache-control", "public, max-age=60")] => 60 ; "max age")]
+  #[test_case(&[("date", "Tue, 15 Nov 1994 08:12:31 GMT"), ("expires", "Tue, 15 Nov 1994 08:14:31 GMT")] => 120 ; "expires")]
+  #[test_case(&[("expires", "0")] => 0 ; "invalid expires")]
+  #[test_case(&[("date", "Tue, 15 Nov 1994 08:12:31 GMT"), ("last-modified", "Tue, 15 Nov 1994 08:02:31 GMT")] => 60 ; "heuristic")]
+  #[test_case(&[] => 0 ; "no freshness information")]
+  fn test_freshness_lifetime(response_headers: &[(&'static str, &'static str)]) -> i64 {
+    entry(response_headers, &[]).freshness_lifetime()
+  }
+
+  #[test]
+  fn test_lookup_fresh_then_stale() {
+    let stored = entry(&[("cache-control", "max-age=60"), ("age", "10")], &[]);
+    match lookup(Some(stored.clone()), &HeaderMap::new(), 1030) {
+      CacheLookup::Fresh(_, age, lifetime) => assert_eq!((age, lifetime), (40, 60)),
+      other => panic!("expected fresh response, got {:?}", other),
+    }
+    assert!(matches!(
+      lookup(Some(stored), &HeaderMap::new(), 1050),
+      CacheLookup::Stale(_, _)
+    ));
+  }
+
+  #[test]
+  fn test_lookup_vary_and_no_cache() {
+    let stored = entry(
+      &[("cache-control", "max-age=60"), ("vary", "Accept")],
+      &[("accept", "application/json")],
+    );
+    assert!(matches!(
+      lookup(Some(stored.clone()), &headers(&[("accept", "text/html")]), 1000),
+      CacheLookup::Miss(_)
+    ));
+    assert!(matches!(
+      lookup(
+        Some(stored),
+        &headers(&[("accept", "application/json"), ("cache-control", "no-cache")]),
+        1000
+      ),
+      CacheLookup::Stale(_, _)
+    ));
+    assert!(matches!(lookup(None, &HeaderMap::new(), 1000), CacheLookup::Miss(_)));
+  }
+
+  #[test]
+  fn test_vary_credentials_are_hashed() {
+    let stored = entry(
+      &[("cache-control", "max-age=60"), ("vary", "Authorization, Accept")],
+      &[("authorization", "Bearer secret"), ("accept", "application/json")],
+    );
+    let cache = HttpCache {
+      dir: std::env::temp_dir().join(format!("apix-cache-{}", std::process::id())),
+    };
+    std::fs::create_dir_all(&cache.dir).unwrap();
+    cache.store("get", "http://localhost/users", &stored).unwrap();
+    let path = cache.path("get", "http://localhost/users");
+    let content = std::fs::read_to_string(&path).unwrap();
+    assert!(!content.contains("secret"));
+    assert!(content.contains("application/json"));
+    #[cfg(unix)]
+    {
+      use std::os::unix::fs::PermissionsExt;
+      let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+      assert_eq!(mode & 0o777, PRIVATE_FILE_MODE);
+    }
+    let stored = cache.load("get", "http://localhost/users").unwrap();
+    let request = |auth: &'static str| headers(&[("authorization", auth), ("accept", "application/json")]);
+    assert!(matches!(
+      lookup(Some(stored.clone()), &request("Bearer secret"), 1000),
+      CacheLookup::Fresh(_, _, _)
+    ));
+    assert!(matches!(
+      lookup(Some(stored), &request("Bearer other"), 1000),
+      CacheLookup::Miss(_)
+    ));
+    std::fs::remove_dir_all(&cache.dir).unwrap();
+  }
+
+  #[test]
+  fn test_conditional_headers_and_refresh() {
+    let mut stored = entry(&[("etag", "\"v1\""), ("cache-control", "max-age=0")], &[]);
+    assert_eq!(stored.conditional_headers().get(IF_NONE_MATCH).unwrap(), "\"v1\"");
+    stored.refresh(&headers(&[("cache-control", "max-age=30")]), 2000);
+    assert_eq!(stored.freshness_lifetime(), 30);
+    assert_eq!(stored.headers().get(ETAG).unwrap(), "\"v1\"");
+    assert_eq!(stored.current_age(2010), 10);
+  }
+
+  #[test_case(200, &[], &[] => Ok(()) ; "ok")]
+  #[test_case(200, &[("cache-control", "no-store")], &[] => matches Err(_) ; "request no store")]
+  #[test_case(200, &[], &[("cache-control", "no-store")] => matches Err(_) ; "response no store")]
+  #[test_case(200, &[], &[("vary", "*")] => matches Err(_) ; "vary everything")]
+  #[test_case(201, &[], &[] => matches Err(_) ; "created")]
+  #[test_case(201, &[], &[("cache-control", "max-age=10")] => Ok(()) ; "created with max age")]
+  fn test_storable(
+    status: u16,
+    request_headers: &[(&'static str, &'static str)],
+    response_headers: &[(&'static str, &'static str)],
+  ) -> Result<(), String> {
+    storable(
+      StatusCode::from_u16(status).unwrap(),
+      &headers(request_headers),
+      &headers(response_headers),
+    )
+  }
+}
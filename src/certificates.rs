@@ -0,0 +1,442 @@
+use super::http_utils::{TlsVersion, TrustStore};
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use openssl::asn1::Asn1Time;
+use openssl::hash::MessageDigest;
+use openssl::x509::{X509NameRef, X509Ref, X509};
+use rustls::client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier};
+use rustls::version::{TLS12, TLS13};
+use rustls::{Certificate, ClientConfig, OwnedTrustAnchor, RootCertStore, ServerName, SupportedProtocolVersion};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+// details of a certificate of the server chain, `--show-cert`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CertificateInfo {
+  pub subject: String,
+  pub issuer: String,
+  pub alt_names: Vec<String>,
+  pub not_before: String,
+  pub not_after: String,
+  pub expired: bool,
+  pub fingerprint: String,
+  pub pin: String,
+}
+
+static PIN_PREFIX: &str = "sha256//";
+
+fn format_name(name: &X509NameRef) -> String {
+  name
+    .entries()
+    .map(|entry| {
+      let key = entry.object().nid().short_name().unwrap_or("?");
+      let value = entry
+        .data()
+        .as_utf8()
+        .map(|value| value.to_string())
+        .unwrap_or_default();
+      format!("{}={}", key, value)
+    })
+    .collect::<Vec<_>>()
+    .join(", ")
+}
+
+fn alt_names(certificate: &X509Ref) -> Vec<String> {
+  certificate
+    .subject_alt_names()
+    .map(|names| {
+      names
+        .iter()
+        .filter_map(|name| {
+          name.dnsname().map(str::to_string).or_else(|| {
+            let ip = match name.ipaddress()? {
+              [a, b, c, d] => IpAddr::from([*a, *b, *c, *d]),
+              bytes => IpAddr::from(<[u8; 16]>::try_from(bytes).ok()?),
+            };
+            Some(ip.to_string())
+          })
+        })
+        .collect()
+    })
+    .unwrap_or_default()
+}
+
+pub fn certificate_info(certificate: &X509Ref) -> Result<CertificateInfo> {
+  let fingerprint = certificate
+    .digest(MessageDigest::sha256())?
+    .iter()
+    .map(|byte| format!("{:02X}", byte))
+    .collect::<Vec<_>>()
+    .join(":");
+  let public_key = certificate.public_key()?.public_key_to_der()?;
+  let pin = format!("{}{}", PIN_PREFIX, base64::encode(openssl::sha::sha256(&public_key)));
+  Ok(CertificateInfo {
+    subject: format_name(certificate.subject_name()),
+    issuer: format_name(certificate.issuer_name()),
+    alt_names: alt_names(certificate),
+    not_before: certificate.not_before().to_string(),
+    not_after: certificate.not_after().to_string(),
+    expired: certificate.not_after() < Asn1Time::days_from_now(0)?,
+    fingerprint,
+    pin,
+  })
+}
+
+// chain sent by a server in the handshake of a request connection, with its verification result
+pub type PeerChain = (Vec<CertificateInfo>, Result<(), String>);
+
+// chains are recorded by server name, connections reused from the pool keep the chain of their handshake
+static PEER_CHAINS: Lazy<Mutex<HashMap<String, PeerChain>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+// chain of the last handshake with a server, recorded by clients built for `--show-cert`
+pub fn peer_certificates(server: &str) -> Option<PeerChain> {
+  PEER_CHAINS.lock().unwrap().get(server).cloned()
+}
+
+pub fn format_certificates(chain: &[CertificateInfo], verification: &Result<(), String>) -> String {
+  let mut output = String::new();
+  for (index, certificate) in chain.iter().enumerate() {
+    output.push_str(&format!("certificate {}:\n", index));
+    output.push_str(&format!("  subject: {}\n", certificate.subject));
+    output.push_str(&format!("  issuer: {}\n", certificate.issuer));
+    if !certificate.alt_names.is_empty() {
+      output.push_str(&format!("  alt names: {}\n", certificate.alt_names.join(", ")));
+    }
+    output.push_str(&format!(
+      "  validity: {} to {}{}\n",
+      certificate.not_before,
+      certificate.not_after,
+      if certificate.expired { " (expired)" } else { "" }
+    ));
+    output.push_str(&format!("  sha256 fingerprint: {}\n", certificate.fingerprint));
+    output.push_str(&format!("  public key pin: {}\n", certificate.pin));
+  }
+  match verification {
+    Ok(()) => output.push_str("verification: ok\n"),
+    Err(error) => output.push_str(&format!("verification: failed, {}\n", error)),
+  }
+  output
+}
+
+// `sha256//BASE64` hashes of subject public key infos, separated by `;` like curl `--pinnedpubkey`
+pub fn split_pins(value: &str) -> Vec<String> {
+  value
+    .split(';')
+    .map(str::trim)
+    .filter(|pin| !pin.is_empty())
+    .map(str::to_string)
+    .collect()
+}
+
+pub fn parse_pins(value: &str) -> Result<Vec<String>> {
+  split_pins(value)
+    .into_iter()
+    .map(|pin| {
+      let hash = pin
+        .strip_prefix(PIN_PREFIX)
+        .and_then(|hash| base64::decode(hash).ok())
+        .filter(|hash| hash.len() == 32);
+      match hash {
+        Some(_) => Ok(pin),
+        None => Err(anyhow::anyhow!(
+          "Bad certificate pin: \"{}\", should be sha256// followed by a base64 sha-256 hash",
+          pin
+        )),
+      }
+    })
+    .collect()
+}
+
+// pins match when a certificate of the server chain has one of their public keys
+pub fn check_pins(server: &str, chain: &[CertificateInfo], pins: &[String]) -> Result<(), String> {
+  if chain.iter().any(|certificate| pins.contains(&certificate.pin)) {
+    return Ok(());
+  }
+  Err(format!(
+    "Certificate pinning failed for {}, no certificate of the chain matches the expected pins\nexpected: {}\nfound: {}",
+    server,
+    pins.join(", "),
+    chain
+      .iter()
+      .map(|certificate| certificate.pin.as_str())
+      .collect::<Vec<_>>()
+      .join(", ")
+  ))
+}
+
+fn chain_info(end_entity: &Certificate, intermediates: &[Certificate]) -> Result<Vec<CertificateInfo>> {
+  std::iter::once(end_entity)
+    .chain(intermediates)
+    .map(|certificate| {
+      let certificate = X509::from_der(&certificate.0)?;
+      certificate_info(&certificate)
+    })
+    .collect()
+}
+
+// operating system roots are read from the openssl certificate locations, rustls does not use the platform store
+fn root_store(trust_store: Option<TrustStore>) -> Result<RootCertStore> {
+  let mut roots = RootCertStore::empty();
+  if trust_store == Some(TrustStore::Bundled) {
+    roots.add_server_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(|anchor| {
+      OwnedTrustAnchor::from_subject_spki_name_constraints(anchor.subject, anchor.spki, anchor.name_constraints)
+    }));
+    return Ok(roots);
+  }
+  let probe = openssl_probe::probe();
+  let dir_entries = probe
+    .cert_dir
+    .iter()
+    .flat_map(std::fs::read_dir)
+    .flatten()
+    .flatten()
+    .map(|entry| entry.path());
+  for path in probe.cert_file.into_iter().chain(dir_entries) {
+    if let Ok(file) = File::open(&path) {
+      if let Ok(certificates) = rustls_pemfile::certs(&mut BufReader::new(file)) {
+        roots.add_parsable_certificates(&certificates);
+      }
+    }
+  }
+  if roots.is_empty() {
+    return Err(anyhow::anyhow!(
+      "No root certificate found in the system store, use --trust-store bundled"
+    ));
+  }
+  Ok(roots)
+}
+
+// rustls only supports tls 1.2 and 1.3
+fn protocol_versions(
+  tls_min: Option<TlsVersion>,
+  tls_max: Option<TlsVersion>,
+) -> Result<Vec<&'static SupportedProtocolVersion>> {
+  let versions = [(TlsVersion::V1_2, &TLS12), (TlsVersion::V1_3, &TLS13)]
+    .into_iter()
+    .filter(|(version, _)| tls_min.is_none_or(|tls_min| *version >= tls_min))
+    .filter(|(version, _)| tls_max.is_none_or(|tls_max| *version <= tls_max))
+    .map(|(_, version)| version)
+    .collect::<Vec<_>>();
+  match versions.is_empty() {
+    true => Err(anyhow::anyhow!("Certificate pinning only supports tls 1.2 and 1.3")),
+    false => Ok(versions),
+  }
+}
+
+// usual chain verification, then the chain of the handshake itself is recorded for `--show-cert` and checked
+// against the pins
+struct InspectingVerifier {
+  inner: WebPkiVerifier,
+  pins: Vec<String>,
+  record: bool,
+}
+
+impl ServerCertVerifier for InspectingVerifier {
+  fn verify_server_cert(
+    &self,
+    end_entity: &Certificate,
+    intermediates: &[Certificate],
+    server_name: &ServerName,
+    scts: &mut dyn Iterator<Item = &[u8]>,
+    ocsp_response: &[u8],
+    now: SystemTime,
+  ) -> Result<ServerCertVerified, rustls::Error> {
+    let verified = self
+      .inner
+      .verify_server_cert(end_entity, intermediates, server_name, scts, ocsp_response, now);
+    let chain = chain_info(end_entity, intermediates).map_err(|e| rustls::Error::General(e.to_string()))?;
+    let server = match server_name {
+      ServerName::DnsName(name) => name.as_ref().to_string(),
+      ServerName::IpAddress(ip) => ip.to_string(),
+      _ => String::new(),
+    };
+    if self.record {
+      let verification = verified.as_ref().map(|_| ()).map_err(|e| e.to_string());
+      PEER_CHAINS
+        .lock()
+        .unwrap()
+        .insert(server.clone(), (chain.clone(), verification));
+    }
+    let verified = verified?;
+    if !self.pins.is_empty() {
+      check_pins(&server, &chain, &self.pins).map_err(rustls::Error::General)?;
+    }
+    Ok(verified)
+  }
+}
+
+// tls config of clients with certificate pins or `--show-cert`, so every connection the requests are sent on is
+// inspected
+pub fn inspecting_tls_config(
+  pins: &[String],
+  show_cert: bool,
+  trust_store: Option<TrustStore>,
+  tls_min: Option<TlsVersion>,
+  tls_max: Option<TlsVersion>,
+) -> Result<ClientConfig> {
+  inspecting_config(pins, show_cert, root_store(trust_store)?, tls_min, tls_max)
+}
+
+fn inspecting_config(
+  pins: &[String],
+  show_cert: bool,
+  roots: RootCertStore,
+  tls_min: Option<TlsVersion>,
+  tls_max: Option<TlsVersion>,
+) -> Result<ClientConfig> {
+  let verifier = InspectingVerifier {
+    inner: WebPkiVerifier::new(roots, None),
+    pins: parse_pins(&pins.join(";"))?,
+    record: show_cert,
+  };
+  let mut config = ClientConfig::builder()
+    .with_safe_default_cipher_suites()
+    .with_safe_default_kx_groups()
+    .with_protocol_versions(&protocol_versions(tls_min, tls_max)?)?
+    .with_custom_certificate_verifier(Arc::new(verifier))
+    .with_no_client_auth();
+  config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+  Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use openssl::asn1::Asn1Time;
+  use openssl::pkey::PKey;
+  use openssl::rsa::Rsa;
+  use openssl::x509::extension::SubjectAlternativeName;
+  use openssl::x509::{X509Builder, X509NameBuilder, X509};
+  use test_case::test_case;
+
+  fn self_signed(days: u32) -> X509 {
+    self_signed_with_key(days).0
+  }
+
+  fn self_signed_with_key(days: u32) -> (X509, PKey<openssl::pkey::Private>) {
+    let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+    let mut name = X509NameBuilder::new().unwrap();
+    name.append_entry_by_text("CN", "apix.test").unwrap();
+    name.append_entry_by_text("O", "Apix").unwrap();
+    let name = name.build();
+    let mut builder = X509Builder::new().unwrap();
+    builder.set_version(2).unwrap();
+    let serial = openssl::bn::BigNum::from_u32(1).unwrap().to_asn1_integer().unwrap();
+    builder.set_serial_number(&serial).unwrap();
+    builder.set_subject_name(&name).unwrap();
+    builder.set_issuer_name(&name).unwrap();
+    builder.set_pubkey(&key).unwrap();
+    builder.set_not_before(&Asn1Time::days_from_now(0).unwrap()).unwrap();
+    builder.set_not_after(&Asn1Time::days_from_now(days).unwrap()).unwrap();
+    let alt_names = SubjectAlternativeName::new()
+      .dns("apix.test")
+      .ip("127.0.0.1")
+      .build(&builder.x509v3_context(None, None))
+      .unwrap();
+    builder.append_extension(alt_names).unwrap();
+    builder.sign(&key, MessageDigest::sha256()).unwrap();
+    (builder.build(), key)
+  }
+
+  // https server answering once on a local port with the given certificate
+  fn serve_tls(certificate: &X509, key: &PKey<openssl::pkey::Private>) -> std::net::SocketAddr {
+    use openssl::ssl::{SslAcceptor, SslMethod};
+    use std::io::{Read, Write};
+    let mut acceptor = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls_server()).unwrap();
+    acceptor.set_certificate(certificate).unwrap();
+    acceptor.set_private_key(key).unwrap();
+    let acceptor = acceptor.build();
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+      for stream in listener.incoming().flatten() {
+        if let Ok(mut stream) = acceptor.accept(stream) {
+          let mut buffer = [0u8; 1024];
+          let _ = stream.read(&mut buffer);
+          let _ = stream.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\nok");
+        }
+      }
+    });
+    address
+  }
+
+  #[tokio::test]
+  async fn test_handshake_inspection() {
+    let (certificate, key) = self_signed_with_key(30);
+    let address = serve_tls(&certificate, &key);
+    let pin = certificate_info(&certificate).unwrap().pin;
+    let request = |pins: Vec<String>, trusted: bool| {
+      let mut roots = RootCertStore::empty();
+      if trusted {
+        roots.add(&Certificate(certificate.to_der().unwrap())).unwrap();
+      }
+      let config = inspecting_config(&pins, true, roots, None, None).unwrap();
+      let client = reqwest::Client::builder()
+        .use_preconfigured_tls(config)
+        .resolve("apix.test", address)
+        .build()
+        .unwrap();
+      async move {
+        client
+          .get(format!("https://apix.test:{}/", address.port()))
+          .send()
+          .await
+      }
+    };
+    assert_eq!(request(vec![pin.clone()], true).await.unwrap().status(), 200);
+    let (chain, verification) = peer_certificates("apix.test").unwrap();
+    assert_eq!(chain[0].pin, pin);
+    assert!(verification.is_ok());
+    let bad_pin = "sha256//47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=".to_string();
+    let error = request(vec![bad_pin], true).await.unwrap_err();
+    assert!(format!("{:?}", error).contains("Certificate pinning failed for apix.test"));
+    // untrusted chains are still recorded, so they can be inspected
+    assert!(request(Vec::new(), false).await.is_err());
+    let (chain, verification) = peer_certificates("apix.test").unwrap();
+    assert_eq!(chain[0].pin, pin);
+    assert!(verification.is_err());
+  }
+
+  #[test]
+  fn test_check_pins() {
+    let info = certificate_info(&self_signed(30)).unwrap();
+    let chain = vec![info.clone()];
+    assert!(check_pins("apix.test", &chain, std::slice::from_ref(&info.pin)).is_ok());
+    let error = check_pins(
+      "apix.test",
+      &chain,
+      &["sha256//47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=".to_string()],
+    )
+    .unwrap_err();
+    assert!(error.starts_with("Certificate pinning failed for apix.test"));
+    assert!(error.ends_with(&format!("found: {}", info.pin)));
+  }
+
+  #[test]
+  fn test_protocol_versions() {
+    assert_eq!(protocol_versions(None, None).unwrap().len(), 2);
+    assert_eq!(
+      protocol_versions(Some(TlsVersion::V1_0), Some(TlsVersion::V1_2)).unwrap(),
+      vec![&TLS12]
+    );
+    assert_eq!(protocol_versions(Some(TlsVersion::V1_3), None).unwrap(), vec![&TLS13]);
+    assert!(protocol_versions(None, Some(TlsVersion::V1_1)).is_err());
+  }
+
+  #[test_case("sha256//47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=" => 1 ; "single")]
+  #[test_case("sha256//47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=; sha256//LPJNul+wow4m6DsqxbninhsWHlwfp0JecwQzYpOLmCQ=" => 2 ; "several")]
+  fn test_parse_pins(value: &str) -> usize {
+    parse_pins(value).unwrap().len()
+  }
+
+  #[test_case("47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=" ; "missing prefix")]
+  #[test_case("sha256//not base64" ; "not base64")]
+  #[test_case("sha256//YWJj" ; "wrong length")]
+  fn test_parse_bad_pins(value: &str) {
+    assert!(parse_pins(value).is_err());
+  }
+}
@@ -0,0 +1,249 @@
+use super::manifests::{ApixConfiguration, ApixManifest};
+use anyhow::Result;
+use clap::App;
+use clap_complete::{generate, Shell};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+static BASH_DYNAMIC: &str = r#"_apix_dynamic() {
+    local kind=""
+    case "${COMP_CWORD}:${COMP_WORDS[1]}:${COMP_WORDS[2]}" in
+        2:exec:*) kind="request" ;;
+        3:config:get|3:config:set|3:config:delete) kind="config" ;;
+        4:ctl:get|4:ctl:edit|4:ctl:delete) kind="${COMP_WORDS[3]}" ;;
+    esac
+    if [[ "${kind}" == "request" || "${kind}" == "story" || "${kind}" == "config" ]]; then
+        COMPREPLY=( $(compgen -W "$(apix complete "${kind}" 2>/dev/null)" -- "${COMP_WORDS[COMP_CWORD]}") )
+        return 0
+    fi
+    _apix "$@"
+}
+
+complete -F _apix_dynamic -o bashdefault -o default apix
+"#;
+
+static ZSH_DYNAMIC: &str = r#"_apix_dynamic() {
+    local kind=""
+    case "${CURRENT}:${words[2]}:${words[3]}" in
+        3:exec:*) kind="request" ;;
+        4:config:(get|set|delete)) kind="config" ;;
+        5:ctl:(get|edit|delete)) kind="${words[4]}" ;;
+    esac
+    if [[ "${kind}" == (request|story|config) ]]; then
+        local -a candidates
+        candidates=(${(f)"$(apix complete "${kind}" 2>/dev/null)"})
+        compadd -a candidates
+        return
+    fi
+    _apix "$@"
+}
+
+_apix_dynamic "$@"
+"#;
+
+static FISH_DYNAMIC: &str = r#"complete -c apix -n "__fish_seen_subcommand_from exec" -f -a "(apix complete request 2>/dev/null)"
+complete -c apix -n "__fish_seen_subcommand_from config; and __fish_seen_subcommand_from get set delete" -f -a "(apix complete config 2>/dev/null)"
+complete -c apix -n "__fish_seen_subcommand_from ctl; and __fish_seen_subcommand_from request" -f -a "(apix complete request 2>/dev/null)"
+complete -c apix -n "__fish_seen_subcommand_from ctl; and __fish_seen_subcommand_from story" -f -a "(apix complete story 2>/dev/null)"
+"#;
+
+// generate static completions from clap, then hook dynamic completions of manifest names and config keys
+pub fn generate_completions(shell: Shell, app: &mut App) -> Result<String> {
+  let mut buffer = Vec::new();
+  generate(shell, app, app.get_name().to_string(), &mut buffer);
+  let script = String::from_utf8(buffer)?;
+  let script = match shell {
+    Shell::Bash => script.replace("complete -F _apix -o bashdefault -o default apix\n", BASH_DYNAMIC),
+    Shell::Zsh => match script.rfind("_apix \"$@\"") {
+      Some(index) => format!("{}{}", &script[..index], ZSH_DYNAMIC),
+      None => script,
+    },
+    Shell::Fish => format!("{}{}", script, FISH_DYNAMIC),
+    _ => script,
+  };
+  Ok(script)
+}
+
+// list completion candidates for a kind of value
+pub fn list_candidates(kind: &str) -> Result<Vec<String>> {
+  match kind {
+    "config" => Ok(ApixConfiguration::once().index.keys().cloned().collect()),
+    kind => Ok(
+      ApixManifest::find_manifests_by_kind(kind)?
+        .map(|(_, manifest)| manifest.name().to_string())
+        .collect(),
+    ),
+  }
+}
+
+// line added to `.zshrc` so completion functions in `~/.zfunc` are found
+static ZSH_FPATH: &str = "fpath=(~/.zfunc $fpath)\nautoload -Uz compinit && compinit\n";
+
+// files written to install completions of a shell
+#[derive(Debug, PartialEq)]
+pub struct CompletionInstall {
+  pub script: PathBuf,
+  // shell startup file and the lines to append to it, when the script location is not loaded by default
+  pub startup: Option<(PathBuf, String)>,
+}
+
+// shell of the user, from its `SHELL` environment variable
+pub fn detect_shell() -> Result<Shell> {
+  let shell = std::env::var("SHELL").map_err(|_| anyhow::anyhow!("Could not detect your shell, use --shell"))?;
+  let name = Path::new(&shell)
+    .file_name()
+    .and_then(|name| name.to_str())
+    .unwrap_or_default();
+  Shell::from_str(name)
+    .map_err(|_| anyhow::anyhow!("Shell {} is not supported, use --shell to choose another one", shell))
+}
+
+// completion locations loaded by each shell, following xdg directories for bash and fish, and `ZDOTDIR` for zsh
+pub fn install_locations(shell: Shell, home: &Path, env: impl Fn(&str) -> Option<String>) -> Result<CompletionInstall> {
+  let xdg = |name: &str, default: &str| {
+    env(name)
+      .filter(|value| !value.is_empty())
+      .map(PathBuf::from)
+      .unwrap_or_else(|| home.join(default))
+  };
+  match shell {
+    Shell::Bash => Ok(CompletionInstall {
+      script: xdg("XDG_DATA_HOME", ".local/share").join("bash-completion/completions/apix"),
+      startup: None,
+    }),
+    Shell::Zsh => Ok(CompletionInstall {
+      script: home.join(".zfunc/_apix"),
+      startup: Some((xdg("ZDOTDIR", "").join(".zshrc"), ZSH_FPATH.to_string())),
+    }),
+    Shell::Fish => Ok(CompletionInstall {
+      script: xdg("XDG_CONFIG_HOME", ".config").join("fish/completions/apix.fish"),
+      startup: None,
+    }),
+    shell => Err(anyhow::anyhow!(
+      "Installing completions for {} is not supported, use `apix completions {}` to print them",
+      shell,
+      shell
+    )),
+  }
+}
+
+// append lines loading completions to a shell startup file, unless it already loads them
+// the file is only appended to, so content apix can't read is never lost
+fn append_startup_lines(startup: &Path, lines: &str) -> Result<bool> {
+  let content = match std::fs::read(startup) {
+    Ok(content) => content,
+    Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+    Err(e) => return Err(anyhow::anyhow!("Could not read {}\ncause: {}", startup.display(), e)),
+  };
+  if content.windows(b".zfunc".len()).any(|window| window == b".zfunc") {
+    return Ok(false);
+  }
+  let separator = match content.last() {
+    None | Some(b'\n') => "",
+    Some(_) => "\n",
+  };
+  std::fs::OpenOptions::new()
+    .append(true)
+    .create(true)
+    .open(startup)
+    .and_then(|mut file| file.write_all(format!("{}# apix completions\n{}", separator, lines).as_bytes()))
+    .map_err(|e| anyhow::anyhow!("Could not update {}\ncause: {}", startup.display(), e))?;
+  Ok(true)
+}
+
+// write completions where the shell loads them, and print every file changed
+pub fn install_completions(shell: Shell, app: &mut App) -> Result<()> {
+  let home = dirs::home_dir().ok_or_else(|| anyhow::anyhow!("Could not find your home directory"))?;
+  let install = install_locations(shell, &home, |name| std::env::var(name).ok())?;
+  if let Some(parent) = install.script.parent() {
+    std::fs::create_dir_all(parent)
+      .map_err(|e| anyhow::anyhow!("Could not create {}\ncause: {}", parent.display(), e))?;
+  }
+  std::fs::write(&install.script, generate_completions(shell, app)?)
+    .map_err(|e| anyhow::anyhow!("Could not write {}\ncause: {}", install.script.display(), e))?;
+  println!("Wrote {} completions to {}", shell, install.script.display());
+  if let Some((startup, lines)) = install.startup {
+    if append_startup_lines(&startup, &lines)? {
+      println!("Added to {}:\n{}", startup.display(), lines.trim_end());
+    } else {
+      println!("{} already loads {}", startup.display(), install.script.display());
+    }
+  }
+  println!("Restart your shell to use them");
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::build_args::build_cli;
+
+  #[test]
+  fn test_bash_completions_are_dynamic() {
+    let script = generate_completions(Shell::Bash, &mut build_cli()).unwrap();
+    assert!(script.contains("apix complete \"${kind}\""));
+    assert!(script.ends_with("complete -F _apix_dynamic -o bashdefault -o default apix\n"));
+    assert!(!script.contains("complete -F _apix -o"));
+  }
+
+  #[test]
+  fn test_zsh_completions_are_dynamic() {
+    let script = generate_completions(Shell::Zsh, &mut build_cli()).unwrap();
+    assert!(script.ends_with("_apix_dynamic \"$@\"\n"));
+  }
+
+  #[test]
+  fn test_fish_completions_are_dynamic() {
+    let script = generate_completions(Shell::Fish, &mut build_cli()).unwrap();
+    assert!(script.contains("(apix complete request 2>/dev/null)"));
+  }
+
+  #[test]
+  fn test_install_locations() {
+    let home = Path::new("/home/apix");
+    let no_env = |_: &str| None;
+    assert_eq!(
+      install_locations(Shell::Bash, home, no_env).unwrap().script,
+      PathBuf::from("/home/apix/.local/share/bash-completion/completions/apix")
+    );
+    assert_eq!(
+      install_locations(Shell::Fish, home, |_| Some("/xdg/config".to_string()))
+        .unwrap()
+        .script,
+      PathBuf::from("/xdg/config/fish/completions/apix.fish")
+    );
+    let zsh = install_locations(Shell::Zsh, home, no_env).unwrap();
+    assert_eq!(zsh.script, PathBuf::from("/home/apix/.zfunc/_apix"));
+    assert_eq!(zsh.startup.unwrap().0, PathBuf::from("/home/apix/.zshrc"));
+    let zsh = install_locations(Shell::Zsh, home, |name| {
+      (name == "ZDOTDIR").then(|| "/home/apix/.config/zsh".to_string())
+    })
+    .unwrap();
+    assert_eq!(zsh.startup.unwrap().0, PathBuf::from("/home/apix/.config/zsh/.zshrc"));
+    assert!(install_locations(Shell::PowerShell, home, no_env).is_err());
+  }
+
+  #[test]
+  fn test_append_startup_lines() {
+    let dir = std::env::temp_dir().join(format!("apix-completions-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let zshrc = dir.join(".zshrc");
+    // content that is not utf-8 is kept
+    std::fs::write(&zshrc, b"export NAME=\xe9t\xe9").unwrap();
+    assert!(append_startup_lines(&zshrc, ZSH_FPATH).unwrap());
+    let mut expected = b"export NAME=\xe9t\xe9\n# apix completions\n".to_vec();
+    expected.extend(ZSH_FPATH.as_bytes());
+    assert_eq!(std::fs::read(&zshrc).unwrap(), expected);
+    assert!(!append_startup_lines(&zshrc, ZSH_FPATH).unwrap());
+    assert_eq!(std::fs::read(&zshrc).unwrap(), expected);
+    let created = dir.join("created");
+    assert!(append_startup_lines(&created, ZSH_FPATH).unwrap());
+    assert!(std::fs::read_to_string(&created)
+      .unwrap()
+      .starts_with("# apix completions\n"));
+    // startup files that can't be read are left untouched
+    assert!(append_startup_lines(&dir, ZSH_FPATH).is_err());
+    std::fs::remove_dir_all(dir).unwrap();
+  }
+}
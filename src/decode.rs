@@ -0,0 +1,143 @@
+use anyhow::Result;
+use protobuf::reflect::{FileDescriptor, MessageDescriptor};
+use reqwest::header::{HeaderMap, CONTENT_TYPE};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+// converts binary response bodies to json, `--decode msgpack` or `--decode proto:<schema.proto>:<Message>`
+#[derive(Debug, Clone, PartialEq)]
+pub enum BodyDecoder {
+  MessagePack,
+  Protobuf { schema: PathBuf, message: String },
+}
+
+impl FromStr for BodyDecoder {
+  type Err = anyhow::Error;
+
+  fn from_str(value: &str) -> Result<Self> {
+    match value.split_once(':') {
+      None if value == "msgpack" => Ok(BodyDecoder::MessagePack),
+      Some(("proto", schema_message)) => match schema_message.rsplit_once(':') {
+        Some((schema, message)) if !schema.is_empty() && !message.is_empty() => Ok(BodyDecoder::Protobuf {
+          schema: PathBuf::from(schema),
+          message: message.to_string(),
+        }),
+        _ => Err(anyhow::anyhow!(
+          "Bad protobuf decoder: \"{}\", should be of the form \"proto:<schema.proto>:<Message>\"",
+          value
+        )),
+      },
+      _ => Err(anyhow::anyhow!(
+        "Bad decoder: \"{}\", should be msgpack or proto:<schema.proto>:<Message>",
+        value
+      )),
+    }
+  }
+}
+
+impl BodyDecoder {
+  // messagepack is self describing, so it is decoded from its content type without any option
+  pub fn detect(headers: &HeaderMap) -> Option<Self> {
+    let content_type = headers.get(CONTENT_TYPE)?.to_str().ok()?;
+    content_type.contains("msgpack").then_some(BodyDecoder::MessagePack)
+  }
+
+  pub fn decode(&self, bytes: &[u8]) -> Result<Value> {
+    match self {
+      BodyDecoder::MessagePack => {
+        rmp_serde::from_slice(bytes).map_err(|e| anyhow::anyhow!("Invalid messagepack body\ncause: {}", e))
+      }
+      BodyDecoder::Protobuf { schema, message } => {
+        let descriptor = message_descriptor(schema, message)?;
+        let decoded = descriptor
+          .parse_from_bytes(bytes)
+          .map_err(|e| anyhow::anyhow!("Invalid protobuf body for message {}\ncause: {}", message, e))?;
+        let json = protobuf_json_mapping::print_to_string(decoded.as_ref())
+          .map_err(|e| anyhow::anyhow!("Could not convert protobuf message {} to json\ncause: {}", message, e))?;
+        Ok(serde_json::from_str(&json)?)
+      }
+    }
+  }
+}
+
+// parse schema and its imports (relative to the schema directory), message is looked up by name or full name
+fn message_descriptor(schema: &Path, message: &str) -> Result<MessageDescriptor> {
+  let include = schema.parent().filter(|parent| !parent.as_os_str().is_empty());
+  let parsed = protobuf_parse::Parser::new()
+    .pure()
+    .include(include.unwrap_or_else(|| Path::new(".")))
+    .input(schema)
+    .parse_and_typecheck()
+    .map_err(|e| anyhow::anyhow!("Could not parse protobuf schema {:?}\ncause: {:#}", schema, e))?;
+  let files = FileDescriptor::new_dynamic_fds(parsed.file_descriptors, &[])
+    .map_err(|e| anyhow::anyhow!("Invalid protobuf schema {:?}\ncause: {}", schema, e))?;
+  let full_name = format!(".{}", message.trim_start_matches('.'));
+  files
+    .iter()
+    .find_map(|file| {
+      file
+        .message_by_package_relative_name(message)
+        .or_else(|| file.message_by_full_name(&full_name))
+    })
+    .ok_or_else(|| anyhow::anyhow!("Message {} not found in protobuf schema {:?}", message, schema))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use reqwest::header::HeaderValue;
+  use serde_json::json;
+  use test_case::test_case;
+
+  #[test_case("msgpack" => BodyDecoder::MessagePack ; "msgpack")]
+  #[test_case("proto:schemas/user.proto:api.User" => BodyDecoder::Protobuf {
+    schema: PathBuf::from("schemas/user.proto"),
+    message: "api.User".to_string()
+  } ; "protobuf")]
+  fn test_parse_decoder(value: &str) -> BodyDecoder {
+    value.parse().unwrap()
+  }
+
+  #[test_case("proto:user.proto" ; "missing message")]
+  #[test_case("avro" ; "unknown decoder")]
+  fn test_parse_invalid_decoder(value: &str) {
+    assert!(value.parse::<BodyDecoder>().is_err());
+  }
+
+  #[test]
+  fn test_decode_msgpack() {
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/x-msgpack"));
+    let decoder = BodyDecoder::detect(&headers).unwrap();
+    let body = json!({"name": "apix", "stars": 42, "tags": ["http", "cli"]});
+    let bytes = rmp_serde::to_vec_named(&body).unwrap();
+    assert_eq!(decoder.decode(&bytes).unwrap(), body);
+  }
+
+  #[test]
+  fn test_decode_protobuf() {
+    let dir = std::env::temp_dir().join(format!("apix-decode-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let schema = dir.join("user.proto");
+    std::fs::write(
+      &schema,
+      "syntax = \"proto3\";\npackage api;\nmessage User {\n  string name = 1;\n  int32 stars = 2;\n}\n",
+    )
+    .unwrap();
+    let bytes = [0x0a, 0x04, b'a', b'p', b'i', b'x', 0x10, 0x2a];
+    for message in ["User", "api.User"] {
+      let decoder = BodyDecoder::Protobuf {
+        schema: schema.clone(),
+        message: message.to_string(),
+      };
+      assert_eq!(decoder.decode(&bytes).unwrap(), json!({"name": "apix", "stars": 42}));
+    }
+    let missing = BodyDecoder::Protobuf {
+      schema,
+      message: "Missing".to_string(),
+    };
+    assert!(missing.decode(&bytes).is_err());
+    std::fs::remove_dir_all(dir).unwrap();
+  }
+}
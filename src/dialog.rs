@@ -1,6 +1,7 @@
+use super::editor::edit_value;
 use super::manifests::ApixParameter;
 use anyhow::Result;
-use dialoguer::{theme::ColorfulTheme, Input, Password};
+use dialoguer::{theme::ColorfulTheme, Confirm, Input, MultiSelect, Password};
 use jsonschema::{Draft, JSONSchema};
 use serde_json::Value;
 
@@ -11,48 +12,293 @@ fn input_to_value(input: &str) -> Value {
   }
 }
 
+fn validate(schema: &JSONSchema, value: &Value) -> Result<(), String> {
+  if let Err(errors) = schema.validate(value) {
+    let mut msg: Vec<String> = vec!["Invalid input:".to_string()];
+    for (index, cause) in errors.enumerate() {
+      msg.push(format!("cause {}: {}", index, cause));
+    }
+    return Err(msg.join("\n"));
+  }
+  Ok(())
+}
+
+// allowed items of an array schema, when its items are an enum
+fn array_choices(schema: &Value) -> Option<&Vec<Value>> {
+  match schema.get("type") {
+    Some(Value::String(kind)) if kind == "array" => schema.get("items")?.get("enum")?.as_array(),
+    _ => None,
+  }
+}
+
+fn choice_label(choice: &Value) -> String {
+  match choice {
+    Value::String(choice) => choice.clone(),
+    choice => choice.to_string(),
+  }
+}
+
+// ask to check items of an enum, asked again until the selection matches the schema (`minItems`, `maxItems`)
+fn ask_choices(name: &str, schema: &JSONSchema, choices: &[Value], default: Option<&Value>) -> Result<Value> {
+  let theme = ColorfulTheme::default();
+  let labels = choices.iter().map(choice_label).collect::<Vec<_>>();
+  let checked = choices
+    .iter()
+    .map(|choice| match default {
+      Some(Value::Array(default)) => default.contains(choice),
+      _ => false,
+    })
+    .collect::<Vec<_>>();
+  loop {
+    let selection = MultiSelect::with_theme(&theme)
+      .with_prompt(format!("{} (space to select, enter to confirm)", name))
+      .items(&labels)
+      .defaults(&checked)
+      .interact()?;
+    let value = Value::Array(selection.into_iter().map(|index| choices[index].clone()).collect());
+    match validate(schema, &value) {
+      Ok(()) => return Ok(value),
+      Err(msg) => eprintln!("{}", msg),
+    }
+  }
+}
+
+// numeric input typed as the schema asks, integers are kept as integers for `type: number` too
+fn number_value(kind: &str, input: &str) -> Result<Value, String> {
+  let input = input.trim();
+  let integer = input.parse::<i64>().map(Value::from);
+  match kind {
+    "integer" => integer.map_err(|_| format!("Invalid input: {} is not an integer", input)),
+    _ => integer.or_else(|_| {
+      input
+        .parse::<f64>()
+        .ok()
+        .and_then(serde_json::Number::from_f64)
+        .map(Value::Number)
+        .ok_or_else(|| format!("Invalid input: {} is not a number", input))
+    }),
+  }
+}
+
+// range allowed by `minimum`/`maximum` of a numeric schema, shown in its prompt
+fn range_hint(schema: &Value) -> Option<String> {
+  let bound = |name: &str, exclusive: &str| {
+    schema
+      .get(exclusive)
+      .filter(|bound| bound.is_number())
+      .map(|bound| (bound.to_string(), true))
+      .or_else(|| {
+        schema
+          .get(name)
+          .filter(|bound| bound.is_number())
+          .map(|bound| (bound.to_string(), false))
+      })
+  };
+  match (
+    bound("minimum", "exclusiveMinimum"),
+    bound("maximum", "exclusiveMaximum"),
+  ) {
+    (None, None) => None,
+    (Some((min, false)), Some((max, false))) => Some(format!("[{}..{}]", min, max)),
+    (min, max) => {
+      let min = min.map(|(min, exclusive)| format!("{} {}", if exclusive { ">" } else { ">=" }, min));
+      let max = max.map(|(max, exclusive)| format!("{} {}", if exclusive { "<" } else { "<=" }, max));
+      Some(format!(
+        "[{}]",
+        min.into_iter().chain(max).collect::<Vec<_>>().join(", ")
+      ))
+    }
+  }
+}
+
+fn ask_number(
+  name: &str,
+  kind: &str,
+  value_schema: &Value,
+  schema: &JSONSchema,
+  default: Option<&Value>,
+) -> Result<Value> {
+  let theme = ColorfulTheme::default();
+  let mut input = Input::<String>::with_theme(&theme);
+  match range_hint(value_schema) {
+    Some(range) => input.with_prompt(format!("{} {}", name, range)),
+    None => input.with_prompt(name),
+  };
+  if let Some(default) = default.filter(|default| default.is_number()) {
+    input.default(default.to_string());
+  }
+  let value = input
+    .validate_with(|input: &String| number_value(kind, input).and_then(|value| validate(schema, &value)))
+    .interact_text()?;
+  number_value(kind, &value).map_err(|e| anyhow::anyhow!(e))
+}
+
+fn ask_boolean(name: &str, default: Option<&Value>) -> Result<Value> {
+  let theme = ColorfulTheme::default();
+  let value = Confirm::with_theme(&theme)
+    .with_prompt(name)
+    .default(default.and_then(Value::as_bool).unwrap_or(false))
+    .interact()?;
+  Ok(Value::Bool(value))
+}
+
+// value to start editing a schema from: its default or example, otherwise an empty value of its type
+fn schema_skeleton(schema: &Value) -> Value {
+  let example = schema
+    .get("default")
+    .or_else(|| schema.get("example"))
+    .or_else(|| schema.get("examples").and_then(|examples| examples.get(0)))
+    .or_else(|| schema.get("enum").and_then(|choices| choices.get(0)));
+  if let Some(example) = example {
+    return example.clone();
+  }
+  match schema.get("type").and_then(Value::as_str) {
+    Some("object") => Value::Object(
+      schema
+        .get("properties")
+        .and_then(Value::as_object)
+        .map(|properties| {
+          properties
+            .iter()
+            .map(|(name, property)| (name.clone(), schema_skeleton(property)))
+            .collect()
+        })
+        .unwrap_or_default(),
+    ),
+    Some("array") => Value::Array(vec![]),
+    Some("string") => Value::String(String::new()),
+    Some("integer") | Some("number") => Value::from(0),
+    Some("boolean") => Value::Bool(false),
+    _ => Value::Null,
+  }
+}
+
 pub trait Dialog {
-  fn ask(&self) -> Result<Value>;
+  fn ask(&self) -> Result<Value> {
+    self.ask_with_default(None)
+  }
+  fn ask_with_default(&self, current: Option<&Value>) -> Result<Value>;
 }
 
 impl Dialog for ApixParameter {
-  fn ask(&self) -> Result<Value> {
+  // current value is proposed as default instead of the schema one, kept for passwords left empty
+  fn ask_with_default(&self, current: Option<&Value>) -> Result<Value> {
     let value_schema = self.schema.as_ref().unwrap();
     let schema = JSONSchema::options()
       .with_draft(Draft::Draft7)
       .compile(value_schema)
       .map_err(|err| anyhow::anyhow!("{}", err))?;
     if self.password {
-      let input = Password::with_theme(&ColorfulTheme::default())
-        .with_prompt(&self.name)
-        .interact()?;
-
-      Ok(Value::String(input))
+      let theme = ColorfulTheme::default();
+      let mut password = Password::with_theme(&theme);
+      match current {
+        Some(_) => password
+          .with_prompt(format!("{} (empty keeps current value)", self.name))
+          .allow_empty_password(true),
+        None => password.with_prompt(&self.name),
+      };
+      let input = password.interact()?;
+      match current {
+        Some(current) if input.is_empty() => Ok(current.clone()),
+        _ => Ok(Value::String(input)),
+      }
     } else {
       // check if schema has a default value
-      let default = value_schema.as_object().and_then(|obj| obj.get("default"));
+      let default = current.or_else(|| value_schema.as_object().and_then(|obj| obj.get("default")));
+      if let Some(choices) = array_choices(value_schema) {
+        return ask_choices(&self.name, &schema, choices, default);
+      }
+      match value_schema.get("type").and_then(Value::as_str) {
+        Some("boolean") => return ask_boolean(&self.name, default),
+        // objects are edited in the terminal editor, a single line prompt is not fit for nested json
+        Some("object") => {
+          let initial = default.cloned().unwrap_or_else(|| schema_skeleton(value_schema));
+          return edit_value(&self.name, &initial, |value| validate(&schema, value));
+        }
+        Some(kind @ ("integer" | "number")) => return ask_number(&self.name, kind, value_schema, &schema, default),
+        _ => {}
+      }
       let theme = ColorfulTheme::default();
       let mut input = Input::with_theme(&theme);
       input.with_prompt(&self.name);
-      if let Some(default) = default {
-        input.default(serde_json::to_string(default)?);
+      match default {
+        Some(Value::String(default)) if current.is_some() => {
+          input.default(default.clone());
+        }
+        Some(default) => {
+          input.default(serde_json::to_string(default)?);
+        }
+        None => {}
       }
       let value = input
-        .validate_with(|input: &String| {
-          let value = input_to_value(input);
-          let result = schema.validate(&value);
-          if let Err(errors) = result {
-            let mut msg: Vec<String> = vec!["Invalid input:".to_string()];
-            for (index, cause) in errors.enumerate() {
-              msg.push(format!("cause {}: {}", index, cause));
-            }
-            return Err(msg.join("\n"));
-          }
-          Ok(())
-        })
+        .validate_with(|input: &String| validate(&schema, &input_to_value(input)))
         .interact_text()?;
 
       Ok(input_to_value(&value))
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+  use test_case::test_case;
+
+  #[test]
+  fn test_array_choices() {
+    let schema = json!({ "type": "array", "items": { "type": "string", "enum": ["read", "write"] } });
+    assert_eq!(array_choices(&schema), Some(&vec![json!("read"), json!("write")]));
+    assert_eq!(
+      array_choices(&json!({ "type": "array", "items": { "type": "string" } })),
+      None
+    );
+    assert_eq!(array_choices(&json!({ "type": "string", "enum": ["read"] })), None);
+  }
+
+  #[test]
+  fn test_validate_selection() {
+    let schema = json!({ "type": "array", "minItems": 1, "items": { "enum": ["read", "write"] } });
+    let schema = JSONSchema::options()
+      .with_draft(Draft::Draft7)
+      .compile(&schema)
+      .unwrap();
+    assert!(validate(&schema, &json!(["read"])).is_ok());
+    assert!(validate(&schema, &json!([])).is_err());
+  }
+
+  #[test_case("integer", "42" => Ok(json!(42)))]
+  #[test_case("integer", "4.2" => Err("Invalid input: 4.2 is not an integer".to_string()))]
+  #[test_case("number", " 42 " => Ok(json!(42)))]
+  #[test_case("number", "4.2" => Ok(json!(4.2)))]
+  #[test_case("number", "four" => Err("Invalid input: four is not a number".to_string()))]
+  fn test_number_value(kind: &str, input: &str) -> Result<Value, String> {
+    number_value(kind, input)
+  }
+
+  #[test_case(json!({ "type": "integer", "minimum": 1, "maximum": 100 }) => Some("[1..100]".to_string()))]
+  #[test_case(json!({ "type": "integer", "minimum": 1 }) => Some("[>= 1]".to_string()))]
+  #[test_case(json!({ "type": "number", "exclusiveMinimum": 0, "maximum": 1 }) => Some("[> 0, <= 1]".to_string()))]
+  #[test_case(json!({ "type": "number" }) => None)]
+  fn test_range_hint(schema: Value) -> Option<String> {
+    range_hint(&schema)
+  }
+
+  #[test]
+  fn test_schema_skeleton() {
+    let schema = json!({
+      "type": "object",
+      "properties": {
+        "name": { "type": "string", "example": "jane" },
+        "age": { "type": "integer" },
+        "role": { "enum": ["admin", "user"] },
+        "address": { "type": "object", "properties": { "city": { "type": "string", "default": "Paris" } } },
+        "tags": { "type": "array", "items": { "type": "string" } }
+      }
+    });
+    assert_eq!(
+      schema_skeleton(&schema),
+      json!({ "name": "jane", "age": 0, "role": "admin", "address": { "city": "Paris" }, "tags": [] })
+    );
+  }
+}
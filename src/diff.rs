@@ -0,0 +1,141 @@
+use serde_json::Value;
+use similar::TextDiff;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Difference {
+  Added(String, Value),
+  Removed(String, Value),
+  Changed(String, Value, Value),
+}
+
+fn child_path(path: &str, key: &str) -> String {
+  if path.is_empty() {
+    key.to_string()
+  } else {
+    format!("{}.{}", path, key)
+  }
+}
+
+fn diff_at(path: &str, left: &Value, right: &Value, differences: &mut Vec<Difference>) {
+  match (left, right) {
+    (Value::Object(left), Value::Object(right)) => {
+      for (key, left_value) in left {
+        match right.get(key) {
+          Some(right_value) => diff_at(&child_path(path, key), left_value, right_value, differences),
+          None => differences.push(Difference::Removed(child_path(path, key), left_value.clone())),
+        }
+      }
+      for (key, right_value) in right {
+        if !left.contains_key(key) {
+          differences.push(Difference::Added(child_path(path, key), right_value.clone()));
+        }
+      }
+    }
+    (Value::Array(left), Value::Array(right)) => {
+      for index in 0..left.len().max(right.len()) {
+        let item_path = format!("{}[{}]", path, index);
+        match (left.get(index), right.get(index)) {
+          (Some(left_value), Some(right_value)) => diff_at(&item_path, left_value, right_value, differences),
+          (Some(left_value), None) => differences.push(Difference::Removed(item_path, left_value.clone())),
+          (None, Some(right_value)) => differences.push(Difference::Added(item_path, right_value.clone())),
+          (None, None) => {}
+        }
+      }
+    }
+    _ if left != right => differences.push(Difference::Changed(path.to_string(), left.clone(), right.clone())),
+    _ => {}
+  }
+}
+
+// compute structural differences between two json values, paths use `a.b[0].c` notation
+pub fn diff_values(left: &Value, right: &Value) -> Vec<Difference> {
+  let mut differences = Vec::new();
+  diff_at("", left, right, &mut differences);
+  differences
+}
+
+// render differences as a unified diff like text, suitable for `diff` syntax highlighting
+pub fn to_diff_text(differences: &[Difference]) -> String {
+  let mut output = String::new();
+  for difference in differences {
+    match difference {
+      Difference::Added(path, value) => output.push_str(&format!("+{}: {}\n", path, value)),
+      Difference::Removed(path, value) => output.push_str(&format!("-{}: {}\n", path, value)),
+      Difference::Changed(path, left, right) => {
+        output.push_str(&format!("-{}: {}\n+{}: {}\n", path, left, path, right));
+      }
+    }
+  }
+  output
+}
+
+// line based unified diff of two texts, empty when they are equal
+pub fn diff_lines(old: &str, new: &str, old_name: &str, new_name: &str) -> String {
+  if old == new {
+    return String::new();
+  }
+  TextDiff::from_lines(old, new)
+    .unified_diff()
+    .context_radius(3)
+    .header(old_name, new_name)
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  #[test]
+  fn test_diff_values() {
+    let left = json!({
+      "status": 200,
+      "headers": { "content-type": "application/json", "x-old": "1" },
+      "body": { "items": [{ "name": "a" }, { "name": "b" }] }
+    });
+    let right = json!({
+      "status": 404,
+      "headers": { "content-type": "application/json", "x-new": "2" },
+      "body": { "items": [{ "name": "c" }] }
+    });
+    assert_eq!(
+      diff_values(&left, &right),
+      vec![
+        Difference::Changed("status".to_string(), json!(200), json!(404)),
+        Difference::Removed("headers.x-old".to_string(), json!("1")),
+        Difference::Added("headers.x-new".to_string(), json!("2")),
+        Difference::Changed("body.items[0].name".to_string(), json!("a"), json!("c")),
+        Difference::Removed("body.items[1]".to_string(), json!({ "name": "b" })),
+      ]
+    );
+  }
+
+  #[test]
+  fn test_diff_equal_values() {
+    let value = json!({ "a": [1, 2, { "b": null }] });
+    assert!(diff_values(&value, &value).is_empty());
+  }
+
+  #[test]
+  fn test_to_diff_text() {
+    let differences = vec![
+      Difference::Changed("status".to_string(), json!(200), json!(404)),
+      Difference::Added("headers.x-new".to_string(), json!("2")),
+    ];
+    assert_eq!(
+      to_diff_text(&differences),
+      "-status: 200\n+status: 404\n+headers.x-new: \"2\"\n"
+    );
+  }
+
+  #[test]
+  fn test_diff_lines() {
+    let old = "kind: Request\nmetadata:\n  name: users\nspec:\n  method: get\n";
+    let new = "kind: Request\nmetadata:\n  name: users\nspec:\n  method: post\n";
+    assert_eq!(
+      diff_lines(old, new, "current", "applied"),
+      "--- current\n+++ applied\n@@ -2,4 +2,4 @@\n metadata:\n   name: users\n spec:\n-  method: get\n+  method: post\n"
+    );
+    assert_eq!(diff_lines(old, old, "current", "applied"), "");
+  }
+}
@@ -1,10 +1,15 @@
 use std::path::PathBuf;
 
-use super::http_utils::Language;
+use super::http_utils::{Language, ServerTiming};
 use anyhow::Result;
-use bat::{Input, PrettyPrinter};
+use bat::{Input, PrettyPrinter, WrappingMode};
+use comfy_table::{ContentArrangement, Table};
+use indexmap::IndexMap;
+use once_cell::sync::OnceCell;
 use reqwest::{Request, Response};
 use serde_json::Value;
+use std::str::FromStr;
+use std::time::Duration;
 use term_size::dimensions_stdout;
 use url::Position;
 
@@ -18,46 +23,206 @@ pub fn print_separator() {
   }
 }
 
-pub fn pretty_print(content: String, theme: &str, language: &str, enable_color: bool) -> Result<()> {
-  match language {
-    "json" => {
-      let json: Value = serde_json::from_str(&content)?;
-      let formatted = serde_json::to_string_pretty(&json)?;
-      PrettyPrinter::new()
-        .input(Input::from_reader(formatted.as_bytes()))
-        .language(language)
-        .colored_output(enable_color)
-        .theme(theme)
-        .print()
-        .map_err(|err| anyhow::anyhow!("Failed to print result: {:#}", err))?;
+// presentation options of pretty printed outputs, unset options keep each output default
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PrintStyle {
+  pub grid: Option<bool>,
+  pub header: Option<bool>,
+  pub line_numbers: Option<bool>,
+  pub wrapping: Option<WrappingMode>,
+  pub tab_width: Option<usize>,
+  // theme by highlighted language (`theme.json` config key), overriding the global theme
+  pub themes: IndexMap<String, String>,
+}
+
+static PRINT_STYLE: OnceCell<PrintStyle> = OnceCell::new();
+
+impl PrintStyle {
+  // parse bat like options: style is `plain`, `full` or a list of `grid`, `header`, `numbers`
+  pub fn parse(style: Option<&str>, wrap: Option<&str>, tab_width: Option<&str>) -> Result<Self> {
+    let mut print_style = Self::default();
+    if let Some(style) = style {
+      let components = style.split(',').map(str::trim).collect::<Vec<_>>();
+      for component in &components {
+        if !["plain", "full", "grid", "header", "numbers"].contains(component) {
+          return Err(anyhow::anyhow!(
+            "Bad style component: \"{}\", should be one of plain, full, grid, header, numbers",
+            component
+          ));
+        }
+      }
+      let enabled = |component: &str| Some(components.contains(&"full") || components.contains(&component));
+      print_style.grid = enabled("grid");
+      print_style.header = enabled("header");
+      print_style.line_numbers = enabled("numbers");
     }
-    _ => {
-      PrettyPrinter::new()
-        .input(Input::from_reader(content.as_bytes()))
-        .language(language)
-        .colored_output(enable_color)
-        .theme(theme)
-        .print()
-        .map_err(|err| anyhow::anyhow!("Failed to print result: {:#}", err))?;
+    print_style.wrapping = match wrap {
+      Some("auto") | Some("character") => Some(WrappingMode::Character),
+      Some("never") => Some(WrappingMode::NoWrapping(true)),
+      Some(wrap) => {
+        return Err(anyhow::anyhow!(
+          "Bad wrap mode: \"{}\", should be one of auto, character, never",
+          wrap
+        ))
+      }
+      None => None,
+    };
+    print_style.tab_width = tab_width
+      .map(|width| {
+        width
+          .parse::<usize>()
+          .map_err(|_| anyhow::anyhow!("Bad tab width: \"{}\", should be a positive number", width))
+      })
+      .transpose()?;
+    Ok(print_style)
+  }
+
+  pub fn with_themes(self, themes: IndexMap<String, String>) -> Self {
+    Self { themes, ..self }
+  }
+
+  // set style used by every pretty printed output, can only be set once
+  pub fn init(self) {
+    PRINT_STYLE.set(self).ok();
+  }
+
+  fn current() -> &'static PrintStyle {
+    PRINT_STYLE.get_or_init(PrintStyle::default)
+  }
+
+  // apply style to a printer, `decorations` is the default for grid, header and line numbers
+  fn apply(&self, printer: &mut PrettyPrinter, decorations: bool) {
+    printer
+      .grid(self.grid.unwrap_or(decorations))
+      .header(self.header.unwrap_or(decorations))
+      .line_numbers(self.line_numbers.unwrap_or(decorations))
+      .tab_width(self.tab_width);
+    if let Some(wrapping) = self.wrapping {
+      printer.wrapping_mode(wrapping);
     }
   }
-  Ok(())
 }
 
-pub fn pretty_print_file(path: PathBuf, theme: &str, language: &str, enable_color: bool) -> Result<()> {
-  PrettyPrinter::new()
-    .input_file(path)
+fn print_input(input: Input, theme: &str, language: &str, enable_color: bool, decorations: bool) -> Result<()> {
+  let style = PrintStyle::current();
+  let theme = style.themes.get(language).map(String::as_str).unwrap_or(theme);
+  let mut printer = PrettyPrinter::new();
+  printer
+    .input(input)
     .language(language)
     .colored_output(enable_color)
-    .theme(theme)
-    .grid(true)
-    .header(true)
-    .line_numbers(true)
+    .theme(theme);
+  style.apply(&mut printer, decorations);
+  printer
     .print()
     .map_err(|err| anyhow::anyhow!("Failed to print result: {:#}", err))?;
   Ok(())
 }
 
+pub fn pretty_print(content: String, theme: &str, language: &str, enable_color: bool) -> Result<()> {
+  match language {
+    "json" => {
+      // content may not be json when syntax is forced, print it as is in this case
+      let formatted = match serde_json::from_str::<Value>(&content) {
+        Ok(json) => serde_json::to_string_pretty(&json)?,
+        Err(_) => content,
+      };
+      print_input(
+        Input::from_reader(formatted.as_bytes()),
+        theme,
+        language,
+        enable_color,
+        false,
+      )
+    }
+    _ => print_input(
+      Input::from_reader(content.as_bytes()),
+      theme,
+      language,
+      enable_color,
+      false,
+    ),
+  }
+}
+
+// how response bodies are printed, `--output`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+  // csv bodies as an aligned table
+  Table,
+  // bodies as received
+  Text,
+  // values exported by the request as shell statements, bodies are not printed
+  Shell,
+}
+
+impl FromStr for OutputFormat {
+  type Err = anyhow::Error;
+
+  fn from_str(value: &str) -> Result<Self> {
+    match value {
+      "table" => Ok(OutputFormat::Table),
+      "text" => Ok(OutputFormat::Text),
+      "shell" => Ok(OutputFormat::Shell),
+      _ => Err(anyhow::anyhow!(
+        "Bad output format: \"{}\", should be table, text or shell",
+        value
+      )),
+    }
+  }
+}
+
+// csv content as an aligned table followed by a row count footer
+pub fn format_csv_table(content: &str) -> Result<String> {
+  let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(content.as_bytes());
+  let mut table = Table::new();
+  table
+    .load_preset("││──├─┼┤│─┼├┤┬┴╭╮╰╯")
+    .set_content_arrangement(ContentArrangement::Dynamic)
+    .set_header(
+      reader
+        .headers()
+        .map_err(|e| anyhow::anyhow!("Invalid csv content\ncause: {}", e))?
+        .iter(),
+    );
+  let mut rows = 0;
+  for record in reader.records() {
+    let record = record.map_err(|e| anyhow::anyhow!("Invalid csv content\ncause: {}", e))?;
+    table.add_row(record.iter());
+    rows += 1;
+  }
+  Ok(format!("{}\n{} row{}", table, rows, if rows == 1 { "" } else { "s" }))
+}
+
+// backend phases of `Server-Timing` headers next to the client side duration
+pub fn format_server_timing(timings: &[ServerTiming], client_duration: Duration) -> String {
+  let mut table = Table::new();
+  table
+    .load_preset("││──├─┼┤│─┼├┤┬┴╭╮╰╯")
+    .set_content_arrangement(ContentArrangement::Dynamic)
+    .set_header(["Server timing", "Duration", "Description"]);
+  for timing in timings {
+    table.add_row(vec![
+      timing.name.clone(),
+      timing
+        .duration
+        .map(|duration| format!("{}ms", duration))
+        .unwrap_or_default(),
+      timing.description.clone().unwrap_or_default(),
+    ]);
+  }
+  table.add_row(vec![
+    "client".to_string(),
+    format!("{}ms", client_duration.as_millis()),
+    "time to response headers".to_string(),
+  ]);
+  table.to_string()
+}
+
+pub fn pretty_print_file(path: PathBuf, theme: &str, language: &str, enable_color: bool) -> Result<()> {
+  print_input(Input::from_file(path), theme, language, enable_color, true)
+}
+
 impl HttpDisplay for Request {
   fn print(&self, theme: &str, enable_color: bool) -> Result<()> {
     let mut output = format!(
@@ -79,13 +244,7 @@ impl HttpDisplay for Request {
     if let (Some(body), Some(language)) = (self.body(), self.get_language()) {
       println!();
       if let Some(bytes) = body.as_bytes() {
-        PrettyPrinter::new()
-          .input(Input::from_reader(bytes))
-          .language(language)
-          .colored_output(enable_color)
-          .theme(theme)
-          .print()
-          .map_err(|err| anyhow::anyhow!("Failed to print result: {:#}", err))?;
+        print_input(Input::from_reader(bytes), theme, language, enable_color, false)?;
       }
     }
     Ok(())
@@ -106,3 +265,53 @@ impl HttpDisplay for Response {
     Ok(())
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use test_case::test_case;
+
+  #[test_case(Some("plain") => (Some(false), Some(false), Some(false)))]
+  #[test_case(Some("full") => (Some(true), Some(true), Some(true)))]
+  #[test_case(Some("grid,numbers") => (Some(true), Some(false), Some(true)))]
+  #[test_case(None => (None, None, None))]
+  fn test_parse_style(style: Option<&str>) -> (Option<bool>, Option<bool>, Option<bool>) {
+    let style = PrintStyle::parse(style, None, None).unwrap();
+    (style.grid, style.header, style.line_numbers)
+  }
+
+  #[test]
+  fn test_parse_wrap_and_tab_width() {
+    let style = PrintStyle::parse(None, Some("never"), Some("4")).unwrap();
+    assert_eq!(style.wrapping, Some(WrappingMode::NoWrapping(true)));
+    assert_eq!(style.tab_width, Some(4));
+  }
+
+  #[test_case(Some("borders"), None, None ; "bad style")]
+  #[test_case(None, Some("sometimes"), None ; "bad wrap")]
+  #[test_case(None, None, Some("-2") ; "bad tab width")]
+  fn test_parse_invalid(style: Option<&str>, wrap: Option<&str>, tab_width: Option<&str>) {
+    assert!(PrintStyle::parse(style, wrap, tab_width).is_err());
+  }
+
+  #[test]
+  fn test_format_server_timing() {
+    let timings = vec![ServerTiming {
+      name: "db".to_string(),
+      duration: Some(53.5),
+      description: Some("Database".to_string()),
+    }];
+    let table = format_server_timing(&timings, Duration::from_millis(80));
+    assert!(table.contains("db") && table.contains("53.5ms") && table.contains("Database"));
+    assert!(table.contains("client") && table.contains("80ms"));
+  }
+
+  #[test]
+  fn test_format_csv_table() {
+    let table = format_csv_table("name,stars\napix,42\n\"bat, the cat\",9000\n").unwrap();
+    let lines = table.lines().collect::<Vec<_>>();
+    assert!(lines[1].contains("name") && lines[1].contains("stars"));
+    assert!(table.contains("bat, the cat"));
+    assert_eq!(lines.last(), Some(&"2 rows"));
+  }
+}
@@ -0,0 +1,122 @@
+use crate::assertions::Capture;
+use anyhow::Result;
+use indexmap::IndexMap;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde_json::Value;
+use std::str::FromStr;
+
+static ENV_NAME: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[A-Za-z_][A-Za-z0-9_]*$").unwrap());
+
+// values that can be written without quotes
+static UNQUOTED: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[A-Za-z0-9_\-./:@+,=%]+$").unwrap());
+
+// capture request exports from a json representation of a response
+pub fn capture_exports(exports: &IndexMap<String, String>, response: &Value) -> Result<IndexMap<String, Value>> {
+  exports
+    .iter()
+    .map(|(name, capture)| {
+      if !ENV_NAME.is_match(name) {
+        return Err(anyhow::anyhow!(
+          "Invalid export name \"{}\", should be a valid env variable name",
+          name
+        ));
+      }
+      let value = Capture::from_str(capture)
+        .and_then(|capture| capture.evaluate(response))
+        .map_err(|e| anyhow::anyhow!("Failed to export {}\ncause: {}", name, e))?;
+      Ok((name.clone(), value))
+    })
+    .collect()
+}
+
+fn format_value(value: &Value) -> String {
+  let value = match value {
+    Value::String(value) => value.clone(),
+    value => value.to_string(),
+  };
+  if UNQUOTED.is_match(&value) {
+    return value;
+  }
+  let escaped = value
+    .replace('\\', "\\\\")
+    .replace('"', "\\\"")
+    .replace('$', "\\$")
+    .replace('`', "\\`")
+    .replace('\n', "\\n");
+  format!("\"{}\"", escaped)
+}
+
+// `KEY=value` lines, values with spaces or special characters are double quoted
+pub fn to_dotenv(values: &IndexMap<String, Value>) -> String {
+  values
+    .iter()
+    .map(|(name, value)| format!("{}={}\n", name, format_value(value)))
+    .collect()
+}
+
+// `export KEY='value'` statements, to be evaluated by posix shells
+pub fn to_shell(values: &IndexMap<String, Value>) -> String {
+  values
+    .iter()
+    .map(|(name, value)| {
+      let value = match value {
+        Value::String(value) => value.clone(),
+        value => value.to_string(),
+      };
+      format!("export {}='{}'\n", name, value.replace('\'', "'\\''"))
+    })
+    .collect()
+}
+
+pub fn write_dotenv(path: &str, values: &IndexMap<String, Value>) -> Result<()> {
+  std::fs::write(path, to_dotenv(values)).map_err(|e| anyhow::anyhow!("Failed to write {}\ncause: {}", path, e))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use indexmap::indexmap;
+  use serde_json::json;
+  use test_case::test_case;
+
+  #[test_case(json!("abc.def-123") => "abc.def-123" ; "plain")]
+  #[test_case(json!("Bearer abc") => "\"Bearer abc\"" ; "spaces")]
+  #[test_case(json!("a\"$b\nc") => "\"a\\\"\\$b\\nc\"" ; "escaped")]
+  #[test_case(json!("") => "\"\"" ; "empty")]
+  #[test_case(json!(42) => "42" ; "number")]
+  #[test_case(json!({"id": 1}) => "\"{\\\"id\\\":1}\"" ; "object")]
+  fn test_format_value(value: Value) -> String {
+    format_value(&value)
+  }
+
+  #[test]
+  fn test_shell_quoting() {
+    let values = indexmap! { "TOKEN".to_string() => json!("it's $HOME") };
+    assert_eq!(to_shell(&values), "export TOKEN='it'\\''s $HOME'\n");
+  }
+
+  #[test]
+  fn test_capture_exports() {
+    let response = json!({
+      "status": 200,
+      "headers": { "x-request-id": "42" },
+      "body": { "access_token": "abc", "expires_in": 3600 }
+    });
+    let exports = indexmap! {
+      "TOKEN".to_string() => "body.access_token".to_string(),
+      "EXPIRES_IN".to_string() => "body.expires_in".to_string(),
+      "REQUEST_ID".to_string() => "headers[\"x-request-id\"]".to_string(),
+    };
+    let values = capture_exports(&exports, &response).unwrap();
+    assert_eq!(to_dotenv(&values), "TOKEN=abc\nEXPIRES_IN=3600\nREQUEST_ID=42\n");
+    assert_eq!(
+      to_shell(&values),
+      "export TOKEN='abc'\nexport EXPIRES_IN='3600'\nexport REQUEST_ID='42'\n"
+    );
+    let invalid = indexmap! { "MY-TOKEN".to_string() => "body.access_token".to_string() };
+    assert!(capture_exports(&invalid, &response).is_err());
+    let missing = indexmap! { "TOKEN".to_string() => "body.token".to_string() };
+    assert!(capture_exports(&missing, &response).is_err());
+  }
+}
@@ -1,5 +1,12 @@
+use super::diff::{diff_values, to_diff_text};
+use super::display::pretty_print;
+use super::paths::lock_manifests;
+use super::validators::validate_manifest;
 use anyhow::Result;
+use dialoguer::{theme::ColorfulTheme, Confirm};
+use serde_json::Value;
 use std::ffi::OsString;
+use std::path::{Path, PathBuf};
 
 // get the user default editor
 fn get_default_editor() -> OsString {
@@ -16,9 +23,158 @@ fn get_default_editor() -> OsString {
   }
 }
 
+// split editor command into program and arguments, shell-words style (eg: `code --wait`)
+// an existing path is kept whole, and windows backslashes are not treated as escapes
+fn parse_editor(editor: &str) -> Vec<String> {
+  if Path::new(editor).exists() {
+    return vec![editor.to_string()];
+  }
+  let command = if cfg!(windows) {
+    editor.replace('\\', "\\\\")
+  } else {
+    editor.to_string()
+  };
+  match shell_words::split(&command) {
+    Ok(words) if !words.is_empty() => words,
+    _ => vec![editor.to_string()],
+  }
+}
+
 // edit file with default editor
 pub fn edit_file(file: &str) -> Result<()> {
   let editor = get_default_editor();
-  std::process::Command::new(&editor).arg(file).spawn()?.wait()?;
+  let mut words = parse_editor(&editor.to_string_lossy()).into_iter();
+  let program = words.next().unwrap_or_default();
+  std::process::Command::new(&program)
+    .args(words)
+    .arg(file)
+    .spawn()
+    .map_err(|e| anyhow::anyhow!("Could not launch editor '{}'\ncause: {}", program, e))?
+    .wait()?;
   Ok(())
 }
+
+// temporary copy keeps the original file name, so editors still detect yaml syntax
+fn temporary_copy_path(file: &str) -> PathBuf {
+  let filename = Path::new(file)
+    .file_name()
+    .map(|name| name.to_string_lossy().to_string())
+    .unwrap_or_else(|| "manifest.yaml".to_string());
+  std::env::temp_dir().join(format!("apix-{}-{}", uuid::Uuid::new_v4(), filename))
+}
+
+// structural changes between two manifest contents, rendered as diff text
+fn manifest_changes(original: &str, edited: &str) -> String {
+  let original = serde_yaml::from_str::<Value>(original).unwrap_or(Value::Null);
+  let edited = serde_yaml::from_str::<Value>(edited).unwrap_or(Value::Null);
+  to_diff_text(&diff_values(&original, &edited))
+}
+
+fn edit_copy(file: &str, copy: &Path, theme: &str, enable_color: bool) -> Result<bool> {
+  let original = std::fs::read_to_string(file).map_err(|e| anyhow::anyhow!("Could not read {}\ncause: {}", file, e))?;
+  std::fs::write(copy, &original)?;
+  loop {
+    edit_file(&copy.to_string_lossy())?;
+    let edited = std::fs::read_to_string(copy)?;
+    if edited == original {
+      eprintln!("{} unchanged", file);
+      return Ok(false);
+    }
+    match validate_manifest(&edited) {
+      Ok(_) => {
+        let changes = manifest_changes(&original, &edited);
+        if !changes.is_empty() {
+          pretty_print(changes, theme, "diff", enable_color)?;
+        }
+        // manifests are locked once edited, never overwriting changes saved meanwhile by another apix process
+        let _lock = lock_manifests()?;
+        if std::fs::read_to_string(file).ok().as_deref() != Some(original.as_str()) {
+          return Err(anyhow::anyhow!("{} was changed while editing, edition cancelled", file));
+        }
+        std::fs::write(file, edited).map_err(|e| anyhow::anyhow!("Failed to save {}\ncause: {}", file, e))?;
+        return Ok(true);
+      }
+      Err(err) => {
+        eprintln!("{}", err);
+        let reopen = atty::is(atty::Stream::Stdin)
+          && Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt("Re-open editor to fix errors?")
+            .default(true)
+            .interact()
+            .unwrap_or(false);
+        if !reopen {
+          return Err(anyhow::anyhow!("Edition cancelled, {} left unchanged", file));
+        }
+      }
+    }
+  }
+}
+
+// edit a manifest on a temporary copy, the file is only saved once the copy is a valid manifest
+// returns whether the manifest was changed
+pub fn edit_manifest(file: &str, theme: &str, enable_color: bool) -> Result<bool> {
+  let copy = temporary_copy_path(file);
+  let result = edit_copy(file, &copy, theme, enable_color);
+  std::fs::remove_file(&copy).ok();
+  result
+}
+
+// edit a json value in a temporary file, reopened until it parses and is accepted by `validate`
+pub fn edit_value(name: &str, initial: &Value, validate: impl Fn(&Value) -> Result<(), String>) -> Result<Value> {
+  let file = std::env::temp_dir().join(format!("apix-{}-{}.json", uuid::Uuid::new_v4(), name));
+  std::fs::write(&file, serde_json::to_string_pretty(initial)?)?;
+  let result = loop {
+    if let Err(err) = edit_file(&file.to_string_lossy()) {
+      break Err(err);
+    }
+    let edited = std::fs::read_to_string(&file)?;
+    let error = match serde_json::from_str::<Value>(&edited) {
+      Ok(value) => match validate(&value) {
+        Ok(()) => break Ok(value),
+        Err(err) => err,
+      },
+      Err(err) => format!("Invalid json\ncause: {}", err),
+    };
+    eprintln!("{}", error);
+    let reopen = Confirm::with_theme(&ColorfulTheme::default())
+      .with_prompt("Re-open editor to fix errors?")
+      .default(true)
+      .interact()
+      .unwrap_or(false);
+    if !reopen {
+      break Err(anyhow::anyhow!("Edition of parameter {} cancelled", name));
+    }
+  };
+  std::fs::remove_file(&file).ok();
+  result
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use test_case::test_case;
+
+  #[test_case("vi" => vec!["vi"])]
+  #[test_case("code --wait" => vec!["code", "--wait"])]
+  #[test_case("'my editor' -n" => vec!["my editor", "-n"])]
+  #[test_case("emacsclient -c -a \"\"" => vec!["emacsclient", "-c", "-a", ""])]
+  fn test_parse_editor(editor: &str) -> Vec<String> {
+    parse_editor(editor)
+  }
+
+  #[test]
+  fn test_manifest_changes() {
+    let original = "kind: Request\nspec:\n  request:\n    method: get\n";
+    let edited = "kind: Request\nspec:\n  request:\n    method: post\n";
+    assert_eq!(
+      manifest_changes(original, edited),
+      "-spec.request.method: \"get\"\n+spec.request.method: \"post\"\n"
+    );
+  }
+
+  #[test]
+  fn test_temporary_copy_path_keeps_file_name() {
+    let path = temporary_copy_path("apix/get-user.yaml");
+    assert!(path.to_string_lossy().ends_with("-get-user.yaml"));
+  }
+}
@@ -0,0 +1,253 @@
+use super::manifests::ApixConfiguration;
+use anyhow::Result;
+use dialoguer::{theme::ColorfulTheme, Password};
+use once_cell::sync::Lazy;
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+use std::collections::HashMap;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+
+// prefix of encrypted files, followed by base64 of salt, nonce, tag and ciphertext
+static ENCRYPTED_PREFIX: &str = "apix-encrypted:v1:";
+static SALT_LENGTH: usize = 16;
+static NONCE_LENGTH: usize = 12;
+static TAG_LENGTH: usize = 16;
+static PBKDF2_ITERATIONS: usize = 100_000;
+static KEYRING_SERVICE: &str = "apix";
+
+// where the secret files are encrypted with comes from, set with the `encryption` config key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SecretSource {
+  // `APIX_PASSPHRASE` environment variable, or asked once per run
+  Passphrase,
+  // random secret generated once per project, stored in the os keyring
+  Keyring,
+}
+
+impl std::str::FromStr for SecretSource {
+  type Err = anyhow::Error;
+  fn from_str(value: &str) -> Result<Self> {
+    match value {
+      "passphrase" => Ok(Self::Passphrase),
+      "keyring" => Ok(Self::Keyring),
+      _ => Err(anyhow::anyhow!(
+        "Bad encryption: \"{}\", should be one of passphrase, keyring",
+        value
+      )),
+    }
+  }
+}
+
+fn derive_key(secret: &str, salt: &[u8]) -> Result<Vec<u8>> {
+  let mut key = vec![0u8; 32];
+  openssl::pkcs5::pbkdf2_hmac(
+    secret.as_bytes(),
+    salt,
+    PBKDF2_ITERATIONS,
+    openssl::hash::MessageDigest::sha256(),
+    &mut key,
+  )?;
+  Ok(key)
+}
+
+pub fn is_encrypted(content: &str) -> bool {
+  content.starts_with(ENCRYPTED_PREFIX)
+}
+
+// aes-256-gcm with a key derived from the secret and a random salt, so identical contents never look alike
+pub fn encrypt(plaintext: &[u8], secret: &str) -> Result<String> {
+  let mut salt = vec![0u8; SALT_LENGTH];
+  let mut nonce = vec![0u8; NONCE_LENGTH];
+  openssl::rand::rand_bytes(&mut salt)?;
+  openssl::rand::rand_bytes(&mut nonce)?;
+  let key = derive_key(secret, &salt)?;
+  let mut tag = vec![0u8; TAG_LENGTH];
+  let ciphertext = encrypt_aead(Cipher::aes_256_gcm(), &key, Some(&nonce), &[], plaintext, &mut tag)?;
+  let payload = [salt, nonce, tag, ciphertext].concat();
+  Ok(format!("{}{}\n", ENCRYPTED_PREFIX, base64::encode(payload)))
+}
+
+pub fn decrypt(content: &str, secret: &str) -> Result<Vec<u8>> {
+  let payload = content
+    .trim_end()
+    .strip_prefix(ENCRYPTED_PREFIX)
+    .and_then(|payload| base64::decode(payload).ok())
+    .filter(|payload| payload.len() >= SALT_LENGTH + NONCE_LENGTH + TAG_LENGTH)
+    .ok_or_else(|| anyhow::anyhow!("Encrypted content is corrupted"))?;
+  let (salt, payload) = payload.split_at(SALT_LENGTH);
+  let (nonce, payload) = payload.split_at(NONCE_LENGTH);
+  let (tag, ciphertext) = payload.split_at(TAG_LENGTH);
+  let key = derive_key(secret, salt)?;
+  decrypt_aead(Cipher::aes_256_gcm(), &key, Some(nonce), &[], ciphertext, tag)
+    .map_err(|_| anyhow::anyhow!("Could not decrypt, wrong passphrase or key"))
+}
+
+// keyring entries are per project, identified by its directory
+fn keyring_account() -> Result<String> {
+  Ok(std::env::current_dir()?.canonicalize()?.to_string_lossy().to_string())
+}
+
+fn keyring_lookup(account: &str) -> Result<Option<String>> {
+  let output = if cfg!(target_os = "macos") {
+    Command::new("security")
+      .args(["find-generic-password", "-s", KEYRING_SERVICE, "-a", account, "-w"])
+      .output()
+  } else if cfg!(windows) {
+    return Err(anyhow::anyhow!(
+      "Keyring encryption is not supported on windows, use `apix config set encryption passphrase`"
+    ));
+  } else {
+    Command::new("secret-tool")
+      .args(["lookup", "service", KEYRING_SERVICE, "account", account])
+      .output()
+  }
+  .map_err(|e| anyhow::anyhow!("Could not access the os keyring\ncause: {}", e))?;
+  let secret = String::from_utf8_lossy(&output.stdout).trim().to_string();
+  Ok(Some(secret).filter(|secret| output.status.success() && !secret.is_empty()))
+}
+
+// argument of a `security -i` command line
+fn security_quote(value: &str) -> String {
+  format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn keyring_store(account: &str, secret: &str) -> Result<()> {
+  // secret is written to stdin, arguments are visible to other users in the process list
+  let (mut command, input) = if cfg!(target_os = "macos") {
+    let mut command = Command::new("security");
+    command.arg("-i");
+    let input = format!(
+      "add-generic-password -s {} -a {} -w {}\n",
+      KEYRING_SERVICE,
+      security_quote(account),
+      security_quote(secret)
+    );
+    (command, input)
+  } else {
+    let mut command = Command::new("secret-tool");
+    command.args([
+      "store",
+      "--label",
+      "apix project key",
+      "service",
+      KEYRING_SERVICE,
+      "account",
+      account,
+    ]);
+    (command, secret.to_string())
+  };
+  let status = command
+    .stdin(Stdio::piped())
+    .spawn()
+    .and_then(|mut child| {
+      child.stdin.take().unwrap().write_all(input.as_bytes())?;
+      child.wait()
+    })
+    .map_err(|e| anyhow::anyhow!("Could not access the os keyring\ncause: {}", e))?;
+  match status.success() {
+    true => Ok(()),
+    false => Err(anyhow::anyhow!("Could not store the project key in the os keyring")),
+  }
+}
+
+fn keyring_secret() -> Result<String> {
+  let account = keyring_account()?;
+  if let Some(secret) = keyring_lookup(&account)? {
+    return Ok(secret);
+  }
+  let mut key = [0u8; 32];
+  openssl::rand::rand_bytes(&mut key)?;
+  let secret = key.iter().map(|byte| format!("{:02x}", byte)).collect::<String>();
+  keyring_store(&account, &secret)?;
+  Ok(secret)
+}
+
+fn passphrase_secret() -> Result<String> {
+  if let Ok(passphrase) = std::env::var("APIX_PASSPHRASE") {
+    return Ok(passphrase);
+  }
+  if !atty::is(atty::Stream::Stdin) {
+    return Err(anyhow::anyhow!(
+      "Missing passphrase of encrypted files, set APIX_PASSPHRASE"
+    ));
+  }
+  Ok(
+    Password::with_theme(&ColorfulTheme::default())
+      .with_prompt("Passphrase of encrypted files")
+      .interact()?,
+  )
+}
+
+// secrets are only resolved once per run and source, so a passphrase is not asked for each file
+fn secret(source: SecretSource) -> Result<String> {
+  static SECRETS: Lazy<Mutex<HashMap<SecretSource, String>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+  let mut secrets = SECRETS.lock().unwrap();
+  if let Some(secret) = secrets.get(&source) {
+    return Ok(secret.clone());
+  }
+  let secret = match source {
+    SecretSource::Passphrase => passphrase_secret()?,
+    SecretSource::Keyring => keyring_secret()?,
+  };
+  secrets.insert(source, secret.clone());
+  Ok(secret)
+}
+
+fn configured_source() -> Result<Option<SecretSource>> {
+  ApixConfiguration::once()
+    .get("encryption")
+    .filter(|value| !value.is_empty() && value.as_str() != "off")
+    .map(|value| value.parse())
+    .transpose()
+}
+
+// content to write at rest, encrypted when the `encryption` config key is set
+pub fn seal(content: &str) -> Result<String> {
+  match configured_source()? {
+    Some(source) => encrypt(content.as_bytes(), &secret(source)?),
+    None => Ok(content.to_string()),
+  }
+}
+
+// content read from disk, decrypted when it was written encrypted
+pub fn unseal(content: String) -> Result<String> {
+  if !is_encrypted(&content) {
+    return Ok(content);
+  }
+  let source = configured_source()?.unwrap_or(SecretSource::Passphrase);
+  Ok(String::from_utf8(decrypt(&content, &secret(source)?)?)?)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_encrypt_decrypt() {
+    let encrypted = encrypt(b"headers:\n  authorization: Bearer token\n", "secret").unwrap();
+    assert!(is_encrypted(&encrypted));
+    assert!(!encrypted.contains("Bearer"));
+    assert_ne!(
+      encrypted,
+      encrypt(b"headers:\n  authorization: Bearer token\n", "secret").unwrap()
+    );
+    assert_eq!(
+      decrypt(&encrypted, "secret").unwrap(),
+      b"headers:\n  authorization: Bearer token\n"
+    );
+    assert!(decrypt(&encrypted, "other").is_err());
+    assert!(decrypt("apix-encrypted:v1:AAAA", "secret").is_err());
+  }
+
+  #[test]
+  fn test_security_quote() {
+    assert_eq!(security_quote("/home/my \"apis\""), "\"/home/my \\\"apis\\\"\"");
+    assert_eq!(security_quote("c:\\apis"), "\"c:\\\\apis\"");
+  }
+
+  #[test]
+  fn test_unseal_plain_content() {
+    assert_eq!(unseal("cookies: {}\n".to_string()).unwrap(), "cookies: {}\n");
+  }
+}
@@ -1,16 +1,42 @@
-use crate::manifests::ApixRequest;
-use crate::requests::{make_request, AdvancedBody, RequestOptions};
+use crate::assertions::{check_assertions, Assertion};
+use crate::auth::apply_auth;
+use crate::bench::{print_stage_reports, run_profile};
+use crate::certificates::split_pins;
+use crate::diff::{diff_values, to_diff_text};
+use crate::display::pretty_print;
+use crate::dotenv::{capture_exports, to_shell, write_dotenv};
+use crate::history::{self, HistoryEntry, Redactor, NO_HISTORY_ANNOTATION};
+use crate::http_utils::{merge_url_queries, resolve_idempotency_key, BodyFormat};
+use crate::manifests::{ApixApi, ApixParameter, ApixRequest};
+use crate::metrics::{push_metrics, save_metrics};
+use crate::progress_component::BulkProgressComponent;
+use crate::project_context::ApixProjectContext;
+use crate::report::{print_reports, save_reports, ExecutionReport, ExecutionStatus, RepeatStats};
+use crate::requests::{
+  fetch_response, is_http_error, make_request, print_text_body, AdvancedBody, OutputBuffer, RequestOptions,
+  ResponseMetrics, RetryPolicy, DEFAULT_MAX_RETRIES,
+};
+use crate::signing::RequestSigner;
+use crate::story::run_story;
+use crate::transform::ResponseTransform;
+use crate::validators::{is_template, validate_response_schema, validate_url};
 
 use super::dialog::Dialog;
-use super::template::{MapTemplate, StringTemplate, ValueTemplate};
-use super::{ApixKind, ApixManifest};
+use super::template::{
+  register_shared_templates, request_engine, MapTemplate, StringTemplate, ValueTemplate, URL_TEMPLATE_SUFFIX,
+};
+use super::{ApixConfiguration, ApixKind, ApixManifest};
 use anyhow::Result;
+use futures::stream::{self, StreamExt};
 use indexmap::IndexMap;
 use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
 use serde_json::Value;
 use std::collections::HashMap;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
 use tera::{Context, Tera};
+use url::{Position, Url};
 
 struct RequestTemplate<'a> {
   request: &'a ApixRequest,
@@ -18,6 +44,7 @@ struct RequestTemplate<'a> {
   context: Context,
   file: &'a str,
   annotations: IndexMap<String, String>,
+  api: Option<ApixApi>,
 }
 
 #[derive(Debug, Clone)]
@@ -25,31 +52,50 @@ struct RequestParams<'a> {
   url: String,
   method: String,
   headers: HeaderMap,
-  queries: IndexMap<String, String>,
+  queries: Vec<(String, String)>,
   body: Option<AdvancedBody>,
   options: RequestOptions<'a>,
 }
 
-// ask for all parameters in manifest request
-fn ask_for_required_parameters(
-  request: &ApixRequest,
-  params: &Option<IndexMap<String, String>>,
+// `urlsafe` config enables url encoding of values substituted in url templates
+fn urlsafe_urls() -> bool {
+  ApixConfiguration::once().get("urlsafe").map(String::as_str) == Some("true")
+}
+
+// ask for all parameters in manifest request, interactive mode asks for every parameter with given ones as defaults
+// parameter schemas are resolved against the components of the api first
+pub fn ask_for_required_parameters(
+  parameters: &[ApixParameter],
+  api: Option<&ApixApi>,
+  params: &Option<IndexMap<String, Value>>,
+  interactive: bool,
 ) -> Result<serde_json::Map<String, serde_json::Value>, anyhow::Error> {
+  let parameters = parameters
+    .iter()
+    .map(|parameter| parameter.with_resolved_schema(api))
+    .collect::<Result<Vec<_>>>()?;
+  if interactive {
+    return parameters
+      .iter()
+      .map(|parameter| {
+        let current = params.as_ref().and_then(|params| params.get(&parameter.name)).cloned();
+        Ok((parameter.name.clone(), parameter.ask_with_default(current.as_ref())?))
+      })
+      .collect();
+  }
   match params {
-    Some(params) => request
-      .parameters
+    Some(params) => parameters
       .iter()
       .filter(|param| param.required || params.get(&param.name).is_some())
       .map(|parameter| {
         if let Some(param) = params.get(&parameter.name) {
-          Ok((parameter.name.clone(), Value::String(param.clone())))
+          Ok((parameter.name.clone(), param.clone()))
         } else {
           Ok((parameter.name.clone(), parameter.ask()?))
         }
       })
       .collect(),
-    None => request
-      .parameters
+    None => parameters
       .iter()
       .filter(|param| param.required)
       .map(|parameter| Ok((parameter.name.clone(), parameter.ask()?)))
@@ -58,12 +104,24 @@ fn ask_for_required_parameters(
 }
 
 impl<'a> RequestTemplate<'a> {
-  fn new(manifest: &'a ApixManifest, file: &'a str, params: &Option<IndexMap<String, String>>) -> Result<Self> {
+  fn new(
+    manifest: &'a ApixManifest,
+    file: &'a str,
+    params: &Option<IndexMap<String, Value>>,
+    interactive: bool,
+  ) -> Result<Self> {
     match manifest.kind() {
       ApixKind::Request(request) => {
-        let parameters = Value::Object(ask_for_required_parameters(request, params)?);
+        let api = manifest.linked_api();
+        let parameters = Value::Object(ask_for_required_parameters(
+          &request.parameters,
+          api.as_ref(),
+          params,
+          interactive,
+        )?);
         let env: HashMap<String, String> = std::env::vars().collect();
-        let mut engine = Tera::default();
+        let mut engine = request_engine(urlsafe_urls());
+        register_shared_templates(&mut engine)?;
         let mut context = Context::new();
 
         context.insert("manifest", &manifest);
@@ -82,6 +140,7 @@ impl<'a> RequestTemplate<'a> {
           context,
           file,
           annotations,
+          api,
         })
       }
       _ => Err(anyhow::anyhow!("Request manifest expected")),
@@ -91,10 +150,12 @@ impl<'a> RequestTemplate<'a> {
   fn render_context(&mut self) -> Result<&mut Self> {
     let rendered_context = self.engine.render_value(
       &format!("{}#/context", self.file),
-      &Value::Object(serde_json::Map::from_iter(self.request.context.clone().into_iter())),
+      &Value::Object(serde_json::Map::from_iter(self.request.context.clone())),
       &self.context,
     )?;
-    self.context.insert("context", &rendered_context);
+    self
+      .context
+      .insert("context", &ApixProjectContext::load()?.merge(&rendered_context));
     Ok(self)
   }
 
@@ -103,25 +164,41 @@ impl<'a> RequestTemplate<'a> {
     let proxy_url = self.annotations.get("apix.io/proxy-url").map(String::to_owned);
     let proxy_login = self.annotations.get("apix.io/proxy-login").map(String::to_owned);
     let proxy_password = self.annotations.get("apix.io/proxy-password").map(String::to_owned);
+    let idempotency_key = self
+      .annotations
+      .get("apix.io/idempotency-key")
+      .map(|key| resolve_idempotency_key(key));
+    let pins = self
+      .annotations
+      .get("apix.io/pin")
+      .map(|pins| split_pins(pins))
+      .unwrap_or_default();
     let options = options.clone();
     RequestOptions {
       output_filename: options.output_filename.or(output_filename),
       proxy_url: options.proxy_url.or(proxy_url),
       proxy_login: options.proxy_login.or(proxy_login),
       proxy_password: options.proxy_password.or(proxy_password),
+      idempotency_key: options.idempotency_key.or(idempotency_key),
+      pins: if options.pins.is_empty() { pins } else { options.pins },
       ..options
     }
   }
 
   fn render_url(&mut self) -> Result<String> {
-    self
+    tracing::debug!(template = %format!("{}{}", self.file, URL_TEMPLATE_SUFFIX), "render template");
+    self.engine.add_raw_template(
+      &format!("{}{}", self.file, URL_TEMPLATE_SUFFIX),
+      &self.request.request.url,
+    )?;
+    let url = self
       .engine
-      .add_raw_template(&format!("{}#/url", self.file), &self.request.request.url)?;
-    let url = self.engine.render(&format!("{}#/url", self.file), &self.context)?;
+      .render(&format!("{}{}", self.file, URL_TEMPLATE_SUFFIX), &self.context)?;
     Ok(url)
   }
 
   fn render_method(&mut self) -> Result<String> {
+    tracing::debug!(template = %format!("{}#/method", self.file), "render template");
     self
       .engine
       .add_raw_template(&format!("{}#/method", self.file), &self.request.request.method)?;
@@ -149,12 +226,16 @@ impl<'a> RequestTemplate<'a> {
     Ok(headers)
   }
 
-  fn render_queries(&mut self) -> Result<IndexMap<String, String>> {
-    let queries = self.engine.render_map(
-      &format!("{}#/queries", self.file),
-      &self.request.request.queries,
-      &self.context,
-    )?;
+  fn render_queries(&mut self) -> Result<Vec<(String, String)>> {
+    let mut queries = Vec::new();
+    for (key, value) in &self.request.request.queries {
+      for value in value.values() {
+        let rendered = self
+          .engine
+          .render_string(&format!("{}#/queries.{}", self.file, key), value, &self.context)?;
+        queries.push((key.clone(), rendered));
+      }
+    }
     Ok(queries)
   }
 
@@ -183,13 +264,43 @@ impl<'a> RequestTemplate<'a> {
     }
   }
 
-  fn render_request_params(&mut self, options: &RequestOptions<'a>) -> Result<RequestParams> {
+  fn render_request_params(&mut self, options: &RequestOptions<'a>) -> Result<RequestParams<'a>> {
     let url = self.render_url()?;
     let method = self.render_method()?;
-    let headers = self.render_headers()?;
-    let queries = self.render_queries()?;
+    let mut headers = self.render_headers()?;
+    let mut queries = self.render_queries()?;
+    if let Some(auth) = self.api.as_ref().and_then(|api| api.auth.as_ref()) {
+      apply_auth(auth, &mut headers, &mut queries, |name, value| {
+        Ok(
+          self
+            .engine
+            .render_string(&format!("{}#/auth/{}", self.file, name), value, &self.context)?,
+        )
+      })?;
+    }
     let body = self.render_body()?;
-    let options = self.render_options(options);
+    let mut options = self.render_options(options);
+    if let Some(signing) = self.api.as_ref().and_then(|api| api.signing.clone()) {
+      let key = self
+        .engine
+        .render_string(&format!("{}#/signing/key", self.file), &signing.key, &self.context)?;
+      options.signer = Some(RequestSigner::new(signing, key));
+    }
+    apply_api_accept(self.api.as_ref(), &mut options);
+    if !self.request.retry_on.is_empty() {
+      options.retry = Some(RetryPolicy {
+        statuses: self.request.retry_on.clone(),
+        max_retries: self.request.max_retries.unwrap_or(DEFAULT_MAX_RETRIES),
+      });
+    }
+    if let Some(transform) = &self.request.transform {
+      options.transform = Some(ResponseTransform::new(
+        self.engine.clone(),
+        self.context.clone(),
+        format!("{}#/transform", self.file),
+        transform,
+      )?);
+    }
     Ok(RequestParams {
       url,
       method,
@@ -201,15 +312,57 @@ impl<'a> RequestTemplate<'a> {
   }
 }
 
+// the api accept replaces the configured default, but not the one of `--json`/`--form`, request headers override both
+pub fn apply_api_accept(api: Option<&ApixApi>, options: &mut RequestOptions) {
+  if options.body_format.is_some() {
+    return;
+  }
+  if let Some(accept) = api.and_then(|api| api.accept.clone()) {
+    options.accept = Some(accept);
+  }
+}
+
+// lightweight performance check, a slow request only prints a warning in soft mode
+pub fn check_duration(duration_ms: u128, max_duration_ms: u64, soft: bool) -> Result<()> {
+  let check = format!("duration {}ms <= {}ms", duration_ms, max_duration_ms);
+  match (duration_ms <= max_duration_ms as u128, soft) {
+    (true, _) => eprintln!("✔ {}", check),
+    (false, true) => eprintln!("⚠ {}", check),
+    (false, false) => {
+      eprintln!("✘ {}", check);
+      return Err(anyhow::anyhow!(
+        "Request took {}ms, more than the expected {}ms",
+        duration_ms,
+        max_duration_ms
+      ));
+    }
+  }
+  Ok(())
+}
+
 pub async fn handle_execute(
   file: &str,
   manifest: &ApixManifest,
-  params: Option<IndexMap<String, String>>,
+  params: Option<IndexMap<String, Value>>,
   options: RequestOptions<'_>,
-) -> Result<()> {
-  let mut template = RequestTemplate::new(manifest, file, &params)?;
+) -> Result<ResponseMetrics> {
+  let mut template = RequestTemplate::new(manifest, file, &params, options.interactive)?;
+  let assertions = template
+    .request
+    .assertions
+    .iter()
+    .map(|assertion| Assertion::from_str(assertion))
+    .collect::<Result<Vec<_>>>()
+    .map_err(|e| anyhow::anyhow!("Invalid assertion in {}\ncause: {}", file, e))?;
   let params = template.render_context()?.render_request_params(&options)?;
-  make_request(
+  let recorded = (!template.annotations.contains_key(NO_HISTORY_ANNOTATION)).then(|| {
+    (
+      params.method.clone(),
+      merge_url_queries(&params.url, Some(&params.queries)).map(|url| url.to_string()),
+      params.headers.clone(),
+    )
+  });
+  let metrics = make_request(
     &params.url,
     &params.method,
     Some(&params.headers),
@@ -217,5 +370,501 @@ pub async fn handle_execute(
     params.body,
     params.options,
   )
-  .await
+  .await?;
+  if let Some((method, url, headers)) = recorded {
+    let mut redactor = Redactor::from_config()?;
+    let parameters = match template.context.get("parameters") {
+      Some(Value::Object(parameters)) => Some(parameters),
+      _ => None,
+    };
+    if let Some(parameters) = parameters {
+      redactor = redactor.with_secrets(parameters, &template.request.parameters);
+    }
+    let mut entry = HistoryEntry::new(&method, &url?, &metrics, &redactor)
+      .with_request(manifest.name())
+      .with_headers(&headers, &redactor);
+    if let Some(parameters) = parameters {
+      entry = entry.with_parameters(parameters, &template.request.parameters, &redactor);
+    }
+    history::record(entry);
+  }
+  if !assertions.is_empty() {
+    let response = metrics
+      .response
+      .as_ref()
+      .ok_or_else(|| anyhow::anyhow!("Assertions can't be checked on binary responses"))?;
+    check_assertions(&assertions, &response.to_json())?;
+  }
+  if let Some(max_duration_ms) = template
+    .request
+    .expect
+    .as_ref()
+    .and_then(|expect| expect.max_duration_ms)
+  {
+    check_duration(metrics.duration.as_millis(), max_duration_ms, options.soft)?;
+  }
+  if let Some(schema) = &template.request.response_schema {
+    let response = metrics
+      .response
+      .as_ref()
+      .ok_or_else(|| anyhow::anyhow!("Response schema can't be checked on binary responses"))?;
+    validate_response_schema(schema, &response.to_json()["body"])?;
+  }
+  Ok(metrics)
+}
+
+// execute a request `repeat` times in a row, parameters are asked once, failures are counted instead of stopping
+pub async fn handle_execute_repeat(
+  file: &str,
+  manifest: &ApixManifest,
+  params: Option<IndexMap<String, Value>>,
+  options: RequestOptions<'_>,
+  repeat: usize,
+) -> Result<()> {
+  let params = match manifest.kind() {
+    ApixKind::Request(request) => ask_for_required_parameters(
+      &request.parameters,
+      manifest.linked_api().as_ref(),
+      &params,
+      options.interactive,
+    )?,
+    _ => return Err(anyhow::anyhow!("Request manifest expected")),
+  };
+  let params = Some(params.into_iter().collect::<IndexMap<_, _>>());
+  let options = RequestOptions {
+    interactive: false,
+    ..options
+  };
+  let mut stats = RepeatStats::default();
+  for _ in 0..repeat {
+    let start = Instant::now();
+    let result = handle_execute(file, manifest, params.clone(), options.clone()).await;
+    stats.record_result(&result, start);
+  }
+  stats.check()
+}
+
+// values exported by a request, for steps run after apix
+pub fn request_exports(manifest: &ApixManifest, metrics: &ResponseMetrics) -> Result<IndexMap<String, Value>> {
+  let exports = match manifest.kind() {
+    ApixKind::Request(request) if !request.exports.is_empty() => &request.exports,
+    _ => return Err(anyhow::anyhow!("Request {} has no exports", manifest.name())),
+  };
+  let response = metrics
+    .response
+    .as_ref()
+    .ok_or_else(|| anyhow::anyhow!("Exports can't be captured on binary responses"))?;
+  capture_exports(exports, &response.to_json())
+}
+
+// write exports to a dotenv file and/or print them as shell statements
+pub fn handle_exports(
+  manifest: &ApixManifest,
+  metrics: &ResponseMetrics,
+  env_file: Option<&str>,
+  shell: bool,
+) -> Result<()> {
+  if env_file.is_none() && !shell {
+    return Ok(());
+  }
+  let exports = request_exports(manifest, metrics)?;
+  if let Some(env_file) = env_file {
+    write_dotenv(env_file, &exports)?;
+  }
+  if shell {
+    print!("{}", to_shell(&exports));
+  }
+  Ok(())
+}
+
+// run the load profile of a request, the request is rendered once and its responses are not displayed
+pub async fn handle_bench(
+  file: &str,
+  manifest: &ApixManifest,
+  params: Option<IndexMap<String, Value>>,
+  options: RequestOptions<'_>,
+) -> Result<()> {
+  let mut template = RequestTemplate::new(manifest, file, &params, options.interactive)?;
+  let profile = template
+    .request
+    .load
+    .clone()
+    .ok_or_else(|| anyhow::anyhow!("Request {} has no load profile", manifest.name()))?;
+  let params = &template.render_context()?.render_request_params(&options)?;
+  let reports = run_profile(&profile, move || async move {
+    fetch_response(
+      &params.url,
+      &params.method,
+      Some(&params.headers),
+      Some(&params.queries),
+      params.body.clone(),
+      params.options.clone(),
+    )
+    .await
+    .is_ok_and(|response| !is_http_error(response.status))
+  })
+  .await?;
+  print_stage_reports(&reports);
+  Ok(())
+}
+
+// one side of a response comparison
+#[derive(Debug, Clone, Default)]
+pub struct DiffSide {
+  pub base_url: Option<String>,
+  pub params: Option<IndexMap<String, Value>>,
+}
+
+// renders values of ad-hoc requests with `parameters` and `env`, values without 'Tera' expressions are kept as is
+pub struct AdhocTemplate {
+  engine: Tera,
+  context: Context,
+}
+
+impl AdhocTemplate {
+  pub fn new(params: Option<&IndexMap<String, Value>>) -> Result<Self> {
+    let env: HashMap<String, String> = std::env::vars().collect();
+    let mut engine = request_engine(urlsafe_urls());
+    register_shared_templates(&mut engine)?;
+    let mut context = Context::new();
+    context.insert("parameters", &params.cloned().unwrap_or_default());
+    context.insert("env", &env);
+    Ok(Self { engine, context })
+  }
+
+  pub fn render(&mut self, name: &str, content: &str) -> Result<String> {
+    if is_template(content) {
+      Ok(self.engine.render_string(name, content, &self.context)?)
+    } else {
+      Ok(content.to_string())
+    }
+  }
+
+  // rendered url must be a valid http(s) url
+  pub fn render_url(&mut self, url: &str) -> Result<String> {
+    let rendered = self.render(&format!("cli{}", URL_TEMPLATE_SUFFIX), url)?;
+    validate_url(&rendered)?;
+    Ok(rendered)
+  }
+
+  pub fn render_body(
+    &mut self,
+    body: Option<AdvancedBody>,
+    format: Option<BodyFormat>,
+  ) -> Result<Option<AdvancedBody>> {
+    match (body, format) {
+      (Some(AdvancedBody::String(body)), Some(format)) => Ok(Some(AdvancedBody::String(
+        format.serialize(&self.render("body", &body)?)?,
+      ))),
+      (Some(AdvancedBody::String(body)), None) => Ok(Some(AdvancedBody::String(self.render("body", &body)?))),
+      (body, _) => Ok(body),
+    }
+  }
+}
+
+// replace scheme, host and port of an url by the ones of a base url, keeping base url path as a prefix
+fn rebase_url(url: &str, base_url: &str) -> Result<String> {
+  let url = Url::parse(url)?;
+  Ok(format!(
+    "{}{}",
+    base_url.trim_end_matches('/'),
+    &url[Position::BeforePath..]
+  ))
+}
+
+async fn fetch_side(
+  file: &str,
+  manifest: &ApixManifest,
+  params: &Option<IndexMap<String, Value>>,
+  side: DiffSide,
+  options: &RequestOptions<'_>,
+) -> Result<Value> {
+  let params = match (params.clone(), side.params) {
+    (Some(mut params), Some(side_params)) => {
+      params.extend(side_params);
+      Some(params)
+    }
+    (params, side_params) => params.or(side_params),
+  };
+  let mut template = RequestTemplate::new(manifest, file, &params, options.interactive)?;
+  let params = template.render_context()?.render_request_params(options)?;
+  let url = match side.base_url {
+    Some(base_url) => rebase_url(&params.url, &base_url)?,
+    None => params.url,
+  };
+  let response = fetch_response(
+    &url,
+    &params.method,
+    Some(&params.headers),
+    Some(&params.queries),
+    params.body,
+    params.options,
+  )
+  .await?;
+  Ok(response.to_json())
+}
+
+// execute a request manifest against two environments and print a structural diff of the responses
+pub async fn handle_diff(
+  file: &str,
+  manifest: &ApixManifest,
+  params: Option<IndexMap<String, Value>>,
+  sides: (DiffSide, DiffSide),
+  options: RequestOptions<'_>,
+) -> Result<()> {
+  let left = fetch_side(file, manifest, &params, sides.0, &options).await?;
+  let right = fetch_side(file, manifest, &params, sides.1, &options).await?;
+  let differences = diff_values(&left, &right);
+  if differences.is_empty() {
+    println!("Responses are identical");
+    return Ok(());
+  }
+  pretty_print(
+    to_diff_text(&differences),
+    options.theme,
+    "diff",
+    options.is_output_terminal,
+  )?;
+  Err(anyhow::anyhow!(
+    "Found {} differences between responses",
+    differences.len()
+  ))
+}
+
+// expand manifest file arguments, glob patterns are resolved from the current directory
+pub fn expand_manifest_files<'a>(patterns: impl Iterator<Item = &'a str>) -> Result<Vec<String>> {
+  let mut files = Vec::new();
+  for pattern in patterns {
+    if pattern.contains(['*', '?', '[', '{']) {
+      let mut matches = globwalk::glob(pattern)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| entry.path().to_str().map(str::to_string))
+        .collect::<Vec<_>>();
+      if matches.is_empty() {
+        return Err(anyhow::anyhow!("No manifest file matches pattern '{}'", pattern));
+      }
+      matches.sort();
+      files.extend(matches);
+    } else {
+      files.push(pattern.to_string());
+    }
+  }
+  Ok(files)
+}
+
+// reports of a manifest file, stories are reported step by step
+async fn execute_file(
+  file: &str,
+  params: &Option<IndexMap<String, Value>>,
+  options: &RequestOptions<'_>,
+) -> Vec<ExecutionReport> {
+  let start = Instant::now();
+  let (name, continue_on_error, result) = match ApixManifest::from_file(std::path::Path::new(file)) {
+    Ok(manifest) if manifest.kind().as_story().is_some() => {
+      match run_story(file, &manifest, params.clone(), options).await {
+        Ok(reports) => return reports,
+        Err(err) => (manifest.name().to_string(), false, Err(err)),
+      }
+    }
+    Ok(manifest) => (
+      manifest.name().to_string(),
+      matches!(manifest.kind(), ApixKind::Request(request) if request.continue_on_error),
+      handle_execute(file, &manifest, params.clone(), options.clone()).await,
+    ),
+    Err(err) => (String::new(), false, Err(err)),
+  };
+  vec![ExecutionReport {
+    file: file.to_string(),
+    name,
+    status: match result {
+      Ok(metrics) => ExecutionStatus::Success(metrics),
+      Err(err) if continue_on_error => ExecutionStatus::Ignored(err.to_string()),
+      Err(err) => ExecutionStatus::Failure(err.to_string()),
+    },
+    duration: start.elapsed(),
+  }]
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BulkOptions {
+  pub concurrency: usize,
+  pub report_file: Option<String>,
+  pub metrics_file: Option<String>,
+  pub pushgateway_url: Option<String>,
+  pub continue_on_error: bool,
+}
+
+impl BulkOptions {
+  // whether a run summary has to be exported somewhere
+  pub fn has_exports(&self) -> bool {
+    self.report_file.is_some() || self.metrics_file.is_some() || self.pushgateway_url.is_some()
+  }
+}
+
+// execute manifest files with at most `concurrency` requests in flight, skipping remaining ones after the
+// first failure unless `continue_on_error` is set, and print a consolidated report
+pub async fn handle_execute_files(
+  files: &[String],
+  params: Option<IndexMap<String, Value>>,
+  options: RequestOptions<'_>,
+  bulk_options: BulkOptions,
+) -> Result<()> {
+  let concurrency = bulk_options.concurrency.max(1);
+  let failed = AtomicBool::new(false);
+  // verbose output is printed as requests are sent, progress bars would be drawn over it
+  let progress = (concurrency > 1 && options.progress && !options.verbose)
+    .then(|| BulkProgressComponent::new(files.len(), concurrency));
+  let executions = stream::iter(files)
+    .map(|file| async {
+      if failed.load(Ordering::SeqCst) {
+        let skipped = ExecutionReport {
+          file: file.to_string(),
+          name: String::new(),
+          status: ExecutionStatus::Skipped,
+          duration: Default::default(),
+        };
+        return (vec![skipped], None);
+      }
+      let slot = progress.as_ref().and_then(|progress| progress.start(file));
+      // bodies would be drawn over progress bars, they are printed in files order once all are executed
+      let output_buffer = progress.as_ref().map(|_| OutputBuffer::default());
+      let options = RequestOptions {
+        output_buffer: output_buffer.clone(),
+        ..options.clone()
+      };
+      let reports = execute_file(file, &params, &options).await;
+      if reports.iter().any(ExecutionReport::is_failure) && !bulk_options.continue_on_error {
+        failed.store(true, Ordering::SeqCst);
+      }
+      if let Some(progress) = progress.as_ref() {
+        progress.finish(slot);
+      }
+      (reports, output_buffer)
+    })
+    .buffered(concurrency)
+    .collect::<Vec<_>>()
+    .await;
+  if let Some(progress) = progress {
+    progress.join().await?;
+  }
+  let mut reports = Vec::new();
+  for (file_reports, output_buffer) in executions {
+    for (body, language) in output_buffer
+      .into_iter()
+      .flat_map(|buffer| buffer.lock().unwrap().split_off(0))
+    {
+      print_text_body(&body, language.as_deref(), &options)?;
+    }
+    reports.extend(file_reports);
+  }
+  print_reports(&reports);
+  if let Some(report_file) = bulk_options.report_file {
+    save_reports(&reports, &report_file)?;
+  }
+  if let Some(metrics_file) = bulk_options.metrics_file {
+    save_metrics(&reports, &metrics_file)?;
+  }
+  if let Some(pushgateway_url) = bulk_options.pushgateway_url {
+    push_metrics(&reports, &pushgateway_url).await?;
+  }
+  let failed = reports
+    .iter()
+    .filter(|report| report.is_failure())
+    .map(|report| &report.file)
+    .collect::<std::collections::HashSet<_>>();
+  match failed.len() {
+    0 => Ok(()),
+    failed => Err(anyhow::anyhow!("{} of {} manifests failed", failed, files.len())),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use test_case::test_case;
+
+  #[test_case("https://prod.apix.io/users?id=1", "https://staging.apix.io" => "https://staging.apix.io/users?id=1")]
+  #[test_case("https://prod.apix.io/users", "http://localhost:8080/api/" => "http://localhost:8080/api/users")]
+  fn test_rebase_url(url: &str, base_url: &str) -> String {
+    rebase_url(url, base_url).unwrap()
+  }
+
+  #[test_case(None, Some("text/plain") => Some("application/xml".to_string()) ; "api over config")]
+  #[test_case(Some(BodyFormat::Json), Some("text/plain") => Some("text/plain".to_string()) ; "cli over api")]
+  #[test_case(None, None => Some("application/xml".to_string()) ; "api without config")]
+  fn test_apply_api_accept(body_format: Option<BodyFormat>, accept: Option<&str>) -> Option<String> {
+    let api = ApixApi {
+      accept: Some("application/xml".to_string()),
+      ..Default::default()
+    };
+    let mut options = RequestOptions {
+      body_format,
+      accept: accept.map(str::to_string),
+      ..Default::default()
+    };
+    apply_api_accept(Some(&api), &mut options);
+    options.accept
+  }
+
+  #[test_case(120, 500, false => true ; "fast")]
+  #[test_case(800, 500, false => false ; "slow")]
+  #[test_case(800, 500, true => true ; "slow in soft mode")]
+  fn test_check_duration(duration_ms: u128, max_duration_ms: u64, soft: bool) -> bool {
+    check_duration(duration_ms, max_duration_ms, soft).is_ok()
+  }
+
+  #[test]
+  fn test_expand_manifest_files_keeps_plain_paths() {
+    let files = expand_manifest_files(["examples/request.yaml", "missing.yaml"].into_iter()).unwrap();
+    assert_eq!(files, vec!["examples/request.yaml", "missing.yaml"]);
+  }
+
+  #[test]
+  fn test_expand_manifest_files_resolves_globs() {
+    let files = expand_manifest_files(["examples/postman-echo-p*.yaml"].into_iter()).unwrap();
+    assert_eq!(
+      files,
+      vec![
+        "./examples/postman-echo-patch.yaml",
+        "./examples/postman-echo-post-file.yaml",
+        "./examples/postman-echo-post.yaml",
+        "./examples/postman-echo-put.yaml",
+      ]
+    );
+  }
+
+  #[test]
+  fn test_expand_manifest_files_fails_without_match() {
+    assert!(expand_manifest_files(["examples/*.none"].into_iter()).is_err());
+  }
+
+  #[test]
+  fn test_render_repeated_queries() {
+    let manifest: ApixManifest = serde_yaml::from_str(
+      r#"
+apiVersion: apix.io/v1
+kind: Request
+metadata:
+  name: todos
+spec:
+  request:
+    method: get
+    url: https://jsonplaceholder.typicode.com/todos
+    queries:
+      tag: [a, "{{ env.APIX_TEST_UNSET | default(value='b') }}"]
+      _limit: "1"
+"#,
+    )
+    .unwrap();
+    let mut template = RequestTemplate::new(&manifest, "todos.yaml", &None, false).unwrap();
+    assert_eq!(
+      template.render_queries().unwrap(),
+      vec![
+        ("tag".to_string(), "a".to_string()),
+        ("tag".to_string(), "b".to_string()),
+        ("_limit".to_string(), "1".to_string()),
+      ]
+    );
+  }
 }
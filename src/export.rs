@@ -0,0 +1,243 @@
+use crate::hurl;
+use crate::manifests::{ApixKind, ApixManifest, ApixRequest};
+use anyhow::Result;
+use clap::ArgMatches;
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+use serde_json::Value;
+use std::path::PathBuf;
+
+// `{{ parameters.name }}`, `{{ context.name }}` and `{{ env.NAME }}` expressions, the only ones with a rest client equivalent
+pub static SIMPLE_VARIABLE: Lazy<Regex> =
+  Lazy::new(|| Regex::new(r"\{\{\s*(parameters|context|env)\.([A-Za-z_][A-Za-z0-9_]*)\s*\}\}").unwrap());
+
+// file variables are global to a rest client file, so they are prefixed with the request name to keep requests apart
+fn variable_prefix(name: &str) -> String {
+  let prefix: String = name
+    .chars()
+    .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+    .collect();
+  format!("{}_", prefix)
+}
+
+// rewrite apix variables as rest client ones, other tera expressions are kept untouched
+fn to_http_variables(template: &str, prefix: &str) -> String {
+  SIMPLE_VARIABLE
+    .replace_all(template, |captures: &Captures| match &captures[1] {
+      "env" => format!("{{{{$processEnv {}}}}}", &captures[2]),
+      _ => format!("{{{{{}{}}}}}", prefix, &captures[2]),
+    })
+    .into_owned()
+}
+
+fn value_to_string(value: &Value) -> String {
+  match value {
+    Value::String(value) => value.clone(),
+    value => value.to_string(),
+  }
+}
+
+// one `### name` block of a rest client `.http`/`.rest` file, with its variables declared first
+pub fn to_http(name: &str, request: &ApixRequest) -> Result<String> {
+  let prefix = variable_prefix(name);
+  let mut content = String::new();
+  for parameter in &request.parameters {
+    let default = parameter
+      .schema
+      .as_ref()
+      .and_then(|schema| schema.get("default"))
+      .map(value_to_string)
+      .unwrap_or_default();
+    content.push_str(&format!("@{}{} = {}\n", prefix, parameter.name, default));
+  }
+  for (key, value) in &request.context {
+    content.push_str(&format!(
+      "@{}{} = {}\n",
+      prefix,
+      key,
+      to_http_variables(&value_to_string(value), &prefix)
+    ));
+  }
+  if !content.is_empty() {
+    content.push('\n');
+  }
+  let template = &request.request;
+  content.push_str(&format!("### {}\n# @name {}\n", name, name));
+  let mut url = to_http_variables(&template.url, &prefix);
+  for (key, value) in &template.queries {
+    for value in value.values() {
+      url.push(if url.contains('?') { '&' } else { '?' });
+      url.push_str(&format!("{}={}", key, to_http_variables(value, &prefix)));
+    }
+  }
+  content.push_str(&format!("{} {}\n", template.method.to_uppercase(), url));
+  for (key, value) in &template.headers {
+    content.push_str(&format!("{}: {}\n", key, to_http_variables(value, &prefix)));
+  }
+  match &template.body {
+    Some(Value::String(body)) => content.push_str(&format!("\n{}\n", to_http_variables(body, &prefix))),
+    Some(body) => {
+      if !template
+        .headers
+        .keys()
+        .any(|key| key.eq_ignore_ascii_case("content-type"))
+      {
+        content.push_str("Content-Type: application/json\n");
+      }
+      content.push_str(&format!(
+        "\n{}\n",
+        to_http_variables(&serde_json::to_string_pretty(body)?, &prefix)
+      ));
+    }
+    None => {}
+  }
+  Ok(content)
+}
+
+fn export_requests(matches: &ArgMatches) -> Result<Vec<(String, ApixRequest)>> {
+  let manifests: Vec<ApixManifest> = match matches.value_of("name") {
+    Some(name) => vec![ApixManifest::find_manifest("request", name)
+      .map(|(_, manifest)| manifest)
+      .ok_or_else(|| anyhow::anyhow!("No resource of type request where found with name {}", name))?],
+    None => ApixManifest::find_manifests_by_kind("request")?
+      .map(|(_, manifest)| manifest)
+      .collect(),
+  };
+  Ok(
+    manifests
+      .into_iter()
+      .filter_map(|manifest| match manifest.kind() {
+        ApixKind::Request(request) => Some((manifest.name().to_string(), request.clone())),
+        _ => None,
+      })
+      .collect(),
+  )
+}
+
+// manifest files of a project as one multi-document yaml, kept verbatim so comments and labels are preserved
+pub fn to_bundle(documents: &[(PathBuf, String)]) -> String {
+  documents
+    .iter()
+    .map(|(path, content)| {
+      let content = content.trim_start_matches("---\n").trim_end();
+      format!("---\n# {}\n{}\n", path.display(), content)
+    })
+    .collect()
+}
+
+fn export_bundle(file: Option<&str>) -> Result<()> {
+  let current_dir = std::env::current_dir()?;
+  let output = file.map(|file| current_dir.join(file));
+  let mut documents = ApixManifest::find_manifests()?
+    .filter(|(path, _)| Some(path) != output.as_ref())
+    .map(|(path, _)| {
+      let content = std::fs::read_to_string(&path)
+        .map_err(|e| anyhow::anyhow!("Could not read {}\ncause: {}", path.display(), e))?;
+      Ok((path.strip_prefix(&current_dir).unwrap_or(&path).to_path_buf(), content))
+    })
+    .collect::<Result<Vec<_>>>()?;
+  documents.sort_by(|(a, _), (b, _)| a.cmp(b));
+  let bundle = to_bundle(&documents);
+  match file {
+    Some("-") | None => print!("{}", bundle),
+    Some(file) => {
+      std::fs::write(file, bundle).map_err(|e| anyhow::anyhow!("Failed to write bundle {}\ncause: {}", file, e))?;
+      eprintln!("Exported {} manifests to {}", documents.len(), file);
+    }
+  }
+  Ok(())
+}
+
+pub fn handle_export(matches: &ArgMatches) -> Result<()> {
+  let (format, matches) = match matches.subcommand() {
+    Some(("bundle", matches)) => return export_bundle(matches.value_of("file")),
+    Some(subcommand) => subcommand,
+    None => return Ok(()),
+  };
+  let requests = export_requests(matches)?;
+  let content = match format {
+    "http" => requests
+      .iter()
+      .map(|(name, request)| to_http(name, request))
+      .collect::<Result<Vec<_>>>()?
+      .join("\n"),
+    "hurl" => requests
+      .iter()
+      .map(|(name, request)| hurl::to_hurl(name, request))
+      .collect::<Result<Vec<_>>>()?
+      .join("\n"),
+    _ => return Err(anyhow::anyhow!("Unsupported export format {}", format)),
+  };
+  match matches.value_of("output-file") {
+    Some(file) => {
+      std::fs::write(file, content).map_err(|e| anyhow::anyhow!("Failed to write export {}\ncause: {}", file, e))?
+    }
+    None => print!("{}", content),
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::manifests::{ApixParameter, ApixRequestTemplate};
+  use indexmap::indexmap;
+  use serde_json::json;
+
+  #[test]
+  fn test_to_http_variables() {
+    assert_eq!(
+      to_http_variables(
+        "{{ context.url }}/todos/{{parameters.id}}?user={{ env.USER }}&next={{ parameters.id + 1 }}",
+        "todo_"
+      ),
+      "{{todo_url}}/todos/{{todo_id}}?user={{$processEnv USER}}&next={{ parameters.id + 1 }}"
+    );
+  }
+
+  #[test]
+  fn test_to_http() {
+    let request = ApixRequest::new(
+      vec![ApixParameter::new(
+        "id".to_string(),
+        true,
+        false,
+        None,
+        Some(json!({ "type": "integer", "default": 1 })),
+      )],
+      indexmap! { "url".to_string() => json!("https://example.com") },
+      ApixRequestTemplate::new(
+        "post".to_string(),
+        "{{ context.url }}/todos/{{ parameters.id }}".to_string(),
+        indexmap! { "Accept".to_string() => "application/json".to_string() },
+        vec![
+          ("tag".to_string(), "a".to_string()),
+          ("tag".to_string(), "b".to_string()),
+        ],
+        Some(json!({ "title": "{{ env.TITLE }}" })),
+      ),
+    );
+    assert_eq!(
+      to_http("create-todo", &request).unwrap(),
+      "@create_todo_id = 1\n@create_todo_url = https://example.com\n\n### create-todo\n# @name create-todo\nPOST {{create_todo_url}}/todos/{{create_todo_id}}?tag=a&tag=b\nAccept: application/json\nContent-Type: application/json\n\n{\n  \"title\": \"{{$processEnv TITLE}}\"\n}\n"
+    );
+  }
+
+  #[test]
+  fn test_to_bundle() {
+    let documents = vec![
+      (
+        PathBuf::from(".apix/api.yaml"),
+        "---\napiVersion: apix.io/v1\nkind: Api\n".to_string(),
+      ),
+      (
+        PathBuf::from("get-user.yaml"),
+        "# users\nkind: Request\nmetadata:\n  labels:\n    apix.io/api: api\n".to_string(),
+      ),
+    ];
+    assert_eq!(
+      to_bundle(&documents),
+      "---\n# .apix/api.yaml\napiVersion: apix.io/v1\nkind: Api\n---\n# get-user.yaml\n# users\nkind: Request\nmetadata:\n  labels:\n    apix.io/api: api\n"
+    );
+  }
+}
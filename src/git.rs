@@ -0,0 +1,54 @@
+use super::manifests::ApixConfiguration;
+use anyhow::{anyhow, Result};
+use cmd_lib::{run_cmd, run_fun};
+use std::path::Path;
+
+fn commit_message(action: &str, files: &[String]) -> String {
+  let names = files
+    .iter()
+    .map(|file| {
+      Path::new(file)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| file.to_string())
+    })
+    .collect::<Vec<_>>()
+    .join(", ");
+  format!("apix ctl {}: {}", action, names)
+}
+
+// stage and commit changed manifest files when `auto-commit` configuration is enabled
+pub fn auto_commit(action: &str, files: &[String]) -> Result<()> {
+  if ApixConfiguration::once().get("auto-commit").map(String::as_str) != Some("true") || files.is_empty() {
+    return Ok(());
+  }
+  if run_fun! {git rev-parse --is-inside-work-tree 2>/dev/null}.is_err() {
+    eprintln!("auto-commit skipped, current directory is not a git repository");
+    return Ok(());
+  }
+  let changes =
+    run_fun! {git status --porcelain -- $[files]}.map_err(|e| anyhow!("Failed to get git status\ncause: {}", e))?;
+  if changes.trim().is_empty() {
+    return Ok(());
+  }
+  let message = commit_message(action, files);
+  run_cmd! {
+    git add -A -- $[files];
+    git commit --quiet -m $message -- $[files]
+  }
+  .map_err(|e| anyhow!("Failed to commit manifest changes\ncause: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_commit_message() {
+    let files = vec!["/project/apix/get-user.yaml".to_string(), "story.yaml".to_string()];
+    assert_eq!(
+      commit_message("edit", &files),
+      "apix ctl edit: get-user.yaml, story.yaml"
+    );
+  }
+}
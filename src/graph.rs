@@ -0,0 +1,273 @@
+use crate::manifests::{ApixManifest, ApixStep, ApixStories, ApixStory};
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+// `steps.<name>.response<path>` references, a value captured from a previous step response
+static STEP_REFERENCE: Lazy<Regex> = Lazy::new(|| {
+  Regex::new(r#"steps\.([A-Za-z_][A-Za-z0-9_\-]*)\.response((?:\.[A-Za-z0-9_\-]+|\[[^\]]*\])*)"#).unwrap()
+});
+
+// value of a step flowing into another step
+#[derive(Debug, Clone, PartialEq)]
+pub struct StepFlow {
+  pub story: String,
+  pub step: String,
+  pub path: String,
+}
+
+fn step_templates(step: &ApixStep) -> Vec<String> {
+  let mut templates: Vec<String> = step
+    .request
+    .templates("request")
+    .into_iter()
+    .map(|(_, template)| template)
+    .collect();
+  templates.extend(step.context.values().cloned());
+  templates.extend(step.if_.iter().cloned());
+  templates.extend(step.assert_.iter().cloned());
+  templates.extend(step.assertions.iter().cloned());
+  templates
+}
+
+// values of previous steps used by a step, steps are looked up in its own story first, then in the other stories
+pub fn step_flows(stories: &ApixStories, story: &ApixStory, step: &ApixStep) -> Vec<StepFlow> {
+  let mut flows: Vec<StepFlow> = Vec::new();
+  for template in step_templates(step) {
+    for captures in STEP_REFERENCE.captures_iter(&template) {
+      let source = std::iter::once(story)
+        .chain(stories.stories.iter())
+        .find(|candidate| candidate.steps.iter().any(|candidate| candidate.name == captures[1]));
+      if let Some(source) = source {
+        let flow = StepFlow {
+          story: source.name.clone(),
+          step: captures[1].to_string(),
+          path: format!("response{}", &captures[2]),
+        };
+        if !flows.contains(&flow) {
+          flows.push(flow);
+        }
+      }
+    }
+  }
+  flows
+}
+
+fn dot_escape(value: &str) -> String {
+  value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn node_id(story: &str, step: &str) -> String {
+  format!("\"{}.{}\"", dot_escape(story), dot_escape(step))
+}
+
+// graphviz document with a cluster per story, sequence edges between steps, dashed `needs` edges between stories
+// and dotted edges for values flowing from a step response to another step
+pub fn to_dot(name: &str, stories: &ApixStories) -> String {
+  let mut dot = format!("digraph \"{}\" {{\n  compound=true;\n  rankdir=LR;\n", dot_escape(name));
+  for story in &stories.stories {
+    dot.push_str(&format!(
+      "  subgraph \"cluster_{}\" {{\n    label=\"{}\";\n",
+      dot_escape(&story.name),
+      dot_escape(&story.name)
+    ));
+    for step in &story.steps {
+      dot.push_str(&format!(
+        "    {} [shape=box, label=\"{}\\n{} {}\"];\n",
+        node_id(&story.name, &step.name),
+        dot_escape(&step.name),
+        step.request.method.to_uppercase(),
+        dot_escape(&step.request.url)
+      ));
+    }
+    for (previous, next) in story.steps.iter().zip(story.steps.iter().skip(1)) {
+      dot.push_str(&format!(
+        "    {} -> {};\n",
+        node_id(&story.name, &previous.name),
+        node_id(&story.name, &next.name)
+      ));
+    }
+    dot.push_str("  }\n");
+  }
+  for story in &stories.stories {
+    let needed = story
+      .needs
+      .as_ref()
+      .and_then(|needs| stories.stories.iter().find(|needed| &needed.name == needs));
+    if let (Some(needed), Some(first)) = (needed, story.steps.first()) {
+      if let Some(last) = needed.steps.last() {
+        dot.push_str(&format!(
+          "  {} -> {} [style=dashed, label=\"needs\", ltail=\"cluster_{}\", lhead=\"cluster_{}\"];\n",
+          node_id(&needed.name, &last.name),
+          node_id(&story.name, &first.name),
+          dot_escape(&needed.name),
+          dot_escape(&story.name)
+        ));
+      }
+    }
+    for step in &story.steps {
+      for flow in step_flows(stories, story, step) {
+        dot.push_str(&format!(
+          "  {} -> {} [style=dotted, color=blue, label=\"{}\"];\n",
+          node_id(&flow.story, &flow.step),
+          node_id(&story.name, &step.name),
+          dot_escape(&flow.path)
+        ));
+      }
+    }
+  }
+  dot.push_str("}\n");
+  dot
+}
+
+// ascii tree of stories and their steps, with the values each step uses from previous steps
+pub fn to_tree(name: &str, stories: &ApixStories) -> String {
+  let mut tree = format!("{}\n", name);
+  for (story_index, story) in stories.stories.iter().enumerate() {
+    let last_story = story_index + 1 == stories.stories.len();
+    let (branch, indent) = if last_story {
+      ("└── ", "    ")
+    } else {
+      ("├── ", "│   ")
+    };
+    match &story.needs {
+      Some(needs) => tree.push_str(&format!("{}{} (needs {})\n", branch, story.name, needs)),
+      None => tree.push_str(&format!("{}{}\n", branch, story.name)),
+    }
+    for (step_index, step) in story.steps.iter().enumerate() {
+      let last_step = step_index + 1 == story.steps.len();
+      let (step_branch, step_indent) = if last_step {
+        ("└── ", "    ")
+      } else {
+        ("├── ", "│   ")
+      };
+      tree.push_str(&format!(
+        "{}{}{} {} {}\n",
+        indent,
+        step_branch,
+        step.name,
+        step.request.method.to_uppercase(),
+        step.request.url
+      ));
+      let flows = step_flows(stories, story, step);
+      for (flow_index, flow) in flows.iter().enumerate() {
+        let flow_branch = if flow_index + 1 == flows.len() {
+          "└── "
+        } else {
+          "├── "
+        };
+        let source = if flow.story == story.name {
+          flow.step.clone()
+        } else {
+          format!("{}.{}", flow.story, flow.step)
+        };
+        tree.push_str(&format!(
+          "{}{}{}uses {}.{}\n",
+          indent, step_indent, flow_branch, source, flow.path
+        ));
+      }
+    }
+  }
+  tree
+}
+
+pub fn handle_graph(name: &str, format: &str) -> Result<()> {
+  let manifest = ApixManifest::find_manifest("story", name)
+    .map(|(_, manifest)| manifest)
+    .ok_or_else(|| anyhow::anyhow!("No resource of type story where found with name {}", name))?;
+  let stories = manifest
+    .kind()
+    .as_story()
+    .ok_or_else(|| anyhow::anyhow!("Resource {} is not a story", name))?;
+  match format {
+    "tree" => print!("{}", to_tree(name, stories)),
+    _ => print!("{}", to_dot(name, stories)),
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn stories() -> ApixStories {
+    serde_yaml::from_str(
+      r#"
+stories:
+  - name: login
+    steps:
+      - name: get_token
+        request:
+          method: post
+          url: "{{ parameters.url }}/token"
+  - name: users
+    needs: login
+    steps:
+      - name: get_user
+        request:
+          method: get
+          url: "{{ parameters.url }}/user"
+          headers:
+            Authorization: "Bearer {{ steps.get_token.response.body.token }}"
+      - name: delete_user
+        if: "{{ steps.get_user.response.status == 200 }}"
+        request:
+          method: delete
+          url: "{{ parameters.url }}/user/{{ steps.get_user.response.body.id }}"
+"#,
+    )
+    .unwrap()
+  }
+
+  #[test]
+  fn test_step_flows() {
+    let stories = stories();
+    let users = &stories.stories[1];
+    assert_eq!(
+      step_flows(&stories, users, &users.steps[1]),
+      vec![
+        StepFlow {
+          story: "users".to_string(),
+          step: "get_user".to_string(),
+          path: "response.body.id".to_string(),
+        },
+        StepFlow {
+          story: "users".to_string(),
+          step: "get_user".to_string(),
+          path: "response.status".to_string(),
+        },
+      ]
+    );
+  }
+
+  #[test]
+  fn test_to_tree() {
+    assert_eq!(
+      to_tree("users", &stories()),
+      r#"users
+├── login
+│   └── get_token POST {{ parameters.url }}/token
+└── users (needs login)
+    ├── get_user GET {{ parameters.url }}/user
+    │   └── uses login.get_token.response.body.token
+    └── delete_user DELETE {{ parameters.url }}/user/{{ steps.get_user.response.body.id }}
+        ├── uses get_user.response.body.id
+        └── uses get_user.response.status
+"#
+    );
+  }
+
+  #[test]
+  fn test_to_dot() {
+    let dot = to_dot("users", &stories());
+    assert!(dot.starts_with("digraph \"users\" {\n"));
+    assert!(dot.contains("  subgraph \"cluster_login\" {\n"));
+    assert!(dot.contains("    \"users.get_user\" -> \"users.delete_user\";\n"));
+    assert!(dot.contains(
+      "  \"login.get_token\" -> \"users.get_user\" [style=dashed, label=\"needs\", ltail=\"cluster_login\", lhead=\"cluster_users\"];\n"
+    ));
+    assert!(dot.contains(
+      "  \"login.get_token\" -> \"users.get_user\" [style=dotted, color=blue, label=\"response.body.token\"];\n"
+    ));
+  }
+}
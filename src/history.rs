@@ -0,0 +1,751 @@
+use super::manifests::{ApixConfiguration, ApixParameter, PROJECT_DIR};
+use super::paths::{lock_file, write_atomic, PRIVATE_FILE_MODE};
+use super::requests::ResponseMetrics;
+use anyhow::Result;
+use clap::ArgMatches;
+use comfy_table::{ContentArrangement, Table};
+use hmac::{Hmac, Mac};
+use indexmap::IndexMap;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use reqwest::header::{HeaderMap, HeaderName, AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION, SET_COOKIE};
+use reqwest::StatusCode;
+use rusqlite::types::Value as SqlValue;
+use rusqlite::{params, params_from_iter, Connection};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use url::{form_urlencoded, Url};
+
+// annotation of request manifests that must never be recorded
+pub static NO_HISTORY_ANNOTATION: &str = "apix.io/no-history";
+
+static SENSITIVE_HEADERS: [HeaderName; 4] = [AUTHORIZATION, PROXY_AUTHORIZATION, COOKIE, SET_COOKIE];
+
+// how secrets are stored in history, `history-redaction` config key
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Redaction {
+  // secrets are replaced by a short hmac-sha256 keyed by a project secret, so identical secrets can still be matched
+  Hash,
+  // secrets are not stored at all
+  Strip,
+}
+
+impl FromStr for Redaction {
+  type Err = anyhow::Error;
+
+  fn from_str(value: &str) -> Result<Self> {
+    match value {
+      "hash" => Ok(Redaction::Hash),
+      "strip" => Ok(Redaction::Strip),
+      _ => Err(anyhow::anyhow!(
+        "Bad history redaction: \"{}\", should be hash or strip",
+        value
+      )),
+    }
+  }
+}
+
+impl Redaction {
+  pub fn from_config() -> Result<Self> {
+    ApixConfiguration::once()
+      .get("history-redaction")
+      .map_or(Ok(Redaction::Hash), |value| value.parse())
+  }
+}
+
+// replaces secrets found in urls and header values when they are not stored at all
+static STRIPPED_SECRET: &str = "[redacted]";
+
+// random key of project history hashes, hashes can't be brute forced without it
+fn project_key(path: &Path) -> Result<Vec<u8>> {
+  if let Some(parent) = path.parent() {
+    std::fs::create_dir_all(parent)?;
+  }
+  let _lock = lock_file(path)?;
+  match std::fs::read_to_string(path) {
+    Ok(key) => base64::decode(key.trim()).map_err(|e| anyhow::anyhow!("Invalid history key {:?}\ncause: {}", path, e)),
+    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+      let key = rand::random::<[u8; 32]>().to_vec();
+      write_atomic(path, base64::encode(&key).as_bytes(), false, Some(PRIVATE_FILE_MODE))
+        .map_err(|e| anyhow::anyhow!("Failed to save history key {:?}\ncause: {}", path, e))?;
+      Ok(key)
+    }
+    Err(e) => Err(anyhow::anyhow!("Failed to read history key {:?}\ncause: {}", path, e)),
+  }
+}
+
+// redacts secrets of recorded requests: sensitive headers, password parameters and their values found elsewhere
+pub struct Redactor {
+  redaction: Redaction,
+  key: Vec<u8>,
+  secrets: Vec<String>,
+}
+
+impl Redactor {
+  pub fn new(redaction: Redaction, key: Vec<u8>) -> Self {
+    Self {
+      redaction,
+      key,
+      secrets: Vec::new(),
+    }
+  }
+
+  // outside projects nothing is recorded, a throwaway key is used
+  pub fn from_config() -> Result<Self> {
+    let key = match Path::new(PROJECT_DIR).is_dir() {
+      true => project_key(&PathBuf::from(".apix").join("history.key"))?,
+      false => rand::random::<[u8; 32]>().to_vec(),
+    };
+    Ok(Self::new(Redaction::from_config()?, key))
+  }
+
+  // values of parameters declared with `password: true`, scrubbed from urls and headers
+  pub fn with_secrets(mut self, parameters: &serde_json::Map<String, Value>, declared: &[ApixParameter]) -> Self {
+    for (name, value) in parameters {
+      if Self::is_secret(declared, name) {
+        let secret = match value {
+          Value::String(value) => value.clone(),
+          value => value.to_string(),
+        };
+        if !secret.is_empty() {
+          self.secrets.push(secret);
+        }
+      }
+    }
+    self
+  }
+
+  fn is_secret(declared: &[ApixParameter], name: &str) -> bool {
+    declared
+      .iter()
+      .any(|parameter| parameter.name == name && parameter.password)
+  }
+
+  fn redact(&self, secret: &str) -> Option<String> {
+    match self.redaction {
+      Redaction::Hash => {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).expect("hmac accepts keys of any size");
+        mac.update(secret.as_bytes());
+        let hash = format!("{:x}", mac.finalize().into_bytes());
+        Some(format!("hmac-sha256:{}", &hash[..16]))
+      }
+      Redaction::Strip => None,
+    }
+  }
+
+  // replace secrets found in a text, as is or url encoded
+  fn scrub(&self, text: &str) -> String {
+    let mut text = text.to_string();
+    for secret in &self.secrets {
+      let replacement = self.redact(secret).unwrap_or_else(|| STRIPPED_SECRET.to_string());
+      let encoded = [
+        secret.clone(),
+        utf8_percent_encode(secret, NON_ALPHANUMERIC).to_string(),
+        form_urlencoded::byte_serialize(secret.as_bytes()).collect::<String>(),
+      ];
+      for encoded in encoded {
+        text = text.replace(&encoded, &replacement);
+      }
+    }
+    text
+  }
+}
+
+// a request sent from a project, with its secrets redacted
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntry {
+  pub timestamp: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub request: Option<String>,
+  pub method: String,
+  pub url: String,
+  pub status: u16,
+  pub duration_ms: u64,
+  // generated correlation id, to find the request in server logs
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub request_id: Option<String>,
+  // key sent to make retries of the request idempotent, to send again with `--idempotency-key`
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub idempotency_key: Option<String>,
+  #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+  pub headers: IndexMap<String, String>,
+  #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+  pub parameters: IndexMap<String, Value>,
+}
+
+impl HistoryEntry {
+  // secrets found in `url` are redacted
+  pub fn new(method: &str, url: &str, metrics: &ResponseMetrics, redactor: &Redactor) -> Self {
+    Self {
+      timestamp: chrono::Utc::now().to_rfc3339(),
+      request: None,
+      method: method.to_uppercase(),
+      url: redactor.scrub(url),
+      status: metrics.status.as_u16(),
+      duration_ms: metrics.duration.as_millis() as u64,
+      request_id: metrics.request_id.clone(),
+      idempotency_key: metrics.idempotency_key.clone(),
+      headers: IndexMap::new(),
+      parameters: IndexMap::new(),
+    }
+  }
+
+  pub fn with_request(mut self, name: &str) -> Self {
+    self.request = Some(name.to_string());
+    self
+  }
+
+  // credentials and cookies headers are redacted, secrets found in other headers too
+  pub fn with_headers(mut self, headers: &HeaderMap, redactor: &Redactor) -> Self {
+    for (key, value) in headers {
+      let value = String::from_utf8_lossy(value.as_bytes());
+      if !SENSITIVE_HEADERS.contains(key) {
+        self.headers.insert(key.to_string(), redactor.scrub(&value));
+      } else if let Some(redacted) = redactor.redact(&value) {
+        self.headers.insert(key.to_string(), redacted);
+      }
+    }
+    self
+  }
+
+  // parameters declared with `password: true` are redacted
+  pub fn with_parameters(
+    mut self,
+    parameters: &serde_json::Map<String, Value>,
+    declared: &[ApixParameter],
+    redactor: &Redactor,
+  ) -> Self {
+    for (name, value) in parameters {
+      if !Redactor::is_secret(declared, name) {
+        self.parameters.insert(name.clone(), value.clone());
+      } else if let Some(redacted) = redactor.redact(&value.to_string()) {
+        self.parameters.insert(name.clone(), Value::String(redacted));
+      }
+    }
+    self
+  }
+}
+
+// filters of listed and exported history entries, `limit` keeps the latest entries
+#[derive(Debug, Clone, Default)]
+pub struct HistoryFilter {
+  pub url_contains: Option<String>,
+  pub status: Option<u16>,
+  pub request_id: Option<String>,
+  pub limit: Option<usize>,
+}
+
+impl HistoryFilter {
+  pub fn from_matches(matches: &ArgMatches) -> Result<Self> {
+    Ok(Self {
+      url_contains: matches.value_of("url-contains").map(str::to_string),
+      status: matches.value_of("status").map(str::parse).transpose()?,
+      request_id: matches.value_of("request-id").map(str::to_string),
+      limit: matches.value_of("limit").map(str::parse).transpose()?,
+    })
+  }
+}
+
+static HISTORY_BUSY_TIMEOUT: Duration = Duration::from_secs(10);
+
+static HISTORY_SCHEMA: &str = r#"
+CREATE TABLE IF NOT EXISTS history (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  timestamp TEXT NOT NULL,
+  request TEXT,
+  method TEXT NOT NULL,
+  url TEXT NOT NULL,
+  status INTEGER NOT NULL,
+  duration_ms INTEGER NOT NULL,
+  headers TEXT NOT NULL,
+  parameters TEXT NOT NULL,
+  request_id TEXT,
+  idempotency_key TEXT
+);
+CREATE INDEX IF NOT EXISTS history_url ON history (url);
+CREATE INDEX IF NOT EXISTS history_status ON history (status);
+CREATE INDEX IF NOT EXISTS history_timestamp ON history (timestamp);
+"#;
+
+// columns added since history was created, added to older databases
+static HISTORY_ADDED_COLUMNS: [(&str, &str); 2] = [("request_id", "TEXT"), ("idempotency_key", "TEXT")];
+
+static HISTORY_ADDED_INDEXES: &str = r#"
+CREATE INDEX IF NOT EXISTS history_request_id ON history (request_id);
+"#;
+
+fn add_missing_columns(connection: &Connection) -> rusqlite::Result<()> {
+  let columns = connection
+    .prepare("SELECT name FROM pragma_table_info('history')")?
+    .query_map([], |row| row.get::<_, String>(0))?
+    .collect::<rusqlite::Result<Vec<_>>>()?;
+  for (name, kind) in HISTORY_ADDED_COLUMNS {
+    if !columns.iter().any(|column| column == name) {
+      connection.execute(&format!("ALTER TABLE history ADD COLUMN {} {}", name, kind), [])?;
+    }
+  }
+  connection.execute_batch(HISTORY_ADDED_INDEXES)
+}
+
+// history of a project, stored in an embedded sqlite database
+pub struct History {
+  connection: Connection,
+}
+
+impl History {
+  // history is only kept for projects
+  pub fn open() -> Result<Option<Self>> {
+    if !Path::new(PROJECT_DIR).is_dir() {
+      return Ok(None);
+    }
+    Self::open_path(&PathBuf::from(".apix").join("history.db")).map(Some)
+  }
+
+  fn open_path(path: &Path) -> Result<Self> {
+    if let Some(parent) = path.parent() {
+      std::fs::create_dir_all(parent)?;
+    }
+    let connection =
+      Connection::open(path).map_err(|e| anyhow::anyhow!("Failed to open history {:?}\ncause: {}", path, e))?;
+    // concurrent apix processes wait for each other instead of failing on a locked database
+    connection.busy_timeout(HISTORY_BUSY_TIMEOUT)?;
+    connection
+      .execute_batch(HISTORY_SCHEMA)
+      .and_then(|_| add_missing_columns(&connection))
+      .map_err(|e| anyhow::anyhow!("Failed to create history {:?}\ncause: {}", path, e))?;
+    Ok(Self { connection })
+  }
+
+  pub fn record(&self, entry: &HistoryEntry) -> Result<()> {
+    self.connection.execute(
+      "INSERT INTO history (timestamp, request, method, url, status, duration_ms, headers, parameters, request_id,
+       idempotency_key)
+       VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+      params![
+        entry.timestamp,
+        entry.request,
+        entry.method,
+        entry.url,
+        entry.status,
+        entry.duration_ms,
+        serde_json::to_string(&entry.headers)?,
+        serde_json::to_string(&entry.parameters)?,
+        entry.request_id,
+        entry.idempotency_key,
+      ],
+    )?;
+    Ok(())
+  }
+
+  // matching entries, oldest first
+  pub fn entries(&self, filter: &HistoryFilter) -> Result<Vec<HistoryEntry>> {
+    let mut conditions = Vec::new();
+    let mut values: Vec<SqlValue> = Vec::new();
+    if let Some(url_contains) = &filter.url_contains {
+      conditions.push("instr(url, ?) > 0");
+      values.push(SqlValue::Text(url_contains.clone()));
+    }
+    if let Some(status) = filter.status {
+      conditions.push("status = ?");
+      values.push(SqlValue::Integer(status.into()));
+    }
+    if let Some(request_id) = &filter.request_id {
+      conditions.push("request_id = ?");
+      values.push(SqlValue::Text(request_id.clone()));
+    }
+    let condition = match conditions.is_empty() {
+      true => String::new(),
+      false => format!("WHERE {}", conditions.join(" AND ")),
+    };
+    let limit = filter.limit.map_or(-1, |limit| limit as i64);
+    let query = format!(
+      "SELECT * FROM (
+         SELECT id, timestamp, request, method, url, status, duration_ms, headers, parameters, request_id,
+           idempotency_key
+         FROM history {} ORDER BY id DESC LIMIT {}
+       ) ORDER BY id",
+      condition, limit
+    );
+    let mut statement = self.connection.prepare(&query)?;
+    let rows = statement.query_map(params_from_iter(values), |row| {
+      Ok((
+        HistoryEntry {
+          timestamp: row.get(1)?,
+          request: row.get(2)?,
+          method: row.get(3)?,
+          url: row.get(4)?,
+          status: row.get(5)?,
+          duration_ms: row.get(6)?,
+          request_id: row.get(9)?,
+          idempotency_key: row.get(10)?,
+          headers: IndexMap::new(),
+          parameters: IndexMap::new(),
+        },
+        row.get::<_, String>(7)?,
+        row.get::<_, String>(8)?,
+      ))
+    })?;
+    rows
+      .map(|row| {
+        let (entry, headers, parameters) = row?;
+        Ok(HistoryEntry {
+          headers: serde_json::from_str(&headers)?,
+          parameters: serde_json::from_str(&parameters)?,
+          ..entry
+        })
+      })
+      .collect()
+  }
+}
+
+// record a request in project history, recording failures do not fail the request
+pub fn record(entry: HistoryEntry) {
+  if let Err(err) = History::open().and_then(|history| history.map_or(Ok(()), |history| history.record(&entry))) {
+    eprintln!("{:#}", err);
+  }
+}
+
+// http archive 1.2, only request data and response status are recorded
+fn to_har(entries: &[HistoryEntry]) -> Value {
+  let name_values = |values: &IndexMap<String, String>| {
+    values
+      .iter()
+      .map(|(name, value)| json!({"name": name, "value": value}))
+      .collect::<Vec<_>>()
+  };
+  let entries = entries
+    .iter()
+    .map(|entry| {
+      let queries = Url::parse(&entry.url)
+        .map(|url| url.query_pairs().into_owned().collect::<Vec<_>>())
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(name, value)| json!({"name": name, "value": value}))
+        .collect::<Vec<_>>();
+      json!({
+        "startedDateTime": entry.timestamp,
+        "time": entry.duration_ms,
+        "request": {
+          "method": entry.method,
+          "url": entry.url,
+          "httpVersion": "HTTP/1.1",
+          "cookies": [],
+          "headers": name_values(&entry.headers),
+          "queryString": queries,
+          "headersSize": -1,
+          "bodySize": -1,
+        },
+        "response": {
+          "status": entry.status,
+          "statusText": StatusCode::from_u16(entry.status).ok().and_then(|status| status.canonical_reason()).unwrap_or_default(),
+          "httpVersion": "HTTP/1.1",
+          "cookies": [],
+          "headers": [],
+          "content": {"size": -1, "mimeType": ""},
+          "redirectURL": "",
+          "headersSize": -1,
+          "bodySize": -1,
+        },
+        "cache": {},
+        "timings": {"send": 0, "wait": entry.duration_ms, "receive": 0},
+      })
+    })
+    .collect::<Vec<_>>();
+  json!({
+    "log": {
+      "version": "1.2",
+      "creator": {"name": "apix", "version": env!("CARGO_PKG_VERSION")},
+      "entries": entries,
+    }
+  })
+}
+
+pub fn export_history(entries: &[HistoryEntry], format: &str) -> Result<String> {
+  match format {
+    "har" => Ok(serde_json::to_string_pretty(&to_har(entries))?),
+    _ => Ok(serde_yaml::to_string(entries)?),
+  }
+}
+
+fn print_history(entries: Vec<HistoryEntry>) {
+  let mut table = Table::new();
+  table
+    .load_preset("││──├─┼┤│─┼├┤┬┴╭╮╰╯")
+    .set_content_arrangement(ContentArrangement::Dynamic)
+    .set_header(["Time", "Request", "Method", "Url", "Status", "Duration"]);
+  for entry in entries {
+    table.add_row(vec![
+      entry.timestamp,
+      entry.request.unwrap_or_default(),
+      entry.method,
+      entry.url,
+      entry.status.to_string(),
+      format!("{}ms", entry.duration_ms),
+    ]);
+  }
+  println!("{}", table);
+}
+
+pub fn handle_history(matches: &ArgMatches) -> Result<()> {
+  let history = History::open()?.ok_or_else(|| anyhow::anyhow!("History requires a project, see `apix init`"))?;
+  match matches.subcommand() {
+    Some(("export", matches)) => {
+      let entries = history.entries(&HistoryFilter::from_matches(matches)?)?;
+      let content = export_history(&entries, matches.value_of("format").unwrap_or("yaml"))?;
+      match matches.value_of("output-file") {
+        Some(file) => std::fs::write(file, content)
+          .map_err(|e| anyhow::anyhow!("Failed to write history {}\ncause: {}", file, e))?,
+        None => print!("{}", content),
+      }
+    }
+    Some(("list", matches)) => print_history(history.entries(&HistoryFilter::from_matches(matches)?)?),
+    _ => print_history(history.entries(&HistoryFilter::default())?),
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use reqwest::header::HeaderValue;
+  use std::time::Duration;
+  use test_case::test_case;
+
+  fn metrics() -> ResponseMetrics {
+    ResponseMetrics {
+      status: StatusCode::OK,
+      bytes: 0,
+      duration: Duration::from_millis(42),
+      attempts: 1,
+      request_id: None,
+      idempotency_key: None,
+      response: None,
+    }
+  }
+
+  fn redactor(redaction: Redaction) -> Redactor {
+    Redactor::new(redaction, b"project key".to_vec())
+  }
+
+  #[test]
+  fn test_redact_headers() {
+    let headers = HeaderMap::from_iter([
+      (AUTHORIZATION, HeaderValue::from_static("Bearer secret")),
+      (COOKIE, HeaderValue::from_static("id=42")),
+      (HeaderName::from_static("x-tenant"), HeaderValue::from_static("apix")),
+    ]);
+    let redactor = redactor(Redaction::Hash);
+    let entry = HistoryEntry::new("get", "http://localhost", &metrics(), &redactor).with_headers(&headers, &redactor);
+    assert!(entry.headers["authorization"].starts_with("hmac-sha256:"));
+    assert!(!entry.headers["authorization"].contains("secret"));
+    assert_eq!(entry.headers["x-tenant"], "apix");
+    let redactor = Redactor::new(Redaction::Strip, Vec::new());
+    let entry = HistoryEntry::new("get", "http://localhost", &metrics(), &redactor).with_headers(&headers, &redactor);
+    assert_eq!(entry.headers.keys().collect::<Vec<_>>(), vec!["x-tenant"]);
+  }
+
+  #[test]
+  fn test_redact_password_parameters() {
+    let declared = vec![
+      ApixParameter::new("password".to_string(), true, true, None, None),
+      ApixParameter::new("user".to_string(), true, false, None, None),
+    ];
+    let parameters = json!({"password": "secret", "user": "apix"});
+    let redactor = redactor(Redaction::Hash);
+    let entry = HistoryEntry::new("post", "http://localhost", &metrics(), &redactor).with_parameters(
+      parameters.as_object().unwrap(),
+      &declared,
+      &redactor,
+    );
+    assert_eq!(entry.parameters["user"], json!("apix"));
+    assert_ne!(entry.parameters["password"], json!("secret"));
+    let same = HistoryEntry::new("post", "http://localhost", &metrics(), &redactor).with_parameters(
+      parameters.as_object().unwrap(),
+      &declared,
+      &redactor,
+    );
+    assert_eq!(entry.parameters["password"], same.parameters["password"]);
+    // hashes depend on the project key
+    let other_project = Redactor::new(Redaction::Hash, b"other key".to_vec());
+    let other = HistoryEntry::new("post", "http://localhost", &metrics(), &other_project).with_parameters(
+      parameters.as_object().unwrap(),
+      &declared,
+      &other_project,
+    );
+    assert_ne!(entry.parameters["password"], other.parameters["password"]);
+  }
+
+  #[test_case(Redaction::Hash ; "hash")]
+  #[test_case(Redaction::Strip ; "strip")]
+  fn test_scrub_password_values(redaction: Redaction) {
+    let declared = vec![ApixParameter::new("token".to_string(), true, true, None, None)];
+    let parameters = json!({"token": "s3cr3t value"});
+    let redactor = redactor(redaction).with_secrets(parameters.as_object().unwrap(), &declared);
+    let headers = HeaderMap::from_iter([(
+      HeaderName::from_static("x-api-key"),
+      HeaderValue::from_static("key s3cr3t value"),
+    )]);
+    let entry = HistoryEntry::new(
+      "get",
+      "http://localhost/s3cr3t%20value?token=s3cr3t+value",
+      &metrics(),
+      &redactor,
+    )
+    .with_headers(&headers, &redactor);
+    assert!(!entry.url.contains("s3cr3t"));
+    assert!(entry.url.starts_with("http://localhost/"));
+    assert!(!entry.headers["x-api-key"].contains("s3cr3t"));
+    assert!(entry.headers["x-api-key"].starts_with("key "));
+  }
+
+  #[test]
+  fn test_project_key() {
+    let dir = std::env::temp_dir().join(format!("apix-history-key-{}", std::process::id()));
+    let path = dir.join("history.key");
+    let key = project_key(&path).unwrap();
+    assert_eq!(key.len(), 32);
+    assert_eq!(project_key(&path).unwrap(), key);
+    #[cfg(unix)]
+    {
+      use std::os::unix::fs::PermissionsExt;
+      let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+      assert_eq!(mode & 0o777, PRIVATE_FILE_MODE);
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn test_history_filters() {
+    let dir = std::env::temp_dir().join(format!("apix-history-{}", std::process::id()));
+    let history = History::open_path(&dir.join("history.db")).unwrap();
+    for (url, status) in [
+      ("http://localhost/users/1", StatusCode::OK),
+      ("http://localhost/todos/1", StatusCode::NOT_FOUND),
+      ("http://localhost/users/2?fields=name", StatusCode::OK),
+    ] {
+      let metrics = ResponseMetrics { status, ..metrics() };
+      history
+        .record(&HistoryEntry::new("get", url, &metrics, &redactor(Redaction::Hash)))
+        .unwrap();
+    }
+    let urls = |filter: HistoryFilter| {
+      history
+        .entries(&filter)
+        .unwrap()
+        .into_iter()
+        .map(|entry| entry.url)
+        .collect::<Vec<_>>()
+    };
+    assert_eq!(urls(HistoryFilter::default()).len(), 3);
+    let users = HistoryFilter {
+      url_contains: Some("/users".to_string()),
+      ..Default::default()
+    };
+    assert_eq!(
+      urls(users),
+      vec!["http://localhost/users/1", "http://localhost/users/2?fields=name"]
+    );
+    let not_found = HistoryFilter {
+      status: Some(404),
+      ..Default::default()
+    };
+    assert_eq!(urls(not_found), vec!["http://localhost/todos/1"]);
+    let latest = HistoryFilter {
+      limit: Some(1),
+      ..Default::default()
+    };
+    assert_eq!(urls(latest), vec!["http://localhost/users/2?fields=name"]);
+    std::fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn test_history_request_id() {
+    let dir = std::env::temp_dir().join(format!("apix-history-request-id-{}", std::process::id()));
+    let path = dir.join("history.db");
+    // databases created before request ids were recorded get the column
+    std::fs::create_dir_all(&dir).unwrap();
+    Connection::open(&path)
+      .unwrap()
+      .execute_batch(
+        "CREATE TABLE history (id INTEGER PRIMARY KEY AUTOINCREMENT, timestamp TEXT NOT NULL, request TEXT,
+         method TEXT NOT NULL, url TEXT NOT NULL, status INTEGER NOT NULL, duration_ms INTEGER NOT NULL,
+         headers TEXT NOT NULL, parameters TEXT NOT NULL)",
+      )
+      .unwrap();
+    let history = History::open_path(&path).unwrap();
+    for request_id in [Some("apix-1"), None, Some("apix-2")] {
+      let metrics = ResponseMetrics {
+        request_id: request_id.map(str::to_string),
+        ..metrics()
+      };
+      let entry = HistoryEntry::new("get", "http://localhost", &metrics, &redactor(Redaction::Hash));
+      history.record(&entry).unwrap();
+    }
+    let filter = HistoryFilter {
+      request_id: Some("apix-2".to_string()),
+      ..Default::default()
+    };
+    let entries = history.entries(&filter).unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].request_id.as_deref(), Some("apix-2"));
+    let request_ids = history
+      .entries(&HistoryFilter::default())
+      .unwrap()
+      .into_iter()
+      .map(|entry| entry.request_id)
+      .collect::<Vec<_>>();
+    assert_eq!(
+      request_ids,
+      vec![Some("apix-1".to_string()), None, Some("apix-2".to_string())]
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn test_history_idempotency_key() {
+    let dir = std::env::temp_dir().join(format!("apix-history-idempotency-{}", std::process::id()));
+    let history = History::open_path(&dir.join("history.db")).unwrap();
+    let metrics = ResponseMetrics {
+      attempts: 3,
+      idempotency_key: Some("4f0e2c9a-key".to_string()),
+      ..metrics()
+    };
+    let entry = HistoryEntry::new("post", "http://localhost/orders", &metrics, &redactor(Redaction::Hash));
+    assert_eq!(entry.idempotency_key.as_deref(), Some("4f0e2c9a-key"));
+    history.record(&entry).unwrap();
+    let entries = history.entries(&HistoryFilter::default()).unwrap();
+    assert_eq!(entries, vec![entry]);
+    std::fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn test_export_har() {
+    let headers = HeaderMap::from_iter([(HeaderName::from_static("x-tenant"), HeaderValue::from_static("apix"))]);
+    let redactor = redactor(Redaction::Hash);
+    let entry =
+      HistoryEntry::new("get", "http://localhost/users?id=1", &metrics(), &redactor).with_headers(&headers, &redactor);
+    let har: Value = serde_json::from_str(&export_history(&[entry], "har").unwrap()).unwrap();
+    let exported = &har["log"]["entries"][0];
+    assert_eq!(har["log"]["version"], json!("1.2"));
+    assert_eq!(exported["time"], json!(42));
+    assert_eq!(
+      exported["request"]["queryString"],
+      json!([{"name": "id", "value": "1"}])
+    );
+    assert_eq!(
+      exported["request"]["headers"],
+      json!([{"name": "x-tenant", "value": "apix"}])
+    );
+    assert_eq!(exported["response"]["statusText"], json!("OK"));
+  }
+
+  #[test]
+  fn test_parse_redaction() {
+    assert_eq!("strip".parse::<Redaction>().unwrap(), Redaction::Strip);
+    assert!("none".parse::<Redaction>().is_err());
+  }
+}
@@ -1,4 +1,16 @@
-use reqwest::{header::CONTENT_TYPE, Request, Response};
+use crate::manifests::ApixConfiguration;
+use anyhow::Result;
+use chrono::{DateTime, Duration, NaiveDate, TimeZone, Utc};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use reqwest::{
+  header::{HeaderName, CONTENT_TYPE},
+  Request, Response,
+};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+use tera::{Context, Tera};
+use url::Url;
 
 pub trait HttpHeaders {
   fn headers(&self) -> &reqwest::header::HeaderMap;
@@ -18,6 +30,13 @@ impl HttpHeaders for Response {
   }
 }
 
+impl HttpHeaders for reqwest::header::HeaderMap {
+  #[inline]
+  fn headers(&self) -> &reqwest::header::HeaderMap {
+    self
+  }
+}
+
 pub trait Language {
   fn get_language(&self) -> Option<&'static str>;
 }
@@ -35,6 +54,7 @@ where
         Ok(content_type) if content_type.contains("css") => Some("css"),
         Ok(content_type) if content_type.contains("javascript") => Some("js"),
         Ok(content_type) if content_type.contains("yaml") => Some("yaml"),
+        Ok(content_type) if content_type.contains("csv") => Some("csv"),
         Ok(content_type) if content_type.contains("text") => Some("txt"),
         _ => Some("binary"),
       },
@@ -43,6 +63,473 @@ where
   }
 }
 
+// generate a correlation id header for each request from `request-id` and `request-id-header` config keys
+#[derive(Debug)]
+pub struct RequestIdGenerator {
+  header: HeaderName,
+  template: String,
+}
+
+impl RequestIdGenerator {
+  pub fn new(header: &str, template: &str) -> Result<Self> {
+    let header = HeaderName::from_str(header)
+      .map_err(|e| anyhow::anyhow!("Invalid request id header '{}'\ncause: {}", header, e))?;
+    // check template is valid before sending any request
+    Tera::default().add_raw_template("request-id", template)?;
+    Ok(Self {
+      header,
+      template: template.to_string(),
+    })
+  }
+
+  pub fn from_config(config: &ApixConfiguration) -> Result<Option<Self>> {
+    match config.get("request-id") {
+      Some(template) => Ok(Some(Self::new(
+        config
+          .get("request-id-header")
+          .map(String::as_str)
+          .unwrap_or("x-request-id"),
+        template,
+      )?)),
+      None => Ok(None),
+    }
+  }
+
+  pub fn header(&self) -> &HeaderName {
+    &self.header
+  }
+
+  // render the id template, `uuid` holds a new random uuid
+  pub fn generate(&self) -> Result<String> {
+    let mut context = Context::new();
+    context.insert("uuid", &uuid::Uuid::new_v4().to_string());
+    Ok(Tera::one_off(&self.template, &context, false)?)
+  }
+}
+
+// idempotency key value, `auto` generates a new uuid for each apix run
+pub fn resolve_idempotency_key(value: &str) -> String {
+  match value {
+    "auto" => uuid::Uuid::new_v4().to_string(),
+    value => value.to_string(),
+  }
+}
+
+// address family used to connect to servers, forced by binding the local socket to an unspecified address
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IpFamily {
+  V4,
+  V6,
+}
+
+impl IpFamily {
+  pub fn local_address(&self) -> IpAddr {
+    match self {
+      IpFamily::V4 => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+      IpFamily::V6 => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    }
+  }
+}
+
+// tls protocol versions accepted by `--tlsv1.x` (minimum) and `--tls-max` (maximum)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TlsVersion {
+  V1_0,
+  V1_1,
+  V1_2,
+  V1_3,
+}
+
+impl FromStr for TlsVersion {
+  type Err = anyhow::Error;
+
+  fn from_str(value: &str) -> Result<Self> {
+    match value {
+      "1.0" => Ok(TlsVersion::V1_0),
+      "1.1" => Ok(TlsVersion::V1_1),
+      "1.2" => Ok(TlsVersion::V1_2),
+      "1.3" => Ok(TlsVersion::V1_3),
+      _ => Err(anyhow::anyhow!(
+        "Bad tls version: \"{}\", should be 1.0, 1.1, 1.2 or 1.3",
+        value
+      )),
+    }
+  }
+}
+
+impl TlsVersion {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      TlsVersion::V1_0 => "1.0",
+      TlsVersion::V1_1 => "1.1",
+      TlsVersion::V1_2 => "1.2",
+      TlsVersion::V1_3 => "1.3",
+    }
+  }
+
+  pub fn version(&self) -> reqwest::tls::Version {
+    match self {
+      TlsVersion::V1_0 => reqwest::tls::Version::TLS_1_0,
+      TlsVersion::V1_1 => reqwest::tls::Version::TLS_1_1,
+      TlsVersion::V1_2 => reqwest::tls::Version::TLS_1_2,
+      TlsVersion::V1_3 => reqwest::tls::Version::TLS_1_3,
+    }
+  }
+}
+
+// root certificates trusted by https requests: the operating system store, used through the platform tls library,
+// or the Mozilla store bundled with apix, used through rustls
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TrustStore {
+  System,
+  Bundled,
+}
+
+impl FromStr for TrustStore {
+  type Err = anyhow::Error;
+
+  fn from_str(value: &str) -> Result<Self> {
+    match value {
+      "system" => Ok(TrustStore::System),
+      "bundled" => Ok(TrustStore::Bundled),
+      _ => Err(anyhow::anyhow!(
+        "Bad trust store: \"{}\", should be system or bundled",
+        value
+      )),
+    }
+  }
+}
+
+// body serialization selected by `--json` or `--form`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BodyFormat {
+  Json,
+  Form,
+}
+
+impl BodyFormat {
+  pub fn content_type(&self) -> &'static str {
+    match self {
+      BodyFormat::Json => "application/json",
+      BodyFormat::Form => "application/x-www-form-urlencoded",
+    }
+  }
+
+  pub fn accept(&self) -> &'static str {
+    match self {
+      BodyFormat::Json => "application/json, */*;q=0.5",
+      BodyFormat::Form => "*/*",
+    }
+  }
+
+  // json bodies must be valid json, form bodies given as a json object are url encoded,
+  // other form bodies are expected to be already encoded
+  pub fn serialize(&self, body: &str) -> Result<String> {
+    let json = serde_json::from_str::<serde_json::Value>(body);
+    match (self, json) {
+      (BodyFormat::Json, Ok(_)) => Ok(body.to_string()),
+      (BodyFormat::Json, Err(e)) => Err(anyhow::anyhow!("Invalid json body\ncause: {}", e)),
+      (BodyFormat::Form, Ok(serde_json::Value::Object(fields))) => {
+        let mut serializer = url::form_urlencoded::Serializer::new(String::new());
+        for (name, value) in fields {
+          match value {
+            serde_json::Value::String(value) => serializer.append_pair(&name, &value),
+            value => serializer.append_pair(&name, &value.to_string()),
+          };
+        }
+        Ok(serializer.finish())
+      }
+      (BodyFormat::Form, _) => Ok(body.to_string()),
+    }
+  }
+}
+
+// header used to send the sha-256 digest of request bodies
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DigestHeader {
+  // RFC 9530 `Content-Digest: sha-256=:<base64>:`
+  ContentDigest,
+  // legacy RFC 3230 `Digest: SHA-256=<base64>`
+  Digest,
+}
+
+impl FromStr for DigestHeader {
+  type Err = anyhow::Error;
+
+  fn from_str(value: &str) -> Result<Self> {
+    match value.to_lowercase().as_str() {
+      "content-digest" | "true" => Ok(DigestHeader::ContentDigest),
+      "digest" => Ok(DigestHeader::Digest),
+      _ => Err(anyhow::anyhow!(
+        "Bad digest header: \"{}\", should be content-digest or digest",
+        value
+      )),
+    }
+  }
+}
+
+impl DigestHeader {
+  pub fn header(&self) -> HeaderName {
+    match self {
+      DigestHeader::ContentDigest => HeaderName::from_static("content-digest"),
+      DigestHeader::Digest => HeaderName::from_static("digest"),
+    }
+  }
+
+  pub fn value(&self, digest: &[u8]) -> String {
+    match self {
+      DigestHeader::ContentDigest => format!("sha-256=:{}:", base64::encode(digest)),
+      DigestHeader::Digest => format!("SHA-256={}", base64::encode(digest)),
+    }
+  }
+}
+
+// etag for `If-None-Match`, bare values are quoted (eg: `abc` -> `"abc"`)
+pub fn quote_etag(etag: &str) -> String {
+  let etag = etag.trim();
+  if etag == "*" || etag.starts_with('"') || etag.starts_with("W/") {
+    etag.to_string()
+  } else {
+    format!("\"{}\"", etag)
+  }
+}
+
+// parse a size in bytes with an optional unit (`512`, `64KB`, `50MB`, `1GiB`), units are multiples of 1024
+pub fn parse_size(value: &str) -> Result<u64> {
+  let value = value.trim();
+  let digits = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+  let multiplier: u64 = match value[digits..].trim().to_uppercase().as_str() {
+    "" | "B" => 1,
+    "K" | "KB" | "KIB" => 1 << 10,
+    "M" | "MB" | "MIB" => 1 << 20,
+    "G" | "GB" | "GIB" => 1 << 30,
+    _ => 0,
+  };
+  value[..digits]
+    .parse::<u64>()
+    .ok()
+    .filter(|_| multiplier > 0)
+    .and_then(|size| size.checked_mul(multiplier))
+    .ok_or_else(|| anyhow::anyhow!("Bad size: \"{}\", should be a number of bytes, KB, MB or GB", value))
+}
+
+// parse a timeout in seconds, fractions are allowed (`30`, `2.5`)
+pub fn parse_timeout(value: &str) -> Result<std::time::Duration> {
+  value
+    .trim()
+    .parse::<f64>()
+    .ok()
+    .filter(|seconds| seconds.is_finite() && *seconds > 0.0)
+    .map(std::time::Duration::from_secs_f64)
+    .ok_or_else(|| anyhow::anyhow!("Bad timeout: \"{}\", should be a positive number of seconds", value))
+}
+
+// parse an absolute date (HTTP date, RFC 3339, `YYYY-MM-DD`) or a relative one (`now`, `yesterday`, `2 hours ago`, `7d`)
+pub fn parse_date(value: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>> {
+  static RELATIVE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(\d+)\s*(second|sec|s|minute|min|m|hour|h|day|d|week|w)s?(?:\s+ago)?$").unwrap());
+  let value = value.trim();
+  let lowercase = value.to_lowercase();
+  match lowercase.as_str() {
+    "now" => return Ok(now),
+    "yesterday" => return Ok(now - Duration::days(1)),
+    _ => {}
+  }
+  if let Some(captures) = RELATIVE.captures(&lowercase) {
+    let amount = captures[1].parse::<i64>()?;
+    let duration = match &captures[2] {
+      "second" | "sec" | "s" => Duration::seconds(amount),
+      "minute" | "min" | "m" => Duration::minutes(amount),
+      "hour" | "h" => Duration::hours(amount),
+      "day" | "d" => Duration::days(amount),
+      _ => Duration::weeks(amount),
+    };
+    return Ok(now - duration);
+  }
+  DateTime::parse_from_rfc2822(value)
+    .or_else(|_| DateTime::parse_from_rfc3339(value))
+    .map(|date| date.with_timezone(&Utc))
+    .or_else(|_| NaiveDate::parse_from_str(value, "%Y-%m-%d").map(|date| Utc.from_utc_datetime(&date.and_hms(0, 0, 0))))
+    .map_err(|_| {
+      anyhow::anyhow!(
+        "Bad date: \"{}\", should be an HTTP date, an RFC 3339 date, YYYY-MM-DD or relative like \"2 hours ago\"",
+        value
+      )
+    })
+}
+
+// backend phase timing of a `Server-Timing` header, eg: `db;dur=53;desc="Database"`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerTiming {
+  pub name: String,
+  pub duration: Option<f64>,
+  pub description: Option<String>,
+}
+
+// split on `separator` outside of quoted strings
+fn split_unquoted(value: &str, separator: char) -> Vec<&str> {
+  let mut parts = Vec::new();
+  let (mut start, mut quoted, mut escaped) = (0, false, false);
+  for (index, c) in value.char_indices() {
+    match c {
+      _ if escaped => escaped = false,
+      '\\' if quoted => escaped = true,
+      '"' => quoted = !quoted,
+      c if c == separator && !quoted => {
+        parts.push(&value[start..index]);
+        start = index + 1;
+      }
+      _ => {}
+    }
+  }
+  parts.push(&value[start..]);
+  parts
+}
+
+fn unquote(value: &str) -> String {
+  match value.strip_prefix('"').and_then(|value| value.strip_suffix('"')) {
+    Some(quoted) => quoted.replace("\\\"", "\"").replace("\\\\", "\\"),
+    None => value.to_string(),
+  }
+}
+
+// metrics of `Server-Timing` header values, unknown parameters are ignored
+pub fn parse_server_timing(value: &str) -> Vec<ServerTiming> {
+  split_unquoted(value, ',')
+    .into_iter()
+    .filter_map(|metric| {
+      let mut params = split_unquoted(metric, ';').into_iter().map(str::trim);
+      let name = params.next().filter(|name| !name.is_empty())?.to_string();
+      let mut timing = ServerTiming {
+        name,
+        duration: None,
+        description: None,
+      };
+      for param in params {
+        let (key, value) = param.split_once('=').unwrap_or((param, ""));
+        match key.trim().to_lowercase().as_str() {
+          "dur" => timing.duration = unquote(value.trim()).parse().ok(),
+          "desc" => timing.description = Some(unquote(value.trim())),
+          _ => {}
+        }
+      }
+      Some(timing)
+    })
+    .collect()
+}
+
+// delay asked by a `Retry-After` header, either in seconds or as an HTTP date
+pub fn retry_after_delay(value: &str, now: DateTime<Utc>) -> Option<std::time::Duration> {
+  let value = value.trim();
+  if let Ok(seconds) = value.parse::<u64>() {
+    return Some(std::time::Duration::from_secs(seconds));
+  }
+  let date = DateTime::parse_from_rfc2822(value).ok()?.with_timezone(&Utc);
+  Some((date - now).to_std().unwrap_or_default())
+}
+
+// `Range` header value from byte ranges like `0-1023`, `1024-` or `-512`, comma separated
+pub fn range_header(value: &str) -> Result<String> {
+  static RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(\d+-\d*|-\d+)$").unwrap());
+  let ranges = value.trim().trim_start_matches("bytes=");
+  let valid = ranges.split(',').map(str::trim).all(|range| {
+    RE.is_match(range)
+      && match range.split_once('-') {
+        Some((start, end)) if !start.is_empty() && !end.is_empty() => {
+          start.parse::<u64>().ok() <= end.parse::<u64>().ok()
+        }
+        _ => true,
+      }
+  });
+  if valid {
+    Ok(format!(
+      "bytes={}",
+      ranges.split(',').map(str::trim).collect::<Vec<_>>().join(",")
+    ))
+  } else {
+    Err(anyhow::anyhow!(
+      "Bad range: \"{}\", should be byte ranges like 0-1023, 1024- or -512",
+      value
+    ))
+  }
+}
+
+// where `--continue-at` resumes a download, `auto` continues after the bytes already in the output file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContinueAt {
+  Auto,
+  Offset(u64),
+}
+
+impl FromStr for ContinueAt {
+  type Err = anyhow::Error;
+
+  fn from_str(value: &str) -> Result<Self> {
+    match value {
+      "auto" => Ok(ContinueAt::Auto),
+      offset => offset.parse().map(ContinueAt::Offset).map_err(|_| {
+        anyhow::anyhow!(
+          "Bad continue offset: \"{}\", should be a number of bytes or auto",
+          value
+        )
+      }),
+    }
+  }
+}
+
+impl ContinueAt {
+  pub fn offset(&self, file: &str) -> u64 {
+    match self {
+      ContinueAt::Auto => std::fs::metadata(file).map(|metadata| metadata.len()).unwrap_or(0),
+      ContinueAt::Offset(offset) => *offset,
+    }
+  }
+}
+
+// first byte position of a `Content-Range: bytes <start>-<end>/<size>` header
+pub fn content_range_start(content_range: &str) -> Option<u64> {
+  content_range
+    .trim()
+    .strip_prefix("bytes ")?
+    .split('-')
+    .next()?
+    .trim()
+    .parse()
+    .ok()
+}
+
+// complete size of a `Content-Range: bytes <range>/<size>` header
+pub fn content_range_size(content_range: &str) -> Option<u64> {
+  content_range.rsplit('/').next()?.trim().parse().ok()
+}
+
+// format a date as an HTTP date (RFC 9110 IMF-fixdate)
+pub fn format_http_date(date: DateTime<Utc>) -> String {
+  date.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+// merge query pairs of the url with query options, options override url pairs of the same name in place,
+// other options are appended in their order, repeated names are all kept
+pub fn merge_url_queries(url: &str, queries: Option<&[(String, String)]>) -> Result<Url> {
+  let mut url = Url::parse(url).map_err(|e| anyhow::anyhow!("Invalid url {}\ncause: {}", url, e))?;
+  let queries = match queries {
+    Some(queries) if !queries.is_empty() => queries,
+    _ => return Ok(url),
+  };
+  let mut merged: Vec<(String, String)> = Vec::new();
+  let url_keys = url.query_pairs().map(|(key, _)| key.to_string()).collect::<Vec<_>>();
+  for (key, value) in url.query_pairs() {
+    if !queries.iter().any(|(query_key, _)| query_key == &key) {
+      merged.push((key.to_string(), value.to_string()));
+    } else if !merged.iter().any(|(merged_key, _)| merged_key == &key) {
+      merged.extend(queries.iter().filter(|(query_key, _)| query_key == &key).cloned());
+    }
+  }
+  merged.extend(queries.iter().filter(|(key, _)| !url_keys.contains(key)).cloned());
+  url.query_pairs_mut().clear().extend_pairs(merged);
+  Ok(url)
+}
+
 //test get language for HttpHeaders
 #[cfg(test)]
 mod test_get_language {
@@ -97,3 +584,272 @@ mod test_get_language {
     MockHttpHeaders::from_content_type(content_type).get_language().unwrap()
   }
 }
+
+#[cfg(test)]
+mod test_request_id {
+  use super::*;
+
+  #[test]
+  fn test_generate_request_id() {
+    let generator = RequestIdGenerator::new("X-Request-Id", "apix-{{uuid}}").unwrap();
+    let first = generator.generate().unwrap();
+    let second = generator.generate().unwrap();
+    assert_eq!(generator.header().as_str(), "x-request-id");
+    assert!(first.starts_with("apix-"));
+    assert_eq!(first.len(), "apix-".len() + 36);
+    assert_ne!(first, second);
+  }
+
+  #[test]
+  fn test_invalid_request_id() {
+    assert!(RequestIdGenerator::new("X Request Id", "{{uuid}}").is_err());
+    assert!(RequestIdGenerator::new("X-Request-Id", "{{uuid").is_err());
+  }
+}
+
+#[cfg(test)]
+mod test_content_digest {
+  use super::*;
+  use sha2::{Digest, Sha256};
+  use test_case::test_case;
+
+  #[test_case(DigestHeader::ContentDigest => "sha-256=:X48E9qOokqqrvdts8nOJRJN3OWDUoyWxBf7kbu9DBPE=:")]
+  #[test_case(DigestHeader::Digest => "SHA-256=X48E9qOokqqrvdts8nOJRJN3OWDUoyWxBf7kbu9DBPE=")]
+  fn test_digest_value(header: DigestHeader) -> String {
+    header.value(&Sha256::digest(b"{\"hello\": \"world\"}"))
+  }
+
+  #[test_case("content-digest" => DigestHeader::ContentDigest)]
+  #[test_case("Digest" => DigestHeader::Digest)]
+  fn test_digest_header_from_str(value: &str) -> DigestHeader {
+    value.parse().unwrap()
+  }
+}
+
+#[cfg(test)]
+mod test_idempotency_key {
+  use super::*;
+
+  #[test]
+  fn test_resolve_idempotency_key() {
+    assert_eq!(resolve_idempotency_key("order-42"), "order-42");
+    let generated = resolve_idempotency_key("auto");
+    assert!(uuid::Uuid::parse_str(&generated).is_ok());
+    assert_ne!(generated, resolve_idempotency_key("auto"));
+  }
+}
+
+#[cfg(test)]
+mod test_conditional_headers {
+  use super::*;
+  use test_case::test_case;
+
+  #[test_case("abc" => "\"abc\"" ; "bare etag")]
+  #[test_case("\"abc\"" => "\"abc\"" ; "quoted etag")]
+  #[test_case("W/\"abc\"" => "W/\"abc\"")]
+  #[test_case("*" => "*")]
+  fn test_quote_etag(etag: &str) -> String {
+    quote_etag(etag)
+  }
+
+  #[test_case("now" => "Sun, 15 Oct 2023 12:00:00 GMT")]
+  #[test_case("yesterday" => "Sat, 14 Oct 2023 12:00:00 GMT")]
+  #[test_case("2 hours ago" => "Sun, 15 Oct 2023 10:00:00 GMT")]
+  #[test_case("30 min ago" => "Sun, 15 Oct 2023 11:30:00 GMT")]
+  #[test_case("1 week ago" => "Sun, 08 Oct 2023 12:00:00 GMT")]
+  #[test_case("7d" => "Sun, 08 Oct 2023 12:00:00 GMT")]
+  #[test_case("Wed, 21 Oct 2015 07:28:00 GMT" => "Wed, 21 Oct 2015 07:28:00 GMT")]
+  #[test_case("2015-10-21T09:28:00+02:00" => "Wed, 21 Oct 2015 07:28:00 GMT")]
+  #[test_case("2015-10-21" => "Wed, 21 Oct 2015 00:00:00 GMT")]
+  fn test_parse_date(value: &str) -> String {
+    let now = Utc.ymd(2023, 10, 15).and_hms(12, 0, 0);
+    format_http_date(parse_date(value, now).unwrap())
+  }
+
+  #[test]
+  fn test_parse_server_timing() {
+    let timings =
+      parse_server_timing(r#"cache;desc="Cache Read";dur=23.2, db;dur=53, app;desc="a, \"b\"";dur="47.2", miss"#);
+    assert_eq!(
+      timings,
+      vec![
+        ServerTiming {
+          name: "cache".to_string(),
+          duration: Some(23.2),
+          description: Some("Cache Read".to_string()),
+        },
+        ServerTiming {
+          name: "db".to_string(),
+          duration: Some(53.0),
+          description: None,
+        },
+        ServerTiming {
+          name: "app".to_string(),
+          duration: Some(47.2),
+          description: Some("a, \"b\"".to_string()),
+        },
+        ServerTiming {
+          name: "miss".to_string(),
+          duration: None,
+          description: None,
+        },
+      ]
+    );
+    assert!(parse_server_timing(" ").is_empty());
+  }
+
+  #[test_case("512" => Some(512))]
+  #[test_case("64KB" => Some(65536))]
+  #[test_case("50 mb" => Some(52428800))]
+  #[test_case("1GiB" => Some(1073741824))]
+  #[test_case("MB" => None ; "missing number")]
+  #[test_case("10TB" => None ; "unknown unit")]
+  fn test_parse_size(value: &str) -> Option<u64> {
+    parse_size(value).ok()
+  }
+
+  #[test_case("30" => Some(30000))]
+  #[test_case("2.5" => Some(2500))]
+  #[test_case("0" => None ; "zero")]
+  #[test_case("10s" => None ; "unit")]
+  fn test_parse_timeout(value: &str) -> Option<u128> {
+    parse_timeout(value).ok().map(|timeout| timeout.as_millis())
+  }
+
+  #[test_case("120" => Some(120))]
+  #[test_case("Sun, 15 Oct 2023 12:00:30 GMT" => Some(30))]
+  #[test_case("Sun, 15 Oct 2023 11:00:00 GMT" => Some(0) ; "past date")]
+  #[test_case("soon" => None)]
+  fn test_retry_after_delay(value: &str) -> Option<u64> {
+    let now = Utc.ymd(2023, 10, 15).and_hms(12, 0, 0);
+    retry_after_delay(value, now).map(|delay| delay.as_secs())
+  }
+
+  #[test]
+  fn test_parse_bad_date() {
+    assert!(parse_date("next tuesday", Utc::now()).is_err());
+  }
+}
+
+#[cfg(test)]
+mod test_range_header {
+  use super::*;
+  use test_case::test_case;
+
+  #[test_case("0-1023" => "bytes=0-1023")]
+  #[test_case("bytes=1024-" => "bytes=1024-")]
+  #[test_case("-512" => "bytes=-512")]
+  #[test_case("0-99, 200-299" => "bytes=0-99,200-299")]
+  fn test_range_header(value: &str) -> String {
+    range_header(value).unwrap()
+  }
+
+  #[test_case("abc" ; "not a range")]
+  #[test_case("100-10" ; "reversed range")]
+  #[test_case("-" ; "empty range")]
+  fn test_bad_range_header(value: &str) {
+    assert!(range_header(value).is_err());
+  }
+}
+
+#[cfg(test)]
+mod test_continue_at {
+  use super::*;
+  use test_case::test_case;
+
+  #[test_case("auto" => ContinueAt::Auto)]
+  #[test_case("1024" => ContinueAt::Offset(1024))]
+  fn test_parse_continue_at(value: &str) -> ContinueAt {
+    value.parse().unwrap()
+  }
+
+  #[test]
+  fn test_continue_at_auto_missing_file() {
+    assert_eq!(ContinueAt::Auto.offset("missing-download.bin"), 0);
+    assert!("-1".parse::<ContinueAt>().is_err());
+  }
+
+  #[test_case("bytes 1024-2047/4096" => (Some(1024), Some(4096)))]
+  #[test_case("bytes */4096" => (None, Some(4096)))]
+  #[test_case("bytes 0-9/*" => (Some(0), None))]
+  fn test_content_range(content_range: &str) -> (Option<u64>, Option<u64>) {
+    (content_range_start(content_range), content_range_size(content_range))
+  }
+}
+
+#[cfg(test)]
+mod test_merge_url_queries {
+  use super::*;
+  use test_case::test_case;
+
+  #[test_case("http://localhost/users?a=b", &[] => "http://localhost/users?a=b" ; "no options")]
+  #[test_case("http://localhost/users?a=b", &[("c", "d")] => "http://localhost/users?a=b&c=d" ; "options appended")]
+  #[test_case("http://localhost/users?a=b&c=d", &[("a", "x")] => "http://localhost/users?a=x&c=d" ; "option overrides in place")]
+  #[test_case("http://localhost/users?a=1&c=d&a=2", &[("a", "x")]
+    => "http://localhost/users?a=x&c=d" ; "option overrides repeated pairs")]
+  #[test_case("http://localhost/users?a=1&a=2", &[("c", "d e")]
+    => "http://localhost/users?a=1&a=2&c=d+e" ; "repeated pairs kept")]
+  #[test_case("http://localhost/users?tag=a&c=d", &[("tag", "x"), ("tag", "y"), ("e", "1"), ("e", "2")]
+    => "http://localhost/users?tag=x&tag=y&c=d&e=1&e=2" ; "repeated options kept")]
+  fn test_merge_url_queries(url: &str, queries: &[(&str, &str)]) -> String {
+    let queries = queries
+      .iter()
+      .map(|(key, value)| (key.to_string(), value.to_string()))
+      .collect::<Vec<_>>();
+    merge_url_queries(url, Some(&queries)).unwrap().to_string()
+  }
+}
+
+#[cfg(test)]
+mod test_body_format {
+  use super::*;
+  use test_case::test_case;
+
+  #[test_case(BodyFormat::Json, r#"{"name":"apix"}"# => r#"{"name":"apix"}"# ; "json kept")]
+  #[test_case(BodyFormat::Form, r#"{"name":"apix rust","stars":3}"# => "name=apix+rust&stars=3" ; "json object encoded")]
+  #[test_case(BodyFormat::Form, "name=apix" => "name=apix" ; "encoded form kept")]
+  fn test_serialize_body(format: BodyFormat, body: &str) -> String {
+    format.serialize(body).unwrap()
+  }
+
+  #[test]
+  fn test_serialize_invalid_json() {
+    assert!(BodyFormat::Json.serialize("name=apix").is_err());
+  }
+}
+
+#[cfg(test)]
+mod test_tls_version {
+  use super::*;
+  use test_case::test_case;
+
+  #[test_case("1.0" => TlsVersion::V1_0)]
+  #[test_case("1.2" => TlsVersion::V1_2)]
+  #[test_case("1.3" => TlsVersion::V1_3)]
+  fn test_parse_tls_version(value: &str) -> TlsVersion {
+    value.parse().unwrap()
+  }
+
+  #[test]
+  fn test_bad_tls_version() {
+    assert!("1.4".parse::<TlsVersion>().is_err());
+    assert!("tls1.2".parse::<TlsVersion>().is_err());
+  }
+}
+
+#[cfg(test)]
+mod test_trust_store {
+  use super::*;
+  use test_case::test_case;
+
+  #[test_case("system" => TrustStore::System)]
+  #[test_case("bundled" => TrustStore::Bundled)]
+  fn test_parse_trust_store(value: &str) -> TrustStore {
+    value.parse().unwrap()
+  }
+
+  #[test]
+  fn test_bad_trust_store() {
+    assert!("webpki".parse::<TrustStore>().is_err());
+  }
+}
@@ -0,0 +1,554 @@
+use crate::assertions::{Assertion, Capture, Filter, Operator, PathSegment};
+use crate::export::SIMPLE_VARIABLE;
+use crate::git;
+use crate::manifests::{ApixManifest, ApixParameter, ApixRequest, ApixRequestTemplate};
+use anyhow::Result;
+use indexmap::{IndexMap, IndexSet};
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+use serde_json::{json, Value};
+use std::path::Path;
+use std::str::FromStr;
+
+static METHOD_LINE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^([A-Z]+)\s+(\S+)\s*$").unwrap());
+static STATUS_LINE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^HTTP(/[0-9.]+)?\s+([0-9]{3}|\*)\s*$").unwrap());
+static SECTION_LINE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\[([A-Za-z]+)\]\s*$").unwrap());
+static KEY_VALUE_LINE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^([A-Za-z0-9_.\-]+)\s*:\s*(.*)$").unwrap());
+static HURL_VARIABLE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\{\{\s*([A-Za-z_][A-Za-z0-9_\-]*)\s*\}\}").unwrap());
+static IDENT: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[A-Za-z_][A-Za-z0-9_]*$").unwrap());
+
+// rewrite apix variables as hurl ones, given with `--variable` or `HURL_<name>` env variables
+fn to_hurl_variables(template: &str) -> String {
+  SIMPLE_VARIABLE
+    .replace_all(template, |captures: &Captures| format!("{{{{{}}}}}", &captures[2]))
+    .into_owned()
+}
+
+// `$.items[0]['x-id']` from the path following `body`
+fn to_json_path(path: &[PathSegment]) -> Option<String> {
+  let mut json_path = "$".to_string();
+  for segment in path {
+    match segment {
+      PathSegment::Key(key) if IDENT.is_match(key) => json_path.push_str(&format!(".{}", key)),
+      PathSegment::Key(key) if !key.contains(['\'', '"', '\\']) => json_path.push_str(&format!("['{}']", key)),
+      PathSegment::Key(_) => return None,
+      PathSegment::Index(index) => json_path.push_str(&format!("[{}]", index)),
+    }
+  }
+  Some(json_path)
+}
+
+// hurl query and filters equivalent to an apix path, only the `length` filter has a hurl counterpart
+fn to_hurl_query(path: &[PathSegment], filters: &[Filter]) -> Option<String> {
+  let mut query = match path.split_first()? {
+    (PathSegment::Key(root), []) if root == "status" => "status".to_string(),
+    (PathSegment::Key(root), [PathSegment::Key(name)]) if root == "headers" => {
+      format!("header {}", Value::String(name.clone()))
+    }
+    (PathSegment::Key(root), path) if root == "body" => format!("jsonpath \"{}\"", to_json_path(path)?),
+    _ => return None,
+  };
+  for filter in filters {
+    match filter {
+      Filter::Length => query.push_str(" count"),
+      _ => return None,
+    }
+  }
+  Some(query)
+}
+
+fn to_hurl_assert(assertion: &Assertion) -> Option<String> {
+  let query = to_hurl_query(&assertion.path, &assertion.filters)?;
+  let predicate = match assertion.operator {
+    Operator::Equal => "==",
+    Operator::NotEqual => "!=",
+    Operator::Greater => ">",
+    Operator::GreaterOrEqual => ">=",
+    Operator::Lower => "<",
+    Operator::LowerOrEqual => "<=",
+    Operator::Contains => "contains",
+    Operator::NotContains => "not contains",
+    Operator::Matches => "matches",
+    Operator::Exists => return Some(format!("{} exists", query)),
+  };
+  match &assertion.expected {
+    Value::Array(_) | Value::Object(_) => None,
+    expected => Some(format!("{} {} {}", query, predicate, expected)),
+  }
+}
+
+fn value_to_string(value: &Value) -> String {
+  match value {
+    Value::String(value) => value.clone(),
+    value => value.to_string(),
+  }
+}
+
+// one hurl entry, assertions and exports without hurl equivalent are kept as comments
+pub fn to_hurl(name: &str, request: &ApixRequest) -> Result<String> {
+  let mut content = format!("# {}\n", name);
+  for parameter in &request.parameters {
+    let default = parameter
+      .schema
+      .as_ref()
+      .and_then(|schema| schema.get("default"))
+      .map(value_to_string)
+      .unwrap_or_default();
+    content.push_str(&format!("# --variable {}={}\n", parameter.name, default));
+  }
+  for (key, value) in &request.context {
+    content.push_str(&format!(
+      "# --variable {}={}\n",
+      key,
+      to_hurl_variables(&value_to_string(value))
+    ));
+  }
+  let template = &request.request;
+  content.push_str(&format!(
+    "{} {}\n",
+    template.method.to_uppercase(),
+    to_hurl_variables(&template.url)
+  ));
+  for (key, value) in &template.headers {
+    content.push_str(&format!("{}: {}\n", key, to_hurl_variables(value)));
+  }
+  if !template.queries.is_empty() {
+    content.push_str("[QueryStringParams]\n");
+    for (key, value) in &template.queries {
+      for value in value.values() {
+        content.push_str(&format!("{}: {}\n", key, to_hurl_variables(value)));
+      }
+    }
+  }
+  match &template.body {
+    Some(Value::String(body)) => content.push_str(&format!("```\n{}\n```\n", to_hurl_variables(body))),
+    Some(body) => content.push_str(&format!(
+      "{}\n",
+      to_hurl_variables(&serde_json::to_string_pretty(body)?)
+    )),
+    None => {}
+  }
+  if request.exports.is_empty() && request.assertions.is_empty() {
+    return Ok(content);
+  }
+  content.push_str("\nHTTP *\n");
+  if !request.exports.is_empty() {
+    content.push_str("[Captures]\n");
+    for (name, source) in &request.exports {
+      match Capture::from_str(source)
+        .ok()
+        .and_then(|capture| to_hurl_query(&capture.path, &capture.filters))
+      {
+        Some(query) => content.push_str(&format!("{}: {}\n", name, query)),
+        None => content.push_str(&format!("# unsupported apix export: {}: {}\n", name, source)),
+      }
+    }
+  }
+  if !request.assertions.is_empty() {
+    content.push_str("[Asserts]\n");
+    for source in &request.assertions {
+      match Assertion::from_str(source).ok().as_ref().and_then(to_hurl_assert) {
+        Some(assert) => content.push_str(&format!("{}\n", assert)),
+        None => content.push_str(&format!("# unsupported apix assertion: {}\n", source)),
+      }
+    }
+  }
+  Ok(content)
+}
+
+// json string at the start of input, with the rest of input
+fn quoted(input: &str) -> Option<(String, &str)> {
+  if !input.starts_with('"') {
+    return None;
+  }
+  let mut escaped = false;
+  for (index, c) in input.char_indices().skip(1) {
+    match c {
+      '\\' => escaped = !escaped,
+      '"' if !escaped => {
+        let value = serde_json::from_str::<String>(&input[..=index]).ok()?;
+        return Some((value, &input[index + 1..]));
+      }
+      _ => escaped = false,
+    }
+  }
+  None
+}
+
+// apix body path from a jsonpath, only plain keys and indexes are supported
+fn from_json_path(json_path: &str) -> Option<String> {
+  static SEGMENT: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"^(?:\.([A-Za-z_][A-Za-z0-9_\-]*)|\['([^']*)'\]|\["([^"]*)"\]|\[([0-9]+)\])"#).unwrap());
+  let mut rest = json_path.strip_prefix('$')?;
+  let mut path = "body".to_string();
+  while !rest.is_empty() {
+    let captures = SEGMENT.captures(rest)?;
+    match (
+      captures.get(1).or_else(|| captures.get(2)).or_else(|| captures.get(3)),
+      captures.get(4),
+    ) {
+      (Some(key), _) if IDENT.is_match(key.as_str()) => path.push_str(&format!(".{}", key.as_str())),
+      (Some(key), _) => path.push_str(&format!("[{}]", Value::String(key.as_str().to_string()))),
+      (None, Some(index)) => path.push_str(&format!("[{}]", index.as_str())),
+      (None, None) => return None,
+    }
+    rest = &rest[captures[0].len()..];
+  }
+  Some(path)
+}
+
+// apix path and filters from a hurl query, with the rest of input
+fn from_hurl_query(input: &str) -> Option<(String, &str)> {
+  let input = input.trim_start();
+  let (mut path, mut rest) = if let Some(rest) = input.strip_prefix("status") {
+    ("status".to_string(), rest)
+  } else if let Some(rest) = input.strip_prefix("header ") {
+    let (name, rest) = quoted(rest.trim_start())?;
+    (format!("headers[{}]", Value::String(name.to_lowercase())), rest)
+  } else if let Some(rest) = input.strip_prefix("jsonpath ") {
+    let (json_path, rest) = quoted(rest.trim_start())?;
+    (from_json_path(&json_path)?, rest)
+  } else {
+    return None;
+  };
+  loop {
+    rest = rest.trim_start();
+    match rest.strip_prefix("count") {
+      Some(after) if after.is_empty() || after.starts_with(' ') => {
+        path.push_str(" | length");
+        rest = after;
+      }
+      _ => return Some((path, rest)),
+    }
+  }
+}
+
+fn from_hurl_assert(line: &str) -> Option<String> {
+  let (path, rest) = from_hurl_query(line)?;
+  let (negated, rest) = match rest.strip_prefix("not ") {
+    Some(rest) => (true, rest.trim_start()),
+    None => (false, rest),
+  };
+  let symbols = [
+    ("==", "=="),
+    ("!=", "!="),
+    (">=", ">="),
+    ("<=", "<="),
+    (">", ">"),
+    ("<", "<"),
+    ("contains", "contains"),
+    ("includes", "contains"),
+    ("matches", "matches"),
+    ("exists", "exists"),
+  ];
+  let (symbol, operator) = symbols.into_iter().find(|(symbol, _)| rest.starts_with(symbol))?;
+  let value = rest[symbol.len()..].trim();
+  let operator = match (negated, operator) {
+    (false, operator) => operator.to_string(),
+    (true, "contains") => "not contains".to_string(),
+    _ => return None,
+  };
+  let value = match value.strip_prefix('/').and_then(|value| value.strip_suffix('/')) {
+    Some(pattern) if operator == "matches" => Value::String(pattern.replace("\\/", "/")).to_string(),
+    _ => value.to_string(),
+  };
+  let source = format!("{} {} {}", path, operator, value);
+  Assertion::from_str(source.trim())
+    .ok()
+    .map(|_| source.trim().to_string())
+}
+
+fn from_hurl_capture(line: &str) -> Option<String> {
+  let (path, rest) = from_hurl_query(line)?;
+  if !rest.trim().is_empty() {
+    return None;
+  }
+  Capture::from_str(&path).ok().map(|_| path)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Section {
+  Headers,
+  Queries,
+  Captures,
+  Asserts,
+  Body,
+  Unsupported,
+}
+
+#[derive(Default)]
+struct HurlEntry {
+  method: String,
+  url: String,
+  headers: IndexMap<String, String>,
+  queries: Vec<(String, String)>,
+  body: Vec<String>,
+  assertions: Vec<String>,
+  exports: IndexMap<String, String>,
+}
+
+impl HurlEntry {
+  fn into_request(self) -> ApixRequest {
+    let mut variables = IndexSet::new();
+    let mut rewrite = |template: &str| {
+      HURL_VARIABLE
+        .replace_all(template, |captures: &Captures| {
+          variables.insert(captures[1].to_string());
+          format!("{{{{ parameters.{} }}}}", &captures[1])
+        })
+        .into_owned()
+    };
+    let url = rewrite(&self.url);
+    let headers = self
+      .headers
+      .iter()
+      .map(|(key, value)| (key.clone(), rewrite(value)))
+      .collect();
+    let queries = self
+      .queries
+      .iter()
+      .map(|(key, value)| (key.clone(), rewrite(value)))
+      .collect();
+    let body = rewrite(self.body.join("\n").trim());
+    let body = match body.as_str() {
+      "" => None,
+      body => Some(serde_json::from_str(body).unwrap_or_else(|_| Value::String(body.to_string()))),
+    };
+    let parameters = variables
+      .into_iter()
+      .map(|name| ApixParameter::new(name, true, false, None, Some(json!({ "type": "string" }))))
+      .collect();
+    let mut request = ApixRequest::new(
+      parameters,
+      IndexMap::new(),
+      ApixRequestTemplate::new(self.method, url, headers, queries, body),
+    );
+    request.assertions = self.assertions;
+    request.exports = self.exports;
+    request
+  }
+}
+
+// requests of a hurl file, hurl variables become required parameters, unsupported lines are skipped with a warning
+pub fn from_hurl(content: &str) -> Result<Vec<ApixRequest>> {
+  let mut entries: Vec<HurlEntry> = Vec::new();
+  let mut section = Section::Headers;
+  let mut in_response = false;
+  let mut in_multiline = false;
+  for (number, line) in content.lines().enumerate() {
+    let warn = |what: &str| {
+      eprintln!(
+        "warning: unsupported hurl {} at line {}, skipped: {}",
+        what,
+        number + 1,
+        line
+      )
+    };
+    if in_multiline {
+      if line.trim() == "```" {
+        in_multiline = false;
+      } else if let Some(entry) = entries.last_mut() {
+        entry.body.push(line.to_string());
+      }
+      continue;
+    }
+    let trimmed = line.trim();
+    if trimmed.is_empty() || trimmed.starts_with('#') {
+      continue;
+    }
+    if let Some(captures) = METHOD_LINE.captures(trimmed).filter(|_| !STATUS_LINE.is_match(trimmed)) {
+      entries.push(HurlEntry {
+        method: captures[1].to_string(),
+        url: captures[2].to_string(),
+        ..Default::default()
+      });
+      section = Section::Headers;
+      in_response = false;
+      continue;
+    }
+    let entry = match entries.last_mut() {
+      Some(entry) => entry,
+      None => return Err(anyhow::anyhow!("Expected a request at line {}: {}", number + 1, line)),
+    };
+    if let Some(captures) = STATUS_LINE.captures(trimmed) {
+      if &captures[2] != "*" {
+        entry.assertions.push(format!("status == {}", &captures[2]));
+      }
+      section = Section::Headers;
+      in_response = true;
+      continue;
+    }
+    if let Some(captures) = SECTION_LINE.captures(trimmed) {
+      section = match (in_response, &captures[1]) {
+        (false, "QueryStringParams" | "Query") => Section::Queries,
+        (true, "Captures") => Section::Captures,
+        (true, "Asserts") => Section::Asserts,
+        _ => {
+          warn("section");
+          Section::Unsupported
+        }
+      };
+      continue;
+    }
+    match (section, KEY_VALUE_LINE.captures(trimmed)) {
+      (Section::Headers, Some(captures)) if !in_response => {
+        entry.headers.insert(captures[1].to_string(), captures[2].to_string());
+      }
+      (Section::Headers, Some(captures)) => entry.assertions.push(format!(
+        "headers[{}] == {}",
+        Value::String(captures[1].to_lowercase()),
+        Value::String(captures[2].to_string())
+      )),
+      (Section::Queries, Some(captures)) => entry.queries.push((captures[1].to_string(), captures[2].to_string())),
+      (Section::Captures, Some(captures)) => match from_hurl_capture(&captures[2]) {
+        Some(capture) => {
+          entry.exports.insert(captures[1].to_string(), capture);
+        }
+        None => warn("capture"),
+      },
+      (Section::Asserts, _) => match from_hurl_assert(trimmed) {
+        Some(assertion) => entry.assertions.push(assertion),
+        None => warn("assert"),
+      },
+      (Section::Unsupported, _) => {}
+      _ if in_response => warn("response body"),
+      _ => {
+        section = Section::Body;
+        match trimmed.strip_prefix("```") {
+          Some(rest) if !rest.contains('`') => in_multiline = true,
+          _ => entry.body.push(line.to_string()),
+        }
+      }
+    }
+  }
+  Ok(entries.into_iter().map(HurlEntry::into_request).collect())
+}
+
+// save each request of a hurl file as a request manifest named after the file
+pub fn handle_import_hurl(file: &str) -> Result<()> {
+  let content =
+    std::fs::read_to_string(file).map_err(|e| anyhow::anyhow!("Failed to read hurl file {}\ncause: {}", file, e))?;
+  let requests = from_hurl(&content)?;
+  if requests.is_empty() {
+    return Err(anyhow::anyhow!("No request found in hurl file {}", file));
+  }
+  let stem = Path::new(file)
+    .file_stem()
+    .and_then(|stem| stem.to_str())
+    .unwrap_or("request");
+  let count = requests.len();
+  let mut filenames = Vec::new();
+  for (index, request) in requests.into_iter().enumerate() {
+    let name = match count {
+      1 => stem.to_string(),
+      _ => format!("{}-{}", stem, index + 1),
+    };
+    let filename = format!("{}.yaml", &name);
+    let manifest = ApixManifest::new_request("test".to_string(), name, request);
+    std::fs::write(&filename, serde_yaml::to_string(&manifest)?)?;
+    println!("imported {}", filename);
+    filenames.push(filename);
+  }
+  git::auto_commit("import", &filenames)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use indexmap::indexmap;
+  use test_case::test_case;
+
+  #[test_case("status == 200" => Some("status == 200".to_string()) ; "status")]
+  #[test_case("headers[\"content-type\"] contains \"json\"" => Some("header \"content-type\" contains \"json\"".to_string()) ; "header")]
+  #[test_case("body.items[0][\"x-id\"] | length >= 1" => Some("jsonpath \"$.items[0]['x-id']\" count >= 1".to_string()) ; "jsonpath")]
+  #[test_case("body.token exists" => Some("jsonpath \"$.token\" exists".to_string()) ; "exists")]
+  #[test_case("body.name | lower == \"apix\"" => None ; "unsupported filter")]
+  fn test_to_hurl_assert(assertion: &str) -> Option<String> {
+    to_hurl_assert(&Assertion::from_str(assertion).unwrap())
+  }
+
+  #[test_case("status == 200" => Some("status == 200".to_string()) ; "status")]
+  #[test_case("header \"Content-Type\" contains \"json\"" => Some("headers[\"content-type\"] contains \"json\"".to_string()) ; "header")]
+  #[test_case("jsonpath \"$.items[0]['x-id']\" count >= 1" => Some("body.items[0][\"x-id\"] | length >= 1".to_string()) ; "jsonpath")]
+  #[test_case("jsonpath \"$.tags\" not includes \"a\"" => Some("body.tags not contains \"a\"".to_string()) ; "not includes")]
+  #[test_case("jsonpath \"$.name\" matches /^a\\/b$/" => Some("body.name matches \"^a/b$\"".to_string()) ; "regex")]
+  #[test_case("jsonpath \"$..name\" exists" => None ; "descendant")]
+  #[test_case("duration < 1000" => None ; "duration")]
+  fn test_from_hurl_assert(assert: &str) -> Option<String> {
+    from_hurl_assert(assert)
+  }
+
+  #[test]
+  fn test_to_hurl() {
+    let mut request = ApixRequest::new(
+      vec![],
+      indexmap! { "url".to_string() => json!("https://example.com") },
+      ApixRequestTemplate::new(
+        "post".to_string(),
+        "{{ context.url }}/todos".to_string(),
+        indexmap! { "Accept".to_string() => "application/json".to_string() },
+        vec![("tag".to_string(), "a".to_string())],
+        Some(json!({ "title": "{{ env.TITLE }}" })),
+      ),
+    );
+    request.assertions = vec!["status == 201".to_string(), "body.title | upper == \"A\"".to_string()];
+    request.exports = indexmap! { "TODO_ID".to_string() => "body.id".to_string() };
+    assert_eq!(
+      to_hurl("create-todo", &request).unwrap(),
+      "# create-todo\n# --variable url=https://example.com\nPOST {{url}}/todos\nAccept: application/json\n[QueryStringParams]\ntag: a\n{\n  \"title\": \"{{TITLE}}\"\n}\n\nHTTP *\n[Captures]\nTODO_ID: jsonpath \"$.id\"\n[Asserts]\nstatus == 201\n# unsupported apix assertion: body.title | upper == \"A\"\n"
+    );
+  }
+
+  #[test]
+  fn test_from_hurl() {
+    let requests = from_hurl(
+      r#"
+# login
+POST {{base}}/login
+Content-Type: application/json
+{
+  "user": "{{user}}"
+}
+
+HTTP 200
+Content-Type: application/json
+[Captures]
+TOKEN: jsonpath "$.token"
+[Asserts]
+jsonpath "$.token" exists
+
+GET {{base}}/todos
+[QueryStringParams]
+limit: 2
+```
+raw
+```
+"#,
+    )
+    .unwrap();
+    assert_eq!(requests.len(), 2);
+    let login = &requests[0];
+    assert_eq!(
+      login.parameters.iter().map(|p| p.name.as_str()).collect::<Vec<_>>(),
+      ["base", "user"]
+    );
+    assert_eq!(login.request.url, "{{ parameters.base }}/login");
+    assert_eq!(login.request.body, Some(json!({ "user": "{{ parameters.user }}" })));
+    assert_eq!(
+      login.assertions,
+      [
+        "status == 200",
+        "headers[\"content-type\"] == \"application/json\"",
+        "body.token exists"
+      ]
+    );
+    assert_eq!(
+      login.exports,
+      indexmap! { "TOKEN".to_string() => "body.token".to_string() }
+    );
+    let todos = &requests[1];
+    assert_eq!(todos.request.method, "GET");
+    assert_eq!(todos.request.queries["limit"].values(), ["2"]);
+    assert_eq!(todos.request.body, Some(json!("raw")));
+  }
+}
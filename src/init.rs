@@ -0,0 +1,267 @@
+use super::manifests::PROJECT_DIR;
+use anyhow::{anyhow, Result};
+use cmd_lib::run_cmd;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::path::Path;
+
+static GITIGNORE_ENTRIES: [&str; 5] = [
+  ".apix/*.lock",
+  ".apix/context.yaml",
+  ".apix/sessions/",
+  ".apix/history.db*",
+  ".apix/history.key",
+];
+
+static SAMPLE_API: &str = r#"# sample api, requests and stories refer to it with the `apix.io/api` label
+apiVersion: apix.io/v1
+metadata:
+  name: sample
+  labels:
+    app: apix
+kind: Api
+spec:
+  url: https://jsonplaceholder.typicode.com
+  version: 1.0.0
+  description: sample api to start with apix
+"#;
+
+static SAMPLE_REQUEST: &str = r#"# execute this request with:
+# apix exec sample-get-todo --param todoId=1
+apiVersion: apix.io/v1
+metadata:
+  name: sample-get-todo
+  labels:
+    app: apix
+    apix.io/api: sample
+kind: Request
+spec:
+  parameters:
+    - name: todoId
+      required: true
+      schema:
+        type: integer
+        default: 1
+        minimum: 1
+        maximum: 200
+  context:
+    url: https://jsonplaceholder.typicode.com/todos
+  request:
+    method: get
+    url: "{{ context.url }}/{{ parameters.todoId }}"
+    headers:
+      accept: application/json
+  assertions:
+    - status == 200
+    - body.completed exists
+"#;
+
+static SAMPLE_STORY: &str = r#"# sample story chaining two requests, the second one uses the response of the first one
+apiVersion: apix.io/v1
+metadata:
+  name: sample-story
+  labels:
+    app: apix
+    apix.io/api: sample
+kind: Story
+spec:
+  stories:
+    - name: get-todo-owner
+      description: get a todo, then get the user owning it
+      steps:
+        - name: get_todo
+          request:
+            method: get
+            url: https://jsonplaceholder.typicode.com/todos/1
+        - name: get_user
+          request:
+            method: get
+            url: "https://jsonplaceholder.typicode.com/users/{{ steps.get_todo.response.body.userId }}"
+          assertions:
+            - status == 200
+"#;
+
+static SAMPLE_CONFIG: &str = r#"# project configuration, values override user configuration for this project
+# example:
+#   request-id-header: x-correlation-id
+apiVersion: apix.io/v1
+metadata:
+  name: configuration
+  labels:
+    app: apix
+kind: Configuration
+spec: {}
+"#;
+
+static SAMPLES: [(&str, &str); 4] = [
+  ("api.yaml", SAMPLE_API),
+  ("request.yaml", SAMPLE_REQUEST),
+  ("story.yaml", SAMPLE_STORY),
+  ("config.yaml", SAMPLE_CONFIG),
+];
+
+// add apix private files to .gitignore, keeping existing entries
+fn update_gitignore() -> Result<()> {
+  let content = std::fs::read_to_string(".gitignore").unwrap_or_default();
+  let mut lines = content.lines().map(str::to_string).collect::<Vec<_>>();
+  for entry in GITIGNORE_ENTRIES {
+    if !lines.iter().any(|line| line.trim() == entry) {
+      lines.push(entry.to_string());
+    }
+  }
+  std::fs::write(".gitignore", lines.join("\n") + "\n").map_err(|e| anyhow!("Failed to save .gitignore\ncause: {}", e))
+}
+
+// create sample manifests in project directory, existing files are left untouched
+fn create_samples(dir: &Path) -> Result<Vec<String>> {
+  std::fs::create_dir_all(dir).map_err(|e| anyhow!("Failed to create {:?}\ncause: {}", dir, e))?;
+  let mut created = Vec::new();
+  for (filename, content) in SAMPLES {
+    let path = dir.join(filename);
+    if path.exists() {
+      eprintln!("skipping {:?}, file already exists", path);
+      continue;
+    }
+    std::fs::write(&path, content).map_err(|e| anyhow!("Failed to create {:?}\ncause: {}", &path, e))?;
+    created.push(path.to_string_lossy().to_string());
+  }
+  Ok(created)
+}
+
+// replace `{{ project.name }}` placeholders, other template expressions are kept for request execution
+fn substitute_project_name(content: &str, name: &str) -> String {
+  static RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\{\{\s*project\.name\s*\}\}").unwrap());
+  RE.replace_all(content, name).to_string()
+}
+
+// copy template files into destination, skipping vcs metadata and existing files
+fn copy_template(source: &Path, destination: &Path, name: &str) -> Result<Vec<String>> {
+  let mut created = Vec::new();
+  let mut pending = vec![source.to_path_buf()];
+  while let Some(dir) = pending.pop() {
+    for entry in std::fs::read_dir(&dir)? {
+      let path = entry?.path();
+      if path.file_name().is_some_and(|filename| filename == ".git") {
+        continue;
+      }
+      if path.is_dir() {
+        pending.push(path);
+        continue;
+      }
+      let target = destination.join(path.strip_prefix(source)?);
+      if target.exists() {
+        eprintln!("skipping {:?}, file already exists", target);
+        continue;
+      }
+      if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent)?;
+      }
+      match std::fs::read_to_string(&path) {
+        Ok(content) => std::fs::write(&target, substitute_project_name(&content, name)),
+        Err(_) => std::fs::copy(&path, &target).map(|_| ()),
+      }
+      .map_err(|e| anyhow!("Failed to create {:?}\ncause: {}", &target, e))?;
+      created.push(target.to_string_lossy().to_string());
+    }
+  }
+  created.sort();
+  Ok(created)
+}
+
+// shallow clone a starter template and copy its files in current directory
+fn create_from_template(url: &str) -> Result<Vec<String>> {
+  run_cmd! {git --version}.map_err(|_| anyhow!("git command not found, it is required to clone templates"))?;
+  let current_dir = std::env::current_dir()?;
+  let name = current_dir
+    .file_name()
+    .map(|name| name.to_string_lossy().to_string())
+    .unwrap_or_else(|| PROJECT_DIR.to_string());
+  let clone_dir = std::env::temp_dir().join(format!("apix-template-{}", uuid::Uuid::new_v4()));
+  let clone_path = clone_dir.to_string_lossy().to_string();
+  run_cmd! {git clone --quiet --depth 1 $url $clone_path}
+    .map_err(|e| anyhow!("Failed to clone template {}\ncause: {}", url, e))?;
+  let created = copy_template(&clone_dir, &current_dir, &name);
+  std::fs::remove_dir_all(&clone_dir)?;
+  Ok(
+    created?
+      .into_iter()
+      .filter_map(|path| {
+        Path::new(&path)
+          .strip_prefix(&current_dir)
+          .ok()
+          .map(|path| path.to_string_lossy().to_string())
+      })
+      .collect(),
+  )
+}
+
+pub fn handle_init(use_git: bool, template: Option<&str>) -> Result<()> {
+  if use_git {
+    run_cmd! {git --version}.map_err(|_| anyhow!("git command not found"))?;
+  }
+  update_gitignore()?;
+  let created = match template {
+    Some(url) => create_from_template(url)?,
+    None => create_samples(Path::new(PROJECT_DIR))?,
+  };
+  if use_git {
+    run_cmd! {
+      git init;
+      git add .gitignore $[created];
+      git commit -m "Apix init commit"
+    }
+    .map_err(|e| anyhow!("Failed to init apix repository\ncause: {}", e))?;
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::manifests::ApixManifest;
+  use test_case::test_case;
+
+  #[test_case(SAMPLE_API => "Api")]
+  #[test_case(SAMPLE_REQUEST => "Request")]
+  #[test_case(SAMPLE_STORY => "Story")]
+  #[test_case(SAMPLE_CONFIG => "Configuration")]
+  fn test_samples_are_valid_manifests(content: &str) -> String {
+    let manifest: ApixManifest = serde_yaml::from_str(content).unwrap();
+    manifest.kind().to_string()
+  }
+
+  #[test_case("name: {{ project.name }}-api" => "name: shop-api")]
+  #[test_case("url: {{project.name}}/{{ parameters.id }}" => "url: shop/{{ parameters.id }}")]
+  fn test_substitute_project_name(content: &str) -> String {
+    substitute_project_name(content, "shop")
+  }
+
+  #[test]
+  fn test_copy_template() {
+    let root = std::env::temp_dir().join(format!("apix-test-{}", uuid::Uuid::new_v4()));
+    let source = root.join("template");
+    let destination = root.join("project");
+    std::fs::create_dir_all(source.join(".git")).unwrap();
+    std::fs::create_dir_all(source.join("apix")).unwrap();
+    std::fs::create_dir_all(&destination).unwrap();
+    std::fs::write(source.join(".git").join("HEAD"), "ref").unwrap();
+    std::fs::write(source.join("apix").join("api.yaml"), "name: {{ project.name }}").unwrap();
+    std::fs::write(source.join("README.md"), "new readme").unwrap();
+    std::fs::write(destination.join("README.md"), "existing readme").unwrap();
+    let created = copy_template(&source, &destination, "shop").unwrap();
+    assert_eq!(
+      created,
+      vec![destination.join("apix").join("api.yaml").to_string_lossy().to_string()]
+    );
+    assert_eq!(
+      std::fs::read_to_string(destination.join("apix").join("api.yaml")).unwrap(),
+      "name: shop"
+    );
+    assert_eq!(
+      std::fs::read_to_string(destination.join("README.md")).unwrap(),
+      "existing readme"
+    );
+    assert!(!destination.join(".git").exists());
+    std::fs::remove_dir_all(root).unwrap();
+  }
+}
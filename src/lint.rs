@@ -0,0 +1,355 @@
+use crate::execute::expand_manifest_files;
+use crate::manifests::{ApixConfiguration, ApixManifest};
+use crate::validators::is_template;
+use anyhow::Result;
+use indexmap::{IndexMap, IndexSet};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde_yaml::Value;
+use std::str::FromStr;
+use strum_macros::Display;
+
+// best practice checked by `ctl lint`, its level is set with the `lint-<rule>` config key
+#[derive(Debug, Clone, Copy, PartialEq, Display)]
+#[strum(serialize_all = "kebab-case")]
+pub enum LintRule {
+  HardcodedSecret,
+  MissingSchema,
+  UnreachableVariable,
+  UnusedParameter,
+  DuplicateName,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum LintLevel {
+  Off,
+  Warning,
+  Error,
+}
+
+impl FromStr for LintLevel {
+  type Err = anyhow::Error;
+
+  fn from_str(value: &str) -> Result<Self> {
+    match value {
+      "off" => Ok(LintLevel::Off),
+      "warning" | "warn" => Ok(LintLevel::Warning),
+      "error" => Ok(LintLevel::Error),
+      _ => Err(anyhow::anyhow!(
+        "Bad lint level: \"{}\", should be off, warning or error",
+        value
+      )),
+    }
+  }
+}
+
+impl LintRule {
+  fn default_level(&self) -> LintLevel {
+    match self {
+      LintRule::HardcodedSecret | LintRule::DuplicateName => LintLevel::Error,
+      _ => LintLevel::Warning,
+    }
+  }
+
+  pub fn level(&self) -> Result<LintLevel> {
+    ApixConfiguration::once()
+      .get(&format!("lint-{}", self))
+      .map_or(Ok(self.default_level()), |level| level.parse())
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LintIssue {
+  pub file: String,
+  pub rule: LintRule,
+  pub message: String,
+}
+
+impl LintIssue {
+  fn new(file: &str, rule: LintRule, message: String) -> Self {
+    Self {
+      file: file.to_string(),
+      rule,
+      message,
+    }
+  }
+}
+
+// name of a parameter referenced by `parameters.name` or `parameters["name"]`
+static PARAMETER_REFERENCE: Lazy<Regex> =
+  Lazy::new(|| Regex::new(r#"\bparameters(?:\.(\w+)|\[\s*["']([^"']+)["']\s*\])"#).unwrap());
+
+// keys whose literal values are credentials
+static SECRET_KEY: Lazy<Regex> =
+  Lazy::new(|| Regex::new(r"(?i)(password|secret|token|authorization|api[-_]?key|credential)").unwrap());
+
+static SECRET_VALUE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)^(bearer|basic)\s+[^\s{]").unwrap());
+
+fn key_name(key: &Value) -> String {
+  match key {
+    Value::String(key) => key.clone(),
+    key => serde_yaml::to_string(key)
+      .unwrap_or_default()
+      .trim_start_matches("---")
+      .trim()
+      .to_string(),
+  }
+}
+
+// every string of the manifest spec with its location, descriptions are not templates
+fn spec_strings(location: String, value: &Value, strings: &mut Vec<(String, String)>) {
+  match value {
+    Value::String(content) => strings.push((location, content.clone())),
+    Value::Sequence(values) => {
+      for (index, value) in values.iter().enumerate() {
+        spec_strings(format!("{}.{}", location, index), value, strings);
+      }
+    }
+    Value::Mapping(values) => {
+      for (key, value) in values {
+        let key = key_name(key);
+        if key != "description" {
+          spec_strings(format!("{}.{}", location, key), value, strings);
+        }
+      }
+    }
+    _ => {}
+  }
+}
+
+fn lint_secrets(file: &str, strings: &[(String, String)], issues: &mut Vec<LintIssue>) {
+  for (location, value) in strings {
+    let key = location.rsplit('.').next().unwrap_or_default();
+    let secret = (SECRET_KEY.is_match(key) && !value.is_empty()) || SECRET_VALUE.is_match(value);
+    if secret && !is_template(value) {
+      issues.push(LintIssue::new(
+        file,
+        LintRule::HardcodedSecret,
+        format!(
+          "{} is a hardcoded secret, use a password parameter or an env variable",
+          location
+        ),
+      ));
+    }
+  }
+}
+
+// checks of a single manifest, duplicate names are checked across manifests
+pub fn lint_manifest(file: &str, content: &str) -> Result<Vec<LintIssue>> {
+  let document: Value =
+    serde_yaml::from_str(content).map_err(|e| anyhow::anyhow!("Invalid manifest {}\ncause: {}", file, e))?;
+  let spec = document.get("spec").cloned().unwrap_or(Value::Null);
+  let parameters = spec
+    .get("parameters")
+    .and_then(Value::as_sequence)
+    .cloned()
+    .unwrap_or_default();
+  let mut strings = Vec::new();
+  spec_strings("spec".to_string(), &spec, &mut strings);
+  let templates = strings
+    .iter()
+    .filter(|(location, _)| !location.starts_with("spec.parameters."))
+    .cloned()
+    .collect::<Vec<_>>();
+  let mut issues = Vec::new();
+  lint_secrets(file, &strings, &mut issues);
+  let mut declared = IndexSet::new();
+  for parameter in &parameters {
+    let name = parameter.get("name").and_then(Value::as_str).unwrap_or_default();
+    declared.insert(name.to_string());
+    if parameter.get("schema").is_none_or(Value::is_null) {
+      issues.push(LintIssue::new(
+        file,
+        LintRule::MissingSchema,
+        format!("parameter \"{}\" has no schema", name),
+      ));
+    }
+  }
+  let mut referenced = IndexMap::new();
+  for (location, template) in templates.iter().filter(|(_, template)| is_template(template)) {
+    for captures in PARAMETER_REFERENCE.captures_iter(template) {
+      let name = captures.get(1).or_else(|| captures.get(2)).unwrap().as_str();
+      referenced.entry(name.to_string()).or_insert_with(|| location.clone());
+    }
+  }
+  // template libraries use parameters of the requests including them
+  let library = document.get("kind").and_then(Value::as_str) == Some("TemplateLibrary");
+  for (name, location) in &referenced {
+    if !library && !declared.contains(name) {
+      issues.push(LintIssue::new(
+        file,
+        LintRule::UnreachableVariable,
+        format!("{} uses parameter \"{}\" which is not declared", location, name),
+      ));
+    }
+  }
+  for name in &declared {
+    if !referenced.contains_key(name) {
+      issues.push(LintIssue::new(
+        file,
+        LintRule::UnusedParameter,
+        format!("parameter \"{}\" is never used", name),
+      ));
+    }
+  }
+  Ok(issues)
+}
+
+// resources of the same kind sharing a name can't be found by name
+pub fn lint_duplicates(manifests: &[(String, ApixManifest)]) -> Vec<LintIssue> {
+  let mut seen: IndexMap<(String, &str), &str> = IndexMap::new();
+  let mut issues = Vec::new();
+  for (file, manifest) in manifests {
+    if matches!(manifest, ApixManifest::None) {
+      continue;
+    }
+    let key = (manifest.kind().to_string().to_lowercase(), manifest.name());
+    match seen.get(&key) {
+      Some(first) => issues.push(LintIssue::new(
+        file,
+        LintRule::DuplicateName,
+        format!("{} \"{}\" is already defined in {}", key.0, key.1, first),
+      )),
+      None => {
+        seen.insert(key, file);
+      }
+    }
+  }
+  issues
+}
+
+pub fn handle_lint<'a>(patterns: Option<impl Iterator<Item = &'a str>>) -> Result<()> {
+  let files = match patterns {
+    Some(patterns) => expand_manifest_files(patterns)?,
+    None => ApixManifest::find_manifests()?
+      .filter_map(|(path, _)| path.to_str().map(str::to_string))
+      .collect(),
+  };
+  let mut issues = Vec::new();
+  let mut manifests = Vec::new();
+  for file in &files {
+    let content =
+      std::fs::read_to_string(file).map_err(|e| anyhow::anyhow!("Failed to read manifest {}\ncause: {}", file, e))?;
+    issues.extend(lint_manifest(file, &content)?);
+    manifests.push((file.clone(), serde_yaml::from_str(&content).unwrap_or_default()));
+  }
+  issues.extend(lint_duplicates(&manifests));
+  let (mut errors, mut warnings) = (0, 0);
+  for issue in issues {
+    let level = issue.rule.level()?;
+    match level {
+      LintLevel::Off => continue,
+      LintLevel::Warning => warnings += 1,
+      LintLevel::Error => errors += 1,
+    }
+    println!("{}: {}[{}] {}", issue.file, level, issue.rule, issue.message);
+  }
+  println!(
+    "{} manifests checked: {} errors, {} warnings",
+    files.len(),
+    errors,
+    warnings
+  );
+  match errors {
+    0 => Ok(()),
+    errors => Err(anyhow::anyhow!("Lint failed with {} errors", errors)),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use test_case::test_case;
+
+  static REQUEST: &str = r#"
+apiVersion: apix.io/v1
+kind: Request
+metadata:
+  name: get-user
+spec:
+  parameters:
+    - name: id
+      required: true
+      schema:
+        type: integer
+    - name: password
+      password: true
+      schema: null
+    - name: unused
+      schema:
+        type: string
+  request:
+    method: get
+    url: "https://api.example.com/users/{{ parameters.id }}?filter={{ parameters['filter'] }}"
+    headers:
+      authorization: "Basic {{ parameters.password }}"
+      x-api-key: "0123456789"
+    body: "Bearer abcdef"
+"#;
+
+  fn rules(issues: &[LintIssue], rule: LintRule) -> Vec<String> {
+    issues
+      .iter()
+      .filter(|issue| issue.rule == rule)
+      .map(|issue| issue.message.clone())
+      .collect()
+  }
+
+  #[test]
+  fn test_lint_manifest() {
+    let issues = lint_manifest("request.yaml", REQUEST).unwrap();
+    assert_eq!(
+      rules(&issues, LintRule::HardcodedSecret),
+      vec![
+        "spec.request.headers.x-api-key is a hardcoded secret, use a password parameter or an env variable",
+        "spec.request.body is a hardcoded secret, use a password parameter or an env variable",
+      ]
+    );
+    assert_eq!(
+      rules(&issues, LintRule::MissingSchema),
+      vec!["parameter \"password\" has no schema"]
+    );
+    assert_eq!(
+      rules(&issues, LintRule::UnreachableVariable),
+      vec!["spec.request.url uses parameter \"filter\" which is not declared"]
+    );
+    assert_eq!(
+      rules(&issues, LintRule::UnusedParameter),
+      vec!["parameter \"unused\" is never used"]
+    );
+  }
+
+  #[test]
+  fn test_lint_template_library() {
+    let library = r#"
+apiVersion: apix.io/v1
+kind: TemplateLibrary
+metadata:
+  name: common
+spec:
+  templates:
+    owner: '"owner": "{{ parameters.owner }}"'
+"#;
+    assert!(lint_manifest("common.yaml", library).unwrap().is_empty());
+  }
+
+  #[test]
+  fn test_lint_duplicates() {
+    let manifests = ["a.yaml", "b.yaml"]
+      .iter()
+      .map(|file| (file.to_string(), serde_yaml::from_str(REQUEST).unwrap()))
+      .collect::<Vec<_>>();
+    let issues = lint_duplicates(&manifests);
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0].file, "b.yaml");
+    assert_eq!(issues[0].message, "request \"get-user\" is already defined in a.yaml");
+  }
+
+  #[test_case("off" => LintLevel::Off)]
+  #[test_case("warn" => LintLevel::Warning)]
+  #[test_case("error" => LintLevel::Error)]
+  fn test_parse_lint_level(level: &str) -> LintLevel {
+    level.parse().unwrap()
+  }
+}
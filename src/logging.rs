@@ -0,0 +1,92 @@
+use anyhow::Result;
+use std::fs::{File, OpenOptions};
+use std::str::FromStr;
+use std::sync::Mutex;
+use tracing::{Level, Subscriber};
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+pub static LOG_LEVELS: [&str; 5] = ["error", "warn", "info", "debug", "trace"];
+
+// json events of apix itself, one object per line, events of libraries are left out
+fn file_layer<S>(file: File, level: Level) -> impl Layer<S>
+where
+  S: Subscriber + for<'span> LookupSpan<'span>,
+{
+  tracing_subscriber::fmt::layer()
+    .json()
+    .flatten_event(true)
+    .with_ansi(false)
+    .with_writer(Mutex::new(file))
+    .with_filter(Targets::new().with_target("apix", level))
+}
+
+// wire level events of the http stack, reqwest events are sent through the `log` crate
+fn trace_filter() -> Targets {
+  Targets::new()
+    .with_target("hyper", Level::TRACE)
+    .with_target("h2", Level::TRACE)
+    .with_target("reqwest", Level::TRACE)
+    .with_target("apix", Level::INFO)
+}
+
+// structured logs are appended to the log file, separate from what is printed to users
+// `--trace` prints connection events to stderr
+pub fn init_logging(log_file: Option<&str>, log_level: Option<&str>, trace: bool) -> Result<()> {
+  if log_file.is_none() && !trace {
+    return Ok(());
+  }
+  let file_layer = log_file
+    .map(|log_file| {
+      let level = Level::from_str(log_level.unwrap_or("info"))
+        .map_err(|e| anyhow::anyhow!("Invalid log level {}\ncause: {}", log_level.unwrap_or_default(), e))?;
+      let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_file)
+        .map_err(|e| anyhow::anyhow!("Failed to open log file {}\ncause: {}", log_file, e))?;
+      Ok::<_, anyhow::Error>(file_layer(file, level))
+    })
+    .transpose()?;
+  let trace_layer = trace.then(|| {
+    tracing_subscriber::fmt::layer()
+      .with_writer(std::io::stderr)
+      .with_ansi(atty::is(atty::Stream::Stderr))
+      .with_filter(trace_filter())
+  });
+  tracing_subscriber::registry()
+    .with(file_layer)
+    .with(trace_layer)
+    .try_init()
+    .map_err(|e| anyhow::anyhow!("Failed to initialize logging\ncause: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::Value;
+
+  #[test]
+  fn test_file_layer() {
+    let path = std::env::temp_dir().join(format!("apix-log-{}.log", uuid::Uuid::new_v4()));
+    let file = File::create(&path).unwrap();
+    let subscriber = tracing_subscriber::registry().with(file_layer(file, Level::DEBUG));
+    tracing::subscriber::with_default(subscriber, || {
+      tracing::info!(status = 200, duration_ms = 12, "request end");
+      tracing::trace!("too verbose");
+      tracing::info!(target: "hyper::client", "connecting");
+    });
+    let content = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let events = content
+      .lines()
+      .map(|line| serde_json::from_str::<Value>(line).unwrap())
+      .collect::<Vec<_>>();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0]["level"], "INFO");
+    assert_eq!(events[0]["message"], "request end");
+    assert_eq!(events[0]["status"], 200);
+  }
+}
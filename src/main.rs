@@ -1,40 +1,77 @@
+mod apply;
+mod artifacts;
+mod assertions;
+mod auth;
+mod bench;
 mod build_args;
+mod cache;
+mod certificates;
+mod completions;
+mod decode;
 mod dialog;
+mod diff;
 mod display;
+mod dotenv;
 mod editor;
+mod encryption;
 mod execute;
+mod export;
+mod git;
+mod graph;
+mod history;
 mod http_utils;
+mod hurl;
 mod import;
+mod init;
+mod lint;
+mod logging;
 mod manifests;
 mod match_params;
 mod match_prompts;
+mod metrics;
+mod mock;
+mod paths;
 mod progress_component;
+mod project_context;
+mod report;
 mod requests;
+mod resource;
+mod session;
+mod signing;
+mod story;
+mod telemetry;
 mod template;
+mod tokens;
+mod transform;
+mod update;
 mod validators;
 use anyhow::{anyhow, Result};
 use build_args::build_cli;
-use clap::App;
-use clap_complete::{generate, Generator, Shell};
-use cmd_lib::run_cmd;
+use clap_complete::Shell;
 use comfy_table::{ContentArrangement, Table};
-use display::{pretty_print, pretty_print_file};
-use editor::edit_file;
-use execute::handle_execute;
+use diff::diff_lines;
+use display::{pretty_print, pretty_print_file, OutputFormat, PrintStyle};
+use editor::edit_manifest;
+use execute::{
+  expand_manifest_files, handle_bench, handle_diff, handle_execute, handle_execute_files, handle_execute_repeat,
+  handle_exports, AdhocTemplate, BulkOptions, DiffSide,
+};
+use history::{HistoryEntry, Redactor};
+use http_utils::{merge_url_queries, parse_date, parse_size, parse_timeout, RequestIdGenerator};
 use indexmap::indexmap;
-use manifests::{ApixConfiguration, ApixKind, ApixManifest, ApixRequest, ApixRequestTemplate};
-use match_params::{MatchParams, RequestParam};
+use manifests::{
+  ApixConfiguration, ApixKind, ApixManifest, ApixRequest, ApixRequestTemplate, ManifestFilter, ManifestOrder,
+};
+use match_params::MatchParams;
 use match_prompts::MatchPrompts;
+use report::RepeatStats;
 use requests::RequestOptions;
-use std::io;
-use std::io::Write;
+use session::ApixSession;
 use std::string::ToString;
+use std::sync::Mutex;
+use telemetry::Telemetry;
 use validators::validate_url;
 
-fn print_completions<G: Generator>(gen: G, app: &mut App) {
-  generate(gen, app, app.get_name().to_string(), &mut io::stdout());
-}
-
 async fn handle_import(_url: &str) -> Result<()> {
   // let open_api = reqwest::get(url).await?.text().await?;
   // let result = import::import_api(open_api, import::OpenApiType::YAML)
@@ -48,38 +85,108 @@ async fn handle_import(_url: &str) -> Result<()> {
 async fn main() -> Result<()> {
   let is_output_terminal = atty::is(atty::Stream::Stdout);
   let matches = build_cli().get_matches();
+  logging::init_logging(
+    matches.value_of("log-file"),
+    matches.value_of("log-level"),
+    matches.is_present("trace"),
+  )?;
   // read config file
   let theme = ApixConfiguration::once().get("theme").unwrap().clone();
+  let print_option = |name: &str| {
+    matches
+      .value_of(name)
+      .map(str::to_string)
+      .or_else(|| ApixConfiguration::once().get(name).cloned())
+  };
+  PrintStyle::parse(
+    print_option("style").as_deref(),
+    print_option("wrap").as_deref(),
+    print_option("tab-width").as_deref(),
+  )?
+  .with_themes(ApixConfiguration::once().get_prefixed("theme."))
+  .init();
+  let otlp_endpoint = matches
+    .value_of("otlp-endpoint")
+    .map(str::to_string)
+    .or_else(|| ApixConfiguration::once().get("otlp-endpoint").cloned());
+  let telemetry = (otlp_endpoint.is_some()
+    || matches.is_present("traceparent")
+    || ApixConfiguration::once().get("traceparent").map(String::as_str) == Some("true"))
+  .then(|| Telemetry::new(otlp_endpoint));
+  let request_id = RequestIdGenerator::from_config(&ApixConfiguration::once())?;
+  let default_options = RequestOptions {
+    theme: &theme,
+    is_output_terminal,
+    progress: !matches.is_present("no-progress") && atty::is(atty::Stream::Stderr),
+    content_digest: ApixConfiguration::once()
+      .get("content-digest")
+      .filter(|value| value.as_str() != "false")
+      .map(|value| value.parse())
+      .transpose()?,
+    telemetry: telemetry.as_ref(),
+    request_id: request_id.as_ref(),
+    accept: ApixConfiguration::once().get("accept").cloned(),
+    trust_store: ApixConfiguration::once()
+      .get("trust-store")
+      .map(|value| value.parse())
+      .transpose()?,
+    max_body_size: ApixConfiguration::once()
+      .get("max-body-size")
+      .map(|value| parse_size(value))
+      .transpose()?,
+    timeout: ApixConfiguration::once()
+      .get("timeout")
+      .map(|value| parse_timeout(value))
+      .transpose()?,
+    connect_timeout: ApixConfiguration::once()
+      .get("connect-timeout")
+      .map(|value| parse_timeout(value))
+      .transpose()?,
+    read_timeout: ApixConfiguration::once()
+      .get("read-timeout")
+      .map(|value| parse_timeout(value))
+      .transpose()?,
+    ..Default::default()
+  };
   match matches.subcommand() {
-    Some(("completions", matches)) => {
-      if let Ok(generator) = matches.value_of_t::<Shell>("shell") {
-        let mut app = build_cli();
-        print_completions(generator, &mut app);
+    Some(("completions", matches)) => match matches.subcommand() {
+      Some(("install", matches)) => {
+        let shell = match matches.value_of_t::<Shell>("shell") {
+          Ok(shell) => shell,
+          Err(_) => completions::detect_shell()?,
+        };
+        completions::install_completions(shell, &mut build_cli())?;
       }
-    }
-    Some(("init", _)) => {
-      run_cmd! {git --version}.map_err(|_| anyhow!("git command not found"))?;
-      // create .gitignore
-      let mut gitignore =
-        std::fs::File::create(".gitignore").map_err(|e| anyhow!("Failed to create .gitignore\ncause: {}", e))?;
-      gitignore
-        .write_all(b".apix/context.yaml\n")
-        .map_err(|e| anyhow!("Failed to write to .gitignore\ncause: {}", e))?;
-      gitignore
-        .flush()
-        .map_err(|e| anyhow!("Failed to save .gitignore\ncause: {}", e))?;
-      // init git
-      run_cmd! {
-        git init
-        git add .gitignore
-        git commit -m "Apix init commit"
+      _ => {
+        if let Ok(generator) = matches.value_of_t::<Shell>("shell") {
+          let mut app = build_cli();
+          print!("{}", completions::generate_completions(generator, &mut app)?);
+        }
       }
-      .map_err(|e| anyhow!("Failed to init apix repository\ncause: {}", e))?;
+    },
+    Some(("complete", matches)) => {
+      if let Some(kind) = matches.value_of("kind") {
+        for candidate in completions::list_candidates(kind)? {
+          println!("{}", candidate);
+        }
+      }
+    }
+    Some(("tokens", matches)) => {
+      tokens::handle_tokens(matches).await?;
+    }
+    Some(("cookies", matches)) => {
+      session::handle_cookies(matches)?;
+    }
+    Some(("self-update", matches)) => {
+      update::handle_self_update(matches.is_present("check")).await?;
+    }
+    Some(("init", matches)) => {
+      init::handle_init(!matches.is_present("no-git"), matches.value_of("template"))?;
     }
     Some(("config", matches)) => match matches.subcommand() {
       Some(("list", _)) => {
         pretty_print(
-          serde_yaml::to_string(ApixConfiguration::once())?,
+          serde_yaml::to_string(&*ApixConfiguration::once())?,
           &theme,
           "yaml",
           is_output_terminal,
@@ -87,19 +194,25 @@ async fn main() -> Result<()> {
       }
       Some(("set", matches)) => {
         if let (Some(key), Some(value)) = (matches.value_of("name"), matches.value_of("value")) {
-          if let Some(old_value) = ApixConfiguration::once().set(key.to_string(), value.to_string()) {
-            println!("Replaced config key");
-            pretty_print(
-              format!("-{}: {}\n+{}: {}\n", key, old_value, key, value),
-              &theme,
-              "diff",
-              is_output_terminal,
-            )?;
-          } else {
-            println!("Set config key");
-            pretty_print(format!("{}: {}\n", key, value), &theme, "yaml", is_output_terminal)?;
+          let config = ApixConfiguration::once().as_ref().clone();
+          let mut updated = config.clone();
+          let replaced = updated.set(key.to_string(), value.to_string()).is_some();
+          let changes = diff_lines(
+            serde_yaml::to_string(&config.index)?.trim_start_matches("---\n"),
+            serde_yaml::to_string(&updated.index)?.trim_start_matches("---\n"),
+            "current",
+            "updated",
+          );
+          match (replaced, matches.is_present("dry-run")) {
+            (true, true) => println!("Would replace config key"),
+            (true, false) => println!("Replaced config key"),
+            (false, true) => println!("Would set config key"),
+            (false, false) => println!("Set config key"),
+          }
+          pretty_print(changes, &theme, "diff", is_output_terminal)?;
+          if !matches.is_present("dry-run") {
+            ApixConfiguration::update(|config| Ok(config.set(key.to_string(), value.to_string())))?;
           }
-          ApixConfiguration::once().save()?;
         }
       }
       Some(("get", matches)) => {
@@ -110,62 +223,167 @@ async fn main() -> Result<()> {
       }
       Some(("delete", matches)) => {
         let key = matches.value_of("name").unwrap();
-        if let Some(value) = ApixConfiguration::once().delete(key) {
+        if let Some(value) = ApixConfiguration::update(|config| Ok(config.delete(key)))? {
           println!("Deleted config key");
           pretty_print(format!("{}: {}\n", key, value), &theme, "yaml", is_output_terminal)?;
-          ApixConfiguration::once().save()?;
         }
       }
       _ => {}
     },
-    Some(("history", _submatches)) => {}
+    Some(("history", matches)) => history::handle_history(matches)?,
     Some(("exec", matches)) => {
-      if let Some(file) = matches.value_of("file") {
-        let content = std::fs::read_to_string(file)?;
-        let manifest: ApixManifest = serde_yaml::from_str(&content)?;
-        handle_execute(
-          file,
-          &manifest,
-          matches.match_params(RequestParam::Param),
-          RequestOptions {
-            verbose: matches.is_present("verbose"),
-            theme: &theme,
-            is_output_terminal,
-            output_filename: matches.value_of("output-file").map(str::to_string),
-            proxy_url: matches.value_of("proxy").map(str::to_string),
-            proxy_login: matches.value_of("proxy-login").map(str::to_string),
-            proxy_password: matches.value_of("proxy-password").map(str::to_string),
-          },
-        )
-        .await?;
+      if let Some(patterns) = matches.values_of("file") {
+        let options = RequestOptions {
+          interactive: matches.is_present("interactive"),
+          soft: matches.is_present("soft"),
+          ..matches.match_request_options(&default_options)
+        };
+        let files = expand_manifest_files(patterns)?;
+        let bulk_options = BulkOptions {
+          concurrency: matches.value_of_t::<usize>("concurrency").unwrap_or(1),
+          report_file: matches.value_of("report-json").map(str::to_string),
+          metrics_file: matches.value_of("metrics-file").map(str::to_string),
+          pushgateway_url: matches.value_of("pushgateway").map(str::to_string),
+          continue_on_error: matches.is_present("continue-on-error"),
+        };
+        let repeat = matches.value_of_t::<usize>("repeat").ok();
+        if let (Some(repeat), [file]) = (repeat, files.as_slice()) {
+          let manifest = ApixManifest::from_file(std::path::Path::new(file))?;
+          handle_execute_repeat(
+            file,
+            &manifest,
+            matches.match_template_params("param")?,
+            options,
+            repeat,
+          )
+          .await?;
+        } else if repeat.is_some() {
+          return Err(anyhow!("--repeat can only be used when executing a single request"));
+        } else if files.len() == 1 && !bulk_options.has_exports() {
+          let content = std::fs::read_to_string(&files[0])?;
+          let manifest: ApixManifest = serde_yaml::from_str(&content)?;
+          let params = matches.match_template_params("param")?;
+          if manifest.kind().as_story().is_some() {
+            story::handle_story(&files[0], &manifest, params, options).await?;
+          } else {
+            let shell = options.output_format == Some(OutputFormat::Shell);
+            let metrics = handle_execute(&files[0], &manifest, params, options).await?;
+            handle_exports(&manifest, &metrics, matches.value_of("export-env"), shell)?;
+          }
+        } else if matches.is_present("export-env") || options.output_format == Some(OutputFormat::Shell) {
+          return Err(anyhow!(
+            "--export-env and --output shell can only be used when executing a single request"
+          ));
+        } else {
+          handle_execute_files(&files, matches.match_template_params("param")?, options, bulk_options).await?;
+        }
       } else if let Ok(name) = matches.match_or_input("name", "Request name") {
-        match ApixManifest::find_manifest("request", &name) {
+        let found =
+          ApixManifest::find_manifest("request", &name).or_else(|| ApixManifest::find_manifest("story", &name));
+        match found {
           Some((path, manifest)) => {
             let path = path.to_str().ok_or_else(|| anyhow!("Invalid path"))?;
-            handle_execute(
-              path,
-              &manifest,
-              matches.match_params(RequestParam::Param),
-              RequestOptions {
-                verbose: matches.is_present("verbose"),
-                theme: &theme,
-                is_output_terminal,
-                output_filename: matches.value_of("output-file").map(str::to_string),
-                proxy_url: matches.value_of("proxy").map(str::to_string),
-                proxy_login: matches.value_of("proxy-login").map(str::to_string),
-                proxy_password: matches.value_of("proxy-password").map(str::to_string),
-              },
-            )
-            .await?;
+            let options = RequestOptions {
+              interactive: matches.is_present("interactive"),
+              soft: matches.is_present("soft"),
+              ..matches.match_request_options(&default_options)
+            };
+            let params = matches.match_template_params("param")?;
+            if manifest.kind().as_story().is_some() {
+              story::handle_story(path, &manifest, params, options).await?;
+            } else if let Ok(repeat) = matches.value_of_t::<usize>("repeat") {
+              handle_execute_repeat(path, &manifest, params, options, repeat).await?;
+            } else {
+              let shell = options.output_format == Some(OutputFormat::Shell);
+              let metrics = handle_execute(path, &manifest, params, options).await?;
+              handle_exports(&manifest, &metrics, matches.value_of("export-env"), shell)?;
+            }
           }
           None => {
-            println!("No request where found with name {}", name);
+            println!("No request or story where found with name {}", name);
           }
         }
       }
     }
+    Some(("bench", matches)) => {
+      let (path, manifest) = match matches.value_of("file") {
+        Some(file) => (file.to_string(), ApixManifest::from_file(std::path::Path::new(file))?),
+        None => {
+          let name = matches.match_or_input("name", "Request name")?;
+          let (path, manifest) = ApixManifest::find_manifest("request", &name)
+            .ok_or_else(|| anyhow!("No request where found with name {}", name))?;
+          (
+            path.to_str().ok_or_else(|| anyhow!("Invalid path"))?.to_string(),
+            manifest,
+          )
+        }
+      };
+      handle_bench(
+        &path,
+        &manifest,
+        matches.match_template_params("param")?,
+        matches.match_request_options(&default_options),
+      )
+      .await?;
+    }
+    Some(("diff", matches)) => {
+      let (path, manifest) = match matches.value_of("file") {
+        Some(file) => (file.to_string(), ApixManifest::from_file(std::path::Path::new(file))?),
+        None => {
+          let name = matches.match_or_input("name", "Request name")?;
+          let (path, manifest) = ApixManifest::find_manifest("request", &name)
+            .ok_or_else(|| anyhow!("No request where found with name {}", name))?;
+          (
+            path.to_str().ok_or_else(|| anyhow!("Invalid path"))?.to_string(),
+            manifest,
+          )
+        }
+      };
+      let left = DiffSide {
+        base_url: matches.value_of("left").map(str::to_string),
+        params: matches.match_template_params("left-param")?,
+      };
+      let right = DiffSide {
+        base_url: matches.value_of("right").map(str::to_string),
+        params: matches.match_template_params("right-param")?,
+      };
+      handle_diff(
+        &path,
+        &manifest,
+        matches.match_template_params("param")?,
+        (left, right),
+        matches.match_request_options(&default_options),
+      )
+      .await?;
+    }
+    Some(("download", matches)) => {
+      if let Some(name) = matches.value_of("name") {
+        artifacts::handle_download(
+          name,
+          matches.value_of_t::<usize>("concurrency").ok(),
+          matches.match_request_options(&default_options),
+        )
+        .await?;
+      }
+    }
+    Some(("mock", matches)) => {
+      if let (Some(api), Some(listen)) = (matches.value_of("api"), matches.value_of("listen")) {
+        mock::handle_mock(api, listen).await?;
+      }
+    }
     Some(("ctl", matches)) => match matches.subcommand() {
-      Some(("apply", _submatches)) => {}
+      Some(("context", matches)) => project_context::handle_context(matches)?,
+      Some(("apply", matches)) => {
+        if let Some(file) = matches.value_of("file") {
+          apply::handle_apply(
+            file,
+            matches.is_present("dry-run"),
+            matches.is_present("yes"),
+            &theme,
+            is_output_terminal,
+          )?;
+        }
+      }
       Some(("create", matches)) => match matches.subcommand() {
         Some(("request", matches)) => {
           let name = matches.match_or_input("name", "Request Name")?;
@@ -174,7 +392,10 @@ async fn main() -> Result<()> {
           let url = matches.match_or_validate_input("url", "Request url", |url: &String| {
             validate_url(&url.to_owned()).map(|_| ())
           })?;
-          let headers = matches.match_or_input_multiples("header", "Add request headers?")?;
+          let headers = matches
+            .match_or_input_multiples("header", "Add request headers?")?
+            .into_iter()
+            .collect();
           let queries = matches.match_or_input_multiples("query", "Add request query parameters?")?;
 
           let body = matches
@@ -193,7 +414,11 @@ async fn main() -> Result<()> {
           );
           let request_manifest_yaml = serde_yaml::to_string(&request_manifest)?;
           // save to file with name of request
-          std::fs::write(filename, request_manifest_yaml)?;
+          std::fs::write(&filename, request_manifest_yaml)?;
+          if matches.is_present("edit") {
+            edit_manifest(&filename, &theme, is_output_terminal)?;
+          }
+          git::auto_commit("create", &[filename])?;
         }
         Some(("story", _submatches)) => {}
         _ => {}
@@ -201,13 +426,17 @@ async fn main() -> Result<()> {
       Some(("switch", _submatches)) => {}
       Some(("edit", matches)) => {
         if let Some(filename) = matches.value_of("file") {
-          edit_file(filename)?;
+          if edit_manifest(filename, &theme, is_output_terminal)? {
+            git::auto_commit("edit", &[filename.to_string()])?;
+          }
         } else {
           let resource = matches.match_or_select("resource", "Resource type", &["request", "story"])?;
           let name = matches.match_or_input("name", "Resource name")?;
           match ApixManifest::find_manifest_filename(&resource, &name) {
             Some(filename) => {
-              edit_file(&filename)?;
+              if edit_manifest(&filename, &theme, is_output_terminal)? {
+                git::auto_commit("edit", &[filename])?;
+              }
             }
             None => {
               println!("No resource of type {} where found with name {}", resource, name);
@@ -224,9 +453,20 @@ async fn main() -> Result<()> {
               println!("No resource of type {} where found with name {}", kind, name);
             }
           } else if let Ok(manifests) = ApixManifest::find_manifests_by_kind(kind) {
-            let mut manifests = manifests.peekable();
-            let found = manifests.peek().is_some();
-            if found {
+            let filter = ManifestFilter {
+              created_since: matches
+                .value_of("created-since")
+                .map(|since| parse_date(since, chrono::Utc::now()))
+                .transpose()?,
+              created_by: matches.value_of("created-by").map(str::to_string),
+            };
+            let mut manifests = manifests
+              .filter(|(_, manifest)| filter.matches(manifest))
+              .collect::<Vec<_>>();
+            if let Ok(order) = matches.value_of_t::<ManifestOrder>("sort-by") {
+              order.sort(&mut manifests);
+            }
+            if !manifests.is_empty() {
               if !is_output_terminal {
                 for (path, _) in manifests {
                   pretty_print_file(path, &theme, "yaml", false)?;
@@ -247,7 +487,7 @@ async fn main() -> Result<()> {
                         &request.request.url,
                         &manifest
                           .get_annotation("apix.io/created-at")
-                          .map(String::clone)
+                          .cloned()
                           .unwrap_or_default(),
                       ]);
                     }
@@ -262,7 +502,7 @@ async fn main() -> Result<()> {
                         &story.stories.len().to_string(),
                         &manifest
                           .get_annotation("apix.io/created-at")
-                          .map(String::clone)
+                          .cloned()
                           .unwrap_or_default(),
                       ]);
                     }
@@ -279,36 +519,128 @@ async fn main() -> Result<()> {
           }
         }
       }
-      Some(("delete", _submatches)) => {}
+      Some(("graph", matches)) => {
+        if let Some(story) = matches.value_of("story") {
+          graph::handle_graph(story, matches.value_of("format").unwrap_or("dot"))?;
+        }
+      }
+      Some(("lint", matches)) => lint::handle_lint(matches.values_of("file"))?,
+      Some(("export", matches)) => export::handle_export(matches)?,
+      Some((action @ ("copy" | "rename"), matches)) => {
+        if let (Some(kind), Some(name), Some(new_name)) = (
+          matches.value_of("resource"),
+          matches.value_of("name"),
+          matches.value_of("new-name"),
+        ) {
+          match action {
+            "copy" => resource::handle_copy(kind, name, new_name)?,
+            _ => resource::handle_rename(kind, name, new_name)?,
+          }
+        }
+      }
+      Some(("delete", matches)) => {
+        if let (Some(kind), Some(name)) = (matches.value_of("resource"), matches.value_of("name")) {
+          resource::handle_delete(kind, name)?;
+        }
+      }
       Some(("import", matches)) => {
-        if let Some(url) = matches.value_of("url") {
-          handle_import(url).await?;
+        if let Some(("bundle", matches)) = matches.subcommand() {
+          if let Some(file) = matches.value_of("file") {
+            apply::handle_apply(
+              file,
+              matches.is_present("dry-run"),
+              matches.is_present("yes"),
+              &theme,
+              is_output_terminal,
+            )?;
+          }
+        } else if let Some(url) = matches.value_of("url") {
+          let format = matches
+            .value_of("format")
+            .unwrap_or(if url.ends_with(".hurl") { "hurl" } else { "openapi" });
+          match format {
+            "hurl" => hurl::handle_import_hurl(url)?,
+            _ => handle_import(url).await?,
+          }
         }
       }
       _ => {}
     },
     Some((method, matches)) => {
-      if let Some(url) = matches.value_of("url") {
-        requests::make_request(
-          url,
-          method,
-          matches.match_headers().as_ref(),
-          matches.match_params(RequestParam::Query).as_ref(),
-          matches.match_body(),
-          RequestOptions {
-            verbose: matches.is_present("verbose"),
-            theme: &theme,
-            is_output_terminal,
-            output_filename: matches.value_of("output-file").map(str::to_string),
-            proxy_url: matches.value_of("proxy").map(str::to_string),
-            proxy_login: matches.value_of("proxy-login").map(str::to_string),
-            proxy_password: matches.value_of("proxy-password").map(str::to_string),
-          },
-        )
-        .await?;
+      if let Some(urls) = matches.values_of("url") {
+        let mut template = AdhocTemplate::new(matches.match_template_params("param")?.as_ref())?;
+        let urls = urls.map(|url| template.render_url(url)).collect::<Result<Vec<_>>>()?;
+        let session_name = matches.value_of("session");
+        let original_session = session_name.map(ApixSession::load).transpose()?;
+        let session = original_session.clone().map(Mutex::new);
+        let headers = matches.match_headers();
+        let flag_headers = matches.match_flag_headers()?;
+        let headers = if flag_headers.is_empty() {
+          headers
+        } else {
+          let mut headers = headers.unwrap_or_default();
+          headers.extend(flag_headers);
+          Some(headers)
+        };
+        let queries = matches.match_queries();
+        let options = RequestOptions {
+          session: session.as_ref(),
+          ..matches.match_request_options(&default_options)
+        };
+        if options.output_format == Some(OutputFormat::Shell) {
+          return Err(anyhow!("--output shell can only be used with exec"));
+        }
+        let body = template.render_body(matches.match_body(), options.body_format)?;
+        if matches.is_present("parallel") {
+          requests::make_parallel_requests(&urls, method, headers.as_ref(), queries.as_deref(), body, options).await?;
+        } else {
+          let repeat = matches.value_of_t::<usize>("repeat").ok();
+          for url in &urls {
+            if urls.len() > 1 {
+              println!("==> {} <==", url);
+            }
+            let mut stats = RepeatStats::default();
+            for _ in 0..repeat.unwrap_or(1) {
+              let start = std::time::Instant::now();
+              let result = requests::make_request(
+                url,
+                method,
+                headers.as_ref(),
+                queries.as_deref(),
+                body.clone(),
+                options.clone(),
+              )
+              .await;
+              // repeated requests are all sent, failures are counted in statistics
+              if repeat.is_some() {
+                stats.record_result(&result, start);
+              }
+              let metrics = match result {
+                Ok(metrics) => metrics,
+                Err(_) if repeat.is_some() => continue,
+                Err(err) => return Err(err),
+              };
+              let redactor = Redactor::from_config()?;
+              let recorded_url = merge_url_queries(url, queries.as_deref())?.to_string();
+              history::record(
+                HistoryEntry::new(method, &recorded_url, &metrics, &redactor)
+                  .with_headers(&headers.clone().unwrap_or_default(), &redactor),
+              );
+            }
+            if repeat.is_some() {
+              stats.check()?;
+            }
+          }
+        }
+        if let (Some(session_name), Some(session), Some(original)) = (session_name, session, original_session) {
+          session.into_inner().unwrap().save_changes(session_name, &original)?;
+        }
       }
     }
     _ => {}
   }
+  if let Some(telemetry) = telemetry {
+    telemetry.export().await?;
+  }
   Ok(())
 }
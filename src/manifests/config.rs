@@ -1,50 +1,144 @@
-use super::{ApixKind, ApixManifest, ApixManifestV1, ApixMetadata};
+use super::{ApixKind, ApixManifest, ApixManifestV1, ApixMetadata, PROJECT_DIR};
+use crate::paths::{config_file, lock_file, write_atomic};
 use anyhow::Result;
 use indexmap::{indexmap, IndexMap};
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 use serde_yaml;
-use std::{fs, ops::DerefMut};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, PoisonError, RwLock};
+
+static CONFIG_FILES: [&str; 3] = ["config.yml", "config.toml", "config.json"];
+
+static CONFIG: Lazy<RwLock<Arc<ApixConfiguration>>> =
+  Lazy::new(|| RwLock::new(Arc::new(ApixConfiguration::load().unwrap())));
+
+// user configuration is a yaml manifest, or flat key values in toml and json files
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ConfigFormat {
+  Yaml,
+  Toml,
+  Json,
+}
+
+impl ConfigFormat {
+  fn from_path(path: &Path) -> Self {
+    match path.extension().and_then(|extension| extension.to_str()) {
+      Some("toml") => ConfigFormat::Toml,
+      Some("json") => ConfigFormat::Json,
+      _ => ConfigFormat::Yaml,
+    }
+  }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ApixConfiguration {
   #[serde(flatten, default)]
   pub index: IndexMap<String, String>,
+  // project values override user values, but are never saved in user configuration
+  #[serde(skip)]
+  pub project: IndexMap<String, String>,
+  // values of toml and json files as typed in them, saved back with their type while unchanged
+  #[serde(skip)]
+  typed: IndexMap<String, serde_json::Value>,
 }
 
 impl Default for ApixConfiguration {
   fn default() -> Self {
-    let mut instance = Self { index: IndexMap::new() };
+    let mut instance = Self {
+      index: IndexMap::new(),
+      project: IndexMap::new(),
+      typed: IndexMap::new(),
+    };
     instance.set_defaults();
     instance
   }
 }
 
 impl ApixConfiguration {
-  pub fn once() -> &'static mut ApixConfiguration {
-    static mut CONFIG: Lazy<ApixConfiguration> = Lazy::new(|| ApixConfiguration::load().unwrap());
-    unsafe { CONFIG.deref_mut() }
-  }
-
-  // private function to create apix directory if it does not exist
-  fn create_apix_dir_if_not_exists() -> Result<std::path::PathBuf> {
-    let apix_dir = dirs::home_dir()
-      .expect("Could not find HOME path, login as a user to use Apix")
-      .join(".apix");
-    fs::create_dir_all(&apix_dir)?;
-
-    Ok(apix_dir)
+  // snapshot of shared configuration, loaded on first use, updates replace it without waiting for readers
+  pub fn once() -> Arc<ApixConfiguration> {
+    CONFIG.read().unwrap_or_else(PoisonError::into_inner).clone()
   }
 
-  // private function to load apix configuration from file when given a path
+  // private function to load apix configuration from file when given a path, format is detected from its extension
   fn load_from_path(path: &std::path::Path) -> Result<Self> {
     if let Ok(content) = fs::read_to_string(path) {
-      Self::load_from_string(&content, &format!("config file {:?}", &path))
+      let err_msg = format!("config file {:?}", &path);
+      match ConfigFormat::from_path(path) {
+        ConfigFormat::Yaml => Self::load_from_string(&content, &err_msg),
+        format => Self::load_from_values(&content, format, &err_msg),
+      }
     } else {
       Ok(Self::default())
     }
   }
 
+  // private function to load apix configuration from a flat toml or json document
+  // values are read as strings, only strings, numbers and booleans are allowed
+  fn load_from_values(content: &str, format: ConfigFormat, err_msg: &str) -> Result<Self> {
+    let typed = match format {
+      ConfigFormat::Toml => toml::from_str::<IndexMap<String, toml::Value>>(content)
+        .map_err(|e| anyhow::anyhow!("Could not parse {}: {:#}", &err_msg, e))?
+        .into_iter()
+        .map(|(key, value)| match value {
+          toml::Value::String(value) => Ok((key, serde_json::Value::from(value))),
+          toml::Value::Integer(value) => Ok((key, serde_json::Value::from(value))),
+          toml::Value::Float(value) => Ok((key, serde_json::Value::from(value))),
+          toml::Value::Boolean(value) => Ok((key, serde_json::Value::from(value))),
+          value => Err(anyhow::anyhow!(
+            "Invalid {}: {} = {} is not a string, number or boolean",
+            &err_msg,
+            key,
+            value
+          )),
+        })
+        .collect::<Result<IndexMap<_, _>>>()?,
+      _ => serde_json::from_str::<IndexMap<String, serde_json::Value>>(content)
+        .map_err(|e| anyhow::anyhow!("Could not parse {}: {:#}", &err_msg, e))?
+        .into_iter()
+        .map(|(key, value)| match value {
+          serde_json::Value::String(_) | serde_json::Value::Number(_) | serde_json::Value::Bool(_) => Ok((key, value)),
+          value => Err(anyhow::anyhow!(
+            "Invalid {}: \"{}\": {} is not a string, number or boolean",
+            &err_msg,
+            key,
+            value
+          )),
+        })
+        .collect::<Result<IndexMap<_, _>>>()?,
+    };
+    let index = typed
+      .iter()
+      .map(|(key, value)| match value {
+        serde_json::Value::String(value) => (key.clone(), value.clone()),
+        value => (key.clone(), value.to_string()),
+      })
+      .collect();
+    let mut config = Self {
+      index,
+      project: IndexMap::new(),
+      typed,
+    };
+    config.set_defaults();
+    Ok(config)
+  }
+
+  // values to save in a toml or json file, with the type they were loaded with unless they were changed
+  fn typed_values(&self) -> IndexMap<String, serde_json::Value> {
+    self
+      .index
+      .iter()
+      .map(|(key, value)| match self.typed.get(key) {
+        Some(typed) if typed.as_str().map_or_else(|| typed.to_string(), str::to_string) == *value => {
+          (key.clone(), typed.clone())
+        }
+        _ => (key.clone(), serde_json::Value::String(value.clone())),
+      })
+      .collect()
+  }
+
   // private function to load apix configuration from string when given a content
   fn load_from_string(content: &str, err_msg: &str) -> Result<Self> {
     if !content.is_empty() {
@@ -62,12 +156,26 @@ impl ApixConfiguration {
     Ok(Self::default())
   }
 
-  // private method to save apix configuration to file when given a path
+  // private method to save apix configuration to file when given a path, in the format of its extension
+  // file is replaced atomically, keeping its previous version in a `.bak` file
   fn save_to_path(&self, path: &std::path::Path) -> Result<()> {
-    let manifest = ApixManifest::new_configuration(Some(self.clone()));
-    let file = serde_yaml::to_string(&manifest)?;
-    fs::write(path, file)?;
-    Ok(())
+    let file = match ConfigFormat::from_path(path) {
+      ConfigFormat::Yaml => serde_yaml::to_string(&ApixManifest::new_configuration(Some(self.clone())))?,
+      ConfigFormat::Toml => toml::to_string(&self.typed_values())?,
+      ConfigFormat::Json => serde_json::to_string_pretty(&self.typed_values())? + "\n",
+    };
+    write_atomic(path, file.as_bytes(), true, None)
+  }
+
+  // private function to find user configuration file, the first existing of `config.yml`, `config.toml`, `config.json`
+  fn config_path() -> Result<PathBuf> {
+    for name in CONFIG_FILES {
+      let path = config_file(name)?;
+      if path.exists() {
+        return Ok(path);
+      }
+    }
+    config_file(CONFIG_FILES[0])
   }
 
   // private method to set default values for apix configuration
@@ -77,21 +185,61 @@ impl ApixConfiguration {
     }
   }
 
-  // public function to load apix configuration from apix directory
+  // private function to load project configuration values, without defaults
+  fn load_project_values(path: &std::path::Path) -> Result<IndexMap<String, String>> {
+    match fs::read_to_string(path) {
+      Ok(content) if !content.is_empty() => {
+        let manifest: ApixManifest = serde_yaml::from_str(&content)
+          .map_err(|e| anyhow::anyhow!("Could not parse project config file {:?}: {:#}", path, e))?;
+        match manifest.kind() {
+          ApixKind::Configuration(conf) => Ok(conf.index.clone()),
+          _ => Err(anyhow::anyhow!("Invalid project config file {:?}", path)),
+        }
+      }
+      _ => Ok(IndexMap::new()),
+    }
+  }
+
+  // public function to load apix configuration from user config directory, overridden by project configuration
   pub fn load() -> Result<Self> {
-    let filename = Self::create_apix_dir_if_not_exists()?.join("config.yml");
-    Self::load_from_path(&filename)
+    let filename = Self::config_path()?;
+    let mut config = Self::load_from_path(&filename)?;
+    config.project = Self::load_project_values(&std::path::Path::new(PROJECT_DIR).join("config.yaml"))?;
+    Ok(config)
+  }
+
+  // public function to change shared configuration and save it, without losing changes saved by other apix processes
+  // since it was loaded: configuration file is locked, reloaded, changed and saved
+  pub fn update<T>(change: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+    let path = Self::config_path()?;
+    let mut shared = CONFIG.write().unwrap_or_else(PoisonError::into_inner);
+    Arc::make_mut(&mut shared).update_from_file(&path, change)
   }
 
-  // public method to save apix configuration to apix directory
-  pub fn save(&self) -> Result<()> {
-    let filename = Self::create_apix_dir_if_not_exists()?.join("config.yml");
-    self.save_to_path(&filename)
+  // private method to change configuration saved in a file, self is only replaced once changes are saved
+  fn update_from_file<T>(&mut self, path: &Path, change: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+    let _lock = lock_file(path)?;
+    let mut config = Self::load_from_path(path)?;
+    config.project = self.project.clone();
+    let result = change(&mut config)?;
+    config.save_to_path(path)?;
+    *self = config;
+    Ok(result)
   }
 
   // public method to get apix configuration value by key
   pub fn get(&self, key: &str) -> Option<&String> {
-    self.index.get(key)
+    self.project.get(key).or_else(|| self.index.get(key))
+  }
+
+  // values of keys starting with `prefix` (eg: `theme.` for `theme.json`), keyed without it
+  pub fn get_prefixed(&self, prefix: &str) -> IndexMap<String, String> {
+    self
+      .index
+      .iter()
+      .chain(self.project.iter())
+      .filter_map(|(key, value)| Some((key.strip_prefix(prefix)?.to_string(), value.clone())))
+      .collect()
   }
 
   // public method to set apix configuration value by key
@@ -171,4 +319,102 @@ mod tests {
     assert_eq!(config.get("theme").unwrap(), "Coldark-Dark");
     assert_eq!(config.get("rust").unwrap(), "rust");
   }
+
+  // test project configuration overrides user configuration without being saved
+  #[test]
+  fn test_project_config_override() {
+    let mut config = ApixConfiguration::default();
+    config.project.insert("theme".to_string(), "Coldark-Dark".to_string());
+    assert_eq!(config.get("theme").unwrap(), "Coldark-Dark");
+    let saved = serde_yaml::to_string(&ApixManifest::new_configuration(Some(config))).unwrap();
+    assert!(saved.contains("Monokai Extended"));
+    assert!(!saved.contains("Coldark-Dark"));
+  }
+
+  // test prefixed keys are listed without prefix, project values overriding user ones
+  #[test]
+  fn test_get_prefixed() {
+    let mut config = ApixConfiguration::default();
+    config.set("theme.json".to_string(), "Dracula".to_string());
+    config.set("theme.diff".to_string(), "GitHub".to_string());
+    config.project.insert("theme.json".to_string(), "Nord".to_string());
+    let themes = config.get_prefixed("theme.");
+    assert_eq!(themes.len(), 2);
+    assert_eq!(themes["json"], "Nord");
+    assert_eq!(themes["diff"], "GitHub");
+  }
+
+  // test updates keep values saved by other processes and never save project values
+  #[test]
+  fn test_update_from_file() {
+    let dir = std::env::temp_dir().join(format!("apix-config-update-{}", uuid::Uuid::new_v4()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("config.json");
+    let mut config = ApixConfiguration::default();
+    config.project.insert("urlsafe".to_string(), "true".to_string());
+    fs::write(&path, r#"{"rust": "rust"}"#).unwrap();
+    let previous = config
+      .update_from_file(&path, |config| {
+        Ok(config.set("theme".to_string(), "Coldark-Dark".to_string()))
+      })
+      .unwrap();
+    assert_eq!(previous.unwrap(), "Monokai Extended");
+    assert_eq!(config.get("rust").unwrap(), "rust");
+    assert_eq!(config.get("urlsafe").unwrap(), "true");
+    let saved: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(saved, serde_json::json!({"rust": "rust", "theme": "Coldark-Dark"}));
+    let before = config.clone();
+    assert!(config
+      .update_from_file(&path, |config| {
+        config.set("theme".to_string(), "Nord".to_string());
+        Err::<(), _>(anyhow::anyhow!("failed"))
+      })
+      .is_err());
+    assert_eq!(config, before);
+    fs::remove_dir_all(dir).unwrap();
+  }
+
+  // test ApixConfig toml and json files, saved back in their own format
+  #[test]
+  fn test_config_toml_json() {
+    let dir = std::env::temp_dir().join(format!("apix-config-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let toml_path = dir.join("config.toml");
+    fs::write(&toml_path, "theme = \"Coldark-Dark\"\nurlsafe = true\n").unwrap();
+    let mut config = ApixConfiguration::load_from_path(&toml_path).unwrap();
+    assert_eq!(config.get("theme").unwrap(), "Coldark-Dark");
+    assert_eq!(config.get("urlsafe").unwrap(), "true");
+    config.set("rust".to_string(), "rust".to_string());
+    config.save_to_path(&toml_path).unwrap();
+    assert_eq!(
+      fs::read_to_string(&toml_path).unwrap(),
+      "theme = \"Coldark-Dark\"\nurlsafe = true\nrust = \"rust\"\n"
+    );
+    let json_path = dir.join("config.json");
+    fs::write(
+      &json_path,
+      r#"{"rust": "rust", "timeout": 30, "ratio": 0.5, "urlsafe": false}"#,
+    )
+    .unwrap();
+    let mut config = ApixConfiguration::load_from_path(&json_path).unwrap();
+    assert_eq!(config.get("theme").unwrap(), "Monokai Extended");
+    assert_eq!(config.get("timeout").unwrap(), "30");
+    config.save_to_path(&json_path).unwrap();
+    let saved: serde_json::Value = serde_json::from_str(&fs::read_to_string(&json_path).unwrap()).unwrap();
+    assert_eq!(
+      saved,
+      serde_json::json!({"rust": "rust", "timeout": 30, "ratio": 0.5, "urlsafe": false, "theme": "Monokai Extended"})
+    );
+    // changed values are saved as strings, like values set with `apix config set`
+    config.set("timeout".to_string(), "1m".to_string());
+    config.save_to_path(&json_path).unwrap();
+    let saved: serde_json::Value = serde_json::from_str(&fs::read_to_string(&json_path).unwrap()).unwrap();
+    assert_eq!(saved["timeout"], "1m");
+    // tables and arrays can't be read as strings
+    fs::write(&toml_path, "[proxy]\nurl = \"http://proxy\"\n").unwrap();
+    assert!(ApixConfiguration::load_from_path(&toml_path).is_err());
+    fs::write(&json_path, r#"{"pins": ["sha256//a"]}"#).unwrap();
+    assert!(ApixConfiguration::load_from_path(&json_path).is_err());
+    fs::remove_dir_all(dir).unwrap();
+  }
 }
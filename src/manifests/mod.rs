@@ -4,16 +4,166 @@ pub use self::config::ApixConfiguration;
 pub mod config;
 
 use anyhow::Result;
+use chrono::{DateTime, Utc};
 use indexmap::{indexmap, IndexMap};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
+use std::str::FromStr;
 use strum_macros::Display as EnumDisplay;
 
+// order of listed manifests, `ctl get --sort-by`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ManifestOrder {
+  CreatedAt,
+  Name,
+}
+
+impl FromStr for ManifestOrder {
+  type Err = anyhow::Error;
+
+  fn from_str(value: &str) -> Result<Self> {
+    match value {
+      "created-at" => Ok(ManifestOrder::CreatedAt),
+      "name" => Ok(ManifestOrder::Name),
+      _ => Err(anyhow::anyhow!(
+        "Bad sort order: \"{}\", should be created-at or name",
+        value
+      )),
+    }
+  }
+}
+
+impl ManifestOrder {
+  // manifests without creation date come first, ties keep their listing order
+  pub fn sort(&self, manifests: &mut [(PathBuf, ApixManifest)]) {
+    match self {
+      ManifestOrder::CreatedAt => manifests.sort_by_key(|(_, manifest)| manifest.created_at()),
+      ManifestOrder::Name => manifests.sort_by(|(_, left), (_, right)| left.name().cmp(right.name())),
+    }
+  }
+}
+
+// filter of listed manifests on their creation annotations, `ctl get --created-since --created-by`
+#[derive(Debug, Clone, Default)]
+pub struct ManifestFilter {
+  pub created_since: Option<DateTime<Utc>>,
+  pub created_by: Option<String>,
+}
+
+impl ManifestFilter {
+  pub fn matches(&self, manifest: &ApixManifest) -> bool {
+    let since = self
+      .created_since
+      .is_none_or(|since| manifest.created_at().is_some_and(|created_at| created_at >= since));
+    let by = self.created_by.as_ref().is_none_or(|user| {
+      manifest
+        .get_annotation("apix.io/created-by")
+        .is_some_and(|created_by| created_by == user)
+    });
+    since && by
+  }
+}
+
+// directory holding project manifests, created by `apix init`
+pub static PROJECT_DIR: &str = "apix";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiKeyLocation {
+  #[default]
+  Header,
+  Query,
+}
+
+// credentials applied to every request linked to the api with the `apix.io/api` label
+// values can be 'Tera' templates, missing ones are prompted at execution time
+// example:
+//   auth:
+//     type: bearer
+//     token: "{{ env.API_TOKEN }}"
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ApixAuth {
+  Bearer {
+    token: Option<String>,
+  },
+  Basic {
+    username: Option<String>,
+    password: Option<String>,
+  },
+  ApiKey {
+    name: String,
+    value: Option<String>,
+    #[serde(default, rename = "in")]
+    location: ApiKeyLocation,
+  },
+  #[serde(rename_all = "camelCase")]
+  OAuth2 {
+    issuer: String,
+    audience: Option<String>,
+    scope: Option<String>,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+  },
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum HmacAlgorithm {
+  #[default]
+  Sha256,
+  Sha512,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SignatureEncoding {
+  #[default]
+  Hex,
+  Base64,
+}
+
+// HMAC signature of every request linked to the api, key and string to sign are 'Tera' templates
+// string to sign is rendered with `request.method`, `request.path`, `request.query`, `request.headers`,
+// `request.body`, `request.bodySha256`, `request.date` and `request.timestamp`
+// example:
+//   signing:
+//     key: "{{ env.API_SECRET }}"
+//     stringToSign: "{{ request.method }}\n{{ request.path }}\n{{ request.date }}"
+//     header: X-Signature
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ApixSigning {
+  #[serde(default)]
+  pub algorithm: HmacAlgorithm,
+  pub key: String,
+  pub string_to_sign: String,
+  pub header: String,
+  #[serde(default)]
+  pub encoding: SignatureEncoding,
+  // header value template, `{{ signature }}` by default (eg: "HMAC-SHA256 {{ signature }}")
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub value: Option<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
 pub struct ApixApi {
   pub url: String,
   pub version: String,
   pub description: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub auth: Option<ApixAuth>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub signing: Option<ApixSigning>,
+  // Accept header of requests to this api, unless they set one
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub accept: Option<String>,
+  // OpenAPI description file or url the api was imported from, served by `apix mock`
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub openapi: Option<String>,
+  // shared schemas referenced by parameters of linked requests (eg: `$ref: "#/components/schemas/User"`)
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub components: Option<Value>,
 }
 
 impl ApixApi {
@@ -23,10 +173,55 @@ impl ApixApi {
       url,
       version,
       description,
+      auth: None,
+      signing: None,
+      accept: None,
+      openapi: None,
+      components: None,
     }
   }
 }
 
+fn contains_reference(schema: &Value) -> bool {
+  match schema {
+    Value::Object(object) => object.contains_key("$ref") || object.values().any(contains_reference),
+    Value::Array(items) => items.iter().any(contains_reference),
+    _ => false,
+  }
+}
+
+// inline `$ref` targets of a schema, references already being inlined are recursive and kept as is
+fn inline_references(schema: &Value, root: &Value, stack: &mut Vec<String>) -> Result<Value> {
+  match schema {
+    Value::Object(object) => match object.get("$ref").and_then(Value::as_str) {
+      Some(reference) if stack.iter().any(|inlined| inlined == reference) => Ok(schema.clone()),
+      Some(reference) => {
+        let target = reference
+          .strip_prefix('#')
+          .and_then(|pointer| root.pointer(pointer))
+          .ok_or_else(|| anyhow::anyhow!("Unknown schema reference {}", reference))?;
+        stack.push(reference.to_string());
+        let inlined = inline_references(target, root, stack);
+        stack.pop();
+        inlined
+      }
+      None => Ok(Value::Object(
+        object
+          .iter()
+          .map(|(key, value)| Ok((key.clone(), inline_references(value, root, stack)?)))
+          .collect::<Result<_>>()?,
+      )),
+    },
+    Value::Array(items) => Ok(Value::Array(
+      items
+        .iter()
+        .map(|item| inline_references(item, root, stack))
+        .collect::<Result<_>>()?,
+    )),
+    schema => Ok(schema.clone()),
+  }
+}
+
 fn default_schema() -> Option<Value> {
   Some(json!({ "type": "string" }))
 }
@@ -54,18 +249,79 @@ impl ApixParameter {
       schema,
     }
   }
+
+  // parameter with `$ref` of its schema resolved against the components of its api
+  // recursive references can't be inlined, components are then kept in the schema for validation to resolve them
+  pub fn with_resolved_schema(&self, api: Option<&ApixApi>) -> Result<Self> {
+    let schema = match &self.schema {
+      Some(schema) if contains_reference(schema) => schema,
+      _ => return Ok(self.clone()),
+    };
+    let components = api.and_then(|api| api.components.as_ref()).ok_or_else(|| {
+      anyhow::anyhow!(
+        "Parameter {} references a schema, but its request is not linked to an api with components\nuse the apix.io/api label to link it",
+        self.name
+      )
+    })?;
+    let root = json!({ "components": components });
+    let mut resolved = inline_references(schema, &root, &mut Vec::new())
+      .map_err(|e| anyhow::anyhow!("Invalid schema for parameter {}\ncause: {}", self.name, e))?;
+    if let (true, Value::Object(object)) = (contains_reference(&resolved), &mut resolved) {
+      object.insert("components".to_string(), components.clone());
+    }
+    Ok(Self {
+      schema: Some(resolved),
+      ..self.clone()
+    })
+  }
+}
+
+// performance expectations of a request, checked once the response is received
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ApixExpect {
+  #[serde(default, skip_serializing_if = "Option::is_none", rename = "maxDurationMs")]
+  pub max_duration_ms: Option<u64>,
+}
+
+// stage of a load profile, the rate goes linearly from `from` (the previous stage target by default) to `to`
+// requests per second during `duration` (eg: `30s`, `2m`)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ApixLoadStage {
+  pub duration: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub from: Option<f64>,
+  pub to: f64,
+}
+
+// staged load profile run by `apix bench`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ApixLoadProfile {
+  pub stages: Vec<ApixLoadStage>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ApixStep {
-  name: String,
+  pub name: String,
   #[serde(default, skip_serializing_if = "Option::is_none")]
-  description: Option<String>,
+  pub description: Option<String>,
   #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
-  context: IndexMap<String, String>,
+  pub context: IndexMap<String, String>,
   #[serde(default, skip_serializing_if = "Option::is_none", rename = "if")]
-  if_: Option<String>,
-  request: ApixRequestTemplate,
+  pub if_: Option<String>,
+  pub request: ApixRequestTemplate,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub assertions: Vec<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none", rename = "assert")]
+  pub assert_: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub expect: Option<ApixExpect>,
+  // response status codes retried, at most `maxRetries` times (3 by default)
+  #[serde(default, skip_serializing_if = "Vec::is_empty", rename = "retryOn")]
+  pub retry_on: Vec<u16>,
+  #[serde(default, skip_serializing_if = "Option::is_none", rename = "maxRetries")]
+  pub max_retries: Option<u32>,
+  #[serde(default, skip_serializing_if = "std::ops::Not::not", rename = "continueOnError")]
+  pub continue_on_error: bool,
 }
 
 /**
@@ -96,17 +352,18 @@ pub struct ApixStep {
  *      headers:
  *          Authorization: "Bearer {{steps.get_token.response.body.token}}"
  *          Accept: "application/json"
+ *   assert: "{{ steps.get_user.response.status == 200 and steps.get_user.response.body.id }}"
  */
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ApixStory {
-  name: String,
+  pub name: String,
   #[serde(default, skip_serializing_if = "Option::is_none")]
-  needs: Option<String>,
+  pub needs: Option<String>,
   #[serde(default, skip_serializing_if = "Option::is_none")]
-  description: Option<String>,
+  pub description: Option<String>,
   #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
-  context: IndexMap<String, IndexMap<String, Value>>,
-  steps: Vec<ApixStep>,
+  pub context: IndexMap<String, IndexMap<String, Value>>,
+  pub steps: Vec<ApixStep>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -116,6 +373,43 @@ pub struct ApixStories {
   pub stories: Vec<ApixStory>,
 }
 
+// query value, a list sends the query once per value (eg: `tag: [a, b]` sends `?tag=a&tag=b`)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum ApixQueryValue {
+  Single(String),
+  Multiple(Vec<String>),
+}
+
+impl ApixQueryValue {
+  pub fn values(&self) -> &[String] {
+    match self {
+      ApixQueryValue::Single(value) => std::slice::from_ref(value),
+      ApixQueryValue::Multiple(values) => values,
+    }
+  }
+}
+
+// group query pairs by name, keeping the order of first occurrence
+pub fn group_queries(queries: Vec<(String, String)>) -> IndexMap<String, ApixQueryValue> {
+  let mut grouped: IndexMap<String, ApixQueryValue> = IndexMap::new();
+  for (key, value) in queries {
+    match grouped.remove(&key) {
+      Some(ApixQueryValue::Single(first)) => {
+        grouped.insert(key, ApixQueryValue::Multiple(vec![first, value]));
+      }
+      Some(ApixQueryValue::Multiple(mut values)) => {
+        values.push(value);
+        grouped.insert(key, ApixQueryValue::Multiple(values));
+      }
+      None => {
+        grouped.insert(key, ApixQueryValue::Single(value));
+      }
+    }
+  }
+  grouped
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ApixRequestTemplate {
   pub method: String,
@@ -123,7 +417,7 @@ pub struct ApixRequestTemplate {
   #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
   pub headers: IndexMap<String, String>,
   #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
-  pub queries: IndexMap<String, String>,
+  pub queries: IndexMap<String, ApixQueryValue>,
   #[serde(default, skip_serializing_if = "Option::is_none")]
   pub body: Option<Value>,
 }
@@ -133,17 +427,55 @@ impl ApixRequestTemplate {
     method: String,
     url: String,
     headers: IndexMap<String, String>,
-    queries: IndexMap<String, String>,
+    queries: Vec<(String, String)>,
     body: Option<Value>,
   ) -> Self {
     Self {
       method,
       url,
       headers,
-      queries,
+      queries: group_queries(queries),
       body,
     }
   }
+
+  // list every template string of the request, named after its location
+  pub fn templates(&self, name: &str) -> Vec<(String, String)> {
+    fn body_templates(name: String, value: &Value, templates: &mut Vec<(String, String)>) {
+      match value {
+        Value::String(content) => templates.push((name, content.clone())),
+        Value::Array(values) => {
+          for (index, value) in values.iter().enumerate() {
+            body_templates(format!("{}.{}", name, index), value, templates);
+          }
+        }
+        Value::Object(values) => {
+          for (key, value) in values {
+            body_templates(format!("{}.{}", name, key), value, templates);
+          }
+        }
+        _ => {}
+      }
+    }
+    let mut templates = vec![(format!("{}.url", name), self.url.clone())];
+    for (key, value) in self.headers.iter() {
+      templates.push((format!("{}.headers.{}", name, key), value.clone()));
+    }
+    for (key, value) in self.queries.iter() {
+      match value {
+        ApixQueryValue::Single(value) => templates.push((format!("{}.queries.{}", name, key), value.clone())),
+        ApixQueryValue::Multiple(values) => {
+          for (index, value) in values.iter().enumerate() {
+            templates.push((format!("{}.queries.{}.{}", name, key, index), value.clone()));
+          }
+        }
+      }
+    }
+    if let Some(body) = &self.body {
+      body_templates(format!("{}.body", name), body, &mut templates);
+    }
+    templates
+  }
 }
 
 // exemple of an ApixRequest for a GET request in yaml
@@ -178,6 +510,12 @@ impl ApixRequestTemplate {
 //       {
 //          "param": {{param}}
 //       }
+//   assertions:
+//     - status == 201
+//     - headers["content-type"] contains "json"
+//   responseSchema:
+//     type: object
+//     required: [id]
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ApixRequest {
   #[serde(default, skip_serializing_if = "Vec::is_empty")]
@@ -185,6 +523,28 @@ pub struct ApixRequest {
   #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
   pub context: IndexMap<String, Value>,
   pub request: ApixRequestTemplate,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub assertions: Vec<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub expect: Option<ApixExpect>,
+  // response status codes retried, at most `maxRetries` times (3 by default)
+  #[serde(default, skip_serializing_if = "Vec::is_empty", rename = "retryOn")]
+  pub retry_on: Vec<u16>,
+  #[serde(default, skip_serializing_if = "Option::is_none", rename = "maxRetries")]
+  pub max_retries: Option<u32>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub load: Option<ApixLoadProfile>,
+  // non-critical requests (cleanup, telemetry) don't fail runs of several manifests
+  #[serde(default, skip_serializing_if = "std::ops::Not::not", rename = "continueOnError")]
+  pub continue_on_error: bool,
+  // template rendered with the parsed `response`, its output replaces the body before display and capture
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub transform: Option<String>,
+  // values captured from the response, keyed by env variable name
+  #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+  pub exports: IndexMap<String, String>,
+  #[serde(default, skip_serializing_if = "Option::is_none", rename = "responseSchema")]
+  pub response_schema: Option<Value>,
 }
 
 impl ApixRequest {
@@ -193,27 +553,73 @@ impl ApixRequest {
       parameters,
       context,
       request,
+      assertions: vec![],
+      expect: None,
+      retry_on: vec![],
+      max_retries: None,
+      load: None,
+      continue_on_error: false,
+      transform: None,
+      exports: IndexMap::new(),
+      response_schema: None,
     }
   }
 }
 
+// named snippets and macros shared by every manifest, referenced as `<library>/<template>`
+// example:
+//   kind: TemplateLibrary
+//   spec:
+//     templates:
+//       audit: '"audit": {"user": "{{ env.USER }}"}'
+//       filters: '{% macro page(size=20) %}page=1&size={{ size }}{% endmacro page %}'
+// then `{% include "common/audit" %}` or `{% import "common/filters" as filters %}`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct ApixTemplateLibrary {
+  #[serde(default)]
+  pub templates: IndexMap<String, String>,
+}
+
+// files downloaded together by `apix download`, each one checked against its checksum when given
+// example:
+//   kind: ArtifactSet
+//   spec:
+//     concurrency: 4
+//     artifacts:
+//       - url: https://github.com/ecyrbe/apix-rust/releases/download/v0.6.0/apix-x86_64-unknown-linux-gnu.tar.gz
+//         destination: dist/apix.tar.gz
+//         checksum: sha256:5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct ApixArtifactSet {
+  // downloads in flight at the same time, 4 by default
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub concurrency: Option<usize>,
+  pub artifacts: Vec<ApixArtifact>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ApixArtifact {
+  pub url: String,
+  pub destination: String,
+  // `sha256:<hex digest>`
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub checksum: Option<String>,
+}
+
 #[allow(clippy::large_enum_variant)]
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, EnumDisplay)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, EnumDisplay, Default)]
 #[serde(tag = "kind", content = "spec")]
 pub enum ApixKind {
   Api(ApixApi),
   Configuration(ApixConfiguration),
   Request(ApixRequest),
   Story(ApixStories),
+  TemplateLibrary(ApixTemplateLibrary),
+  ArtifactSet(ApixArtifactSet),
+  #[default]
   None,
 }
 
-impl Default for ApixKind {
-  fn default() -> Self {
-    ApixKind::None
-  }
-}
-
 impl ApixKind {
   #[allow(dead_code)]
   pub fn as_api(&self) -> Option<&ApixApi> {
@@ -268,39 +674,38 @@ pub struct ApixManifestV1 {
 }
 
 #[allow(clippy::large_enum_variant)]
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
 #[serde(tag = "apiVersion")]
 pub enum ApixManifest {
   #[serde(rename = "apix.io/v1")]
   V1(ApixManifestV1),
+  #[default]
   None,
 }
 
-impl Default for ApixManifest {
-  fn default() -> Self {
-    ApixManifest::None
-  }
-}
-
 impl ApixManifest {
+  // manifests are searched in current directory and in its apix project directory
   pub fn find_manifests() -> Result<impl Iterator<Item = (PathBuf, ApixManifest)>> {
     let current_dir = std::env::current_dir()?;
-    let manifests = std::fs::read_dir(current_dir)?.filter_map(|entry| {
-      if let Ok(entry) = entry {
-        let path = entry.path();
-        if path.is_file() {
-          match path.extension() {
-            Some(ext) if ext == "yaml" || ext == "yml" => {
-              if let Ok(manifest) = ApixManifest::from_file(&path) {
-                return Some((path, manifest));
+    let project_entries = std::fs::read_dir(current_dir.join(PROJECT_DIR)).into_iter().flatten();
+    let manifests = std::fs::read_dir(current_dir)?
+      .chain(project_entries)
+      .filter_map(|entry| {
+        if let Ok(entry) = entry {
+          let path = entry.path();
+          if path.is_file() {
+            match path.extension() {
+              Some(ext) if ext == "yaml" || ext == "yml" => {
+                if let Ok(manifest) = ApixManifest::from_file(&path) {
+                  return Some((path, manifest));
+                }
               }
+              _ => {}
             }
-            _ => {}
           }
         }
-      }
-      None
-    });
+        None
+      });
     Ok(manifests)
   }
 
@@ -314,23 +719,18 @@ impl ApixManifest {
   }
 
   pub fn find_manifest(kind: &str, name: &str) -> Option<(PathBuf, ApixManifest)> {
-    Self::find_manifests()
-      .ok()
-      .map(|mut manifests| {
-        manifests.find(|(_, manifest)| match manifest {
-          ApixManifest::V1(manifest) => {
-            manifest.kind.to_string().to_lowercase() == kind && manifest.metadata.name == name
-          }
-          _ => false,
-        })
+    Self::find_manifests().ok().and_then(|mut manifests| {
+      manifests.find(|(_, manifest)| match manifest {
+        ApixManifest::V1(manifest) => {
+          manifest.kind.to_string().to_lowercase() == kind && manifest.metadata.name == name
+        }
+        _ => false,
       })
-      .flatten()
+    })
   }
 
   pub fn find_manifest_filename(kind: &str, name: &str) -> Option<String> {
-    Self::find_manifest(kind, name)
-      .map(|(path, _)| path.to_str().map(str::to_string))
-      .flatten()
+    Self::find_manifest(kind, name).and_then(|(path, _)| path.to_str().map(str::to_string))
   }
 
   #[allow(dead_code)]
@@ -433,6 +833,29 @@ impl ApixManifest {
     }
   }
 
+  // list every template string of the manifest, named after its location
+  pub fn templates(&self) -> Vec<(String, String)> {
+    match self.kind() {
+      ApixKind::Request(request) => request.request.templates("request"),
+      ApixKind::Story(stories) => stories
+        .stories
+        .iter()
+        .flat_map(|story| {
+          story
+            .steps
+            .iter()
+            .flat_map(move |step| step.request.templates(&format!("{}.{}.request", story.name, step.name)))
+        })
+        .collect(),
+      ApixKind::TemplateLibrary(library) => library
+        .templates
+        .iter()
+        .map(|(name, template)| (format!("{}/{}", self.name(), name), template.clone()))
+        .collect(),
+      _ => Vec::new(),
+    }
+  }
+
   #[allow(dead_code)]
   pub fn get_annotation(&self, key: &str) -> Option<&String> {
     match self {
@@ -441,6 +864,14 @@ impl ApixManifest {
     }
   }
 
+  // creation date from the `apix.io/created-at` annotation
+  pub fn created_at(&self) -> Option<DateTime<Utc>> {
+    self
+      .get_annotation("apix.io/created-at")
+      .and_then(|date| DateTime::parse_from_rfc3339(date).ok())
+      .map(|date| date.with_timezone(&Utc))
+  }
+
   #[allow(dead_code)]
   pub fn get_annotations(&self) -> Option<&IndexMap<String, String>> {
     match self {
@@ -456,4 +887,120 @@ impl ApixManifest {
       ApixManifest::None => None,
     }
   }
+
+  // api the manifest is linked to with the `apix.io/api` label
+  pub fn linked_api(&self) -> Option<ApixApi> {
+    let name = self.get_label("apix.io/api")?;
+    let (_, api) = Self::find_manifest("api", name)?;
+    api.kind().as_api().cloned()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use chrono::TimeZone;
+
+  fn manifest(name: &str, created_at: &str, created_by: &str) -> (PathBuf, ApixManifest) {
+    let content = format!(
+      "apiVersion: apix.io/v1\nmetadata:\n  name: {}\n  annotations:\n    apix.io/created-at: \"{}\"\n    apix.io/created-by: {}\nkind: Request\nspec:\n  request:\n    method: get\n    url: http://localhost\n",
+      name, created_at, created_by
+    );
+    (PathBuf::from(name), serde_yaml::from_str(&content).unwrap())
+  }
+
+  fn names(manifests: &[(PathBuf, ApixManifest)]) -> Vec<&str> {
+    manifests.iter().map(|(_, manifest)| manifest.name()).collect()
+  }
+
+  #[test]
+  fn test_sort_manifests() {
+    let mut manifests = vec![
+      manifest("users", "2023-10-14T12:00:00+00:00", "jane"),
+      manifest("todos", "2023-10-01T12:00:00+02:00", "john"),
+      manifest("albums", "2023-10-10T12:00:00+00:00", "jane"),
+    ];
+    ManifestOrder::CreatedAt.sort(&mut manifests);
+    assert_eq!(names(&manifests), vec!["todos", "albums", "users"]);
+    "name".parse::<ManifestOrder>().unwrap().sort(&mut manifests);
+    assert_eq!(names(&manifests), vec!["albums", "todos", "users"]);
+  }
+
+  #[test]
+  fn test_filter_manifests() {
+    let manifests = [
+      manifest("users", "2023-10-14T12:00:00+00:00", "jane"),
+      manifest("todos", "2023-10-01T12:00:00+00:00", "john"),
+      manifest("albums", "2023-10-10T12:00:00+00:00", "jane"),
+    ];
+    let filter = ManifestFilter {
+      created_since: Some(Utc.ymd(2023, 10, 8).and_hms(0, 0, 0)),
+      created_by: None,
+    };
+    let recent = manifests
+      .iter()
+      .filter(|(_, manifest)| filter.matches(manifest))
+      .cloned()
+      .collect::<Vec<_>>();
+    assert_eq!(names(&recent), vec!["users", "albums"]);
+    let filter = ManifestFilter {
+      created_since: None,
+      created_by: Some("john".to_string()),
+    };
+    let john = manifests
+      .iter()
+      .filter(|(_, manifest)| filter.matches(manifest))
+      .cloned()
+      .collect::<Vec<_>>();
+    assert_eq!(names(&john), vec!["todos"]);
+  }
+
+  #[test]
+  fn test_parameter_with_resolved_schema() {
+    let api = ApixApi {
+      components: Some(json!({
+        "schemas": {
+          "Role": { "type": "string", "enum": ["admin", "user"] },
+          "User": {
+            "type": "object",
+            "properties": {
+              "role": { "$ref": "#/components/schemas/Role" },
+              "manager": { "$ref": "#/components/schemas/User" }
+            }
+          }
+        }
+      })),
+      ..Default::default()
+    };
+    let parameter = |reference: &str| {
+      ApixParameter::new(
+        "user".to_string(),
+        true,
+        false,
+        None,
+        Some(json!({ "$ref": reference })),
+      )
+    };
+    let resolved = parameter("#/components/schemas/User")
+      .with_resolved_schema(Some(&api))
+      .unwrap()
+      .schema
+      .unwrap();
+    assert_eq!(resolved["type"], "object");
+    assert_eq!(resolved["properties"]["role"]["enum"], json!(["admin", "user"]));
+    assert_eq!(
+      resolved["properties"]["manager"],
+      json!({ "$ref": "#/components/schemas/User" })
+    );
+    assert_eq!(resolved["components"], api.components.clone().unwrap());
+    let schema = jsonschema::JSONSchema::compile(&resolved).unwrap();
+    assert!(schema.is_valid(&json!({ "role": "admin", "manager": { "role": "user" } })));
+    assert!(!schema.is_valid(&json!({ "manager": { "role": "guest" } })));
+    assert!(parameter("#/components/schemas/Team")
+      .with_resolved_schema(Some(&api))
+      .is_err());
+    assert!(parameter("#/components/schemas/User")
+      .with_resolved_schema(None)
+      .is_err());
+  }
 }
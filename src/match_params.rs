@@ -1,9 +1,18 @@
-use super::requests::AdvancedBody;
+use super::cache::CacheMode;
+use super::certificates::split_pins;
+use super::decode::BodyDecoder;
+use super::display::OutputFormat;
+use super::http_utils::{
+  format_http_date, parse_date, parse_size, parse_timeout, quote_etag, range_header, resolve_idempotency_key,
+  BodyFormat, ContinueAt, DigestHeader, IpFamily, TlsVersion, TrustStore,
+};
+use super::requests::{AdvancedBody, FailMode, RequestOptions};
 use anyhow::Result;
 use indexmap::IndexMap;
 use once_cell::sync::Lazy;
 use regex::Regex;
-use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, IF_MODIFIED_SINCE, IF_NONE_MATCH, RANGE};
+use serde_json::Value;
 use std::str::FromStr;
 use strum_macros::Display;
 
@@ -16,13 +25,17 @@ pub enum RequestParam {
   Param,
 }
 
+// header option, like curl `<name>:` removes the header and `<name>;` sends it with an empty value
 #[derive(Debug)]
-struct HeaderTuple(HeaderName, HeaderValue);
+enum HeaderTuple {
+  Set(HeaderName, HeaderValue),
+  Unset(HeaderName),
+}
 
 impl FromStr for HeaderTuple {
   type Err = anyhow::Error;
   fn from_str(header_string: &str) -> Result<Self, Self::Err> {
-    static RE: Lazy<Regex> = Lazy::new(|| Regex::new("^([\\w-]+):(.*)$").unwrap());
+    static RE: Lazy<Regex> = Lazy::new(|| Regex::new("^([\\w-]+)(?::(.*)|;)$").unwrap());
 
     let header_split = RE.captures(header_string).ok_or_else(|| {
       anyhow::anyhow!(
@@ -30,10 +43,12 @@ impl FromStr for HeaderTuple {
         header_string
       )
     })?;
-    Ok(HeaderTuple(
-      HeaderName::from_str(&header_split[1])?,
-      HeaderValue::from_str(&header_split[2])?,
-    ))
+    let name = HeaderName::from_str(&header_split[1])?;
+    match header_split.get(2) {
+      Some(value) if value.as_str().trim().is_empty() => Ok(HeaderTuple::Unset(name)),
+      Some(value) => Ok(HeaderTuple::Set(name, HeaderValue::from_str(value.as_str())?)),
+      None => Ok(HeaderTuple::Set(name, HeaderValue::from_static(""))),
+    }
   }
 }
 
@@ -56,24 +71,80 @@ impl FromStr for StringTuple {
   }
 }
 
+fn param_value(value: &str) -> Result<Value> {
+  if let Some(literal) = value.strip_prefix("@@") {
+    return Ok(Value::String(format!("@{}", literal)));
+  }
+  let path = match value.strip_prefix('@') {
+    Some(path) => path,
+    None => return Ok(Value::String(value.to_string())),
+  };
+  let content = std::fs::read_to_string(path)
+    .map_err(|e| anyhow::anyhow!("Failed to read parameter file {}\ncause: {}", path, e))?;
+  if path.ends_with(".json") {
+    serde_json::from_str(&content).map_err(|e| anyhow::anyhow!("Invalid json in parameter file {}\ncause: {}", path, e))
+  } else {
+    // files usually end with a newline that is not part of the value
+    let content = content.strip_suffix('\n').unwrap_or(&content);
+    Ok(Value::String(content.strip_suffix('\r').unwrap_or(content).to_string()))
+  }
+}
+
 pub trait MatchParams {
   fn match_headers(&self) -> Option<reqwest::header::HeaderMap>;
-  fn match_params(&self, param_type: RequestParam) -> Option<IndexMap<String, String>>;
+  fn match_unset_headers(&self) -> Vec<HeaderName>;
+  fn match_flag_headers(&self) -> Result<HeaderMap>;
+  fn match_named_params(&self, name: &str) -> Option<IndexMap<String, String>>;
+  fn match_template_params(&self, name: &str) -> Result<Option<IndexMap<String, Value>>>;
+  fn match_queries(&self) -> Option<Vec<(String, String)>>;
   fn match_body(&self) -> Option<AdvancedBody>;
+  fn match_request_options<'a>(&self, defaults: &RequestOptions<'a>) -> RequestOptions<'a>;
 }
 
 impl MatchParams for clap::ArgMatches {
   fn match_headers(&self) -> Option<reqwest::header::HeaderMap> {
     if let Ok(header_tuples) = self.values_of_t::<HeaderTuple>("header") {
-      let headers = header_tuples.iter().map(|tuple| (tuple.0.clone(), tuple.1.clone()));
+      let headers = header_tuples.into_iter().filter_map(|tuple| match tuple {
+        HeaderTuple::Set(name, value) => Some((name, value)),
+        HeaderTuple::Unset(_) => None,
+      });
       Some(HeaderMap::from_iter(headers))
     } else {
       None
     }
   }
 
-  fn match_params(&self, param_type: RequestParam) -> Option<IndexMap<String, String>> {
-    if let Ok(param_tuples) = self.values_of_t::<StringTuple>(&param_type.to_string()) {
+  // headers given without value (`-H 'Accept:'`), removed from the request even when set by default
+  fn match_unset_headers(&self) -> Vec<HeaderName> {
+    self
+      .values_of_t::<HeaderTuple>("header")
+      .unwrap_or_default()
+      .into_iter()
+      .filter_map(|tuple| match tuple {
+        HeaderTuple::Unset(name) => Some(name),
+        HeaderTuple::Set(..) => None,
+      })
+      .collect()
+  }
+
+  // headers from `--if-none-match`, `--if-modified-since` and `--range` flags
+  fn match_flag_headers(&self) -> Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    if let Some(etag) = self.value_of("if-none-match") {
+      headers.insert(IF_NONE_MATCH, HeaderValue::from_str(&quote_etag(etag))?);
+    }
+    if let Some(date) = self.value_of("if-modified-since") {
+      let date = format_http_date(parse_date(date, chrono::Utc::now())?);
+      headers.insert(IF_MODIFIED_SINCE, HeaderValue::from_str(&date)?);
+    }
+    if let Some(range) = self.value_of("range") {
+      headers.insert(RANGE, HeaderValue::from_str(&range_header(range)?)?);
+    }
+    Ok(headers)
+  }
+
+  fn match_named_params(&self, name: &str) -> Option<IndexMap<String, String>> {
+    if let Ok(param_tuples) = self.values_of_t::<StringTuple>(name) {
       let params = param_tuples.iter().map(|tuple| (tuple.0.clone(), tuple.1.clone()));
       Some(IndexMap::from_iter(params))
     } else {
@@ -81,6 +152,29 @@ impl MatchParams for clap::ArgMatches {
     }
   }
 
+  // template parameters, `@<file>` values are read from files, parsed as json for `.json` files,
+  // `@@` starts a value with a literal `@`
+  fn match_template_params(&self, name: &str) -> Result<Option<IndexMap<String, Value>>> {
+    self
+      .match_named_params(name)
+      .map(|params| {
+        params
+          .into_iter()
+          .map(|(key, value)| Ok((key, param_value(&value)?)))
+          .collect::<Result<IndexMap<_, _>>>()
+      })
+      .transpose()
+  }
+
+  // queries keep repeated names, `-q tag:a -q tag:b` sends both values
+  fn match_queries(&self) -> Option<Vec<(String, String)>> {
+    if let Ok(query_tuples) = self.values_of_t::<StringTuple>(&RequestParam::Query.to_string()) {
+      Some(query_tuples.into_iter().map(|tuple| (tuple.0, tuple.1)).collect())
+    } else {
+      None
+    }
+  }
+
   fn match_body(&self) -> Option<AdvancedBody> {
     if let Some(body) = self.value_of("body") {
       Some(AdvancedBody::String(body.to_string()))
@@ -88,6 +182,89 @@ impl MatchParams for clap::ArgMatches {
       self.value_of("file").map(|file| AdvancedBody::File(file.to_string()))
     }
   }
+
+  fn match_request_options<'a>(&self, defaults: &RequestOptions<'a>) -> RequestOptions<'a> {
+    let body_format = match (self.is_present("json"), self.is_present("form")) {
+      (true, _) => Some(BodyFormat::Json),
+      (_, true) => Some(BodyFormat::Form),
+      _ => defaults.body_format,
+    };
+    RequestOptions {
+      verbose: self.is_present("verbose"),
+      output_filename: self.value_of("output-file").map(str::to_string),
+      proxy_url: self.value_of("proxy").map(str::to_string),
+      proxy_login: self.value_of("proxy-login").map(str::to_string),
+      proxy_password: self.value_of("proxy-password").map(str::to_string),
+      syntax: self.value_of("syntax").map(str::to_string),
+      tee: self.is_present("tee"),
+      content_digest: self
+        .value_of_t::<DigestHeader>("content-digest")
+        .ok()
+        .or(defaults.content_digest),
+      idempotency_key: self
+        .value_of("idempotency-key")
+        .map(resolve_idempotency_key)
+        .or_else(|| defaults.idempotency_key.clone()),
+      cache: self.value_of_t::<CacheMode>("cache").ok().or(defaults.cache),
+      continue_at: self.value_of_t::<ContinueAt>("continue-at").ok(),
+      unset_headers: self.match_unset_headers(),
+      accept: body_format
+        .map(|format| format.accept().to_string())
+        .or_else(|| defaults.accept.clone()),
+      body_format,
+      output_format: self.value_of_t::<OutputFormat>("output").ok(),
+      decoder: self.value_of_t::<BodyDecoder>("decode").ok(),
+      raw: self.is_present("raw") || defaults.raw,
+      show_cert: self.is_present("show-cert") || defaults.show_cert,
+      pins: self
+        .values_of("pin")
+        .map(|values| values.flat_map(split_pins).collect())
+        .unwrap_or_else(|| defaults.pins.clone()),
+      fail: match (self.is_present("fail"), self.is_present("fail-with-body")) {
+        (true, _) => Some(FailMode::HideBody),
+        (_, true) => Some(FailMode::KeepBody),
+        _ => defaults.fail,
+      },
+      ip_family: match (self.is_present("ipv4"), self.is_present("ipv6")) {
+        (true, _) => Some(IpFamily::V4),
+        (_, true) => Some(IpFamily::V6),
+        _ => defaults.ip_family,
+      },
+      fresh_dns: self.is_present("fresh-dns") || defaults.fresh_dns,
+      tls_min: [
+        ("tlsv1.0", TlsVersion::V1_0),
+        ("tlsv1.1", TlsVersion::V1_1),
+        ("tlsv1.2", TlsVersion::V1_2),
+        ("tlsv1.3", TlsVersion::V1_3),
+      ]
+      .into_iter()
+      .find(|(arg, _)| self.is_present(arg))
+      .map(|(_, version)| version)
+      .or(defaults.tls_min),
+      tls_max: self.value_of_t::<TlsVersion>("tls-max").ok().or(defaults.tls_max),
+      trust_store: self
+        .value_of_t::<TrustStore>("trust-store")
+        .ok()
+        .or(defaults.trust_store),
+      max_body_size: self
+        .value_of("max-body-size")
+        .and_then(|value| parse_size(value).ok())
+        .or(defaults.max_body_size),
+      timeout: self
+        .value_of("timeout")
+        .and_then(|value| parse_timeout(value).ok())
+        .or(defaults.timeout),
+      connect_timeout: self
+        .value_of("connect-timeout")
+        .and_then(|value| parse_timeout(value).ok())
+        .or(defaults.connect_timeout),
+      read_timeout: self
+        .value_of("read-timeout")
+        .and_then(|value| parse_timeout(value).ok())
+        .or(defaults.read_timeout),
+      ..defaults.clone()
+    }
+  }
 }
 
 #[cfg(test)]
@@ -107,30 +284,109 @@ mod tests {
     assert_eq!(headers.get("foo"), Some(&"bar".parse::<HeaderValue>().unwrap()));
   }
 
+  // test unset and empty headers
+  #[test]
+  fn test_match_unset_headers() {
+    let matches = App::new("test")
+      .arg(
+        arg!(--header "Header to add")
+          .takes_value(true)
+          .multiple_occurrences(true),
+      )
+      .get_matches_from(vec![
+        "test",
+        "--header",
+        "accept:",
+        "--header",
+        "content-type;",
+        "--header",
+        "foo:bar",
+      ]);
+    let headers = matches.match_headers().unwrap();
+    assert_eq!(headers.get("content-type"), Some(&HeaderValue::from_static("")));
+    assert_eq!(headers.get("foo"), Some(&HeaderValue::from_static("bar")));
+    assert!(!headers.contains_key("accept"));
+    assert_eq!(matches.match_unset_headers(), vec![HeaderName::from_static("accept")]);
+  }
+
   // test match queries
   #[test]
   fn test_match_queries() {
     let matches = App::new("test")
       .arg(arg!(--query "Query to add").takes_value(true))
       .get_matches_from(vec!["test", "--query", "foo:bar"]);
-    let queries = matches.match_params(RequestParam::Query);
+    let queries = matches.match_named_params(&RequestParam::Query.to_string());
     assert!(queries.is_some());
     let queries = queries.unwrap();
     assert_eq!(queries.get("foo"), Some(&"bar".to_string()));
   }
 
+  // test match repeated queries
+  #[test]
+  fn test_match_repeated_queries() {
+    let matches = App::new("test")
+      .arg(
+        arg!(--query "Query to add")
+          .takes_value(true)
+          .multiple_occurrences(true),
+      )
+      .get_matches_from(vec!["test", "--query", "tag:a", "--query", "tag:b"]);
+    assert_eq!(
+      matches.match_queries(),
+      Some(vec![
+        ("tag".to_string(), "a".to_string()),
+        ("tag".to_string(), "b".to_string())
+      ])
+    );
+  }
+
   // test match params
   #[test]
   fn test_match_params() {
     let matches = App::new("test")
       .arg(arg!(--param "Param to add").takes_value(true))
       .get_matches_from(vec!["test", "--param", "foo:bar"]);
-    let params = matches.match_params(RequestParam::Param);
+    let params = matches.match_named_params(&RequestParam::Param.to_string());
     assert!(params.is_some());
     let params = params.unwrap();
     assert_eq!(params.get("foo"), Some(&"bar".to_string()));
   }
 
+  // test parameters read from files
+  #[test]
+  fn test_match_template_params() {
+    let dir = std::env::temp_dir().join(format!("apix-params-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let token = dir.join("token.txt");
+    let payload = dir.join("payload.json");
+    std::fs::write(&token, "secret\n").unwrap();
+    std::fs::write(&payload, r#"{"name": "apix"}"#).unwrap();
+    let matches = App::new("test")
+      .arg(
+        arg!(--param "Param to add")
+          .takes_value(true)
+          .multiple_occurrences(true),
+      )
+      .get_matches_from(vec![
+        "test".to_string(),
+        "--param".to_string(),
+        format!("token:@{}", token.display()),
+        "--param".to_string(),
+        format!("data:@{}", payload.display()),
+        "--param".to_string(),
+        "handle:@@apix".to_string(),
+        "--param".to_string(),
+        "name:apix".to_string(),
+      ]);
+    let params = matches.match_template_params("param").unwrap().unwrap();
+    assert_eq!(params["token"], Value::String("secret".to_string()));
+    assert_eq!(params["data"], serde_json::json!({"name": "apix"}));
+    assert_eq!(params["handle"], Value::String("@apix".to_string()));
+    assert_eq!(params["name"], Value::String("apix".to_string()));
+    std::fs::remove_dir_all(dir).unwrap();
+    assert!(param_value("@missing-parameter.txt").is_err());
+  }
+
   // test match body
   #[test]
   fn test_match_body() {
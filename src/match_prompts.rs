@@ -1,7 +1,6 @@
 use anyhow::Result;
 use clap::ArgMatches;
 use dialoguer::{theme::ColorfulTheme, Confirm, Input, Select};
-use indexmap::IndexMap;
 pub trait MatchPrompts {
   fn match_or_input(&self, name: &str, msg: &str) -> Result<String>;
   fn match_or_validate_input<V: FnMut(&String) -> Result<()>>(
@@ -10,7 +9,7 @@ pub trait MatchPrompts {
     msg: &str,
     validator: V,
   ) -> Result<String>;
-  fn match_or_input_multiples(&self, name: &str, msg: &str) -> Result<IndexMap<String, String>>;
+  fn match_or_input_multiples(&self, name: &str, msg: &str) -> Result<Vec<(String, String)>>;
   fn match_or_optional_input(&self, name: &str, msg: &str) -> Result<Option<String>>;
   fn match_or_select<T: ToString>(&self, name: &str, msg: &str, options: &[T]) -> Result<String>;
 }
@@ -41,10 +40,10 @@ impl MatchPrompts for ArgMatches {
     }
   }
 
-  fn match_or_input_multiples(&self, name: &str, msg: &str) -> Result<IndexMap<String, String>> {
+  fn match_or_input_multiples(&self, name: &str, msg: &str) -> Result<Vec<(String, String)>> {
     match self.values_of(name) {
       Some(values) => {
-        let mut map = IndexMap::new();
+        let mut pairs = Vec::new();
         for value in values {
           let mut parts = value.splitn(2, ':');
           let key = parts
@@ -53,12 +52,12 @@ impl MatchPrompts for ArgMatches {
           let value = parts
             .next()
             .ok_or_else(|| anyhow::anyhow!("No value found in '{}'", value))?;
-          map.insert(key.to_string(), value.to_string());
+          pairs.push((key.to_string(), value.to_string()));
         }
-        Ok(map)
+        Ok(pairs)
       }
       None => {
-        let mut map = IndexMap::new();
+        let mut pairs = Vec::new();
         loop {
           let add = Confirm::with_theme(&ColorfulTheme::default())
             .with_prompt(msg)
@@ -70,12 +69,12 @@ impl MatchPrompts for ArgMatches {
             let value = Input::with_theme(&ColorfulTheme::default())
               .with_prompt(format!("{} value", name))
               .interact_text()?;
-            map.insert(key, value);
+            pairs.push((key, value));
           } else {
             break;
           }
         }
-        Ok(map)
+        Ok(pairs)
       }
     }
   }
@@ -0,0 +1,153 @@
+use super::report::{ExecutionReport, ExecutionStatus};
+use anyhow::Result;
+use reqwest::{header::CONTENT_TYPE, Client};
+use std::fmt::Write;
+
+// escape a prometheus label value
+fn escape_label(value: &str) -> String {
+  value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn labels(report: &ExecutionReport) -> String {
+  format!(
+    "name=\"{}\",file=\"{}\"",
+    escape_label(&report.name),
+    escape_label(&report.file)
+  )
+}
+
+// render execution reports using prometheus text exposition format
+pub fn to_prometheus(reports: &[ExecutionReport]) -> String {
+  let executed = reports
+    .iter()
+    .filter(|report| !matches!(report.status, ExecutionStatus::Skipped))
+    .collect::<Vec<_>>();
+  let mut output = String::new();
+  output.push_str("# HELP apix_requests_total Number of executed requests.\n");
+  output.push_str("# TYPE apix_requests_total counter\n");
+  for report in &executed {
+    let status = match &report.status {
+      ExecutionStatus::Success(metrics) => metrics.status.as_u16().to_string(),
+      _ => "error".to_string(),
+    };
+    writeln!(
+      output,
+      "apix_requests_total{{{},status=\"{}\"}} 1",
+      labels(report),
+      status
+    )
+    .unwrap();
+  }
+  output.push_str("# HELP apix_request_failures_total Number of requests that failed to execute.\n");
+  output.push_str("# TYPE apix_request_failures_total counter\n");
+  for report in &executed {
+    writeln!(
+      output,
+      "apix_request_failures_total{{{}}} {}",
+      labels(report),
+      report.is_error() as u8
+    )
+    .unwrap();
+  }
+  output.push_str("# HELP apix_request_duration_seconds Duration of the request execution.\n");
+  output.push_str("# TYPE apix_request_duration_seconds gauge\n");
+  for report in &executed {
+    writeln!(
+      output,
+      "apix_request_duration_seconds{{{}}} {}",
+      labels(report),
+      report.duration.as_secs_f64()
+    )
+    .unwrap();
+  }
+  output.push_str("# HELP apix_request_retries_total Number of times requests were sent again on a retried status.\n");
+  output.push_str("# TYPE apix_request_retries_total counter\n");
+  for report in &executed {
+    writeln!(
+      output,
+      "apix_request_retries_total{{{}}} {}",
+      labels(report),
+      report.retries()
+    )
+    .unwrap();
+  }
+  output.push_str("# HELP apix_response_bytes Size of the response body in bytes.\n");
+  output.push_str("# TYPE apix_response_bytes gauge\n");
+  for report in &executed {
+    writeln!(output, "apix_response_bytes{{{}}} {}", labels(report), report.bytes()).unwrap();
+  }
+  output
+}
+
+pub fn save_metrics(reports: &[ExecutionReport], path: &str) -> Result<()> {
+  std::fs::write(path, to_prometheus(reports))
+    .map_err(|e| anyhow::anyhow!("Failed to write metrics file '{}'\ncause: {}", path, e))
+}
+
+// push metrics to a prometheus pushgateway, grouped under the apix job
+pub async fn push_metrics(reports: &[ExecutionReport], gateway_url: &str) -> Result<()> {
+  let url = format!("{}/metrics/job/apix", gateway_url.trim_end_matches('/'));
+  let response = Client::new()
+    .put(&url)
+    .header(CONTENT_TYPE, "text/plain; version=0.0.4")
+    .body(to_prometheus(reports))
+    .send()
+    .await
+    .map_err(|e| anyhow::anyhow!("Failed to push metrics to '{}'\ncause: {}", url, e))?;
+  if !response.status().is_success() {
+    return Err(anyhow::anyhow!(
+      "Failed to push metrics to '{}'\ncause: pushgateway answered {}",
+      url,
+      response.status()
+    ));
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::requests::ResponseMetrics;
+  use reqwest::StatusCode;
+  use std::time::Duration;
+
+  #[test]
+  fn test_to_prometheus() {
+    let reports = [
+      ExecutionReport {
+        file: "get.yaml".to_string(),
+        name: "get".to_string(),
+        status: ExecutionStatus::Success(ResponseMetrics {
+          status: StatusCode::OK,
+          bytes: 42,
+          duration: Duration::from_millis(200),
+          attempts: 2,
+          request_id: None,
+          idempotency_key: None,
+          response: None,
+        }),
+        duration: Duration::from_millis(250),
+      },
+      ExecutionReport {
+        file: "post.yaml".to_string(),
+        name: "post \"x\"".to_string(),
+        status: ExecutionStatus::Failure("boom".to_string()),
+        duration: Duration::from_millis(500),
+      },
+      ExecutionReport {
+        file: "put.yaml".to_string(),
+        name: String::new(),
+        status: ExecutionStatus::Skipped,
+        duration: Duration::default(),
+      },
+    ];
+    let output = to_prometheus(&reports);
+    assert!(output.contains("apix_requests_total{name=\"get\",file=\"get.yaml\",status=\"200\"} 1\n"));
+    assert!(output.contains("apix_requests_total{name=\"post \\\"x\\\"\",file=\"post.yaml\",status=\"error\"} 1\n"));
+    assert!(output.contains("apix_request_failures_total{name=\"get\",file=\"get.yaml\"} 0\n"));
+    assert!(output.contains("apix_request_duration_seconds{name=\"get\",file=\"get.yaml\"} 0.25\n"));
+    assert!(output.contains("apix_response_bytes{name=\"get\",file=\"get.yaml\"} 42\n"));
+    assert!(output.contains("apix_request_retries_total{name=\"get\",file=\"get.yaml\"} 1\n"));
+    assert!(!output.contains("put.yaml"));
+  }
+}
@@ -0,0 +1,601 @@
+use crate::manifests::ApixManifest;
+use anyhow::Result;
+use regex::Regex;
+use reqwest::StatusCode;
+use serde_json::{json, Map, Value};
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+static METHODS: [&str; 8] = ["get", "put", "post", "delete", "options", "head", "patch", "trace"];
+
+// schemas nested deeper than this are generated as null, to stop on recursive schemas
+static MAX_FAKE_DEPTH: usize = 8;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockResponse {
+  pub status: u16,
+  pub content_type: Option<String>,
+  pub body: String,
+}
+
+impl MockResponse {
+  fn empty(status: u16) -> Self {
+    Self {
+      status,
+      content_type: None,
+      body: String::new(),
+    }
+  }
+}
+
+// operations of an OpenAPI description, answered with their examples or fake data generated from their schemas
+pub struct MockApi {
+  document: Value,
+  base_path: String,
+  routes: Vec<(Regex, String)>,
+}
+
+// `/users/{id}` as `^/users/(?P<p_id>[^/]+)$`, parameter names are prefixed to be valid group names
+fn path_regex(template: &str) -> Regex {
+  let mut pattern = "^".to_string();
+  let mut rest = template;
+  while let Some(start) = rest.find('{') {
+    let end = rest[start..].find('}').map(|end| start + end).unwrap_or(rest.len() - 1);
+    pattern.push_str(&regex::escape(&rest[..start]));
+    let name: String = rest[start + 1..end]
+      .chars()
+      .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+      .collect();
+    pattern.push_str(&format!("(?P<p_{}>[^/]+)", name));
+    rest = &rest[end + 1..];
+  }
+  pattern.push_str(&regex::escape(rest));
+  pattern.push('$');
+  Regex::new(&pattern).unwrap()
+}
+
+// media type of `available` preferred by an Accept header, by quality then order
+fn negotiate<'a>(accept: Option<&str>, available: &[&'a str]) -> Option<&'a str> {
+  let accept = match accept {
+    Some(accept) if !accept.trim().is_empty() => accept,
+    _ => return available.first().copied(),
+  };
+  let mut ranges = accept
+    .split(',')
+    .enumerate()
+    .map(|(index, range)| {
+      let mut parts = range.split(';');
+      let media = parts.next().unwrap_or_default().trim().to_lowercase();
+      let quality = parts
+        .filter_map(|param| param.trim().strip_prefix("q="))
+        .find_map(|quality| quality.parse::<f32>().ok())
+        .unwrap_or(1.0);
+      (media, quality, index)
+    })
+    .filter(|(_, quality, _)| *quality > 0.0)
+    .collect::<Vec<_>>();
+  ranges.sort_by(|a, b| {
+    b.1
+      .partial_cmp(&a.1)
+      .unwrap_or(std::cmp::Ordering::Equal)
+      .then(a.2.cmp(&b.2))
+  });
+  ranges.iter().find_map(|(range, _, _)| {
+    available.iter().copied().find(|media| {
+      let media = media.to_lowercase();
+      let essence = media.split(';').next().unwrap_or_default().trim();
+      match range.strip_suffix("/*") {
+        Some("*") => true,
+        Some(kind) => essence.split('/').next() == Some(kind),
+        None => essence == range,
+      }
+    })
+  })
+}
+
+impl MockApi {
+  pub fn new(document: Value) -> Self {
+    let base_path = document
+      .pointer("/servers/0/url")
+      .and_then(Value::as_str)
+      .map(|url| match url.find("://") {
+        Some(scheme) => url[scheme + 3..]
+          .find('/')
+          .map(|path| &url[scheme + 3 + path..])
+          .unwrap_or(""),
+        None => url,
+      })
+      .unwrap_or("")
+      .trim_end_matches('/')
+      .to_string();
+    // literal paths are matched before templated ones, as with `/users/me` and `/users/{id}`
+    let mut routes = document
+      .get("paths")
+      .and_then(Value::as_object)
+      .map(|paths| {
+        paths
+          .keys()
+          .map(|path| (path_regex(path), path.clone()))
+          .collect::<Vec<_>>()
+      })
+      .unwrap_or_default();
+    routes.sort_by_key(|(_, path)| path.matches('{').count());
+    Self {
+      document,
+      base_path,
+      routes,
+    }
+  }
+
+  // follow local `$ref` (eg: `#/components/schemas/User`)
+  fn resolve<'a>(&'a self, mut value: &'a Value) -> &'a Value {
+    for _ in 0..MAX_FAKE_DEPTH {
+      match value.get("$ref").and_then(Value::as_str) {
+        Some(reference) => match reference
+          .strip_prefix('#')
+          .and_then(|pointer| self.document.pointer(pointer))
+        {
+          Some(target) => value = target,
+          None => return value,
+        },
+        None => return value,
+      }
+    }
+    value
+  }
+
+  // path parameters with an integer or number schema only match numeric segments
+  fn parameters_match(&self, path_item: &Value, operation: &Value, captures: &regex::Captures) -> bool {
+    let parameters = path_item
+      .get("parameters")
+      .and_then(Value::as_array)
+      .into_iter()
+      .chain(operation.get("parameters").and_then(Value::as_array))
+      .flatten()
+      .map(|parameter| self.resolve(parameter))
+      .filter(|parameter| parameter.get("in").and_then(Value::as_str) == Some("path"));
+    for parameter in parameters {
+      let name: String = parameter
+        .get("name")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+      let value = match captures.name(&format!("p_{}", name)) {
+        Some(value) => value.as_str(),
+        None => continue,
+      };
+      let schema = parameter.get("schema").map(|schema| self.resolve(schema));
+      let valid = match schema.and_then(|schema| schema.get("type")).and_then(Value::as_str) {
+        Some("integer") => value.parse::<i64>().is_ok(),
+        Some("number") => value.parse::<f64>().is_ok(),
+        _ => true,
+      };
+      if !valid {
+        return false;
+      }
+    }
+    true
+  }
+
+  fn find_operation(&self, method: &str, path: &str) -> Result<&Value, u16> {
+    let method = method.to_lowercase();
+    let path = path
+      .strip_prefix(&self.base_path)
+      .filter(|path| path.starts_with('/'))
+      .unwrap_or(path);
+    let mut status = 404;
+    for (regex, template) in &self.routes {
+      let captures = match regex.captures(path) {
+        Some(captures) => captures,
+        None => continue,
+      };
+      let path_item = self.resolve(&self.document["paths"][template]);
+      // HEAD falls back to the GET operation of a path, like http servers do
+      let operation = path_item
+        .get(&method)
+        .or_else(|| (method == "head").then(|| path_item.get("get")).flatten());
+      match operation {
+        Some(operation) if self.parameters_match(path_item, operation, &captures) => return Ok(operation),
+        Some(_) => {}
+        None if METHODS.iter().any(|method| path_item.get(method).is_some()) => status = 405,
+        None => {}
+      }
+    }
+    Err(status)
+  }
+
+  // fake value following a schema, its example or default when given
+  pub fn fake(&self, schema: &Value, depth: usize) -> Value {
+    let schema = self.resolve(schema);
+    if depth > MAX_FAKE_DEPTH {
+      return Value::Null;
+    }
+    if let Some(example) = schema.get("example").or_else(|| schema.get("default")) {
+      return example.clone();
+    }
+    if let Some(first) = schema
+      .get("enum")
+      .and_then(Value::as_array)
+      .and_then(|values| values.first())
+    {
+      return first.clone();
+    }
+    if let Some(all) = schema.get("allOf").and_then(Value::as_array) {
+      let mut merged = Map::new();
+      for schema in all {
+        if let Value::Object(values) = self.fake(schema, depth + 1) {
+          merged.extend(values);
+        }
+      }
+      return Value::Object(merged);
+    }
+    if let Some(first) = schema
+      .get("oneOf")
+      .or_else(|| schema.get("anyOf"))
+      .and_then(Value::as_array)
+      .and_then(|schemas| schemas.first())
+    {
+      return self.fake(first, depth + 1);
+    }
+    let kind = schema.get("type").and_then(Value::as_str).unwrap_or_else(|| {
+      if schema.get("properties").is_some() {
+        "object"
+      } else if schema.get("items").is_some() {
+        "array"
+      } else {
+        "string"
+      }
+    });
+    match kind {
+      "object" => Value::Object(
+        schema
+          .get("properties")
+          .and_then(Value::as_object)
+          .map(|properties| {
+            properties
+              .iter()
+              .map(|(name, property)| (name.clone(), self.fake(property, depth + 1)))
+              .collect()
+          })
+          .unwrap_or_default(),
+      ),
+      "array" => match schema.get("items") {
+        Some(items) => json!([self.fake(items, depth + 1)]),
+        None => json!([]),
+      },
+      "integer" => json!(schema.get("minimum").and_then(Value::as_i64).unwrap_or(0)),
+      "number" => json!(schema.get("minimum").and_then(Value::as_f64).unwrap_or(0.0)),
+      "boolean" => json!(true),
+      "null" => Value::Null,
+      _ => json!(match schema.get("format").and_then(Value::as_str) {
+        Some("date-time") => "2022-01-01T00:00:00Z",
+        Some("date") => "2022-01-01",
+        Some("email") => "user@example.com",
+        Some("uuid") => "3fa85f64-5717-4562-b3fc-2c963f66afa6",
+        Some("uri") | Some("url") => "https://example.com",
+        _ => "string",
+      }),
+    }
+  }
+
+  // example of a media type, the first named example, or fake data from its schema
+  fn example(&self, media: &Value) -> Value {
+    if let Some(example) = media.get("example") {
+      return example.clone();
+    }
+    let named = media
+      .get("examples")
+      .and_then(Value::as_object)
+      .and_then(|examples| examples.values().next())
+      .and_then(|example| self.resolve(example).get("value"));
+    match named {
+      Some(example) => example.clone(),
+      None => media
+        .get("schema")
+        .map(|schema| self.fake(schema, 0))
+        .unwrap_or(Value::Null),
+    }
+  }
+
+  // answer a request with the first success response of its operation, or the `default` one
+  pub fn respond(&self, method: &str, path: &str, accept: Option<&str>) -> MockResponse {
+    let operation = match self.find_operation(method, path.split('?').next().unwrap_or(path)) {
+      Ok(operation) => operation,
+      Err(status) => return MockResponse::empty(status),
+    };
+    let responses = operation.get("responses").and_then(Value::as_object);
+    let mut codes = responses
+      .map(|responses| responses.keys().cloned().collect::<Vec<_>>())
+      .unwrap_or_default();
+    codes.sort();
+    let code = codes
+      .iter()
+      .find(|code| code.starts_with('2'))
+      .or_else(|| codes.iter().find(|code| code.as_str() == "default"))
+      .or_else(|| codes.first());
+    let (code, response) = match (code, responses) {
+      (Some(code), Some(responses)) => (code, self.resolve(&responses[code])),
+      _ => return MockResponse::empty(200),
+    };
+    let status = code.parse::<u16>().unwrap_or(200);
+    let content = match response.get("content").and_then(Value::as_object) {
+      Some(content) if !content.is_empty() => content,
+      _ => return MockResponse::empty(status),
+    };
+    let available = content.keys().map(String::as_str).collect::<Vec<_>>();
+    let media_type = match negotiate(accept, &available) {
+      Some(media_type) => media_type,
+      None => return MockResponse::empty(406),
+    };
+    let body = match self.example(&content[media_type]) {
+      Value::String(text) if !media_type.contains("json") => text,
+      value if media_type.contains("yaml") => serde_yaml::to_string(&value).unwrap_or_default(),
+      value => serde_json::to_string_pretty(&value).unwrap_or_default(),
+    };
+    MockResponse {
+      status,
+      content_type: Some(media_type.to_string()),
+      body,
+    }
+  }
+}
+
+async fn handle_connection(mut socket: TcpStream, api: &MockApi) -> Result<()> {
+  let mut request = Vec::new();
+  let mut buffer = [0u8; 4096];
+  let head_end = loop {
+    if let Some(position) = request.windows(4).position(|window| window == b"\r\n\r\n") {
+      break position + 4;
+    }
+    let read = socket.read(&mut buffer).await?;
+    if read == 0 || request.len() > 64 * 1024 {
+      return Ok(());
+    }
+    request.extend_from_slice(&buffer[..read]);
+  };
+  let head = String::from_utf8_lossy(&request[..head_end]).into_owned();
+  let mut lines = head.lines();
+  let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+  let (method, path) = (
+    request_line.next().unwrap_or_default(),
+    request_line.next().unwrap_or("/"),
+  );
+  let headers = lines
+    .take_while(|line| !line.is_empty())
+    .filter_map(|line| line.split_once(':'))
+    .map(|(name, value)| (name.trim(), value.trim()))
+    .collect::<Vec<_>>();
+  let header = |name: &str| {
+    headers
+      .iter()
+      .find(|(key, _)| key.eq_ignore_ascii_case(name))
+      .map(|(_, value)| *value)
+  };
+  // read the request body before answering, closing a socket with unread data resets the connection
+  let mut remaining = header("content-length")
+    .and_then(|length| length.parse::<usize>().ok())
+    .unwrap_or(0)
+    .saturating_sub(request.len() - head_end);
+  while remaining > 0 {
+    let read = socket.read(&mut buffer).await?;
+    if read == 0 {
+      return Ok(());
+    }
+    remaining = remaining.saturating_sub(read);
+  }
+  let response = api.respond(method, path, header("accept"));
+  eprintln!("{} {} -> {}", method, path, response.status);
+  let reason = StatusCode::from_u16(response.status)
+    .ok()
+    .and_then(|status| status.canonical_reason())
+    .unwrap_or("");
+  let mut answer = format!("HTTP/1.1 {} {}\r\n", response.status, reason);
+  if let Some(content_type) = &response.content_type {
+    answer.push_str(&format!("content-type: {}\r\n", content_type));
+  }
+  // HEAD answers report the length of the GET body without sending it
+  answer.push_str(&format!(
+    "content-length: {}\r\nconnection: close\r\n\r\n",
+    response.body.len()
+  ));
+  if !method.eq_ignore_ascii_case("head") {
+    answer.push_str(&response.body);
+  }
+  socket.write_all(answer.as_bytes()).await?;
+  socket.shutdown().await?;
+  Ok(())
+}
+
+async fn load_document(location: &str) -> Result<Value> {
+  let content = if location.starts_with("http://") || location.starts_with("https://") {
+    reqwest::get(location).await?.error_for_status()?.text().await?
+  } else {
+    std::fs::read_to_string(location)
+      .map_err(|e| anyhow::anyhow!("Could not read OpenAPI description {}\ncause: {}", location, e))?
+  };
+  serde_yaml::from_str(&content).map_err(|e| anyhow::anyhow!("Invalid OpenAPI description {}\ncause: {}", location, e))
+}
+
+// serve the OpenAPI description an api was imported from until interrupted
+pub async fn handle_mock(api: &str, address: &str) -> Result<()> {
+  let manifest = ApixManifest::find_manifest("api", api)
+    .map(|(_, manifest)| manifest)
+    .ok_or_else(|| anyhow::anyhow!("No resource of type api where found with name {}", api))?;
+  let location = manifest
+    .kind()
+    .as_api()
+    .and_then(|api| api.openapi.clone())
+    .ok_or_else(|| anyhow::anyhow!("Api {} was not imported from an OpenAPI description", api))?;
+  let mock = Arc::new(MockApi::new(load_document(&location).await?));
+  let listener = TcpListener::bind(address)
+    .await
+    .map_err(|e| anyhow::anyhow!("Could not listen on {}\ncause: {}", address, e))?;
+  eprintln!("Mocking api {} on http://{}", api, listener.local_addr()?);
+  loop {
+    let (socket, _) = listener.accept().await?;
+    let mock = mock.clone();
+    tokio::spawn(async move {
+      if let Err(e) = handle_connection(socket, &mock).await {
+        eprintln!("Mock connection failed\ncause: {}", e);
+      }
+    });
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use test_case::test_case;
+
+  fn api() -> MockApi {
+    MockApi::new(
+      serde_yaml::from_str(
+        r##"
+openapi: 3.0.0
+servers:
+  - url: https://api.example.com/v1
+paths:
+  /users/me:
+    get:
+      responses:
+        "200":
+          content:
+            application/json:
+              example: { "id": 0, "name": "me" }
+  /users/{id}:
+    parameters:
+      - $ref: "#/components/parameters/id"
+    get:
+      responses:
+        "404":
+          description: not found
+        "200":
+          content:
+            application/json:
+              schema:
+                $ref: "#/components/schemas/User"
+            text/plain:
+              examples:
+                jane:
+                  value: jane
+    delete:
+      responses:
+        "204":
+          description: deleted
+components:
+  parameters:
+    id:
+      name: id
+      in: path
+      schema:
+        type: integer
+  schemas:
+    User:
+      type: object
+      properties:
+        id:
+          type: integer
+          minimum: 1
+        email:
+          type: string
+          format: email
+        roles:
+          type: array
+          items:
+            enum: [admin, user]
+"##,
+      )
+      .unwrap(),
+    )
+  }
+
+  #[test]
+  fn test_path_regex() {
+    let regex = path_regex("/users/{id}/roles/{role-name}");
+    let captures = regex.captures("/users/42/roles/admin").unwrap();
+    assert_eq!(&captures["p_id"], "42");
+    assert_eq!(&captures["p_role_name"], "admin");
+    assert!(!regex.is_match("/users/42/roles"));
+  }
+
+  #[test_case(None => Some("application/json") ; "no accept")]
+  #[test_case(Some("text/plain") => Some("text/plain") ; "exact")]
+  #[test_case(Some("text/*;q=0.5, application/json") => Some("application/json") ; "quality")]
+  #[test_case(Some("*/*") => Some("application/json") ; "any")]
+  #[test_case(Some("application/xml") => None ; "not acceptable")]
+  fn test_negotiate(accept: Option<&str>) -> Option<&'static str> {
+    negotiate(accept, &["application/json", "text/plain"])
+  }
+
+  #[test]
+  fn test_respond() {
+    let api = api();
+    assert_eq!(
+      api.respond("GET", "/v1/users/42", None),
+      MockResponse {
+        status: 200,
+        content_type: Some("application/json".to_string()),
+        body: serde_json::to_string_pretty(&json!({ "id": 1, "email": "user@example.com", "roles": ["admin"] }))
+          .unwrap(),
+      }
+    );
+    assert_eq!(
+      api.respond("GET", "/v1/users/42?full=true", Some("text/plain")).body,
+      "jane"
+    );
+    assert_eq!(
+      api.respond("GET", "/v1/users/me", None).body,
+      serde_json::to_string_pretty(&json!({ "id": 0, "name": "me" })).unwrap()
+    );
+    assert_eq!(api.respond("DELETE", "/v1/users/42", None), MockResponse::empty(204));
+    assert_eq!(api.respond("GET", "/v1/users/42", Some("application/xml")).status, 406);
+    assert_eq!(api.respond("PUT", "/v1/users/42", None).status, 405);
+    assert_eq!(api.respond("GET", "/v1/users/jane", None).status, 404);
+    assert_eq!(api.respond("GET", "/v1/orders", None).status, 404);
+    assert_eq!(
+      api.respond("HEAD", "/v1/users/me", None),
+      api.respond("GET", "/v1/users/me", None)
+    );
+    assert_eq!(api.respond("HEAD", "/v1/orders", None).status, 404);
+  }
+
+  async fn exchange(request: &[&[u8]]) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = tokio::spawn(async move {
+      let (socket, _) = listener.accept().await.unwrap();
+      handle_connection(socket, &api()).await.unwrap();
+    });
+    let mut client = TcpStream::connect(address).await.unwrap();
+    for part in request {
+      client.write_all(part).await.unwrap();
+      tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    let mut response = String::new();
+    client.read_to_string(&mut response).await.unwrap();
+    server.await.unwrap();
+    response
+  }
+
+  #[tokio::test]
+  async fn test_handle_connection_head() {
+    let body = api().respond("GET", "/v1/users/me", None).body;
+    let response = exchange(&[b"HEAD /v1/users/me HTTP/1.1\r\nhost: localhost\r\n\r\n"]).await;
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains(&format!("content-length: {}\r\n", body.len())));
+    assert!(response.ends_with("\r\n\r\n"));
+  }
+
+  #[tokio::test]
+  async fn test_handle_connection_reads_body() {
+    let response = exchange(&[
+      b"DELETE /v1/users/42 HTTP/1.1\r\ncontent-length: 10\r\n\r\nhello",
+      b" body",
+    ])
+    .await;
+    assert!(response.starts_with("HTTP/1.1 204 No Content\r\n"));
+  }
+}
@@ -0,0 +1,201 @@
+use anyhow::Result;
+use fs2::FileExt;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+// user files were all kept in `~/.apix` before following platform conventions
+fn legacy_dir() -> Option<PathBuf> {
+  dirs::home_dir().map(|home| home.join(".apix"))
+}
+
+fn apix_dir(base: Option<PathBuf>, kind: &str) -> Result<PathBuf> {
+  let dir = base
+    .map(|base| base.join("apix"))
+    .ok_or_else(|| anyhow::anyhow!("Could not find {} directory, login as a user to use Apix", kind))?;
+  std::fs::create_dir_all(&dir).map_err(|e| anyhow::anyhow!("Failed to create {:?}\ncause: {}", &dir, e))?;
+  Ok(dir)
+}
+
+// move a file or directory from its legacy location, an existing target is never replaced
+fn migrate(legacy: &Path, target: &Path) -> Result<()> {
+  if !legacy.exists() || target.exists() {
+    return Ok(());
+  }
+  std::fs::rename(legacy, target)
+    .map_err(|e| anyhow::anyhow!("Failed to move {:?} to {:?}\ncause: {}", legacy, target, e))?;
+  eprintln!("moved {:?} to {:?}", legacy, target);
+  Ok(())
+}
+
+// path of a user configuration file, `$XDG_CONFIG_HOME/apix` on linux
+pub fn config_file(name: &str) -> Result<PathBuf> {
+  let path = apix_dir(dirs::config_dir(), "configuration")?.join(name);
+  if let Some(legacy) = legacy_dir() {
+    migrate(&legacy.join(name), &path)?;
+  }
+  Ok(path)
+}
+
+// path of a user state directory, `$XDG_STATE_HOME/apix` on linux, local data directory on other platforms
+pub fn state_dir(name: &str) -> Result<PathBuf> {
+  let path = apix_dir(dirs::state_dir().or_else(dirs::data_local_dir), "state")?.join(name);
+  if let Some(legacy) = legacy_dir() {
+    migrate(&legacy.join(name), &path)?;
+  }
+  std::fs::create_dir_all(&path).map_err(|e| anyhow::anyhow!("Failed to create {:?}\ncause: {}", &path, e))?;
+  Ok(path)
+}
+
+// path of a user cache directory, `$XDG_CACHE_HOME/apix` on linux, cached files are not migrated
+pub fn cache_dir(name: &str) -> Result<PathBuf> {
+  let path = apix_dir(dirs::cache_dir(), "cache")?.join(name);
+  std::fs::create_dir_all(&path).map_err(|e| anyhow::anyhow!("Failed to create {:?}\ncause: {}", &path, e))?;
+  Ok(path)
+}
+
+// permissions of files and directories holding credentials, only the user can read them
+pub static PRIVATE_FILE_MODE: u32 = 0o600;
+pub static PRIVATE_DIR_MODE: u32 = 0o700;
+
+// restrict a directory to the user on unix, other platforms rely on the user profile permissions
+pub fn restrict_dir(path: &Path) -> Result<()> {
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(PRIVATE_DIR_MODE))
+      .map_err(|e| anyhow::anyhow!("Failed to restrict permissions of {:?}\ncause: {}", path, e))?;
+  }
+  #[cfg(not(unix))]
+  let _ = path;
+  Ok(())
+}
+
+// replace a file content without ever leaving it half written: content is written and synced to a temporary file of
+// the same directory, then renamed over the file, with `backup` the previous content is kept in a `.bak` file
+// the temporary file is created with `mode` on unix, so content is never readable with wider permissions
+pub fn write_atomic(path: &Path, content: &[u8], backup: bool, mode: Option<u32>) -> Result<()> {
+  let file_name = path
+    .file_name()
+    .and_then(|name| name.to_str())
+    .ok_or_else(|| anyhow::anyhow!("Invalid file path {:?}", path))?;
+  let temp_path = path.with_file_name(format!(".{}.{}.tmp", file_name, uuid::Uuid::new_v4()));
+  let write = || -> std::io::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    if let Some(mode) = mode {
+      use std::os::unix::fs::OpenOptionsExt;
+      options.mode(mode);
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+    let mut file = options.open(&temp_path)?;
+    file.write_all(content)?;
+    file.sync_all()?;
+    if backup && path.exists() {
+      std::fs::copy(path, path.with_file_name(format!("{}.bak", file_name)))?;
+    }
+    std::fs::rename(&temp_path, path)
+  };
+  write().map_err(|e| {
+    let _ = std::fs::remove_file(&temp_path);
+    anyhow::anyhow!("Failed to write {:?}\ncause: {}", path, e)
+  })
+}
+
+// advisory lock guarding read-modify-write of a file against other apix processes, released when dropped
+// the lock is taken on a `.lock` file next to it, since atomic writes replace the file itself
+pub struct FileLock {
+  _file: File,
+}
+
+pub fn lock_file(path: &Path) -> Result<FileLock> {
+  let file_name = path
+    .file_name()
+    .and_then(|name| name.to_str())
+    .ok_or_else(|| anyhow::anyhow!("Invalid file path {:?}", path))?;
+  let lock_path = path.with_file_name(format!("{}.lock", file_name));
+  let file =
+    File::create(&lock_path).map_err(|e| anyhow::anyhow!("Failed to create lock {:?}\ncause: {}", lock_path, e))?;
+  if file.try_lock_exclusive().is_err() {
+    eprintln!("waiting for another apix process to release {:?}", path);
+    file
+      .lock_exclusive()
+      .map_err(|e| anyhow::anyhow!("Failed to lock {:?}\ncause: {}", path, e))?;
+  }
+  Ok(FileLock { _file: file })
+}
+
+// manifests of the current project are rewritten under a single lock, in `.apix/manifests.lock`
+pub fn lock_manifests() -> Result<FileLock> {
+  let dir = Path::new(".apix");
+  std::fs::create_dir_all(dir).map_err(|e| anyhow::anyhow!("Failed to create {:?}\ncause: {}", dir, e))?;
+  lock_file(&dir.join("manifests"))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_migrate() {
+    let dir = std::env::temp_dir().join(format!("apix-paths-{}", std::process::id()));
+    let legacy = dir.join("legacy");
+    let target = dir.join("target");
+    std::fs::create_dir_all(legacy.join("tokens")).unwrap();
+    std::fs::create_dir_all(&target).unwrap();
+    std::fs::write(legacy.join("tokens").join("token.json"), "{}").unwrap();
+    std::fs::write(legacy.join("config.yml"), "legacy").unwrap();
+    std::fs::write(target.join("config.yml"), "current").unwrap();
+
+    migrate(&legacy.join("tokens"), &target.join("tokens")).unwrap();
+    assert!(target.join("tokens").join("token.json").exists());
+    assert!(!legacy.join("tokens").exists());
+
+    migrate(&legacy.join("config.yml"), &target.join("config.yml")).unwrap();
+    assert_eq!(std::fs::read_to_string(target.join("config.yml")).unwrap(), "current");
+
+    migrate(&legacy.join("missing"), &target.join("missing")).unwrap();
+    assert!(!target.join("missing").exists());
+    std::fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn test_write_atomic() {
+    let dir = std::env::temp_dir().join(format!("apix-atomic-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("config.yml");
+    write_atomic(&path, b"first", true, None).unwrap();
+    assert!(!dir.join("config.yml.bak").exists());
+    write_atomic(&path, b"second", true, None).unwrap();
+    write_atomic(&path, b"third", true, None).unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "third");
+    assert_eq!(std::fs::read_to_string(dir.join("config.yml.bak")).unwrap(), "second");
+    write_atomic(&dir.join("other.json"), b"{}", false, Some(PRIVATE_FILE_MODE)).unwrap();
+    #[cfg(unix)]
+    {
+      use std::os::unix::fs::PermissionsExt;
+      let mode = std::fs::metadata(dir.join("other.json")).unwrap().permissions().mode();
+      assert_eq!(mode & 0o777, PRIVATE_FILE_MODE);
+    }
+    // only written files and backup are left, temporary files are renamed
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 3);
+    assert!(write_atomic(&dir.join("missing").join("config.yml"), b"", true, None).is_err());
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 3);
+    std::fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn test_lock_file() {
+    let dir = std::env::temp_dir().join(format!("apix-lock-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("config.yml");
+    let lock = lock_file(&path).unwrap();
+    let other = File::open(dir.join("config.yml.lock")).unwrap();
+    assert!(other.try_lock_exclusive().is_err());
+    drop(lock);
+    assert!(other.try_lock_exclusive().is_ok());
+    std::fs::remove_dir_all(dir).unwrap();
+  }
+}
@@ -1,4 +1,7 @@
-use indicatif::{ProgressBar, ProgressStyle};
+use anyhow::Result;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use std::sync::Mutex;
+use tokio::task::JoinHandle;
 
 pub struct FileProgress {
   path: String,
@@ -8,11 +11,17 @@ pub struct FileProgress {
 pub enum FileProgressComponent {
   Download(FileProgress),
   Upload(FileProgress),
+  // response body read in memory, cleared once complete so the body is displayed alone
+  Response(FileProgress),
 }
 
 impl FileProgress {
-  fn new(path: String, size_hint: u64) -> Self {
-    let progress = ProgressBar::new(size_hint);
+  fn new(path: String, size_hint: u64, visible: bool) -> Self {
+    let progress = if visible {
+      ProgressBar::new(size_hint)
+    } else {
+      ProgressBar::hidden()
+    };
     progress.set_style(ProgressStyle::default_bar().template(
       "{msg} - {percent}%\n{spinner:.green} [{elapsed_precise}] {wide_bar:.cyan/blue} {bytes}/{total_bytes} ({bytes_per_sec}, {eta})",
     ).tick_chars("🕐🕑🕒🕓🕔🕕🕖🕗🕘🕙🕚🕛"));
@@ -22,14 +31,18 @@ impl FileProgress {
 }
 
 impl FileProgressComponent {
-  pub fn new_download(path: String, size_hint: u64) -> Self {
-    let progress = FileProgress::new(path, size_hint);
+  pub fn new_download(path: String, size_hint: u64, visible: bool) -> Self {
+    let progress = FileProgress::new(path, size_hint, visible);
     FileProgressComponent::Download(progress)
   }
-  pub fn new_upload(path: String, size_hint: u64) -> Self {
-    let progress = FileProgress::new(path, size_hint);
+  pub fn new_upload(path: String, size_hint: u64, visible: bool) -> Self {
+    let progress = FileProgress::new(path, size_hint, visible);
     FileProgressComponent::Upload(progress)
   }
+  pub fn new_response(url: String, size_hint: u64, visible: bool) -> Self {
+    let progress = FileProgress::new(url, size_hint, visible);
+    FileProgressComponent::Response(progress)
+  }
   pub fn update_progress(&self, bytes: u64) {
     match self {
       FileProgressComponent::Download(component) => {
@@ -50,6 +63,112 @@ impl FileProgressComponent {
           component.progress.finish_with_message("Upload Complete");
         }
       }
+      FileProgressComponent::Response(component) => {
+        component
+          .progress
+          .set_message(format!("Downloading Response {}", component.path));
+        component.progress.inc(bytes);
+        if component.progress.position() >= component.progress.length() {
+          component.progress.finish_and_clear();
+        }
+      }
+    }
+  }
+}
+
+pub struct BulkProgressComponent {
+  total: ProgressBar,
+  slots: Mutex<Vec<ProgressBar>>,
+  handle: JoinHandle<std::io::Result<()>>,
+}
+
+impl BulkProgressComponent {
+  // one bar for overall progress and one spinner per concurrent execution slot
+  pub fn new(count: usize, concurrency: usize) -> Self {
+    let multi = MultiProgress::new();
+    let total = multi.add(ProgressBar::new(count as u64));
+    total.set_style(
+      ProgressStyle::default_bar().template("[{elapsed_precise}] {wide_bar:.cyan/blue} {pos}/{len} manifests"),
+    );
+    let slots = (0..concurrency.min(count))
+      .map(|_| {
+        let slot = multi.add(ProgressBar::new_spinner());
+        slot.set_style(ProgressStyle::default_spinner().template("{spinner:.green} {msg}"));
+        slot.enable_steady_tick(120);
+        slot
+      })
+      .collect();
+    let handle = tokio::task::spawn_blocking(move || multi.join());
+    Self {
+      total,
+      slots: Mutex::new(slots),
+      handle,
+    }
+  }
+
+  pub fn start(&self, file: &str) -> Option<ProgressBar> {
+    let slot = self.slots.lock().unwrap().pop()?;
+    slot.set_message(format!("Executing {}", file));
+    Some(slot)
+  }
+
+  pub fn finish(&self, slot: Option<ProgressBar>) {
+    self.total.inc(1);
+    if let Some(slot) = slot {
+      slot.set_message("Waiting");
+      self.slots.lock().unwrap().push(slot);
+    }
+  }
+
+  pub async fn join(self) -> Result<()> {
+    for slot in self.slots.into_inner().unwrap() {
+      slot.finish_and_clear();
+    }
+    self.total.finish();
+    self.handle.await??;
+    Ok(())
+  }
+}
+
+pub struct MultiDownloadProgressComponent {
+  bars: Vec<ProgressBar>,
+  handle: JoinHandle<std::io::Result<()>>,
+}
+
+impl MultiDownloadProgressComponent {
+  // one bar per file, all of them created upfront as MultiProgress only draws bars added before it is joined
+  pub fn new(files: &[String], visible: bool) -> Self {
+    let multi = MultiProgress::new();
+    if !visible {
+      multi.set_draw_target(indicatif::ProgressDrawTarget::hidden());
+    }
+    let bars = files
+      .iter()
+      .map(|file| {
+        let bar = multi.add(ProgressBar::new(0));
+        bar.set_style(
+          ProgressStyle::default_bar()
+            .template("{msg:30!} [{elapsed_precise}] {wide_bar:.cyan/blue} {bytes}/{total_bytes} ({bytes_per_sec})"),
+        );
+        bar.set_message(file.clone());
+        bar
+      })
+      .collect();
+    let handle = tokio::task::spawn_blocking(move || multi.join());
+    Self { bars, handle }
+  }
+
+  pub fn bar(&self, index: usize) -> &ProgressBar {
+    &self.bars[index]
+  }
+
+  pub async fn join(self) -> Result<()> {
+    for bar in &self.bars {
+      if !bar.is_finished() {
+        bar.abandon();
+      }
     }
+    self.handle.await??;
+    Ok(())
   }
 }
@@ -0,0 +1,121 @@
+use crate::encryption::{seal, unseal};
+use crate::paths::{lock_file, write_atomic, PRIVATE_FILE_MODE};
+use anyhow::Result;
+use clap::ArgMatches;
+use indexmap::IndexMap;
+use serde_json::Value;
+use std::path::PathBuf;
+
+// values kept between runs in `.apix/context.yaml`, like captured tokens, encrypted at rest when configured
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ApixProjectContext {
+  pub values: IndexMap<String, Value>,
+}
+
+impl ApixProjectContext {
+  fn path() -> PathBuf {
+    PathBuf::from(".apix").join("context.yaml")
+  }
+
+  // a missing context is an empty one, encrypted contexts are decrypted
+  pub fn load() -> Result<Self> {
+    let path = Self::path();
+    match std::fs::read_to_string(&path) {
+      Ok(content) => {
+        let content =
+          unseal(content).map_err(|e| anyhow::anyhow!("Could not decrypt context file {:?}: {:#}", &path, e))?;
+        let values = serde_yaml::from_str::<Option<IndexMap<String, Value>>>(&content)
+          .map_err(|e| anyhow::anyhow!("Could not parse context file {:?}: {:#}", &path, e))?;
+        Ok(Self {
+          values: values.unwrap_or_default(),
+        })
+      }
+      Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+      Err(e) => Err(anyhow::anyhow!("Could not read context file {:?}\ncause: {}", &path, e)),
+    }
+  }
+
+  // change the context while other apix processes can't, context file is locked, reloaded, changed and saved
+  pub fn update<T>(change: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+    let path = Self::path();
+    if let Some(parent) = path.parent() {
+      std::fs::create_dir_all(parent)?;
+    }
+    let _lock = lock_file(&path)?;
+    let mut context = Self::load()?;
+    let result = change(&mut context)?;
+    // context holds credentials, only readable by its owner
+    write_atomic(
+      &path,
+      seal(&serde_yaml::to_string(&context.values)?)?.as_bytes(),
+      false,
+      Some(PRIVATE_FILE_MODE),
+    )
+    .map_err(|e| anyhow::anyhow!("Failed to save context {:?}\ncause: {}", &path, e))?;
+    Ok(result)
+  }
+
+  // request or step context rendered over saved values, rendered values take precedence
+  pub fn merge(&self, rendered: &Value) -> Value {
+    let mut values = serde_json::Map::from_iter(self.values.clone());
+    if let Value::Object(rendered) = rendered {
+      values.extend(rendered.clone());
+    }
+    Value::Object(values)
+  }
+}
+
+// `apix ctl context get|set|delete`
+pub fn handle_context(matches: &ArgMatches) -> Result<()> {
+  match matches.subcommand() {
+    Some(("get", matches)) => {
+      let context = ApixProjectContext::load()?;
+      match matches.value_of("name") {
+        Some(name) => match context.values.get(name) {
+          Some(Value::String(value)) => println!("{}", value),
+          Some(value) => println!("{}", value),
+          None => return Err(anyhow::anyhow!("No value named {} in context", name)),
+        },
+        None => print!("{}", serde_yaml::to_string(&context.values)?),
+      }
+    }
+    Some(("set", matches)) => {
+      let name = matches.value_of("name").unwrap_or_default();
+      let value = matches.value_of("value").unwrap_or_default();
+      ApixProjectContext::update(|context| {
+        context
+          .values
+          .insert(name.to_string(), Value::String(value.to_string()));
+        Ok(())
+      })?;
+    }
+    Some(("delete", matches)) => {
+      let name = matches.value_of("name").unwrap_or_default();
+      let removed = ApixProjectContext::update(|context| Ok(context.values.shift_remove(name)))?;
+      if removed.is_none() {
+        return Err(anyhow::anyhow!("No value named {} in context", name));
+      }
+    }
+    _ => {}
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use indexmap::indexmap;
+  use serde_json::json;
+
+  #[test]
+  fn test_merge_context() {
+    let context = ApixProjectContext {
+      values: indexmap! { "token".to_string() => json!("saved"), "user".to_string() => json!("apix") },
+    };
+    assert_eq!(
+      context.merge(&json!({"token": "rendered", "id": 1})),
+      json!({"token": "rendered", "user": "apix", "id": 1})
+    );
+    assert_eq!(context.merge(&Value::Null), json!({"token": "saved", "user": "apix"}));
+  }
+}
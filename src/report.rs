@@ -0,0 +1,305 @@
+use super::requests::{is_http_error, ResponseMetrics};
+use anyhow::Result;
+use comfy_table::{ContentArrangement, Table};
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+
+#[derive(Debug)]
+pub enum ExecutionStatus {
+  Success(ResponseMetrics),
+  Failure(String),
+  // failure of a request that continues on error, it does not fail the run
+  Ignored(String),
+  Skipped,
+}
+
+#[derive(Debug)]
+pub struct ExecutionReport {
+  pub file: String,
+  pub name: String,
+  pub status: ExecutionStatus,
+  pub duration: Duration,
+}
+
+impl ExecutionReport {
+  pub fn is_failure(&self) -> bool {
+    matches!(self.status, ExecutionStatus::Failure(_))
+  }
+
+  // failed requests, including ignored ones
+  pub fn is_error(&self) -> bool {
+    matches!(self.status, ExecutionStatus::Failure(_) | ExecutionStatus::Ignored(_))
+  }
+
+  pub fn bytes(&self) -> u64 {
+    match &self.status {
+      ExecutionStatus::Success(metrics) => metrics.bytes,
+      _ => 0,
+    }
+  }
+
+  // times the request was sent again after a retried status
+  pub fn retries(&self) -> u32 {
+    match &self.status {
+      ExecutionStatus::Success(metrics) => metrics.attempts.saturating_sub(1),
+      _ => 0,
+    }
+  }
+
+  fn status_label(&self) -> String {
+    match &self.status {
+      ExecutionStatus::Success(metrics) => metrics.status.to_string(),
+      ExecutionStatus::Failure(cause) => format!("Failed: {}", cause),
+      ExecutionStatus::Ignored(cause) => format!("Failed, ignored: {}", cause),
+      ExecutionStatus::Skipped => "Skipped".to_string(),
+    }
+  }
+
+  pub fn to_json(&self) -> Value {
+    let (status, error) = match &self.status {
+      ExecutionStatus::Success(metrics) => (json!(metrics.status.as_u16()), Value::Null),
+      ExecutionStatus::Failure(cause) => (json!("failed"), json!(cause)),
+      ExecutionStatus::Ignored(cause) => (json!("ignored"), json!(cause)),
+      ExecutionStatus::Skipped => (json!("skipped"), Value::Null),
+    };
+    json!({
+      "file": self.file,
+      "name": self.name,
+      "status": status,
+      "error": error,
+      "durationMs": self.duration.as_millis() as u64,
+      "requestDurationMs": match &self.status {
+        ExecutionStatus::Success(metrics) => json!(metrics.duration.as_millis() as u64),
+        _ => Value::Null,
+      },
+      "bytes": self.bytes(),
+      "retries": self.retries(),
+      "requestId": match &self.status {
+        ExecutionStatus::Success(metrics) => json!(metrics.request_id),
+        _ => Value::Null,
+      },
+      "idempotencyKey": match &self.status {
+        ExecutionStatus::Success(metrics) => json!(metrics.idempotency_key),
+        _ => Value::Null,
+      },
+    })
+  }
+}
+
+// print a summary table of a run, with totals in the last row
+pub fn print_reports(reports: &[ExecutionReport]) {
+  let mut table = Table::new();
+  table
+    .load_preset("││──├─┼┤│─┼├┤┬┴╭╮╰╯")
+    .set_content_arrangement(ContentArrangement::Dynamic)
+    .set_header(["File", "Name", "Status", "Duration", "Bytes", "Retries"]);
+  for report in reports {
+    table.add_row(vec![
+      report.file.clone(),
+      report.name.clone(),
+      report.status_label(),
+      format!("{}ms", report.duration.as_millis()),
+      report.bytes().to_string(),
+      report.retries().to_string(),
+    ]);
+  }
+  let executed = reports
+    .iter()
+    .filter(|report| !matches!(report.status, ExecutionStatus::Skipped))
+    .count();
+  let failed = reports.iter().filter(|report| report.is_failure()).count();
+  let ignored = reports.iter().filter(|report| report.is_error()).count() - failed;
+  let summary = match ignored {
+    0 => format!("{} executed, {} failed", executed, failed),
+    ignored => format!("{} executed, {} failed, {} ignored", executed, failed, ignored),
+  };
+  table.add_row(vec![
+    "Total".to_string(),
+    String::new(),
+    summary,
+    format!(
+      "{}ms",
+      reports
+        .iter()
+        .map(|report| report.duration)
+        .sum::<Duration>()
+        .as_millis()
+    ),
+    reports.iter().map(ExecutionReport::bytes).sum::<u64>().to_string(),
+    reports.iter().map(ExecutionReport::retries).sum::<u32>().to_string(),
+  ]);
+  eprintln!("{table}");
+}
+
+// latencies of a request executed several times in a row, `--repeat`
+#[derive(Debug, Default)]
+pub struct RepeatStats {
+  durations: Vec<Duration>,
+  succeeded: usize,
+}
+
+impl RepeatStats {
+  pub fn record(&mut self, duration: Duration, success: bool) {
+    self.durations.push(duration);
+    self.succeeded += success as usize;
+  }
+
+  // requests failing to be sent are timed from `start`, http errors are not successes
+  pub fn record_result(&mut self, result: &Result<ResponseMetrics>, start: Instant) {
+    match result {
+      Ok(metrics) => self.record(metrics.duration, !is_http_error(metrics.status)),
+      Err(err) => {
+        eprintln!("{:#}", err);
+        self.record(start.elapsed(), false);
+      }
+    }
+  }
+
+  pub fn count(&self) -> usize {
+    self.durations.len()
+  }
+
+  pub fn failed(&self) -> usize {
+    self.durations.len() - self.succeeded
+  }
+
+  // print the summary on stderr, failing when any request failed
+  pub fn check(&self) -> Result<()> {
+    eprintln!("{}", self.summary());
+    match self.failed() {
+      0 => Ok(()),
+      failed => Err(anyhow::anyhow!(
+        "{} of {} requests failed",
+        failed,
+        self.durations.len()
+      )),
+    }
+  }
+
+  // nearest rank percentile
+  fn percentile(sorted: &[Duration], percent: usize) -> Duration {
+    let rank = (sorted.len() * percent).div_ceil(100).max(1);
+    sorted[rank - 1]
+  }
+
+  // min, avg, p95 and max latencies
+  pub fn latencies(&self) -> Option<[Duration; 4]> {
+    let mut sorted = self.durations.clone();
+    sorted.sort();
+    let average = sorted.iter().sum::<Duration>() / sorted.len().max(1) as u32;
+    Some([
+      *sorted.first()?,
+      average,
+      Self::percentile(&sorted, 95),
+      *sorted.last()?,
+    ])
+  }
+
+  pub fn summary(&self) -> String {
+    match self.latencies() {
+      Some([min, average, p95, max]) => format!(
+        "{} requests, {} succeeded, min {}ms, avg {}ms, p95 {}ms, max {}ms",
+        self.count(),
+        self.succeeded,
+        min.as_millis(),
+        average.as_millis(),
+        p95.as_millis(),
+        max.as_millis()
+      ),
+      None => "0 requests".to_string(),
+    }
+  }
+}
+
+// write the run summary as a json document
+pub fn save_reports(reports: &[ExecutionReport], path: &str) -> Result<()> {
+  let summary = json!({
+    "executed": reports.iter().filter(|report| !matches!(report.status, ExecutionStatus::Skipped)).count(),
+    "failed": reports.iter().filter(|report| report.is_failure()).count(),
+    "ignored": reports.iter().filter(|report| matches!(report.status, ExecutionStatus::Ignored(_))).count(),
+    "bytes": reports.iter().map(ExecutionReport::bytes).sum::<u64>(),
+    "retries": reports.iter().map(ExecutionReport::retries).sum::<u32>(),
+    "reports": reports.iter().map(ExecutionReport::to_json).collect::<Vec<_>>(),
+  });
+  std::fs::write(path, serde_json::to_string_pretty(&summary)?)
+    .map_err(|e| anyhow::anyhow!("Failed to write report file '{}'\ncause: {}", path, e))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use reqwest::StatusCode;
+
+  #[test]
+  fn test_report_to_json() {
+    let report = ExecutionReport {
+      file: "get.yaml".to_string(),
+      name: "get".to_string(),
+      status: ExecutionStatus::Success(ResponseMetrics {
+        status: StatusCode::OK,
+        bytes: 42,
+        duration: Duration::from_millis(10),
+        attempts: 3,
+        request_id: Some("id".to_string()),
+        idempotency_key: Some("key".to_string()),
+        response: None,
+      }),
+      duration: Duration::from_millis(12),
+    };
+    assert_eq!(
+      report.to_json(),
+      json!({
+        "file": "get.yaml",
+        "name": "get",
+        "status": 200,
+        "error": null,
+        "durationMs": 12,
+        "requestDurationMs": 10,
+        "bytes": 42,
+        "retries": 2,
+        "requestId": "id",
+        "idempotencyKey": "key",
+      })
+    );
+  }
+
+  #[test]
+  fn test_failed_report_to_json() {
+    let report = ExecutionReport {
+      file: "get.yaml".to_string(),
+      name: "get".to_string(),
+      status: ExecutionStatus::Failure("boom".to_string()),
+      duration: Duration::from_millis(3),
+    };
+    assert_eq!(report.to_json()["status"], json!("failed"));
+    assert_eq!(report.to_json()["error"], json!("boom"));
+    assert_eq!(report.to_json()["bytes"], json!(0));
+  }
+
+  #[test]
+  fn test_repeat_stats() {
+    let mut stats = RepeatStats::default();
+    assert_eq!(stats.summary(), "0 requests");
+    for millis in (1..=20).rev() {
+      stats.record(Duration::from_millis(millis * 10), millis != 7);
+    }
+    assert_eq!(
+      stats.summary(),
+      "20 requests, 19 succeeded, min 10ms, avg 105ms, p95 190ms, max 200ms"
+    );
+  }
+
+  #[test]
+  fn test_ignored_report_to_json() {
+    let report = ExecutionReport {
+      file: "cleanup.yaml".to_string(),
+      name: "cleanup".to_string(),
+      status: ExecutionStatus::Ignored("boom".to_string()),
+      duration: Duration::from_millis(3),
+    };
+    assert!(!report.is_failure());
+    assert!(report.is_error());
+    assert_eq!(report.to_json()["status"], json!("ignored"));
+    assert_eq!(report.to_json()["error"], json!("boom"));
+  }
+}
@@ -1,39 +1,85 @@
-use super::display::{pretty_print, print_separator, HttpDisplay};
-use super::http_utils::Language;
+use super::cache::{lookup, storable, CacheEntry, CacheLookup, CacheMode, HttpCache};
+use super::certificates::{format_certificates, inspecting_tls_config, peer_certificates};
+use super::decode::BodyDecoder;
+use super::display::{
+  format_csv_table, format_server_timing, pretty_print, print_separator, HttpDisplay, OutputFormat,
+};
+use super::http_utils::{
+  content_range_size, content_range_start, merge_url_queries, parse_server_timing, retry_after_delay, BodyFormat,
+  ContinueAt, DigestHeader, IpFamily, Language, RequestIdGenerator, TlsVersion, TrustStore,
+};
 use super::progress_component::FileProgressComponent;
+use super::session::ApixSession;
+use super::signing::RequestSigner;
+use super::telemetry::{Span, Telemetry};
+use super::transform::ResponseTransform;
 use anyhow::Result;
-use futures::stream::TryStreamExt;
+use bytes::Bytes;
+use futures::stream::{self, Stream, StreamExt, TryStreamExt};
 use indexmap::IndexMap;
 use once_cell::sync::Lazy;
+use percent_encoding::percent_decode_str;
 use reqwest::{
-  header::{HeaderMap, HeaderValue, ACCEPT, ACCEPT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, USER_AGENT},
-  Body, Client, Method,
+  header::{
+    HeaderMap, HeaderName, HeaderValue, ACCEPT, ACCEPT_ENCODING, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, RANGE,
+    RETRY_AFTER, USER_AGENT,
+  },
+  Body, Client, Method, Response, StatusCode,
 };
 use serde_json::Value;
+use sha2::{Digest, Sha256};
 use std::fs::File;
+use std::io::Write;
 use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
 use tokio::fs::File as AsyncFile;
+use tokio::io::{AsyncSeekExt, AsyncWrite, AsyncWriteExt};
 use tokio_util::codec::{BytesCodec, FramedRead};
 use tokio_util::compat::FuturesAsyncReadCompatExt;
 use url::Url;
 
 static APP_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"),);
 
+static IDEMPOTENCY_KEY: &str = "idempotency-key";
+static SERVER_TIMING: &str = "server-timing";
+
+static CLIENT_CACHE_SIZE: usize = 16;
+
+// body file name reading body from stdin
+static STDIN_FILE: &str = "-";
+
+// Accept header sent when neither the request, the api nor the `accept` config set one
+static DEFAULT_ACCEPT: &str = "application/json";
+
 static DEFAULT_HEADERS: Lazy<HeaderMap> = Lazy::new(|| {
   HeaderMap::from_iter([
     (USER_AGENT, HeaderValue::from_str(APP_USER_AGENT).unwrap()),
-    (ACCEPT, HeaderValue::from_static("application/json")),
     (ACCEPT_ENCODING, HeaderValue::from_static("gzip")),
-    (CONTENT_TYPE, HeaderValue::from_static("application/json")),
   ])
 });
 
-fn merge_with_defaults(headers: &HeaderMap) -> HeaderMap {
+// explicit headers override default ones, unset headers are removed from both
+// json Content-Type is only a default for requests sending a body
+fn merge_with_defaults(headers: &HeaderMap, options: &RequestOptions, has_body: bool) -> Result<HeaderMap> {
   let mut merged = DEFAULT_HEADERS.clone();
+  merged.insert(
+    ACCEPT,
+    HeaderValue::from_str(options.accept.as_deref().unwrap_or(DEFAULT_ACCEPT))?,
+  );
+  if has_body {
+    let content_type = options
+      .body_format
+      .map_or("application/json", |format| format.content_type());
+    merged.insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+  }
   for (key, value) in headers {
     merged.insert(key.clone(), value.clone());
   }
-  merged
+  for name in &options.unset_headers {
+    merged.remove(name);
+  }
+  Ok(merged)
 }
 
 #[derive(Debug, Clone)]
@@ -54,7 +100,10 @@ impl AdvancedBody {
   }
 }
 
-#[derive(Debug, Clone)]
+// bodies to print with their language, in the order they were received
+pub type OutputBuffer = Arc<Mutex<Vec<(String, Option<String>)>>>;
+
+#[derive(Debug, Clone, Default)]
 pub struct RequestOptions<'a> {
   pub verbose: bool,
   pub theme: &'a str,
@@ -63,43 +112,322 @@ pub struct RequestOptions<'a> {
   pub proxy_url: Option<String>,
   pub proxy_login: Option<String>,
   pub proxy_password: Option<String>,
+  pub telemetry: Option<&'a Telemetry>,
+  pub request_id: Option<&'a RequestIdGenerator>,
+  pub session: Option<&'a Mutex<ApixSession>>,
+  pub syntax: Option<String>,
+  pub tee: bool,
+  pub progress: bool,
+  pub content_digest: Option<DigestHeader>,
+  pub idempotency_key: Option<String>,
+  pub cache: Option<CacheMode>,
+  pub signer: Option<RequestSigner>,
+  pub ip_family: Option<IpFamily>,
+  // no connection pooling, so each request resolves its host again
+  pub fresh_dns: bool,
+  pub tls_min: Option<TlsVersion>,
+  pub tls_max: Option<TlsVersion>,
+  pub trust_store: Option<TrustStore>,
+  pub continue_at: Option<ContinueAt>,
+  pub unset_headers: Vec<HeaderName>,
+  pub accept: Option<String>,
+  pub body_format: Option<BodyFormat>,
+  pub output_format: Option<OutputFormat>,
+  // bodies printed while progress bars of concurrent executions are drawn, printed once they are done
+  pub output_buffer: Option<OutputBuffer>,
+  pub decoder: Option<BodyDecoder>,
+  pub raw: bool,
+  pub interactive: bool,
+  pub soft: bool,
+  pub fail: Option<FailMode>,
+  pub transform: Option<ResponseTransform>,
+  pub retry: Option<RetryPolicy>,
+  pub show_cert: bool,
+  pub pins: Vec<String>,
+  // responses read in memory fail beyond this size, streamed ones are not limited
+  pub max_body_size: Option<u64>,
+  pub timeout: Option<Duration>,
+  pub connect_timeout: Option<Duration>,
+  // max time without receiving anything, while waiting for the response and then between body chunks
+  pub read_timeout: Option<Duration>,
 }
 
-pub async fn make_request(
+// status codes retried for a request, distinct from connection errors
+// delays follow `Retry-After` when sent, otherwise they double from 1s for the first retry, and are capped to
+// `MAX_RETRY_DELAY`
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+  pub statuses: Vec<u16>,
+  pub max_retries: u32,
+}
+
+pub static DEFAULT_MAX_RETRIES: u32 = 3;
+static MAX_RETRY_DELAY: Duration = Duration::from_secs(120);
+// smaller bodies read in memory are received too fast for a progress bar to be useful
+static RESPONSE_PROGRESS_THRESHOLD: u64 = 1 << 20;
+
+impl RetryPolicy {
+  fn retries(&self, status: StatusCode, attempt: u32) -> bool {
+    attempt < self.max_retries && self.statuses.contains(&status.as_u16())
+  }
+
+  // delay before `retry`, the first retry being 1
+  fn delay(&self, retry: u32, headers: &HeaderMap) -> Duration {
+    headers
+      .get(RETRY_AFTER)
+      .and_then(|value| value.to_str().ok())
+      .and_then(|value| retry_after_delay(value, chrono::Utc::now()))
+      .unwrap_or_else(|| Duration::from_secs(1 << retry.saturating_sub(1).min(7)))
+      .min(MAX_RETRY_DELAY)
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct ResponseMetrics {
+  pub status: StatusCode,
+  pub bytes: u64,
+  pub duration: Duration,
+  // times the request was sent, retries included, 0 when served from cache
+  pub attempts: u32,
+  pub request_id: Option<String>,
+  pub idempotency_key: Option<String>,
+  pub response: Option<CapturedResponse>,
+}
+
+// response received for a sent request, with data needed to compute metrics
+struct SentRequest {
+  response: Response,
+  request_id: Option<String>,
+  idempotency_key: Option<String>,
+  start: Instant,
+}
+
+#[derive(Debug, Clone)]
+pub struct CapturedResponse {
+  pub status: StatusCode,
+  pub headers: HeaderMap,
+  pub body: String,
+}
+
+impl CapturedResponse {
+  // json representation of the response, body is parsed when it is valid json
+  pub fn to_json(&self) -> Value {
+    let headers = self
+      .headers
+      .iter()
+      .map(|(key, value)| {
+        (
+          key.to_string(),
+          Value::String(value.to_str().unwrap_or_default().to_string()),
+        )
+      })
+      .collect::<serde_json::Map<_, _>>();
+    let body = serde_json::from_str(&self.body).unwrap_or_else(|_| Value::String(self.body.clone()));
+    serde_json::json!({
+      "status": self.status.as_u16(),
+      "headers": headers,
+      "body": body,
+    })
+  }
+}
+
+// sha-256 digest of the body bytes sent on the wire, files are read once more to compute it
+fn body_digest(body: &AdvancedBody) -> Result<Vec<u8>> {
+  let mut hasher = Sha256::new();
+  match body {
+    AdvancedBody::String(body) => hasher.update(body.as_bytes()),
+    AdvancedBody::Json(body) => hasher.update(serde_json::to_vec(body)?),
+    AdvancedBody::File(file_path) if file_path == STDIN_FILE => {
+      return Err(anyhow::anyhow!(
+        "Body streamed from stdin can't be read twice, digest and signature need a file body"
+      ))
+    }
+    AdvancedBody::File(file_path) => {
+      let mut file =
+        File::open(file_path).map_err(|e| anyhow::anyhow!("Could not open File '{}'\nCause: {}", file_path, e))?;
+      std::io::copy(&mut file, &mut hasher)?;
+    }
+  }
+  Ok(hasher.finalize().to_vec())
+}
+
+// connection options a client is built with, requests with the same options share the same client
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ClientKey {
+  proxy_url: Option<String>,
+  proxy_login: Option<String>,
+  proxy_password: Option<String>,
+  ip_family: Option<IpFamily>,
+  fresh_dns: bool,
+  tls_min: Option<TlsVersion>,
+  tls_max: Option<TlsVersion>,
+  trust_store: Option<TrustStore>,
+  timeout: Option<Duration>,
+  connect_timeout: Option<Duration>,
+  pins: Vec<String>,
+  show_cert: bool,
+  // compressed bodies are asked for and decompressed
+  gzip: bool,
+}
+
+impl ClientKey {
+  fn new(options: &RequestOptions<'_>) -> Self {
+    Self {
+      proxy_url: options.proxy_url.clone(),
+      proxy_login: options.proxy_login.clone(),
+      proxy_password: options.proxy_password.clone(),
+      ip_family: options.ip_family,
+      fresh_dns: options.fresh_dns,
+      tls_min: options.tls_min,
+      tls_max: options.tls_max,
+      trust_store: options.trust_store,
+      timeout: options.timeout,
+      connect_timeout: options.connect_timeout,
+      pins: options.pins.clone(),
+      show_cert: options.show_cert,
+      gzip: true,
+    }
+  }
+
+  fn build_client(&self) -> Result<Client> {
+    let mut client_builder = Client::builder();
+    if let Some(proxy_url) = &self.proxy_url {
+      let mut proxy = reqwest::Proxy::all(proxy_url)?;
+      if let (Some(proxy_login), Some(proxy_password)) = (&self.proxy_login, &self.proxy_password) {
+        proxy = proxy.basic_auth(proxy_login, proxy_password);
+      }
+      client_builder = client_builder.proxy(proxy);
+    }
+    if let Some(ip_family) = self.ip_family {
+      client_builder = client_builder.local_address(ip_family.local_address());
+    }
+    if self.fresh_dns {
+      client_builder = client_builder.pool_max_idle_per_host(0);
+    }
+    if let (Some(tls_min), Some(tls_max)) = (self.tls_min, self.tls_max) {
+      if tls_min > tls_max {
+        return Err(anyhow::anyhow!(
+          "Minimum tls version {} is greater than maximum tls version {}",
+          tls_min.as_str(),
+          tls_max.as_str()
+        ));
+      }
+    }
+    if !self.pins.is_empty() || self.show_cert {
+      // certificates are inspected during the handshake of every connection, tls versions are part of the config
+      let config = inspecting_tls_config(&self.pins, self.show_cert, self.trust_store, self.tls_min, self.tls_max)?;
+      client_builder = client_builder.use_preconfigured_tls(config);
+    } else {
+      if self.trust_store == Some(TrustStore::Bundled) {
+        client_builder = client_builder.use_rustls_tls();
+      }
+      if let Some(tls_min) = self.tls_min {
+        client_builder = client_builder.min_tls_version(tls_min.version());
+      }
+      if let Some(tls_max) = self.tls_max {
+        client_builder = client_builder.max_tls_version(tls_max.version());
+      }
+    }
+    if let Some(timeout) = self.timeout {
+      client_builder = client_builder.timeout(timeout);
+    }
+    if let Some(connect_timeout) = self.connect_timeout {
+      client_builder = client_builder.connect_timeout(connect_timeout);
+    }
+    client_builder
+      .gzip(self.gzip)
+      .build()
+      .map_err(|e| anyhow::anyhow!("Failed to configure http client\ncause: {}", e))
+  }
+}
+
+// clients are cached so bulk runs and stories reuse connections instead of rebuilding identical clients
+// oldest client is dropped when cache is full
+fn cached_client(options: &RequestOptions<'_>) -> Result<Client> {
+  cached_client_for(ClientKey::new(options))
+}
+
+// client downloading bodies as sent by servers, without asking for compressed encodings, like artifacts checked
+// against a checksum
+pub fn cached_download_client(options: &RequestOptions<'_>) -> Result<Client> {
+  cached_client_for(ClientKey {
+    gzip: false,
+    ..ClientKey::new(options)
+  })
+}
+
+fn cached_client_for(key: ClientKey) -> Result<Client> {
+  static CLIENTS: Lazy<Mutex<IndexMap<ClientKey, Client>>> = Lazy::new(|| Mutex::new(IndexMap::new()));
+  let mut clients = CLIENTS.lock().unwrap();
+  if let Some(client) = clients.get(&key) {
+    return Ok(client.clone());
+  }
+  let client = key.build_client()?;
+  if clients.len() >= CLIENT_CACHE_SIZE {
+    clients.shift_remove_index(0);
+  }
+  clients.insert(key, client.clone());
+  Ok(client)
+}
+
+async fn send_request(
   url: &str,
   method: &str,
   headers: Option<&HeaderMap>,
-  queries: Option<&IndexMap<String, String>>,
+  queries: Option<&[(String, String)]>,
   body: Option<AdvancedBody>,
-  options: RequestOptions<'_>,
-) -> Result<()> {
-  let mut client_builder = Client::builder();
-  if let Some(proxy_url) = options.proxy_url {
-    let mut proxy = reqwest::Proxy::all(&proxy_url)?;
-    if let (Some(proxy_login), Some(proxy_password)) = (options.proxy_login, options.proxy_password) {
-      proxy = proxy.basic_auth(&proxy_login, &proxy_password);
+  options: &RequestOptions<'_>,
+) -> Result<SentRequest> {
+  let client = cached_client(options)?;
+  let url = merge_url_queries(url, queries)?;
+  // session headers and cookies are only sent to the origin and domains they were set for
+  let session_headers = match options.session {
+    Some(session) => {
+      let mut session = session.lock().unwrap();
+      if let Some(headers) = headers {
+        session.update_headers(&url, headers);
+      }
+      Some(session.apply(&url, headers)?)
     }
-    client_builder = client_builder.proxy(proxy);
-  }
-  let client = client_builder.gzip(true).build()?;
+    None => None,
+  };
+  let headers = session_headers.as_ref().or(headers);
   let mut builder = client.request(Method::from_str(&method.to_uppercase())?, url);
-  if let Some(headers) = headers {
-    builder = builder.headers(merge_with_defaults(headers))
-  } else {
-    builder = builder.headers(DEFAULT_HEADERS.clone())
-  }
-  if let Some(query) = queries {
-    builder = builder.query(query);
+  builder = builder.headers(merge_with_defaults(
+    headers.unwrap_or(&HeaderMap::new()),
+    options,
+    body.is_some(),
+  )?);
+  let digest = match (&body, options.content_digest) {
+    (Some(body), Some(digest_header))
+      if !headers.is_some_and(|headers| headers.contains_key(digest_header.header())) =>
+    {
+      Some((digest_header, body_digest(body)?))
+    }
+    _ => None,
+  };
+  if let Some((digest_header, digest)) = digest {
+    builder = builder.header(digest_header.header(), digest_header.value(&digest));
   }
+  let signed_body_digest = match (&options.signer, &body) {
+    (Some(_), Some(body)) => Some(body_digest(body)?),
+    (Some(_), None) => Some(Sha256::digest(b"").to_vec()),
+    _ => None,
+  };
   match body {
     Some(AdvancedBody::String(body)) => {
       builder = builder.body(body);
     }
+    // stdin is streamed as it is read, with chunked transfer encoding since its size is unknown
+    Some(AdvancedBody::File(file_path)) if file_path == STDIN_FILE => {
+      let stream = FramedRead::new(tokio::io::stdin(), BytesCodec::new());
+      builder = builder.body(Body::wrap_stream(stream));
+    }
     Some(AdvancedBody::File(file_path)) => {
       let file =
         File::open(&file_path).map_err(|e| anyhow::anyhow!("Could not open File '{}'\nCause: {}", &file_path, e))?;
       let file_size = file.metadata()?.len();
-      let progress_bar = FileProgressComponent::new_upload(file_path, file_size);
+      let progress_bar = FileProgressComponent::new_upload(file_path, file_size, options.progress);
       let async_file = AsyncFile::from_std(file);
       let stream = FramedRead::new(async_file, BytesCodec::new()).inspect_ok(move |bytes| {
         progress_bar.update_progress(bytes.len() as u64);
@@ -113,61 +441,1160 @@ pub async fn make_request(
     }
     None => {}
   }
-  let req = builder.build()?;
+  let request_id = match options.request_id {
+    Some(generator) if !headers.is_some_and(|headers| headers.contains_key(generator.header())) => {
+      let request_id = generator.generate()?;
+      builder = builder.header(generator.header().clone(), &request_id);
+      Some(request_id)
+    }
+    _ => None,
+  };
+  if let Some(idempotency_key) = &options.idempotency_key {
+    if !headers.is_some_and(|headers| headers.contains_key(IDEMPOTENCY_KEY)) {
+      builder = builder.header(IDEMPOTENCY_KEY, idempotency_key);
+    }
+  }
+  let span_id = options.telemetry.map(Telemetry::new_span_id);
+  if let (Some(telemetry), Some(span_id)) = (options.telemetry, &span_id) {
+    if !headers.is_some_and(|headers| headers.contains_key("traceparent")) {
+      builder = builder.header("traceparent", telemetry.traceparent(span_id));
+    }
+  }
+  let mut req = builder.build()?;
+  // signature is computed last, so it covers every header sent
+  if let (Some(signer), Some(digest)) = (&options.signer, &signed_body_digest) {
+    signer.sign(&mut req, digest)?;
+  }
+  let idempotency_key = req
+    .headers()
+    .get(IDEMPOTENCY_KEY)
+    .and_then(|value| value.to_str().ok())
+    .map(str::to_string);
   if options.verbose {
     req.print(options.theme, options.is_output_terminal)?;
     println!();
     print_separator();
   }
-  let result = client.execute(req).await?;
+  let span_name = format!("{} {}", req.method(), req.url().path());
+  let span_attributes = vec![
+    ("http.method".to_string(), Value::String(req.method().to_string())),
+    ("http.url".to_string(), Value::String(req.url().to_string())),
+  ];
+  let inspected_server = (options.show_cert && req.url().scheme() == "https")
+    .then(|| {
+      req
+        .url()
+        .host_str()
+        .map(|host| host.trim_start_matches('[').trim_end_matches(']').to_string())
+    })
+    .flatten();
+  if !options.pins.is_empty() && req.url().scheme() != "https" {
+    return Err(anyhow::anyhow!("Certificate pins need an https url, got {}", req.url()));
+  }
+  tracing::info!(method = %req.method(), url = %req.url(), "request start");
+  let span_start = SystemTime::now();
+  let start = Instant::now();
+  let result = match options.read_timeout {
+    Some(read_timeout) => tokio::time::timeout(read_timeout, client.execute(req))
+      .await
+      .map_err(|_| read_timeout_error(read_timeout))
+      .and_then(|result| Ok(result?)),
+    None => client.execute(req).await.map_err(anyhow::Error::from),
+  };
+  // chain of the connection the request was sent on, printed even when the handshake failed
+  if let Some((chain, verification)) = inspected_server.as_deref().and_then(peer_certificates) {
+    eprint!("{}", format_certificates(&chain, &verification));
+  }
+  match &result {
+    Ok(response) => tracing::info!(
+      status = response.status().as_u16(),
+      duration_ms = start.elapsed().as_millis() as u64,
+      "request end"
+    ),
+    Err(err) => tracing::error!(error = %err, duration_ms = start.elapsed().as_millis() as u64, "request failed"),
+  }
+  if let (Some(telemetry), Some(span_id)) = (options.telemetry, span_id) {
+    let mut attributes = span_attributes;
+    if let Ok(response) = &result {
+      attributes.push(("http.status_code".to_string(), Value::from(response.status().as_u16())));
+    }
+    telemetry.record(Span {
+      span_id,
+      name: span_name,
+      start: span_start,
+      end: SystemTime::now(),
+      attributes,
+      error: match &result {
+        Ok(response) if response.status().is_server_error() => Some(response.status().to_string()),
+        Ok(_) => None,
+        Err(err) => Some(err.to_string()),
+      },
+    });
+  }
+  let result = result?;
+  if let Some(session) = options.session {
+    session.lock().unwrap().update_cookies(result.url(), result.headers());
+  }
   if options.verbose {
     result.print(options.theme, options.is_output_terminal)?;
+    if let Some(remote_addr) = result.remote_addr() {
+      match result.url().domain() {
+        Some(domain) => println!("remote address: {} (resolved from {})", remote_addr, domain),
+        None => println!("remote address: {}", remote_addr),
+      }
+    }
+    let timings = result
+      .headers()
+      .get_all(SERVER_TIMING)
+      .iter()
+      .filter_map(|value| value.to_str().ok())
+      .flat_map(parse_server_timing)
+      .collect::<Vec<_>>();
+    if !timings.is_empty() {
+      println!("{}", format_server_timing(&timings, start.elapsed()));
+    }
+    if let Some(request_id) = &request_id {
+      println!("request id: {}", request_id);
+    }
+    if let Some(idempotency_key) = &idempotency_key {
+      println!("idempotency key: {}", idempotency_key);
+    }
+    println!();
+  }
+  Ok(SentRequest {
+    response: result,
+    request_id,
+    idempotency_key,
+    start,
+  })
+}
+
+fn read_timeout_error(read_timeout: Duration) -> anyhow::Error {
+  anyhow::anyhow!(
+    "Nothing received from server for {:.1}s\nuse --read-timeout to wait longer",
+    read_timeout.as_secs_f64()
+  )
+}
+
+// body chunks of a response, failing when the server sends nothing for longer than `read_timeout`
+fn body_stream(
+  response: Response,
+  read_timeout: Option<Duration>,
+) -> impl Stream<Item = std::io::Result<Bytes>> + Unpin {
+  let chunks = response.bytes_stream().map_err(std::io::Error::other);
+  Box::pin(stream::unfold(Some(chunks), move |chunks| async move {
+    let mut chunks = chunks?;
+    let chunk = match read_timeout {
+      Some(read_timeout) => tokio::time::timeout(read_timeout, chunks.next())
+        .await
+        .unwrap_or_else(|_| Some(Err(std::io::Error::other(read_timeout_error(read_timeout))))),
+      None => chunks.next().await,
+    };
+    // nothing is read after an error
+    chunk.map(|chunk| match chunk {
+      Ok(chunk) => (Ok(chunk), Some(chunks)),
+      Err(e) => (Err(e), None),
+    })
+  }))
+}
+
+// response body read in memory, failing as soon as it grows beyond `--max-body-size`
+async fn read_body(response: Response, options: &RequestOptions<'_>) -> Result<Vec<u8>> {
+  let too_large = |limit: u64| {
+    anyhow::anyhow!(
+      "Response body is larger than the maximum body size of {} bytes\nuse --max-body-size to raise it, or --raw --output-file to stream it to a file",
+      limit
+    )
+  };
+  let limit = options.max_body_size;
+  if let (Some(limit), Some(length)) = (limit, response.content_length()) {
+    if length > limit {
+      return Err(too_large(limit));
+    }
+  }
+  let progress_bar = response
+    .content_length()
+    .filter(|length| options.progress && *length > RESPONSE_PROGRESS_THRESHOLD)
+    .map(|length| FileProgressComponent::new_response(response.url().to_string(), length, true));
+  let mut body = Vec::new();
+  let mut chunks = body_stream(response, options.read_timeout);
+  while let Some(chunk) = chunks.try_next().await? {
+    if let Some(limit) = limit.filter(|limit| (body.len() + chunk.len()) as u64 > *limit) {
+      return Err(too_large(limit));
+    }
+    body.extend_from_slice(&chunk);
+    if let Some(progress_bar) = &progress_bar {
+      progress_bar.update_progress(chunk.len() as u64);
+    }
+  }
+  Ok(body)
+}
+
+// response body read in memory as text, decoded with the charset of its `Content-Type` (utf-8 by default)
+async fn read_text(response: Response, options: &RequestOptions<'_>) -> Result<String> {
+  let charset = response
+    .headers()
+    .get(CONTENT_TYPE)
+    .and_then(|value| value.to_str().ok())
+    .and_then(|value| {
+      value
+        .split(';')
+        .skip(1)
+        .filter_map(|param| param.trim().split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("charset"))
+        .map(|(_, charset)| charset.trim().trim_matches('"').to_string())
+    });
+  let encoding = charset
+    .and_then(|charset| encoding_rs::Encoding::for_label(charset.as_bytes()))
+    .unwrap_or(encoding_rs::UTF_8);
+  let body = read_body(response, options).await?;
+  Ok(encoding.decode(&body).0.into_owned())
+}
+
+// send a request, responses with a status of the retry policy are sent again after a delay, attempts are counted
+async fn send_with_retries(
+  url: &str,
+  method: &str,
+  headers: Option<&HeaderMap>,
+  queries: Option<&[(String, String)]>,
+  body: Option<AdvancedBody>,
+  options: &RequestOptions<'_>,
+) -> Result<(SentRequest, u32)> {
+  // a body streamed from stdin can only be sent once
+  let retryable = !matches!(&body, Some(AdvancedBody::File(file)) if file == STDIN_FILE);
+  let mut attempt = 0;
+  loop {
+    let sent = send_request(url, method, headers, queries, body.clone(), options).await?;
+    let status = sent.response.status();
+    match &options.retry {
+      Some(retry) if retryable && retry.retries(status, attempt) => {
+        attempt += 1;
+        let delay = retry.delay(attempt, sent.response.headers());
+        tracing::warn!(
+          status = status.as_u16(),
+          attempt,
+          max_retries = retry.max_retries,
+          delay_ms = delay.as_millis() as u64,
+          "retrying request"
+        );
+        eprintln!(
+          "{}, retrying in {}s ({}/{})",
+          status,
+          delay.as_secs_f32(),
+          attempt,
+          retry.max_retries
+        );
+        tokio::time::sleep(delay).await;
+      }
+      _ => return Ok((sent, attempt + 1)),
+    }
+  }
+}
+
+// send a request and capture its response instead of displaying it
+pub async fn fetch_response(
+  url: &str,
+  method: &str,
+  headers: Option<&HeaderMap>,
+  queries: Option<&[(String, String)]>,
+  body: Option<AdvancedBody>,
+  options: RequestOptions<'_>,
+) -> Result<CapturedResponse> {
+  let SentRequest { response, .. } = send_request(url, method, headers, queries, body, &options).await?;
+  Ok(CapturedResponse {
+    status: response.status(),
+    headers: response.headers().clone(),
+    body: read_text(response, &options).await?,
+  })
+}
+
+// send a request and capture its response with metrics instead of displaying it, retrying like `make_request`
+pub async fn fetch_metrics(
+  url: &str,
+  method: &str,
+  headers: Option<&HeaderMap>,
+  queries: Option<&[(String, String)]>,
+  body: Option<AdvancedBody>,
+  options: RequestOptions<'_>,
+) -> Result<ResponseMetrics> {
+  let (
+    SentRequest {
+      response,
+      request_id,
+      idempotency_key,
+      start,
+    },
+    attempts,
+  ) = send_with_retries(url, method, headers, queries, body, &options).await?;
+  let status = response.status();
+  let headers = response.headers().clone();
+  let body = read_text(response, &options).await?;
+  Ok(ResponseMetrics {
+    status,
+    bytes: body.len() as u64,
+    duration: start.elapsed(),
+    attempts,
+    request_id,
+    idempotency_key,
+    response: Some(CapturedResponse { status, headers, body }),
+  })
+}
+
+// send the same request to several urls concurrently, responses are printed in urls order once all are received
+pub async fn make_parallel_requests(
+  urls: &[String],
+  method: &str,
+  headers: Option<&HeaderMap>,
+  queries: Option<&[(String, String)]>,
+  body: Option<AdvancedBody>,
+  options: RequestOptions<'_>,
+) -> Result<()> {
+  let responses = futures::future::join_all(urls.iter().map(|url| {
+    let body = body.clone();
+    let options = options.clone();
+    async move {
+      let start = Instant::now();
+      let response = fetch_response(url, method, headers, queries, body, options).await;
+      (response, start.elapsed())
+    }
+  }))
+  .await;
+  let mut failed = 0;
+  for (url, (response, duration)) in urls.iter().zip(responses) {
+    match response {
+      Ok(response) => {
+        println!("==> {} <== {} in {}ms", url, response.status, duration.as_millis());
+        if options.verbose {
+          let mut output = String::new();
+          for (key, value) in &response.headers {
+            output.push_str(&format!("{}: {}\n", key.as_str(), value.to_str()?));
+          }
+          pretty_print(output, options.theme, "yaml", options.is_output_terminal)?;
+        }
+        let language = options.syntax.as_deref().or_else(|| response.headers.get_language());
+        output_text_body(&response.body, language, &options)?;
+      }
+      Err(err) => {
+        failed += 1;
+        println!("==> {} <== failed in {}ms", url, duration.as_millis());
+        eprintln!("{:#}", err);
+      }
+    }
+    println!();
+  }
+  match failed {
+    0 => Ok(()),
+    failed => Err(anyhow::anyhow!("{} of {} requests failed", failed, urls.len())),
+  }
+}
+
+// body transformed by the manifest `transform` template, transformed json bodies are displayed as json
+fn transform_body<'l>(
+  status: StatusCode,
+  headers: &HeaderMap,
+  body: String,
+  language: Option<&'l str>,
+  options: &RequestOptions<'_>,
+) -> Result<(String, Option<&'l str>)> {
+  match &options.transform {
+    Some(transform) => {
+      let body = transform.apply(status, headers, &body)?;
+      let language = serde_json::from_str::<Value>(&body).ok().map(|_| "json");
+      Ok((body, language))
+    }
+    None => Ok((body, language)),
+  }
+}
+
+// save text body to output file and/or pretty print it
+pub fn output_text_body(response_body: &str, language: Option<&str>, options: &RequestOptions<'_>) -> Result<()> {
+  if !response_body.is_empty() {
+    if let Some(output_filename) = &options.output_filename {
+      std::fs::write(output_filename, response_body)?;
+    }
+    if options.output_format == Some(OutputFormat::Shell) {
+      return Ok(());
+    }
+    if options.output_filename.is_none() || options.tee {
+      match &options.output_buffer {
+        Some(buffer) => buffer
+          .lock()
+          .unwrap()
+          .push((response_body.to_string(), language.map(str::to_string))),
+        None => print_text_body(response_body, language, options)?,
+      }
+    }
+  }
+  Ok(())
+}
+
+// write each chunk of a body both to a file and to stdout, as it is received
+async fn tee_body(
+  mut chunks: impl Stream<Item = std::io::Result<Bytes>> + Unpin,
+  file: &mut (impl AsyncWrite + Unpin),
+  stdout: &mut (impl AsyncWrite + Unpin),
+) -> Result<u64> {
+  let mut bytes = 0;
+  while let Some(chunk) = chunks.next().await {
+    let chunk = chunk?;
+    file.write_all(&chunk).await?;
+    stdout.write_all(&chunk).await?;
+    bytes += chunk.len() as u64;
+  }
+  file.flush().await?;
+  stdout.flush().await?;
+  Ok(bytes)
+}
+
+// print text body on stdout, highlighted unless it is raw
+pub fn print_text_body(response_body: &str, language: Option<&str>, options: &RequestOptions<'_>) -> Result<()> {
+  if options.raw {
+    let mut stdout = std::io::stdout();
+    stdout.write_all(response_body.as_bytes())?;
+    stdout.flush()?;
+    return Ok(());
+  }
+  // csv is shown as a table on terminals unless text output is asked, and on any output when table is asked
+  let table = match options.output_format {
+    Some(OutputFormat::Table) => true,
+    Some(OutputFormat::Text) | Some(OutputFormat::Shell) => false,
+    None => language == Some("csv") && options.is_output_terminal,
+  };
+  if table {
+    println!("{}", format_csv_table(response_body)?);
+    return Ok(());
+  }
+  pretty_print(
+    response_body.to_string(),
+    options.theme,
+    language.unwrap_or_default(),
+    options.is_output_terminal,
+  )?;
+  println!();
+  Ok(())
+}
+
+// highlight range responses on stderr, so they are not mistaken for full bodies
+fn print_partial_content(response: &Response) {
+  let status = response.status();
+  if status != StatusCode::PARTIAL_CONTENT && status != StatusCode::RANGE_NOT_SATISFIABLE {
+    return;
+  }
+  let content_range = response
+    .headers()
+    .get(CONTENT_RANGE)
+    .and_then(|value| value.to_str().ok())
+    .unwrap_or("none");
+  let message = format!("{}, content-range: {}", status, content_range);
+  let style = console::Style::new().for_stderr().bold();
+  let style = if status.is_success() {
+    style.yellow()
+  } else {
+    style.red()
+  };
+  eprintln!("{}", style.apply_to(message));
+}
+
+fn print_cache_decision(options: &RequestOptions<'_>, decision: &str) {
+  if options.verbose {
+    eprintln!("cache: {}", decision);
+  }
+}
+
+// display a response served by the cache, `attempts` is 0 when the request was not sent to revalidate it
+fn output_cached_response(
+  entry: &CacheEntry,
+  start: Instant,
+  attempts: u32,
+  options: &RequestOptions<'_>,
+) -> Result<ResponseMetrics> {
+  let status = entry.status();
+  let headers = entry.headers_with_age(chrono::Utc::now().timestamp());
+  if options.verbose {
+    let mut output = format!("{}\n", status);
+    for (key, value) in &headers {
+      output.push_str(&format!("{}: {}\n", key.as_str(), value.to_str()?));
+    }
+    pretty_print(output, options.theme, "yaml", options.is_output_terminal)?;
     println!();
   }
-  let language = result.get_language();
+  let language = options.syntax.as_deref().or_else(|| headers.get_language());
+  let (body, language) = transform_body(status, &headers, entry.body.clone(), language, options)?;
+  output_text_body(&body, language, options)?;
+  Ok(ResponseMetrics {
+    status,
+    bytes: entry.body.len() as u64,
+    duration: start.elapsed(),
+    attempts,
+    request_id: None,
+    idempotency_key: None,
+    response: Some(CapturedResponse { status, headers, body }),
+  })
+}
+
+// download into output file, resuming after `offset` bytes already downloaded
+// the file is left unchanged unless the server resumes exactly at `offset`
+#[allow(clippy::too_many_arguments)]
+async fn resume_download(
+  url: &str,
+  method: &str,
+  headers: Option<&HeaderMap>,
+  queries: Option<&[(String, String)]>,
+  body: Option<AdvancedBody>,
+  filename: &str,
+  offset: u64,
+  options: &RequestOptions<'_>,
+) -> Result<ResponseMetrics> {
+  let file_size = std::fs::metadata(filename).map(|metadata| metadata.len()).unwrap_or(0);
+  if offset > file_size {
+    return Err(anyhow::anyhow!(
+      "Can't continue at byte {}, {} only has {} bytes",
+      offset,
+      filename,
+      file_size
+    ));
+  }
+  let mut range_headers = headers.cloned().unwrap_or_default();
+  if offset > 0 {
+    range_headers.insert(RANGE, HeaderValue::from_str(&format!("bytes={}-", offset))?);
+  }
+  let SentRequest {
+    response,
+    request_id,
+    idempotency_key,
+    start,
+  } = send_request(url, method, Some(&range_headers), queries, body, options).await?;
+  let status = response.status();
+  print_partial_content(&response);
+  let content_range = response
+    .headers()
+    .get(CONTENT_RANGE)
+    .and_then(|value| value.to_str().ok())
+    .unwrap_or_default()
+    .to_string();
+  let metrics = |bytes| ResponseMetrics {
+    status,
+    bytes,
+    duration: start.elapsed(),
+    attempts: 1,
+    request_id: request_id.clone(),
+    idempotency_key: idempotency_key.clone(),
+    response: None,
+  };
+  match status {
+    StatusCode::PARTIAL_CONTENT if offset > 0 && content_range_start(&content_range) == Some(offset) => {}
+    StatusCode::PARTIAL_CONTENT => {
+      return Err(anyhow::anyhow!(
+        "Server did not resume at byte {}, {} left unchanged
+cause: content-range is '{}'",
+        offset,
+        filename,
+        content_range
+      ))
+    }
+    StatusCode::RANGE_NOT_SATISFIABLE if offset > 0 && content_range_size(&content_range) == Some(offset) => {
+      eprintln!("{} is already complete", filename);
+      return Ok(metrics(0));
+    }
+    status if status.is_success() && offset == 0 => {}
+    status if status.is_success() => {
+      return Err(anyhow::anyhow!(
+        "Server ignored range request, {} left unchanged
+cause: {} instead of 206 Partial Content",
+        filename,
+        status
+      ))
+    }
+    status => {
+      return Err(anyhow::anyhow!(
+        "Could not continue download, {} left unchanged
+cause: {}",
+        filename,
+        status
+      ))
+    }
+  }
+  let mut file = tokio::fs::OpenOptions::new()
+    .create(true)
+    .write(true)
+    .truncate(false)
+    .open(filename)
+    .await?;
+  file.set_len(offset).await?;
+  file.seek(std::io::SeekFrom::End(0)).await?;
+  let progress_bar = FileProgressComponent::new_download(
+    filename.to_owned(),
+    response.content_length().unwrap_or(0),
+    options.progress,
+  );
+  let mut stream = body_stream(response, options.read_timeout)
+    .inspect_ok(move |bytes| {
+      progress_bar.update_progress(bytes.len() as u64);
+    })
+    .into_async_read()
+    .compat();
+  let bytes = tokio::io::copy(&mut stream, &mut file).await?;
+  Ok(metrics(bytes))
+}
+
+// exit status of requests answered with an http error, `--fail` hides the body, `--fail-with-body` keeps it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FailMode {
+  HideBody,
+  KeepBody,
+}
+
+pub fn is_http_error(status: StatusCode) -> bool {
+  status.is_client_error() || status.is_server_error()
+}
+
+// fields of usual error bodies (RFC 7807 problems, `{"error": {...}}`, `{"errors": [...]}`), in display order
+fn error_fields(body: &Value) -> Vec<(String, String)> {
+  static FIELDS: [&str; 7] = [
+    "title",
+    "error",
+    "message",
+    "error_description",
+    "detail",
+    "code",
+    "type",
+  ];
+  let text = |value: &Value| match value {
+    Value::String(text) => Some(text.clone()),
+    Value::Number(number) => Some(number.to_string()),
+    _ => None,
+  };
+  let mut fields = Vec::new();
+  for field in FIELDS {
+    match body.get(field) {
+      Some(Value::Object(_)) => fields.extend(
+        error_fields(&body[field])
+          .into_iter()
+          .map(|(name, value)| (format!("{}.{}", field, name), value)),
+      ),
+      Some(value) => fields.extend(text(value).map(|value| (field.to_string(), value))),
+      None => {}
+    }
+  }
+  if let Some(Value::Array(errors)) = body.get("errors") {
+    for (index, error) in errors.iter().enumerate().take(5) {
+      let message = text(error).or_else(|| {
+        ["message", "detail", "title"]
+          .iter()
+          .find_map(|field| error.get(field).and_then(text))
+      });
+      if let Some(message) = message {
+        fields.push((format!("errors[{}]", index), message));
+      }
+    }
+  }
+  fields
+}
+
+// concise summary of an http error, printed on stderr before the body
+fn error_summary(status: StatusCode, body: &str) -> String {
+  let mut summary = status.to_string();
+  if let Ok(body) = serde_json::from_str::<Value>(body) {
+    for (name, value) in error_fields(&body) {
+      summary.push_str(&format!("\n  {}: {}", name, value));
+    }
+  }
+  summary
+}
+
+fn print_error_summary(status: StatusCode, body: &str) {
+  let style = console::Style::new().for_stderr().bold().red();
+  eprintln!("{}", style.apply_to(error_summary(status, body)));
+}
+
+// bodies of http errors are only shown when not failing with `--fail`
+fn shows_body(status: StatusCode, options: &RequestOptions<'_>) -> bool {
+  !is_http_error(status) || options.fail != Some(FailMode::HideBody)
+}
+
+pub async fn make_request(
+  url: &str,
+  method: &str,
+  headers: Option<&HeaderMap>,
+  queries: Option<&[(String, String)]>,
+  body: Option<AdvancedBody>,
+  options: RequestOptions<'_>,
+) -> Result<ResponseMetrics> {
+  let fail = options.fail.is_some();
+  let metrics = send_and_output(url, method, headers, queries, body, options).await?;
+  if fail && is_http_error(metrics.status) {
+    return Err(anyhow::anyhow!("Request failed with status {}", metrics.status));
+  }
+  Ok(metrics)
+}
+
+async fn send_and_output(
+  url: &str,
+  method: &str,
+  headers: Option<&HeaderMap>,
+  queries: Option<&[(String, String)]>,
+  body: Option<AdvancedBody>,
+  options: RequestOptions<'_>,
+) -> Result<ResponseMetrics> {
+  if let (Some(continue_at), Some(filename)) = (options.continue_at, &options.output_filename) {
+    let offset = continue_at.offset(filename);
+    return resume_download(url, method, headers, queries, body, filename, offset, &options).await;
+  }
+  // only GET responses are cached, they are the only ones with a body that can be reused
+  let cache = match options.cache {
+    Some(CacheMode::Rfc) if method.eq_ignore_ascii_case("get") => Some(HttpCache::open()?),
+    _ => None,
+  };
+  let cache_url = merge_url_queries(url, queries)?.to_string();
+  let request_headers = merge_with_defaults(headers.unwrap_or(&HeaderMap::new()), &options, body.is_some())?;
+  let mut stale_entry = None;
+  let mut conditional_headers = None;
+  if let Some(cache) = &cache {
+    match lookup(
+      cache.load(method, &cache_url),
+      &request_headers,
+      chrono::Utc::now().timestamp(),
+    ) {
+      CacheLookup::Fresh(entry, age, lifetime) => {
+        print_cache_decision(&options, &format!("hit, age {}s for a lifetime of {}s", age, lifetime));
+        return output_cached_response(&entry, Instant::now(), 0, &options);
+      }
+      CacheLookup::Stale(entry, reason) => {
+        let validators = entry.conditional_headers();
+        if validators.is_empty() {
+          print_cache_decision(&options, &format!("miss, {} and has no validator", reason));
+        } else {
+          print_cache_decision(&options, &format!("revalidate, {}", reason));
+          let mut revalidation_headers = headers.cloned().unwrap_or_default();
+          revalidation_headers.extend(validators);
+          conditional_headers = Some(revalidation_headers);
+          stale_entry = Some(entry);
+        }
+      }
+      CacheLookup::Miss(reason) => print_cache_decision(&options, &format!("miss, {}", reason)),
+    }
+  }
+  let (
+    SentRequest {
+      response: result,
+      request_id,
+      idempotency_key,
+      start,
+    },
+    attempts,
+  ) = send_with_retries(
+    url,
+    method,
+    conditional_headers.as_ref().or(headers),
+    queries,
+    body,
+    &options,
+  )
+  .await?;
+  let status = result.status();
+  if let (Some(cache), Some(mut entry)) = (&cache, stale_entry) {
+    if status == StatusCode::NOT_MODIFIED {
+      print_cache_decision(&options, "revalidated, stored response is still valid");
+      entry.refresh(result.headers(), chrono::Utc::now().timestamp());
+      cache.store(method, &cache_url, &entry)?;
+      return output_cached_response(&entry, start, attempts, &options);
+    }
+  }
+  print_partial_content(&result);
+  // raw bodies are copied as received, they are not stored in cache as they may not be text
+  if options.raw {
+    let bytes = match &options.output_filename {
+      Some(filename) if options.tee => {
+        let chunks = body_stream(result, options.read_timeout);
+        tee_body(
+          chunks,
+          &mut AsyncFile::create(filename).await?,
+          &mut tokio::io::stdout(),
+        )
+        .await?
+      }
+      Some(filename) => {
+        let mut stream = body_stream(result, options.read_timeout).into_async_read().compat();
+        tokio::io::copy(&mut stream, &mut AsyncFile::create(filename).await?).await?
+      }
+      None => {
+        let mut stream = body_stream(result, options.read_timeout).into_async_read().compat();
+        tokio::io::copy(&mut stream, &mut tokio::io::stdout()).await?
+      }
+    };
+    return Ok(ResponseMetrics {
+      status,
+      bytes,
+      duration: start.elapsed(),
+      attempts,
+      request_id,
+      idempotency_key,
+      response: None,
+    });
+  }
+  // binary bodies with a known encoding are displayed as json instead of being downloaded
+  if let Some(decoder) = options
+    .decoder
+    .clone()
+    .or_else(|| BodyDecoder::detect(result.headers()))
+  {
+    let headers = result.headers().clone();
+    let bytes = read_body(result, &options).await?;
+    let response_body = serde_json::to_string(&decoder.decode(&bytes)?)?;
+    if is_http_error(status) {
+      print_error_summary(status, &response_body);
+    }
+    let (response_body, language) = transform_body(status, &headers, response_body, Some("json"), &options)?;
+    if shows_body(status, &options) {
+      output_text_body(&response_body, language, &options)?;
+    }
+    return Ok(ResponseMetrics {
+      status,
+      bytes: bytes.len() as u64,
+      duration: start.elapsed(),
+      attempts,
+      request_id,
+      idempotency_key,
+      response: Some(CapturedResponse {
+        status,
+        headers,
+        body: response_body,
+      }),
+    });
+  }
+  // a forced syntax also displays bodies that would otherwise be downloaded as binary
+  let language = options.syntax.as_deref().or_else(|| result.get_language());
   if let Some("binary") = language {
     let url = Url::parse(url)?;
     let filename = if let Some(output_filename) = options.output_filename {
       output_filename
     } else {
+      // segments are percent encoded, non-ASCII file names are decoded back
       url
         .path_segments()
-        .and_then(|segments| segments.last())
-        .unwrap_or("unknown.bin")
-        .to_owned()
+        .and_then(|mut segments| segments.next_back())
+        .map(|segment| percent_decode_str(segment).decode_utf8_lossy().to_string())
+        .filter(|segment| !segment.is_empty())
+        .unwrap_or_else(|| "unknown.bin".to_string())
     };
 
-    let progress_bar = FileProgressComponent::new_download(filename.to_owned(), result.content_length().unwrap_or(0));
-    let mut stream = result
-      .bytes_stream()
+    let progress_bar = FileProgressComponent::new_download(
+      filename.to_owned(),
+      result.content_length().unwrap_or(0),
+      options.progress,
+    );
+    let mut stream = body_stream(result, options.read_timeout)
       .inspect_ok(move |bytes| {
         progress_bar.update_progress(bytes.len() as u64);
       })
-      .map_err(|e| futures::io::Error::new(futures::io::ErrorKind::Other, e))
       .into_async_read()
       .compat();
-    if !options.is_output_terminal {
-      tokio::io::copy(&mut stream, &mut tokio::io::stdout()).await?;
+    let bytes = if !options.is_output_terminal {
+      tokio::io::copy(&mut stream, &mut tokio::io::stdout()).await?
     } else {
       let mut file = AsyncFile::create(filename).await?;
-      tokio::io::copy(&mut stream, &mut file).await?;
-    }
+      tokio::io::copy(&mut stream, &mut file).await?
+    };
+    Ok(ResponseMetrics {
+      status,
+      bytes,
+      duration: start.elapsed(),
+      attempts,
+      request_id,
+      idempotency_key,
+      response: None,
+    })
   } else {
-    let response_body = result.text().await?;
-    if !response_body.is_empty() {
-      if let Some(output_filename) = options.output_filename {
-        let mut file = AsyncFile::create(output_filename).await?;
-        tokio::io::copy(&mut response_body.as_bytes(), &mut file).await?;
-      } else {
-        pretty_print(
-          response_body,
-          options.theme,
-          language.unwrap_or_default(),
-          options.is_output_terminal,
-        )?;
-        println!();
+    let headers = result.headers().clone();
+    let response_body = read_text(result, &options).await?;
+    if let Some(cache) = &cache {
+      match storable(status, &request_headers, &headers) {
+        Ok(()) => {
+          let entry = CacheEntry::new(
+            status,
+            &headers,
+            response_body.clone(),
+            &request_headers,
+            chrono::Utc::now().timestamp(),
+          );
+          cache.store(method, &cache_url, &entry)?;
+          print_cache_decision(&options, "stored");
+        }
+        Err(reason) => print_cache_decision(&options, &format!("not stored, {}", reason)),
       }
     }
+    if is_http_error(status) {
+      print_error_summary(status, &response_body);
+    }
+    let bytes = response_body.len() as u64;
+    let (response_body, language) = transform_body(status, &headers, response_body, language, &options)?;
+    let metrics = ResponseMetrics {
+      status,
+      bytes,
+      duration: start.elapsed(),
+      attempts,
+      request_id,
+      idempotency_key,
+      response: Some(CapturedResponse {
+        status,
+        headers,
+        body: response_body.clone(),
+      }),
+    };
+    if shows_body(status, &options) {
+      output_text_body(&response_body, language, &options)?;
+    }
+    Ok(metrics)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_output_text_body_buffered() {
+    let buffer = OutputBuffer::default();
+    let options = RequestOptions {
+      output_buffer: Some(buffer.clone()),
+      ..Default::default()
+    };
+    output_text_body("{}", Some("json"), &options).unwrap();
+    output_text_body("", Some("json"), &options).unwrap();
+    assert_eq!(
+      *buffer.lock().unwrap(),
+      vec![("{}".to_string(), Some("json".to_string()))]
+    );
+  }
+
+  #[test]
+  fn test_retry_policy() {
+    let retry = RetryPolicy {
+      statuses: vec![429, 503],
+      max_retries: 2,
+    };
+    assert!(retry.retries(StatusCode::TOO_MANY_REQUESTS, 0));
+    assert!(!retry.retries(StatusCode::TOO_MANY_REQUESTS, 2));
+    assert!(!retry.retries(StatusCode::INTERNAL_SERVER_ERROR, 0));
+    let mut headers = HeaderMap::new();
+    assert_eq!(retry.delay(1, &headers), Duration::from_secs(1));
+    assert_eq!(retry.delay(2, &headers), Duration::from_secs(2));
+    assert_eq!(retry.delay(3, &headers), Duration::from_secs(4));
+    assert_eq!(retry.delay(10, &headers), MAX_RETRY_DELAY);
+    headers.insert(RETRY_AFTER, HeaderValue::from_static("5"));
+    assert_eq!(retry.delay(2, &headers), Duration::from_secs(5));
+    headers.insert(RETRY_AFTER, HeaderValue::from_static("3600"));
+    assert_eq!(retry.delay(1, &headers), MAX_RETRY_DELAY);
+  }
+
+  #[test]
+  fn test_cached_client_keys() {
+    let options = RequestOptions::default();
+    let proxied = RequestOptions {
+      proxy_url: Some("http://localhost:3128".to_string()),
+      ..Default::default()
+    };
+    assert_eq!(ClientKey::new(&options), ClientKey::new(&RequestOptions::default()));
+    assert_ne!(ClientKey::new(&options), ClientKey::new(&proxied));
+    assert!(cached_client(&options).is_ok());
+    assert!(cached_client(&proxied).is_ok());
+  }
+
+  #[test]
+  fn test_cached_client_tls_versions() {
+    let tls_1_2 = RequestOptions {
+      tls_min: Some(TlsVersion::V1_2),
+      tls_max: Some(TlsVersion::V1_2),
+      ..Default::default()
+    };
+    let reversed = RequestOptions {
+      tls_min: Some(TlsVersion::V1_2),
+      tls_max: Some(TlsVersion::V1_1),
+      ..Default::default()
+    };
+    assert_ne!(ClientKey::new(&tls_1_2), ClientKey::new(&RequestOptions::default()));
+    assert!(cached_client(&tls_1_2).is_ok());
+    assert!(cached_client(&reversed).is_err());
+  }
+
+  #[test]
+  fn test_cached_client_trust_store() {
+    let bundled = RequestOptions {
+      trust_store: Some(TrustStore::Bundled),
+      ..Default::default()
+    };
+    assert_ne!(ClientKey::new(&bundled), ClientKey::new(&RequestOptions::default()));
+    assert!(cached_client(&bundled).is_ok());
+  }
+
+  #[test]
+  fn test_cached_client_pins() {
+    let pinned = RequestOptions {
+      pins: vec!["sha256//47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=".to_string()],
+      trust_store: Some(TrustStore::Bundled),
+      ..Default::default()
+    };
+    assert_ne!(ClientKey::new(&pinned), ClientKey::new(&RequestOptions::default()));
+    assert!(cached_client(&pinned).is_ok());
+    let inspected = RequestOptions {
+      show_cert: true,
+      trust_store: Some(TrustStore::Bundled),
+      ..Default::default()
+    };
+    assert!(cached_client(&inspected).is_ok());
+    let legacy = RequestOptions {
+      tls_max: Some(TlsVersion::V1_1),
+      ..pinned.clone()
+    };
+    assert!(cached_client(&legacy).is_err());
+    let malformed = RequestOptions {
+      pins: vec!["sha256//YWJj".to_string()],
+      ..pinned
+    };
+    assert!(cached_client(&malformed).is_err());
+  }
+
+  #[test]
+  fn test_cached_client_fresh_dns() {
+    let fresh = RequestOptions {
+      fresh_dns: true,
+      ..Default::default()
+    };
+    assert_ne!(ClientKey::new(&fresh), ClientKey::new(&RequestOptions::default()));
+    assert!(cached_client(&fresh).is_ok());
+  }
+
+  #[test]
+  fn test_error_summary() {
+    let problem =
+      r#"{"type": "https://example.com/not-found", "title": "Not Found", "detail": "user 42 does not exist"}"#;
+    assert_eq!(
+      error_summary(StatusCode::NOT_FOUND, problem),
+      "404 Not Found\n  title: Not Found\n  detail: user 42 does not exist\n  type: https://example.com/not-found"
+    );
+    let nested = r#"{"error": {"code": 400, "message": "invalid name"}, "errors": [{"message": "name is required"}, "age is negative"]}"#;
+    assert_eq!(
+      error_summary(StatusCode::BAD_REQUEST, nested),
+      "400 Bad Request\n  error.message: invalid name\n  error.code: 400\n  errors[0]: name is required\n  errors[1]: age is negative"
+    );
+    assert_eq!(
+      error_summary(StatusCode::INTERNAL_SERVER_ERROR, "<html>oops</html>"),
+      "500 Internal Server Error"
+    );
+  }
+
+  #[test]
+  fn test_merge_with_defaults() {
+    let options = RequestOptions {
+      accept: Some("application/xml".to_string()),
+      unset_headers: vec![ACCEPT_ENCODING],
+      ..Default::default()
+    };
+    let headers = merge_with_defaults(&HeaderMap::new(), &options, false).unwrap();
+    assert_eq!(headers.get(ACCEPT).unwrap(), "application/xml");
+    assert!(!headers.contains_key(CONTENT_TYPE));
+    assert!(!headers.contains_key(ACCEPT_ENCODING));
+    let explicit = HeaderMap::from_iter([(CONTENT_TYPE, HeaderValue::from_static("text/plain"))]);
+    let headers = merge_with_defaults(&explicit, &RequestOptions::default(), true).unwrap();
+    assert_eq!(headers.get(ACCEPT).unwrap(), DEFAULT_ACCEPT);
+    assert_eq!(headers.get(CONTENT_TYPE).unwrap(), "text/plain");
+    let headers = merge_with_defaults(&HeaderMap::new(), &RequestOptions::default(), true).unwrap();
+    assert_eq!(headers.get(CONTENT_TYPE).unwrap(), "application/json");
+  }
+
+  // serve each raw http response once, in order
+  async fn serve(responses: Vec<&'static [u8]>) -> String {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+      for response in responses {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut buffer = [0u8; 1024];
+        let _ = socket.read(&mut buffer).await.unwrap();
+        socket.write_all(response).await.unwrap();
+      }
+    });
+    url
+  }
+
+  #[tokio::test]
+  async fn test_read_text_max_body_size() {
+    let url = serve(vec![
+      b"HTTP/1.1 200 OK\r\ncontent-type: text/plain; charset=iso-8859-1\r\ncontent-length: 3\r\n\r\n\xe9t\xe9",
+      b"HTTP/1.1 200 OK\r\ncontent-length: 6\r\n\r\ntoo big",
+      b"HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n3\r\nabc\r\n3\r\ndef\r\n0\r\n\r\n",
+    ])
+    .await;
+    let client = Client::new();
+    let options = RequestOptions {
+      max_body_size: Some(5),
+      ..Default::default()
+    };
+    let response = client.get(&url).send().await.unwrap();
+    assert_eq!(read_text(response, &options).await.unwrap(), "été");
+    let response = client.get(&url).send().await.unwrap();
+    assert!(read_text(response, &options).await.is_err());
+    let response = client.get(&url).send().await.unwrap();
+    assert!(read_body(response, &options).await.is_err());
+  }
+
+  #[tokio::test]
+  async fn test_tee_body() {
+    let chunks = stream::iter(vec![Ok(Bytes::from("{\"id\":")), Ok(Bytes::from("1}"))]);
+    let (mut file, mut stdout) = (Vec::new(), Vec::new());
+    assert_eq!(tee_body(chunks, &mut file, &mut stdout).await.unwrap(), 8);
+    assert_eq!(file, b"{\"id\":1}");
+    assert_eq!(stdout, file);
+    let chunks = stream::iter(vec![Ok(Bytes::from("{")), Err(std::io::Error::other("reset"))]);
+    assert!(tee_body(chunks, &mut Vec::new(), &mut Vec::new()).await.is_err());
+  }
+
+  // test the first retry waits 1s when no `Retry-After` is sent
+  #[tokio::test]
+  async fn test_first_retry_delay() {
+    let url = serve(vec![
+      b"HTTP/1.1 503 Service Unavailable\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+      b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\n{}",
+    ])
+    .await;
+    let options = RequestOptions {
+      retry: Some(RetryPolicy {
+        statuses: vec![503],
+        max_retries: 1,
+      }),
+      ..Default::default()
+    };
+    let start = std::time::Instant::now();
+    let metrics = fetch_metrics(&url, "GET", None, None, None, options).await.unwrap();
+    let elapsed = start.elapsed();
+    assert_eq!(metrics.status, StatusCode::OK);
+    assert_eq!(metrics.attempts, 2);
+    assert!(elapsed >= Duration::from_secs(1) && elapsed < Duration::from_secs(2));
+  }
+
+  #[tokio::test]
+  async fn test_read_body_read_timeout() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+      let (mut socket, _) = listener.accept().await.unwrap();
+      let mut buffer = [0u8; 1024];
+      let _ = socket.read(&mut buffer).await.unwrap();
+      socket
+        .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 10\r\n\r\nabc")
+        .await
+        .unwrap();
+      // body is never completed
+      tokio::time::sleep(Duration::from_secs(10)).await;
+    });
+    let options = RequestOptions {
+      read_timeout: Some(Duration::from_millis(200)),
+      ..Default::default()
+    };
+    let response = Client::new().get(&url).send().await.unwrap();
+    let error = read_body(response, &options).await.unwrap_err();
+    assert!(error.to_string().starts_with("Nothing received from server for 0.2s"));
   }
-  Ok(())
 }
@@ -0,0 +1,181 @@
+use super::git;
+use super::manifests::ApixManifest;
+use super::paths::lock_manifests;
+use anyhow::Result;
+use regex::Regex;
+use std::path::{Path, PathBuf};
+
+// replace the `metadata.name` of a manifest, keeping the rest of the document (comments, order) untouched
+fn rename_manifest_content(content: &str, new_name: &str) -> Result<String> {
+  let lines: Vec<&str> = content.lines().collect();
+  let metadata = lines
+    .iter()
+    .position(|line| line.trim_end() == "metadata:")
+    .ok_or_else(|| anyhow::anyhow!("Manifest has no metadata"))?;
+  let indentation = |line: &str| line.len() - line.trim_start().len();
+  let fields = lines
+    .iter()
+    .enumerate()
+    .skip(metadata + 1)
+    .take_while(|(_, line)| line.trim().is_empty() || line.starts_with(char::is_whitespace))
+    .filter(|(_, line)| !line.trim().is_empty())
+    .collect::<Vec<_>>();
+  // labels or annotations may hold a `name` key too, only fields of metadata itself are looked at
+  let indent = fields.first().map(|(_, line)| indentation(line)).unwrap_or_default();
+  let name = fields
+    .iter()
+    .find(|(_, line)| indentation(line) == indent && line.trim_start().starts_with("name:"))
+    .map(|(index, _)| *index)
+    .ok_or_else(|| anyhow::anyhow!("Manifest has no metadata name"))?;
+  let indent = &lines[name][..indent];
+  let yaml_name = if new_name.chars().all(|c| c.is_ascii_alphanumeric() || "_-.".contains(c)) {
+    new_name.to_string()
+  } else {
+    serde_json::Value::String(new_name.to_string()).to_string()
+  };
+  let mut renamed = lines
+    .iter()
+    .enumerate()
+    .map(|(index, line)| {
+      if index == name {
+        format!("{}name: {}", indent, yaml_name)
+      } else {
+        line.to_string()
+      }
+    })
+    .collect::<Vec<_>>()
+    .join("\n");
+  if content.ends_with('\n') {
+    renamed.push('\n');
+  }
+  Ok(renamed)
+}
+
+// `{% include "<library>/..." %}`, `{% import ... %}` and `{% extends ... %}` references to a template library
+fn rename_library_references(content: &str, name: &str, new_name: &str) -> String {
+  let reference = Regex::new(&format!(
+    r#"(\{{%-?\s*(?:include|import|extends)\s+["']){}/"#,
+    regex::escape(name)
+  ))
+  .unwrap();
+  reference
+    .replace_all(content, format!("${{1}}{}/", new_name).as_str())
+    .into_owned()
+}
+
+// resource file with the new name, next to the original one
+fn target_path(path: &Path, new_name: &str) -> PathBuf {
+  let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("yaml");
+  path.with_file_name(format!("{}.{}", new_name, extension))
+}
+
+fn find_source(kind: &str, name: &str, new_name: &str) -> Result<(PathBuf, String, PathBuf)> {
+  let (path, _) = ApixManifest::find_manifest(kind, name)
+    .ok_or_else(|| anyhow::anyhow!("No resource of type {} where found with name {}", kind, name))?;
+  if ApixManifest::find_manifest(kind, new_name).is_some() {
+    return Err(anyhow::anyhow!(
+      "A resource of type {} named {} already exists",
+      kind,
+      new_name
+    ));
+  }
+  let target = target_path(&path, new_name);
+  if target.exists() {
+    return Err(anyhow::anyhow!("File {} already exists", target.display()));
+  }
+  let content =
+    std::fs::read_to_string(&path).map_err(|e| anyhow::anyhow!("Could not read {}\ncause: {}", path.display(), e))?;
+  Ok((path, rename_manifest_content(&content, new_name)?, target))
+}
+
+// duplicate a resource under a new name, in a new file
+pub fn handle_copy(kind: &str, name: &str, new_name: &str) -> Result<()> {
+  let _lock = lock_manifests()?;
+  let (_, content, target) = find_source(kind, name, new_name)?;
+  std::fs::write(&target, content)
+    .map_err(|e| anyhow::anyhow!("Failed to write {}\ncause: {}", target.display(), e))?;
+  println!("Copied {} {} to {}", kind, name, target.display());
+  git::auto_commit("copy", &[target.to_string_lossy().to_string()])
+}
+
+// rename a resource and its file, template libraries are also renamed in the templates of every manifest
+pub fn handle_rename(kind: &str, name: &str, new_name: &str) -> Result<()> {
+  let _lock = lock_manifests()?;
+  let (path, content, target) = find_source(kind, name, new_name)?;
+  std::fs::write(&target, content)
+    .map_err(|e| anyhow::anyhow!("Failed to write {}\ncause: {}", target.display(), e))?;
+  std::fs::remove_file(&path).map_err(|e| anyhow::anyhow!("Failed to remove {}\ncause: {}", path.display(), e))?;
+  println!("Renamed {} {} to {}", kind, name, new_name);
+  let mut files = vec![path.to_string_lossy().to_string(), target.to_string_lossy().to_string()];
+  if kind == "templatelibrary" {
+    for (path, _) in ApixManifest::find_manifests()? {
+      let content = std::fs::read_to_string(&path)?;
+      let renamed = rename_library_references(&content, name, new_name);
+      if renamed != content {
+        std::fs::write(&path, renamed)
+          .map_err(|e| anyhow::anyhow!("Failed to write {}\ncause: {}", path.display(), e))?;
+        println!("Updated references in {}", path.display());
+        files.push(path.to_string_lossy().to_string());
+      }
+    }
+  }
+  git::auto_commit("rename", &files)
+}
+
+// delete a resource file
+pub fn handle_delete(kind: &str, name: &str) -> Result<()> {
+  let _lock = lock_manifests()?;
+  let (path, _) = ApixManifest::find_manifest(kind, name)
+    .ok_or_else(|| anyhow::anyhow!("No resource of type {} where found with name {}", kind, name))?;
+  std::fs::remove_file(&path).map_err(|e| anyhow::anyhow!("Failed to remove {}\ncause: {}", path.display(), e))?;
+  println!("Deleted {} {}", kind, name);
+  git::auto_commit("delete", &[path.to_string_lossy().to_string()])
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_rename_manifest_content() {
+    let content = r#"apiVersion: apix.io/v1
+kind: Story
+# comments are kept
+metadata:
+  labels:
+    name: apix
+  name: get-user
+spec:
+  stories:
+    - name: get-user
+"#;
+    assert_eq!(
+      rename_manifest_content(content, "get-admin").unwrap(),
+      content.replacen("  name: get-user", "  name: get-admin", 1)
+    );
+    assert!(rename_manifest_content(content, "get: admin")
+      .unwrap()
+      .contains("  name: \"get: admin\"\n"));
+    assert!(rename_manifest_content("kind: Request\n", "get-admin").is_err());
+  }
+
+  #[test]
+  fn test_rename_library_references() {
+    assert_eq!(
+      rename_library_references(
+        r#"body: '{% include "common/audit" %} {%- import 'common/filters' as filters %} common/audit'"#,
+        "common",
+        "shared"
+      ),
+      r#"body: '{% include "shared/audit" %} {%- import 'shared/filters' as filters %} common/audit'"#
+    );
+  }
+
+  #[test]
+  fn test_target_path() {
+    assert_eq!(
+      target_path(Path::new(".apix/get-user.yml"), "get-admin"),
+      PathBuf::from(".apix/get-admin.yml")
+    );
+  }
+}
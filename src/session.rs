@@ -0,0 +1,563 @@
+use crate::encryption::{seal, unseal};
+use crate::paths::{lock_file, write_atomic, PRIVATE_FILE_MODE};
+use anyhow::Result;
+use clap::ArgMatches;
+use indexmap::IndexMap;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, COOKIE, SET_COOKIE};
+use serde::{Deserialize, Serialize};
+use std::hash::Hash;
+use std::path::PathBuf;
+use std::str::FromStr;
+use url::Url;
+
+// first line of curl cookie jars
+static NETSCAPE_HEADER: &str = "# Netscape HTTP Cookie File";
+
+// headers remembered by sessions, only credentials, other headers apply to a single request
+static SESSION_HEADERS: [&str; 3] = ["authorization", "x-api-key", "x-auth-token"];
+
+// cookie kept in a session, only sent back to urls matching its domain, path and `Secure` attribute (RFC 6265)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SessionCookie {
+  pub name: String,
+  pub value: String,
+  pub domain: String,
+  pub path: String,
+  // cookies set without a `Domain` attribute are only sent to the host that set them
+  #[serde(default, rename = "hostOnly", skip_serializing_if = "std::ops::Not::not")]
+  pub host_only: bool,
+  #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+  pub secure: bool,
+}
+
+impl SessionCookie {
+  fn key(&self) -> (String, String, String) {
+    (self.domain.clone(), self.path.clone(), self.name.clone())
+  }
+
+  fn is_sent_to_host(&self, host: &str) -> bool {
+    host == self.domain || (!self.host_only && host.ends_with(&format!(".{}", self.domain)))
+  }
+
+  fn is_sent_to(&self, url: &Url) -> bool {
+    let host = url.host_str().unwrap_or_default().to_lowercase();
+    self.is_sent_to_host(&host) && path_matches(url.path(), &self.path) && (!self.secure || url.scheme() == "https")
+  }
+}
+
+fn path_matches(request_path: &str, cookie_path: &str) -> bool {
+  request_path == cookie_path
+    || (request_path.starts_with(cookie_path)
+      && (cookie_path.ends_with('/') || request_path[cookie_path.len()..].starts_with('/')))
+}
+
+// path of cookies set without a `Path` attribute, the directory of the request path
+fn default_cookie_path(url: &Url) -> String {
+  match url.path().rfind('/') {
+    Some(0) | None => "/".to_string(),
+    Some(index) => url.path()[..index].to_string(),
+  }
+}
+
+fn origin(url: &Url) -> String {
+  url.origin().ascii_serialization()
+}
+
+// named session persisting authorization headers by origin and cookies between ad-hoc requests
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ApixSession {
+  // headers by origin, eg: `https://api.example.com`
+  #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+  pub headers: IndexMap<String, IndexMap<String, String>>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub cookies: Vec<SessionCookie>,
+}
+
+impl ApixSession {
+  fn path(name: &str) -> Result<PathBuf> {
+    static RE: Lazy<Regex> = Lazy::new(|| Regex::new("^[\\w-]+$").unwrap());
+    if !RE.is_match(name) {
+      return Err(anyhow::anyhow!(
+        "Bad session name: \"{}\", should only contain letters, digits, '_' or '-'",
+        name
+      ));
+    }
+    Ok(PathBuf::from(".apix").join("sessions").join(format!("{}.yaml", name)))
+  }
+
+  // load a session from current project, a missing session is an empty one, encrypted sessions are decrypted
+  pub fn load(name: &str) -> Result<Self> {
+    let path = Self::path(name)?;
+    match std::fs::read_to_string(&path) {
+      Ok(content) => {
+        let content =
+          unseal(content).map_err(|e| anyhow::anyhow!("Could not decrypt session file {:?}: {:#}", &path, e))?;
+        serde_yaml::from_str(&content).map_err(|e| anyhow::anyhow!("Could not parse session file {:?}: {:#}", &path, e))
+      }
+      Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+      Err(e) => Err(anyhow::anyhow!("Could not read session file {:?}\ncause: {}", &path, e)),
+    }
+  }
+
+  // change a session while other apix processes can't, session file is locked, reloaded, changed and saved
+  pub fn update<T>(name: &str, change: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+    let path = Self::path(name)?;
+    if let Some(parent) = path.parent() {
+      std::fs::create_dir_all(parent)?;
+    }
+    let _lock = lock_file(&path)?;
+    let mut session = Self::load(name)?;
+    let result = change(&mut session)?;
+    // sessions hold credentials, only readable by their owner
+    write_atomic(
+      &path,
+      seal(&serde_yaml::to_string(&session)?)?.as_bytes(),
+      false,
+      Some(PRIVATE_FILE_MODE),
+    )
+    .map_err(|e| anyhow::anyhow!("Failed to save session {:?}\ncause: {}", &path, e))?;
+    Ok(result)
+  }
+
+  // save changes made since `original` was loaded, keeping changes saved meanwhile by other apix processes
+  pub fn save_changes(&self, name: &str, original: &ApixSession) -> Result<()> {
+    Self::update(name, |session| {
+      let mut headers = header_entries(&session.headers);
+      merge_changes(
+        &mut headers,
+        &header_entries(&original.headers),
+        &header_entries(&self.headers),
+      );
+      session.headers = IndexMap::new();
+      for ((origin, key), value) in headers {
+        session.headers.entry(origin).or_default().insert(key, value);
+      }
+      let mut cookies = cookie_entries(&session.cookies);
+      merge_changes(
+        &mut cookies,
+        &cookie_entries(&original.cookies),
+        &cookie_entries(&self.cookies),
+      );
+      session.cookies = cookies.into_values().collect();
+      Ok(())
+    })
+  }
+
+  // headers to send to `url`: session headers of its origin and cookies matching it, overridden by explicit headers
+  pub fn apply(&self, url: &Url, headers: Option<&HeaderMap>) -> Result<HeaderMap> {
+    let mut merged = HeaderMap::new();
+    for (key, value) in self.headers.get(&origin(url)).into_iter().flatten() {
+      merged.insert(HeaderName::from_str(key)?, HeaderValue::from_str(value)?);
+    }
+    let mut cookies = self
+      .cookies
+      .iter()
+      .filter(|cookie| cookie.is_sent_to(url))
+      .collect::<Vec<_>>();
+    // cookies with longer paths are listed first
+    cookies.sort_by_key(|cookie| std::cmp::Reverse(cookie.path.len()));
+    if !cookies.is_empty() {
+      let cookies = cookies
+        .iter()
+        .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+        .collect::<Vec<_>>()
+        .join("; ");
+      merged.insert(COOKIE, HeaderValue::from_str(&cookies)?);
+    }
+    if let Some(headers) = headers {
+      for (key, value) in headers {
+        merged.insert(key.clone(), value.clone());
+      }
+    }
+    Ok(merged)
+  }
+
+  // remember credential headers explicitly sent to the origin of `url`
+  pub fn update_headers(&mut self, url: &Url, headers: &HeaderMap) {
+    for (key, value) in headers {
+      if !SESSION_HEADERS.contains(&key.as_str()) {
+        continue;
+      }
+      if let Ok(value) = value.to_str() {
+        self
+          .headers
+          .entry(origin(url))
+          .or_default()
+          .insert(key.to_string(), value.to_string());
+      }
+    }
+  }
+
+  fn set_cookie(&mut self, cookie: SessionCookie, expired: bool) {
+    self.cookies.retain(|existing| existing.key() != cookie.key());
+    if !expired {
+      self.cookies.push(cookie);
+    }
+  }
+
+  // remember cookies set by a response to `url`, expired ones are removed and cookies for other domains are ignored
+  pub fn update_cookies(&mut self, url: &Url, headers: &HeaderMap) {
+    let host = url.host_str().unwrap_or_default().to_lowercase();
+    for set_cookie in headers
+      .get_all(SET_COOKIE)
+      .iter()
+      .filter_map(|value| value.to_str().ok())
+    {
+      let mut attributes = set_cookie.split(';').map(str::trim);
+      let (name, value) = match attributes.next().and_then(|cookie| cookie.split_once('=')) {
+        Some((name, value)) if !name.trim().is_empty() => (name.trim(), value.trim()),
+        _ => continue,
+      };
+      let mut cookie = SessionCookie {
+        name: name.to_string(),
+        value: value.to_string(),
+        domain: host.clone(),
+        path: default_cookie_path(url),
+        host_only: true,
+        secure: false,
+      };
+      let mut expired = false;
+      let mut foreign = false;
+      for attribute in attributes {
+        let (key, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+        match key.trim().to_lowercase().as_str() {
+          "domain" => {
+            let domain = value.trim().trim_start_matches('.').to_lowercase();
+            if domain.is_empty() {
+              continue;
+            }
+            cookie.domain = domain;
+            cookie.host_only = false;
+            foreign = !cookie.is_sent_to_host(&host);
+          }
+          "path" if value.trim().starts_with('/') => cookie.path = value.trim().to_string(),
+          "secure" => cookie.secure = true,
+          "max-age" => expired = value.trim().parse::<i64>().map(|age| age <= 0).unwrap_or(false),
+          "expires" => {
+            expired = expired
+              || chrono::DateTime::parse_from_rfc2822(value.trim())
+                .map(|expires| expires < chrono::Utc::now())
+                .unwrap_or(false)
+          }
+          _ => {}
+        }
+      }
+      if !foreign {
+        self.set_cookie(cookie, expired);
+      }
+    }
+  }
+
+  // cookies in curl cookie jar (Netscape) format, only those sent to `domain` when given
+  pub fn export_cookies(&self, domain: Option<&str>) -> String {
+    let mut jar = format!("{}\n", NETSCAPE_HEADER);
+    for cookie in &self.cookies {
+      if domain.is_some_and(|domain| !cookie.is_sent_to_host(&domain.to_lowercase())) {
+        continue;
+      }
+      let (domain, subdomains) = match cookie.host_only {
+        true => (cookie.domain.clone(), "FALSE"),
+        false => (format!(".{}", cookie.domain), "TRUE"),
+      };
+      let secure = if cookie.secure { "TRUE" } else { "FALSE" };
+      jar.push_str(&format!(
+        "{}\t{}\t{}\t{}\t0\t{}\t{}\n",
+        domain, subdomains, cookie.path, secure, cookie.name, cookie.value
+      ));
+    }
+    jar
+  }
+
+  // add cookies of a curl cookie jar, only those sent to `domain` when given, expired cookies are skipped
+  pub fn import_cookies(&mut self, jar: &str, domain: Option<&str>, now: i64) -> Result<usize> {
+    let mut imported = 0;
+    for (index, line) in jar.lines().enumerate() {
+      let line = line.trim_end_matches('\r');
+      // curl marks http only cookies with a prefix looking like a comment
+      let line = match line.strip_prefix("#HttpOnly_") {
+        Some(line) => line,
+        None if line.trim().is_empty() || line.starts_with('#') => continue,
+        None => line,
+      };
+      let fields = line.split('\t').collect::<Vec<_>>();
+      if fields.len() != 7 {
+        return Err(anyhow::anyhow!(
+          "Bad cookie jar line {}: \"{}\", should have 7 tab separated fields",
+          index + 1,
+          line
+        ));
+      }
+      let expires = fields[4]
+        .parse::<i64>()
+        .map_err(|e| anyhow::anyhow!("Bad cookie expiry on cookie jar line {}\ncause: {}", index + 1, e))?;
+      let cookie = SessionCookie {
+        name: fields[5].to_string(),
+        value: fields[6].to_string(),
+        domain: fields[0].trim_start_matches('.').to_lowercase(),
+        path: fields[2].to_string(),
+        host_only: fields[1] != "TRUE",
+        secure: fields[3] == "TRUE",
+      };
+      let sent_to_domain = domain.is_none_or(|domain| cookie.is_sent_to_host(&domain.to_lowercase()));
+      if sent_to_domain && (expires == 0 || expires > now) {
+        self.set_cookie(cookie, false);
+        imported += 1;
+      }
+    }
+    Ok(imported)
+  }
+}
+
+fn header_entries(headers: &IndexMap<String, IndexMap<String, String>>) -> IndexMap<(String, String), String> {
+  headers
+    .iter()
+    .flat_map(|(origin, headers)| {
+      headers
+        .iter()
+        .map(move |(key, value)| ((origin.clone(), key.clone()), value.clone()))
+    })
+    .collect()
+}
+
+fn cookie_entries(cookies: &[SessionCookie]) -> IndexMap<(String, String, String), SessionCookie> {
+  cookies.iter().map(|cookie| (cookie.key(), cookie.clone())).collect()
+}
+
+// apply to `stored` values the values added, changed or removed between `original` and `updated`
+fn merge_changes<K: Hash + Eq + Clone, V: Clone + PartialEq>(
+  stored: &mut IndexMap<K, V>,
+  original: &IndexMap<K, V>,
+  updated: &IndexMap<K, V>,
+) {
+  for (key, value) in updated {
+    if original.get(key) != Some(value) {
+      stored.insert(key.clone(), value.clone());
+    }
+  }
+  for key in original.keys().filter(|key| !updated.contains_key(*key)) {
+    stored.shift_remove(key);
+  }
+}
+
+// `apix cookies export|import`, move session cookies from and to curl cookie jars
+pub fn handle_cookies(matches: &ArgMatches) -> Result<()> {
+  match matches.subcommand() {
+    Some(("export", matches)) => {
+      let session = ApixSession::load(matches.value_of("session").unwrap_or_default())?;
+      let jar = session.export_cookies(matches.value_of("domain"));
+      match matches.value_of("file") {
+        Some("-") | None => print!("{}", jar),
+        Some(file) => {
+          std::fs::write(file, jar).map_err(|e| anyhow::anyhow!("Failed to write cookie jar {}\ncause: {}", file, e))?
+        }
+      }
+    }
+    Some(("import", matches)) => {
+      let name = matches.value_of("session").unwrap_or_default();
+      let file = matches.value_of("file").unwrap_or_default();
+      let jar = match file {
+        "-" => std::io::read_to_string(std::io::stdin())?,
+        file => std::fs::read_to_string(file)
+          .map_err(|e| anyhow::anyhow!("Failed to read cookie jar {}\ncause: {}", file, e))?,
+      };
+      let imported = ApixSession::update(name, |session| {
+        session.import_cookies(&jar, matches.value_of("domain"), chrono::Utc::now().timestamp())
+      })?;
+      println!("{} cookies imported in session {}", imported, name);
+    }
+    _ => {}
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use indexmap::indexmap;
+  use test_case::test_case;
+
+  fn cookie(name: &str, value: &str, domain: &str, path: &str, host_only: bool) -> SessionCookie {
+    SessionCookie {
+      name: name.to_string(),
+      value: value.to_string(),
+      domain: domain.to_string(),
+      path: path.to_string(),
+      host_only,
+      secure: false,
+    }
+  }
+
+  fn value<'a>(session: &'a ApixSession, name: &str) -> Option<&'a str> {
+    session
+      .cookies
+      .iter()
+      .find(|cookie| cookie.name == name)
+      .map(|cookie| cookie.value.as_str())
+  }
+
+  #[test]
+  fn test_session_apply() {
+    let mut session = ApixSession::default();
+    session.headers.insert(
+      "https://api.example.com".to_string(),
+      indexmap! { "authorization".to_string() => "Bearer old".to_string(), "x-api-key".to_string() => "key".to_string() },
+    );
+    session.cookies.push(cookie("id", "42", "example.com", "/", false));
+    session
+      .cookies
+      .push(cookie("lang", "en", "api.example.com", "/v1", true));
+    let explicit = HeaderMap::from_iter([(
+      HeaderName::from_static("authorization"),
+      HeaderValue::from_static("Bearer new"),
+    )]);
+    let url = Url::parse("https://api.example.com/v1/users").unwrap();
+    let headers = session.apply(&url, Some(&explicit)).unwrap();
+    assert_eq!(headers.get("authorization").unwrap(), "Bearer new");
+    assert_eq!(headers.get("x-api-key").unwrap(), "key");
+    assert_eq!(headers.get(COOKIE).unwrap(), "lang=en; id=42");
+  }
+
+  #[test_case("https://api.example.com/v1/users", Some("lang=en; id=42"), true ; "same origin")]
+  #[test_case("https://api.example.com/v2", Some("id=42"), true ; "other path")]
+  #[test_case("https://www.example.com/v1", Some("id=42"), false ; "other subdomain")]
+  #[test_case("http://api.example.com/v1", Some("lang=en"), false ; "insecure scheme")]
+  #[test_case("https://evil.com/v1", None, false ; "other domain")]
+  #[test_case("https://notexample.com/", None, false ; "domain suffix")]
+  fn test_session_apply_scope(url: &str, cookies: Option<&str>, authorized: bool) {
+    let mut session = ApixSession::default();
+    session.headers.insert(
+      "https://api.example.com".to_string(),
+      indexmap! { "authorization".to_string() => "Bearer token".to_string() },
+    );
+    session.cookies.push(SessionCookie {
+      secure: true,
+      ..cookie("id", "42", "example.com", "/", false)
+    });
+    session
+      .cookies
+      .push(cookie("lang", "en", "api.example.com", "/v1", true));
+    let headers = session.apply(&Url::parse(url).unwrap(), None).unwrap();
+    assert_eq!(headers.get(COOKIE).map(|value| value.to_str().unwrap()), cookies);
+    assert_eq!(headers.contains_key("authorization"), authorized);
+  }
+
+  #[test]
+  fn test_session_update_headers() {
+    let mut session = ApixSession::default();
+    let headers = HeaderMap::from_iter([
+      (
+        HeaderName::from_static("authorization"),
+        HeaderValue::from_static("Bearer token"),
+      ),
+      (HeaderName::from_static("x-request-id"), HeaderValue::from_static("42")),
+    ]);
+    session.update_headers(&Url::parse("https://api.example.com:8443/users").unwrap(), &headers);
+    assert_eq!(
+      session.headers,
+      indexmap! {
+        "https://api.example.com:8443".to_string() => indexmap! { "authorization".to_string() => "Bearer token".to_string() }
+      }
+    );
+  }
+
+  #[test]
+  fn test_merge_changes() {
+    let original = indexmap! { "a".to_string() => "1".to_string(), "b".to_string() => "2".to_string() };
+    let updated = indexmap! { "a".to_string() => "10".to_string(), "c".to_string() => "3".to_string() };
+    // another process changed b and added d since original was loaded
+    let mut stored = indexmap! {
+      "a".to_string() => "1".to_string(),
+      "b".to_string() => "20".to_string(),
+      "d".to_string() => "4".to_string(),
+    };
+    merge_changes(&mut stored, &original, &updated);
+    assert_eq!(
+      stored,
+      indexmap! {
+        "a".to_string() => "10".to_string(),
+        "d".to_string() => "4".to_string(),
+        "c".to_string() => "3".to_string(),
+      }
+    );
+  }
+
+  #[test]
+  fn test_session_update_cookies() {
+    let mut session = ApixSession::default();
+    session.cookies.push(cookie("old", "1", "api.example.com", "/", true));
+    let mut headers = HeaderMap::new();
+    headers.append(SET_COOKIE, HeaderValue::from_static("id=42; Path=/; HttpOnly"));
+    headers.append(SET_COOKIE, HeaderValue::from_static("old=; Path=/; Max-Age=0"));
+    headers.append(
+      SET_COOKIE,
+      HeaderValue::from_static("shared=1; Domain=.example.com; Secure"),
+    );
+    headers.append(SET_COOKIE, HeaderValue::from_static("local=1"));
+    headers.append(SET_COOKIE, HeaderValue::from_static("evil=1; Domain=evil.com"));
+    session.update_cookies(&Url::parse("https://api.example.com/v1/users").unwrap(), &headers);
+    assert_eq!(
+      session.cookies,
+      vec![
+        cookie("id", "42", "api.example.com", "/", true),
+        SessionCookie {
+          secure: true,
+          ..cookie("shared", "1", "example.com", "/v1", false)
+        },
+        cookie("local", "1", "api.example.com", "/v1", true),
+      ]
+    );
+  }
+
+  #[test]
+  fn test_session_invalid_name() {
+    assert!(ApixSession::load("../secrets").is_err());
+  }
+
+  #[test]
+  fn test_export_cookies() {
+    let mut session = ApixSession::default();
+    session.cookies.push(cookie("id", "42", "example.com", "/", false));
+    session.cookies.push(SessionCookie {
+      secure: true,
+      ..cookie("token", "abc", "other.com", "/api", true)
+    });
+    assert_eq!(
+      session.export_cookies(None),
+      "# Netscape HTTP Cookie File\n\
+      .example.com\tTRUE\t/\tFALSE\t0\tid\t42\n\
+      other.com\tFALSE\t/api\tTRUE\t0\ttoken\tabc\n"
+    );
+    assert_eq!(
+      session.export_cookies(Some("api.example.com")),
+      "# Netscape HTTP Cookie File\n.example.com\tTRUE\t/\tFALSE\t0\tid\t42\n"
+    );
+  }
+
+  #[test]
+  fn test_import_cookies() {
+    let jar = "# Netscape HTTP Cookie File\n\
+      \n\
+      .example.com\tTRUE\t/\tFALSE\t0\tid\t42\n\
+      #HttpOnly_api.example.com\tFALSE\t/\tTRUE\t2000\ttoken\tabc\n\
+      api.example.com\tFALSE\t/\tFALSE\t500\texpired\tx\n\
+      other.com\tFALSE\t/\tFALSE\t0\tother\ty\n";
+    let mut session = ApixSession::default();
+    assert_eq!(session.import_cookies(jar, Some("api.example.com"), 1000).unwrap(), 2);
+    assert_eq!(session.cookies[0], cookie("id", "42", "example.com", "/", false));
+    assert_eq!(
+      session.cookies[1],
+      SessionCookie {
+        secure: true,
+        ..cookie("token", "abc", "api.example.com", "/", true)
+      }
+    );
+    assert!(value(&session, "expired").is_none());
+    assert!(value(&session, "other").is_none());
+    let mut session = ApixSession::default();
+    assert_eq!(session.import_cookies(jar, None, 1000).unwrap(), 3);
+    assert_eq!(value(&session, "other"), Some("y"));
+    assert!(session.import_cookies("example.com\tid\t42", None, 1000).is_err());
+  }
+}
@@ -0,0 +1,154 @@
+use super::http_utils::format_http_date;
+use super::manifests::{ApixSigning, HmacAlgorithm, SignatureEncoding};
+use anyhow::Result;
+use hmac::{Hmac, Mac};
+use reqwest::header::{HeaderName, HeaderValue, DATE};
+use reqwest::Request;
+use serde_json::{json, Map, Value};
+use sha2::{Sha256, Sha512};
+use std::str::FromStr;
+use tera::{Context, Tera};
+
+// signing scheme of an api, with its key already rendered
+#[derive(Debug, Clone)]
+pub struct RequestSigner {
+  signing: ApixSigning,
+  key: String,
+}
+
+impl RequestSigner {
+  pub fn new(signing: ApixSigning, key: String) -> Self {
+    Self { signing, key }
+  }
+
+  fn hmac(&self, message: &[u8]) -> Result<Vec<u8>> {
+    let key = self.key.as_bytes();
+    Ok(match self.signing.algorithm {
+      HmacAlgorithm::Sha256 => {
+        let mut mac = Hmac::<Sha256>::new_from_slice(key)?;
+        mac.update(message);
+        mac.finalize().into_bytes().to_vec()
+      }
+      HmacAlgorithm::Sha512 => {
+        let mut mac = Hmac::<Sha512>::new_from_slice(key)?;
+        mac.update(message);
+        mac.finalize().into_bytes().to_vec()
+      }
+    })
+  }
+
+  fn encode(&self, signature: &[u8]) -> String {
+    match self.signing.encoding {
+      SignatureEncoding::Hex => signature.iter().map(|byte| format!("{:02x}", byte)).collect(),
+      SignatureEncoding::Base64 => base64::encode(signature),
+    }
+  }
+
+  // add signature header to a built request, a `Date` header is added when missing so it can be signed
+  pub fn sign(&self, request: &mut Request, body_sha256: &[u8]) -> Result<()> {
+    let now = chrono::Utc::now();
+    if !request.headers().contains_key(DATE) {
+      request
+        .headers_mut()
+        .insert(DATE, HeaderValue::from_str(&format_http_date(now))?);
+    }
+    let mut context = Context::new();
+    context.insert("request", &signing_context(request, body_sha256, now.timestamp()));
+    let string_to_sign = Tera::one_off(&self.signing.string_to_sign, &context, false)
+      .map_err(|e| anyhow::anyhow!("Failed to render signing stringToSign\ncause: {:#}", e))?;
+    let signature = self.encode(&self.hmac(string_to_sign.as_bytes())?);
+    context.insert("signature", &signature);
+    let value = match &self.signing.value {
+      Some(value) => Tera::one_off(value, &context, false)
+        .map_err(|e| anyhow::anyhow!("Failed to render signing value\ncause: {:#}", e))?,
+      None => signature,
+    };
+    request.headers_mut().insert(
+      HeaderName::from_str(&self.signing.header)?,
+      HeaderValue::from_str(&value)?,
+    );
+    Ok(())
+  }
+}
+
+fn signing_context(request: &Request, body_sha256: &[u8], timestamp: i64) -> Value {
+  let headers = request
+    .headers()
+    .iter()
+    .filter_map(|(key, value)| {
+      value
+        .to_str()
+        .ok()
+        .map(|value| (key.as_str().to_string(), Value::String(value.to_string())))
+    })
+    .collect::<Map<String, Value>>();
+  let body = request
+    .body()
+    .and_then(|body| body.as_bytes())
+    .map(|bytes| String::from_utf8_lossy(bytes).to_string())
+    .unwrap_or_default();
+  json!({
+    "method": request.method().as_str(),
+    "url": request.url().as_str(),
+    "path": request.url().path(),
+    "query": request.url().query().unwrap_or_default(),
+    "headers": headers,
+    "body": body,
+    "bodySha256": body_sha256.iter().map(|byte| format!("{:02x}", byte)).collect::<String>(),
+    "date": headers.get("date").cloned().unwrap_or_default(),
+    "timestamp": timestamp,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use reqwest::Method;
+
+  fn signer(signing: &str) -> RequestSigner {
+    RequestSigner::new(serde_yaml::from_str(signing).unwrap(), "secret".to_string())
+  }
+
+  fn request() -> Request {
+    let mut request = Request::new(Method::POST, "http://localhost/users?page=1".parse().unwrap());
+    request
+      .headers_mut()
+      .insert(DATE, HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"));
+    request
+  }
+
+  #[test]
+  fn test_sign_hex() {
+    let mut request = request();
+    signer("key: k\nstringToSign: \"{{ request.method }}{{ request.path }}{{ request.date }}\"\nheader: X-Signature")
+      .sign(&mut request, &[])
+      .unwrap();
+    // echo -n "POST/usersWed, 21 Oct 2015 07:28:00 GMT" | openssl dgst -sha256 -hmac secret
+    assert_eq!(
+      request.headers().get("x-signature").unwrap(),
+      "3f99596e5b797d2b8f5fc84aa74eed341909b8997d9a1d4ebcbabfc7b32f3bde"
+    );
+  }
+
+  #[test]
+  fn test_sign_value_template() {
+    let mut request = request();
+    signer(
+      "key: k\nstringToSign: \"{{ request.query }}\"\nheader: Authorization\nencoding: base64\nalgorithm: sha512\nvalue: \"HMAC {{ signature }}\"",
+    )
+    .sign(&mut request, &[])
+    .unwrap();
+    let value = request.headers().get("authorization").unwrap().to_str().unwrap();
+    assert!(value.starts_with("HMAC "));
+    assert_eq!(base64::decode(&value[5..]).unwrap().len(), 64);
+  }
+
+  #[test]
+  fn test_sign_adds_date() {
+    let mut request = Request::new(Method::GET, "http://localhost/".parse().unwrap());
+    signer("key: k\nstringToSign: \"{{ request.date }}\"\nheader: X-Signature")
+      .sign(&mut request, &[])
+      .unwrap();
+    assert!(request.headers().contains_key(DATE));
+  }
+}
@@ -0,0 +1,641 @@
+use crate::assertions::{check_assertions, Assertion};
+use crate::auth::apply_auth;
+use crate::execute::{apply_api_accept, ask_for_required_parameters, check_duration};
+use crate::history::{self, HistoryEntry, Redactor, NO_HISTORY_ANNOTATION};
+use crate::http_utils::merge_url_queries;
+use crate::http_utils::Language;
+use crate::manifests::{
+  ApixApi, ApixConfiguration, ApixKind, ApixManifest, ApixParameter, ApixStep, ApixStories, ApixStory,
+};
+use crate::project_context::ApixProjectContext;
+use crate::report::{print_reports, ExecutionReport, ExecutionStatus};
+use crate::requests::{
+  fetch_metrics, output_text_body, AdvancedBody, CapturedResponse, RequestOptions, ResponseMetrics, RetryPolicy,
+  DEFAULT_MAX_RETRIES,
+};
+use crate::signing::RequestSigner;
+use crate::template::{
+  register_shared_templates, request_engine, ConditionTemplate, MapTemplate, StringTemplate, ValueTemplate,
+};
+use anyhow::Result;
+use indexmap::IndexMap;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::Instant;
+use tera::{Context, Tera};
+use url::Url;
+
+// stories in execution order, a story runs after the one it `needs`, others keep their declaration order
+pub fn story_order(stories: &ApixStories) -> Result<Vec<&ApixStory>> {
+  fn visit<'a>(
+    stories: &'a ApixStories,
+    story: &'a ApixStory,
+    visiting: &mut Vec<&'a str>,
+    ordered: &mut Vec<&'a ApixStory>,
+  ) -> Result<()> {
+    if ordered.iter().any(|ordered| ordered.name == story.name) {
+      return Ok(());
+    }
+    if visiting.contains(&story.name.as_str()) {
+      return Err(anyhow::anyhow!(
+        "Story {} needs itself through {}",
+        story.name,
+        visiting.join(" -> ")
+      ));
+    }
+    visiting.push(&story.name);
+    if let Some(needs) = &story.needs {
+      let needed = stories
+        .stories
+        .iter()
+        .find(|needed| &needed.name == needs)
+        .ok_or_else(|| anyhow::anyhow!("Story {} needs unknown story {}", story.name, needs))?;
+      visit(stories, needed, visiting, ordered)?;
+    }
+    visiting.pop();
+    ordered.push(story);
+    Ok(())
+  }
+  let mut ordered = Vec::new();
+  for story in &stories.stories {
+    visit(stories, story, &mut Vec::new(), &mut ordered)?;
+  }
+  Ok(ordered)
+}
+
+// executes steps one after the other, each step response is exposed to the next ones as `steps.<name>.response`
+pub struct StoryRunner<'a> {
+  file: &'a str,
+  engine: Tera,
+  context: Context,
+  steps: serde_json::Map<String, Value>,
+  api: Option<ApixApi>,
+  // steps are recorded in project history with their secrets redacted, unless the story opts out
+  history: Option<(Redactor, Vec<ApixParameter>)>,
+}
+
+impl<'a> StoryRunner<'a> {
+  pub fn new(file: &'a str, parameters: Value, api: Option<ApixApi>) -> Result<Self> {
+    let urlsafe = ApixConfiguration::once().get("urlsafe").map(String::as_str) == Some("true");
+    let mut engine = request_engine(urlsafe);
+    register_shared_templates(&mut engine)?;
+    let env: HashMap<String, String> = std::env::vars().collect();
+    let mut context = Context::new();
+    context.insert("parameters", &parameters);
+    context.insert("env", &env);
+    Ok(Self {
+      file,
+      engine,
+      context,
+      steps: serde_json::Map::new(),
+      api,
+      history: None,
+    })
+  }
+
+  pub fn with_history(mut self, redactor: Redactor, declared: Vec<ApixParameter>) -> Self {
+    self.history = Some((redactor, declared));
+    self
+  }
+
+  fn template_name(&self, story: &ApixStory, step: &ApixStep, field: &str) -> String {
+    format!("{}#/{}/{}/{}", self.file, story.name, step.name, field)
+  }
+
+  // `story` and `context` of the step, rendered with the responses of previous steps
+  fn render_step_context(&mut self, story: &ApixStory, step: &ApixStep) -> Result<()> {
+    self.context.insert("steps", &self.steps);
+    let story_context = self.engine.render_value(
+      &format!("{}#/{}/context", self.file, story.name),
+      &serde_json::to_value(&story.context)?,
+      &self.context,
+    )?;
+    self.context.insert(
+      "story",
+      &serde_json::json!({ "name": story.name, "context": story_context }),
+    );
+    let step_context = self.engine.render_map(
+      &self.template_name(story, step, "context"),
+      &step.context,
+      &self.context,
+    )?;
+    let step_context = serde_json::to_value(step_context)?;
+    self
+      .context
+      .insert("context", &ApixProjectContext::load()?.merge(&step_context));
+    Ok(())
+  }
+
+  fn render_headers(&mut self, story: &ApixStory, step: &ApixStep) -> Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    let name = self.template_name(story, step, "headers");
+    for (key, value) in self.engine.render_map(&name, &step.request.headers, &self.context)? {
+      headers.insert(
+        HeaderName::from_str(&key).map_err(|e| anyhow::anyhow!("Invalid header name {}\ncause: {}", key, e))?,
+        HeaderValue::from_str(&value).map_err(|e| anyhow::anyhow!("Invalid value of header {}\ncause: {}", key, e))?,
+      );
+    }
+    Ok(headers)
+  }
+
+  fn render_queries(&mut self, story: &ApixStory, step: &ApixStep) -> Result<Vec<(String, String)>> {
+    let mut queries = Vec::new();
+    for (key, value) in &step.request.queries {
+      let name = format!("{}.{}", self.template_name(story, step, "queries"), key);
+      for value in value.values() {
+        queries.push((key.clone(), self.engine.render_string(&name, value, &self.context)?));
+      }
+    }
+    Ok(queries)
+  }
+
+  // run a step, its `if` condition is rendered first and the step is skipped when false
+  // http errors do not fail the step, `assert`, `assertions` and `expect` decide
+  pub async fn run_step(
+    &mut self,
+    story: &ApixStory,
+    step: &ApixStep,
+    options: &RequestOptions<'_>,
+  ) -> Result<ExecutionStatus> {
+    self.render_step_context(story, step)?;
+    if let Some(condition) = &step.if_ {
+      let name = self.template_name(story, step, "if");
+      if !self.engine.render_condition(&name, condition, &self.context)? {
+        return Ok(ExecutionStatus::Skipped);
+      }
+    }
+    let url = self.engine.render_string(
+      &self.template_name(story, step, "url"),
+      &step.request.url,
+      &self.context,
+    )?;
+    let method = self.engine.render_string(
+      &self.template_name(story, step, "method"),
+      &step.request.method,
+      &self.context,
+    )?;
+    let mut headers = self.render_headers(story, step)?;
+    let mut queries = self.render_queries(story, step)?;
+    if let Some(auth) = self.api.as_ref().and_then(|api| api.auth.as_ref()) {
+      let name = self.template_name(story, step, "auth");
+      let (engine, context) = (&mut self.engine, &self.context);
+      apply_auth(auth, &mut headers, &mut queries, |field, value| {
+        Ok(engine.render_string(&format!("{}/{}", name, field), value, context)?)
+      })?;
+    }
+    let body = match &step.request.body {
+      Some(body) => Some(AdvancedBody::Json(self.engine.render_value(
+        &self.template_name(story, step, "body"),
+        body,
+        &self.context,
+      )?)),
+      None => None,
+    };
+    let mut options = options.clone();
+    if let Some(signing) = self.api.as_ref().and_then(|api| api.signing.clone()) {
+      let name = self.template_name(story, step, "signing");
+      let key = self
+        .engine
+        .render_string(&format!("{}/key", name), &signing.key, &self.context)?;
+      options.signer = Some(RequestSigner::new(signing, key));
+    }
+    apply_api_accept(self.api.as_ref(), &mut options);
+    if !step.retry_on.is_empty() {
+      options.retry = Some(RetryPolicy {
+        statuses: step.retry_on.clone(),
+        max_retries: step.max_retries.unwrap_or(DEFAULT_MAX_RETRIES),
+      });
+    }
+    let metrics = fetch_metrics(&url, &method, Some(&headers), Some(&queries), body, options.clone()).await?;
+    let json = metrics
+      .response
+      .as_ref()
+      .map(CapturedResponse::to_json)
+      .unwrap_or_default();
+    self.steps.insert(
+      step.name.clone(),
+      serde_json::json!({
+        "request": { "method": method.to_uppercase(), "url": url },
+        "response": json,
+      }),
+    );
+    eprintln!(
+      "==> {}.{} <== {} in {}ms",
+      story.name,
+      step.name,
+      metrics.status,
+      metrics.duration.as_millis()
+    );
+    self.record_step(
+      story,
+      step,
+      &method,
+      &merge_url_queries(&url, Some(&queries))?,
+      &headers,
+      &metrics,
+    );
+    self.check_step(story, step, &json, metrics.duration.as_millis(), &options)?;
+    Ok(ExecutionStatus::Success(metrics))
+  }
+
+  fn record_step(
+    &self,
+    story: &ApixStory,
+    step: &ApixStep,
+    method: &str,
+    url: &Url,
+    headers: &HeaderMap,
+    metrics: &ResponseMetrics,
+  ) {
+    if let Some((redactor, declared)) = &self.history {
+      let mut entry = HistoryEntry::new(method, url.as_str(), metrics, redactor)
+        .with_request(&format!("{}.{}", story.name, step.name))
+        .with_headers(headers, redactor);
+      if let Some(parameters) = self.context.get("parameters").and_then(Value::as_object) {
+        entry = entry.with_parameters(parameters, declared, redactor);
+      }
+      history::record(entry);
+    }
+  }
+
+  // `assertions` on the step response, `assert` template rendered with the step response available and `expect`
+  fn check_step(
+    &mut self,
+    story: &ApixStory,
+    step: &ApixStep,
+    response: &Value,
+    duration_ms: u128,
+    options: &RequestOptions<'_>,
+  ) -> Result<()> {
+    let assertions = step
+      .assertions
+      .iter()
+      .map(|assertion| Assertion::from_str(assertion))
+      .collect::<Result<Vec<_>>>()
+      .map_err(|e| anyhow::anyhow!("Invalid assertion in step {}\ncause: {}", step.name, e))?;
+    if !assertions.is_empty() {
+      check_assertions(&assertions, response)?;
+    }
+    if let Some(assert) = &step.assert_ {
+      self.context.insert("steps", &self.steps);
+      let name = self.template_name(story, step, "assert");
+      if self.engine.render_condition(&name, assert, &self.context)? {
+        eprintln!("✔ {}", assert);
+      } else {
+        eprintln!("✘ {}", assert);
+        return Err(anyhow::anyhow!("Assertion of step {} failed", step.name));
+      }
+    }
+    if let Some(max_duration_ms) = step.expect.as_ref().and_then(|expect| expect.max_duration_ms) {
+      check_duration(duration_ms, max_duration_ms, options.soft)?;
+    }
+    Ok(())
+  }
+
+  // run all stories in `needs` order, a failing step stops the run unless it has `continueOnError` set,
+  // remaining steps are then reported skipped
+  pub async fn run(&mut self, stories: &ApixStories, options: &RequestOptions<'_>) -> Result<Vec<ExecutionReport>> {
+    let mut reports = Vec::new();
+    let mut failed = false;
+    for story in story_order(stories)? {
+      for step in &story.steps {
+        let start = Instant::now();
+        let status = match failed {
+          true => ExecutionStatus::Skipped,
+          false => match self.run_step(story, step, options).await {
+            Ok(status) => status,
+            Err(e) if step.continue_on_error => {
+              eprintln!("⚠ step {} failed, continuing\ncause: {:#}", step.name, e);
+              ExecutionStatus::Ignored(e.to_string())
+            }
+            Err(e) => {
+              eprintln!("✘ story {} failed at step {}\ncause: {:#}", story.name, step.name, e);
+              failed = true;
+              ExecutionStatus::Failure(e.to_string())
+            }
+          },
+        };
+        reports.push(ExecutionReport {
+          file: self.file.to_string(),
+          name: format!("{}.{}", story.name, step.name),
+          status,
+          duration: start.elapsed(),
+        });
+      }
+    }
+    Ok(reports)
+  }
+}
+
+// run a story manifest and report its steps, every step response is printed in verbose mode, otherwise only the
+// last one
+pub async fn run_story(
+  file: &str,
+  manifest: &ApixManifest,
+  params: Option<IndexMap<String, Value>>,
+  options: &RequestOptions<'_>,
+) -> Result<Vec<ExecutionReport>> {
+  let stories = match manifest.kind() {
+    ApixKind::Story(stories) => stories,
+    _ => return Err(anyhow::anyhow!("Story manifest expected")),
+  };
+  let api = manifest.linked_api();
+  let parameters = ask_for_required_parameters(&stories.parameters, api.as_ref(), &params, options.interactive)?;
+  let mut runner = StoryRunner::new(file, Value::Object(parameters.clone()), api)?;
+  let recorded = !manifest
+    .get_annotations()
+    .is_some_and(|annotations| annotations.contains_key(NO_HISTORY_ANNOTATION));
+  if recorded {
+    let redactor = Redactor::from_config()?.with_secrets(&parameters, &stories.parameters);
+    runner = runner.with_history(redactor, stories.parameters.clone());
+  }
+  let reports = runner.run(stories, options).await?;
+  let responses = reports
+    .iter()
+    .filter_map(|report| match &report.status {
+      ExecutionStatus::Success(metrics) => metrics.response.as_ref(),
+      _ => None,
+    })
+    .collect::<Vec<_>>();
+  let shown = match options.verbose {
+    true => &responses[..],
+    false => &responses[responses.len().saturating_sub(1)..],
+  };
+  for response in shown {
+    let language = options.syntax.as_deref().or_else(|| response.headers.get_language());
+    output_text_body(&response.body, language, options)?;
+  }
+  Ok(reports)
+}
+
+// execute a story manifest and print a summary of its steps, failing when a step failed
+pub async fn handle_story(
+  file: &str,
+  manifest: &ApixManifest,
+  params: Option<IndexMap<String, Value>>,
+  options: RequestOptions<'_>,
+) -> Result<()> {
+  let reports = run_story(file, manifest, params, &options).await?;
+  print_reports(&reports);
+  match reports.iter().filter(|report| report.is_failure()).count() {
+    0 => Ok(()),
+    failed => Err(anyhow::anyhow!("{} of {} steps failed", failed, reports.len())),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+  fn stories(url: &str) -> ApixStories {
+    serde_yaml::from_str(&format!(
+      r#"
+stories:
+  - name: users
+    needs: login
+    steps:
+      - name: get_user
+        request:
+          method: get
+          url: "{url}/user"
+          headers:
+            Authorization: "Bearer {{{{ steps.get_token.response.body.token }}}}"
+        assert: "{{{{ steps.get_user.response.body.auth == 'Bearer abc' }}}}"
+      - name: delete_user
+        if: "{{{{ steps.get_user.response.status == 404 }}}}"
+        request:
+          method: delete
+          url: "{url}/user"
+  - name: login
+    steps:
+      - name: get_token
+        request:
+          method: post
+          url: "{url}/token"
+        assertions:
+          - status == 200
+"#,
+      url = url
+    ))
+    .unwrap()
+  }
+
+  #[test]
+  fn test_story_order() {
+    let stories = stories("http://localhost");
+    let names = story_order(&stories)
+      .unwrap()
+      .iter()
+      .map(|story| story.name.as_str())
+      .collect::<Vec<_>>();
+    assert_eq!(names, vec!["login", "users"]);
+    let mut cyclic = stories.clone();
+    cyclic.stories[1].needs = Some("users".to_string());
+    assert!(story_order(&cyclic).is_err());
+    let mut unknown = stories;
+    unknown.stories[0].needs = Some("admin".to_string());
+    assert!(story_order(&unknown).is_err());
+  }
+
+  #[tokio::test]
+  async fn test_run_stories() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+      loop {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut buffer = [0u8; 4096];
+        let read = socket.read(&mut buffer).await.unwrap();
+        let request = String::from_utf8_lossy(&buffer[..read]).to_string();
+        // authorization is sent back, so the test can check the token was passed from the previous step
+        let auth = request
+          .lines()
+          .filter_map(|line| line.split_once(": "))
+          .find(|(name, _)| name.eq_ignore_ascii_case("authorization"));
+        let body = match auth {
+          Some((_, auth)) => format!(r#"{{"auth":"{}"}}"#, auth),
+          None => r#"{"token":"abc"}"#.to_string(),
+        };
+        let response = format!(
+          "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+          body.len(),
+          body
+        );
+        socket.write_all(response.as_bytes()).await.unwrap();
+      }
+    });
+    let mut runner = StoryRunner::new("story.yaml", Value::Null, None).unwrap();
+    let reports = runner.run(&stories(&url), &RequestOptions::default()).await.unwrap();
+    assert_eq!(
+      statuses(&reports),
+      vec![
+        ("login.get_token".to_string(), "200 OK".to_string()),
+        ("users.get_user".to_string(), "200 OK".to_string()),
+        ("users.delete_user".to_string(), "Skipped".to_string()),
+      ]
+    );
+    assert_eq!(runner.steps["get_user"]["response"]["body"]["auth"], "Bearer abc");
+  }
+
+  // test steps of a signed api are signed, with the api accept header
+  #[tokio::test]
+  async fn test_run_stories_signed_api() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+      let (mut socket, _) = listener.accept().await.unwrap();
+      let mut buffer = [0u8; 4096];
+      let read = socket.read(&mut buffer).await.unwrap();
+      let request = String::from_utf8_lossy(&buffer[..read]).to_string();
+      let header = |name: &str| {
+        request
+          .lines()
+          .filter_map(|line| line.split_once(": "))
+          .find(|(key, _)| key.eq_ignore_ascii_case(name))
+          .map(|(_, value)| value.to_string())
+          .unwrap_or_default()
+      };
+      let body = serde_json::json!({"signature": header("x-signature"), "accept": header("accept")}).to_string();
+      let response = format!(
+        "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+        body.len(),
+        body
+      );
+      socket.write_all(response.as_bytes()).await.unwrap();
+    });
+    let api: ApixApi = serde_yaml::from_str(&format!(
+      r#"
+url: "{url}"
+version: "1.0"
+description: signed api
+accept: application/vnd.apix+json
+signing:
+  key: "{{{{ 'sec' ~ 'ret' }}}}"
+  stringToSign: "{{{{ request.method }}}}{{{{ request.path }}}}"
+  header: X-Signature
+"#,
+      url = url
+    ))
+    .unwrap();
+    let stories: ApixStories = serde_yaml::from_str(&format!(
+      r#"
+stories:
+  - name: signed
+    steps:
+      - name: signed
+        request:
+          method: get
+          url: "{url}/signed"
+"#,
+      url = url
+    ))
+    .unwrap();
+    let mut runner = StoryRunner::new("story.yaml", Value::Null, Some(api)).unwrap();
+    let reports = runner.run(&stories, &RequestOptions::default()).await.unwrap();
+    assert_eq!(
+      statuses(&reports),
+      vec![("signed.signed".to_string(), "200 OK".to_string())]
+    );
+    // echo -n "GET/signed" | openssl dgst -sha256 -hmac secret
+    assert_eq!(
+      runner.steps["signed"]["response"]["body"],
+      serde_json::json!({
+        "signature": "ac4a16c135a8fe67c45fb5ae27680bb568b10e469a0b16a02b8fce76028c780f",
+        "accept": "application/vnd.apix+json"
+      })
+    );
+  }
+
+  fn statuses(reports: &[ExecutionReport]) -> Vec<(String, String)> {
+    reports
+      .iter()
+      .map(|report| {
+        let status = match &report.status {
+          ExecutionStatus::Success(metrics) => metrics.status.to_string(),
+          ExecutionStatus::Failure(_) => "Failed".to_string(),
+          ExecutionStatus::Ignored(_) => "Ignored".to_string(),
+          ExecutionStatus::Skipped => "Skipped".to_string(),
+        };
+        (report.name.clone(), status)
+      })
+      .collect()
+  }
+
+  #[tokio::test]
+  async fn test_run_stories_http_errors() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+      let mut flaky_calls = 0;
+      loop {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut buffer = [0u8; 4096];
+        let read = socket.read(&mut buffer).await.unwrap();
+        let request = String::from_utf8_lossy(&buffer[..read]).to_string();
+        // flaky is unavailable once, then answers
+        let status = match request.split_whitespace().nth(1) {
+          Some("/flaky") => {
+            flaky_calls += 1;
+            if flaky_calls == 1 {
+              "503 Service Unavailable"
+            } else {
+              "200 OK"
+            }
+          }
+          _ => "404 Not Found",
+        };
+        let response = format!(
+          "HTTP/1.1 {}\r\nretry-after: 0\r\ncontent-length: 2\r\nconnection: close\r\n\r\n{{}}",
+          status
+        );
+        socket.write_all(response.as_bytes()).await.unwrap();
+      }
+    });
+    let stories: ApixStories = serde_yaml::from_str(&format!(
+      r#"
+stories:
+  - name: errors
+    steps:
+      - name: flaky
+        request:
+          method: get
+          url: "{url}/flaky"
+        retryOn: [503]
+      - name: missing
+        request:
+          method: get
+          url: "{url}/missing"
+        assertions:
+          - status == 404
+      - name: failing
+        request:
+          method: get
+          url: "{url}/missing"
+        assertions:
+          - status == 200
+      - name: after
+        request:
+          method: get
+          url: "{url}/flaky"
+"#,
+      url = url
+    ))
+    .unwrap();
+    let mut runner = StoryRunner::new("story.yaml", Value::Null, None).unwrap();
+    let reports = runner.run(&stories, &RequestOptions::default()).await.unwrap();
+    assert_eq!(
+      statuses(&reports),
+      vec![
+        ("errors.flaky".to_string(), "200 OK".to_string()),
+        ("errors.missing".to_string(), "404 Not Found".to_string()),
+        ("errors.failing".to_string(), "Failed".to_string()),
+        ("errors.after".to_string(), "Skipped".to_string()),
+      ]
+    );
+    assert_eq!(reports[0].retries(), 1);
+    assert_eq!(reports[1].retries(), 0);
+  }
+}
@@ -0,0 +1,213 @@
+use anyhow::Result;
+use rand::RngCore;
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn to_hex(bytes: &[u8]) -> String {
+  bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn random_hex(size: usize) -> String {
+  let mut bytes = vec![0u8; size];
+  rand::thread_rng().fill_bytes(&mut bytes);
+  to_hex(&bytes)
+}
+
+fn unix_nanos(time: SystemTime) -> String {
+  time
+    .duration_since(UNIX_EPOCH)
+    .map(|duration| duration.as_nanos())
+    .unwrap_or_default()
+    .to_string()
+}
+
+// parse a w3c traceparent header value into a (trace id, parent span id) tuple
+fn parse_traceparent(traceparent: &str) -> Option<(String, String)> {
+  let parts = traceparent.trim().split('-').collect::<Vec<_>>();
+  match parts.as_slice() {
+    [version, trace_id, span_id, _flags]
+      if version.len() == 2
+        && trace_id.len() == 32
+        && span_id.len() == 16
+        && trace_id.chars().chain(span_id.chars()).all(|c| c.is_ascii_hexdigit()) =>
+    {
+      Some((trace_id.to_lowercase(), span_id.to_lowercase()))
+    }
+    _ => None,
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct Span {
+  pub span_id: String,
+  pub name: String,
+  pub start: SystemTime,
+  pub end: SystemTime,
+  pub attributes: Vec<(String, Value)>,
+  pub error: Option<String>,
+}
+
+impl Span {
+  fn to_json(&self, trace_id: &str, parent_span_id: Option<&String>) -> Value {
+    let attributes = self
+      .attributes
+      .iter()
+      .map(|(key, value)| {
+        let value = match value {
+          Value::Number(number) if number.is_i64() || number.is_u64() => json!({ "intValue": number.to_string() }),
+          Value::Number(number) => json!({ "doubleValue": number }),
+          Value::Bool(boolean) => json!({ "boolValue": boolean }),
+          Value::String(string) => json!({ "stringValue": string }),
+          other => json!({ "stringValue": other.to_string() }),
+        };
+        json!({ "key": key, "value": value })
+      })
+      .collect::<Vec<_>>();
+    let status = match &self.error {
+      Some(message) => json!({ "code": 2, "message": message }),
+      None => json!({ "code": 1 }),
+    };
+    let mut span = json!({
+      "traceId": trace_id,
+      "spanId": self.span_id,
+      "name": self.name,
+      "kind": 3,
+      "startTimeUnixNano": unix_nanos(self.start),
+      "endTimeUnixNano": unix_nanos(self.end),
+      "attributes": attributes,
+      "status": status,
+    });
+    if let Some(parent_span_id) = parent_span_id {
+      span["parentSpanId"] = json!(parent_span_id);
+    }
+    span
+  }
+}
+
+// W3C trace context shared by all requests of an apix invocation, spans are collected for OTLP export
+#[derive(Debug)]
+pub struct Telemetry {
+  trace_id: String,
+  parent_span_id: Option<String>,
+  endpoint: Option<String>,
+  spans: Mutex<Vec<Span>>,
+}
+
+impl Telemetry {
+  // continue the trace found in TRACEPARENT environment variable if any, or start a new one
+  pub fn new(endpoint: Option<String>) -> Self {
+    let (trace_id, parent_span_id) = match std::env::var("TRACEPARENT").ok().as_deref().and_then(parse_traceparent) {
+      Some((trace_id, span_id)) => (trace_id, Some(span_id)),
+      None => (random_hex(16), None),
+    };
+    Self {
+      trace_id,
+      parent_span_id,
+      endpoint,
+      spans: Mutex::new(Vec::new()),
+    }
+  }
+
+  pub fn new_span_id(&self) -> String {
+    random_hex(8)
+  }
+
+  pub fn traceparent(&self, span_id: &str) -> String {
+    format!("00-{}-{}-01", self.trace_id, span_id)
+  }
+
+  pub fn record(&self, span: Span) {
+    self.spans.lock().unwrap().push(span);
+  }
+
+  pub fn to_otlp(&self) -> Value {
+    let spans = self
+      .spans
+      .lock()
+      .unwrap()
+      .iter()
+      .map(|span| span.to_json(&self.trace_id, self.parent_span_id.as_ref()))
+      .collect::<Vec<_>>();
+    json!({
+      "resourceSpans": [{
+        "resource": {
+          "attributes": [{ "key": "service.name", "value": { "stringValue": env!("CARGO_PKG_NAME") } }]
+        },
+        "scopeSpans": [{
+          "scope": { "name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION") },
+          "spans": spans,
+        }]
+      }]
+    })
+  }
+
+  // export recorded spans with OTLP/HTTP json protocol
+  pub async fn export(&self) -> Result<()> {
+    if let Some(endpoint) = &self.endpoint {
+      if self.spans.lock().unwrap().is_empty() {
+        return Ok(());
+      }
+      let url = format!("{}/v1/traces", endpoint.trim_end_matches('/'));
+      let response = Client::new()
+        .post(&url)
+        .json(&self.to_otlp())
+        .send()
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to export traces to '{}'\ncause: {}", url, e))?;
+      if !response.status().is_success() {
+        return Err(anyhow::anyhow!(
+          "Failed to export traces to '{}'\ncause: collector answered {}",
+          url,
+          response.status()
+        ));
+      }
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use test_case::test_case;
+
+  #[test_case("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01" => Some(("4bf92f3577b34da6a3ce929d0e0e4736".to_string(), "00f067aa0ba902b7".to_string())))]
+  #[test_case("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7" => None)]
+  #[test_case("00-xyz-00f067aa0ba902b7-01" => None)]
+  fn test_parse_traceparent(traceparent: &str) -> Option<(String, String)> {
+    parse_traceparent(traceparent)
+  }
+
+  #[test]
+  fn test_traceparent() {
+    let telemetry = Telemetry::new(None);
+    let span_id = telemetry.new_span_id();
+    let traceparent = telemetry.traceparent(&span_id);
+    let (trace_id, parsed_span_id) = parse_traceparent(&traceparent).unwrap();
+    assert_eq!(trace_id, telemetry.trace_id);
+    assert_eq!(parsed_span_id, span_id);
+  }
+
+  #[test]
+  fn test_to_otlp() {
+    let telemetry = Telemetry::new(None);
+    telemetry.record(Span {
+      span_id: "00f067aa0ba902b7".to_string(),
+      name: "GET /users".to_string(),
+      start: UNIX_EPOCH,
+      end: UNIX_EPOCH + std::time::Duration::from_millis(5),
+      attributes: vec![("http.status_code".to_string(), json!(200))],
+      error: None,
+    });
+    let span = &telemetry.to_otlp()["resourceSpans"][0]["scopeSpans"][0]["spans"][0];
+    assert_eq!(span["spanId"], json!("00f067aa0ba902b7"));
+    assert_eq!(span["endTimeUnixNano"], json!("5000000"));
+    assert_eq!(
+      span["attributes"][0],
+      json!({ "key": "http.status_code", "value": { "intValue": "200" } })
+    );
+    assert_eq!(span["status"], json!({ "code": 1 }));
+  }
+}
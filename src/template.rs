@@ -1,6 +1,99 @@
+use super::manifests::{ApixManifest, PROJECT_DIR};
+use super::tokens::AuthTokenFunction;
 use indexmap::IndexMap;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
 use serde_json::Value;
-use tera::{Context, Error, Tera};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tera::{Context, Error, Filter, Tera};
+
+// suffix of url template names, values substituted in them are url encoded when automatic encoding is enabled
+pub static URL_TEMPLATE_SUFFIX: &str = "#/url";
+
+// RFC 3986 unreserved characters are the only ones kept as is
+const URLSAFE: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'.').remove(b'_').remove(b'~');
+
+// percent encode a value so it can be used as a single url path segment or query value
+pub fn urlsafe(value: &str) -> String {
+  utf8_percent_encode(value, URLSAFE).to_string()
+}
+
+// `urlsafe` filter, its output is never encoded again by automatic encoding
+struct UrlSafeFilter;
+
+impl Filter for UrlSafeFilter {
+  fn filter(&self, value: &Value, _: &HashMap<String, Value>) -> Result<Value, Error> {
+    match value {
+      Value::String(value) => Ok(Value::String(urlsafe(value))),
+      value => Ok(Value::String(urlsafe(&value.to_string()))),
+    }
+  }
+
+  fn is_safe(&self) -> bool {
+    true
+  }
+}
+
+// template engine of requests with apix functions and filters, when `urlsafe_urls` is set, values substituted in
+// url templates are url encoded unless marked with the `safe` filter
+pub fn request_engine(urlsafe_urls: bool) -> Tera {
+  let mut engine = Tera::default();
+  engine.register_function("auth_token", AuthTokenFunction);
+  engine.register_filter("urlsafe", UrlSafeFilter);
+  if urlsafe_urls {
+    engine.autoescape_on(vec![URL_TEMPLATE_SUFFIX]);
+    engine.set_escape_fn(urlsafe);
+  } else {
+    engine.autoescape_on(vec![]);
+  }
+  engine
+}
+
+// shared templates of the project, `templates/**/*.tera` in current and project directories, named after their path
+// in the templates directory so manifests can `{% include "pagination.tera" %}` or `{% import "macros.tera" as m %}`
+// template libraries manifests are registered too, their templates are named `<library>/<template>`
+pub fn register_shared_templates(engine: &mut Tera) -> anyhow::Result<()> {
+  for dir in [PathBuf::from("templates"), Path::new(PROJECT_DIR).join("templates")] {
+    add_templates_dir(engine, &dir)?;
+  }
+  let libraries = ApixManifest::find_manifests_by_kind("templatelibrary")?.map(|(_, manifest)| manifest);
+  add_template_libraries(engine, libraries)
+}
+
+fn add_template_libraries(engine: &mut Tera, libraries: impl Iterator<Item = ApixManifest>) -> anyhow::Result<()> {
+  let templates = libraries.flat_map(|library| library.templates()).collect::<Vec<_>>();
+  if templates.is_empty() {
+    return Ok(());
+  }
+  engine
+    .add_raw_templates(templates)
+    .map_err(|e| anyhow::anyhow!("Invalid template library\ncause: {:#}", e))
+}
+
+fn add_templates_dir(engine: &mut Tera, dir: &Path) -> anyhow::Result<()> {
+  if !dir.is_dir() {
+    return Ok(());
+  }
+  let files = globwalk::GlobWalkerBuilder::from_patterns(dir, &["**/*.tera"])
+    .build()?
+    .filter_map(|entry| entry.ok())
+    .filter(|entry| entry.file_type().is_file())
+    .map(|entry| {
+      let name = entry
+        .path()
+        .strip_prefix(dir)
+        .unwrap_or_else(|_| entry.path())
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+      (entry.path().to_path_buf(), Some(name))
+    })
+    .collect::<Vec<_>>();
+  engine
+    .add_template_files(files)
+    .map_err(|e| anyhow::anyhow!("Invalid shared template in {:?}\ncause: {:#}", dir, e))
+}
 
 pub trait ValueTemplate {
   fn render_value(&mut self, name: &str, value: &Value, context: &Context) -> Result<Value, Error>;
@@ -27,6 +120,7 @@ impl ValueTemplate for Tera {
         Ok(Value::Array(new_arr))
       }
       Value::String(content) => {
+        tracing::debug!(template = name, "render template");
         self.add_raw_template(name, content)?;
         let new_content = self.render(name, context)?;
         Ok(Value::String(new_content))
@@ -55,6 +149,7 @@ impl MapTemplate for Tera {
     let mut new_map = IndexMap::new();
     for (key, val) in map {
       let template_name = format!("{}.{}", name, key);
+      tracing::debug!(template = template_name.as_str(), "render template");
       self.add_raw_template(&template_name, val)?;
       let new_content = self.render(&template_name, context)?;
       new_map.insert(key.clone(), new_content);
@@ -69,16 +164,30 @@ pub trait StringTemplate {
 
 impl StringTemplate for Tera {
   fn render_string(&mut self, name: &str, content: &str, context: &Context) -> Result<String, Error> {
+    tracing::debug!(template = name, "render template");
     self.add_raw_template(name, content)?;
     self.render(name, context)
   }
 }
 
+pub trait ConditionTemplate {
+  fn render_condition(&mut self, name: &str, content: &str, context: &Context) -> Result<bool, Error>;
+}
+
+impl ConditionTemplate for Tera {
+  // render a template used as a condition, empty, `false`, `0` and `null` results are falsy
+  fn render_condition(&mut self, name: &str, content: &str, context: &Context) -> Result<bool, Error> {
+    let rendered = self.render_string(name, content, context)?;
+    Ok(!matches!(rendered.trim(), "" | "false" | "0" | "null"))
+  }
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
   use serde_json::json;
   use tera::{Context, Tera};
+  use test_case::test_case;
 
   #[test]
   fn test_render_value_object() {
@@ -134,4 +243,102 @@ mod tests {
       .expect("render string");
     assert_eq!(rendered, "test test");
   }
+
+  #[test]
+  fn test_render_condition() {
+    let mut tera = Tera::default();
+    let mut context = Context::new();
+    context.insert(
+      "steps",
+      &json!({ "login": { "response": { "status": 200, "body": { "token": "abc", "empty": "" } } } }),
+    );
+    let mut condition = |content: &str| tera.render_condition("test", content, &context).unwrap();
+    assert!(condition(
+      "{{ steps.login.response.status == 200 and steps.login.response.body.token }}"
+    ));
+    assert!(condition("{{ steps.login.response.body.token }}"));
+    assert!(!condition("{{ steps.login.response.status != 200 }}"));
+    assert!(!condition("{{ steps.login.response.body.empty }}"));
+  }
+
+  #[test_case("https://api.example.com/users/{{ id | urlsafe }}", false => "https://api.example.com/users/a%2Fb%20%C3%A9" ; "explicit filter")]
+  #[test_case("https://api.example.com/users/{{ id }}", false => "https://api.example.com/users/a/b é" ; "raw value")]
+  #[test_case("{{ base | safe }}/users/{{ id }}", true => "https://api.example.com/users/a%2Fb%20%C3%A9" ; "automatic encoding")]
+  #[test_case("{{ base | safe }}/users/{{ id | urlsafe }}", true => "https://api.example.com/users/a%2Fb%20%C3%A9" ; "no double encoding")]
+  fn test_urlsafe_url(url: &str, urlsafe_urls: bool) -> String {
+    let mut tera = request_engine(urlsafe_urls);
+    let mut context = Context::new();
+    context.insert("base", "https://api.example.com");
+    context.insert("id", "a/b é");
+    tera
+      .render_string(&format!("request.yaml{}", URL_TEMPLATE_SUFFIX), url, &context)
+      .unwrap()
+  }
+
+  #[test]
+  fn test_shared_templates() {
+    let dir = std::env::temp_dir().join(format!("apix-templates-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("macros")).unwrap();
+    std::fs::write(dir.join("pagination.tera"), "page={{ page }}&size=20").unwrap();
+    std::fs::write(
+      dir.join("macros").join("users.tera"),
+      "{% macro user(name) %}{\"name\": \"{{ name }}\"}{% endmacro user %}",
+    )
+    .unwrap();
+    let mut tera = request_engine(false);
+    add_templates_dir(&mut tera, &dir).unwrap();
+    let mut context = Context::new();
+    context.insert("page", &2);
+    context.insert("name", "jane");
+    let url = tera
+      .render_string(
+        "request.yaml#/url",
+        "/users?{% include \"pagination.tera\" %}",
+        &context,
+      )
+      .unwrap();
+    assert_eq!(url, "/users?page=2&size=20");
+    let body = tera
+      .render_string(
+        "request.yaml#/body",
+        "{% import \"macros/users.tera\" as users %}{{ users::user(name=name) }}",
+        &context,
+      )
+      .unwrap();
+    assert_eq!(body, "{\"name\": \"jane\"}");
+    std::fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn test_template_libraries() {
+    let library: ApixManifest = serde_yaml::from_str(
+      r#"
+apiVersion: apix.io/v1
+kind: TemplateLibrary
+metadata:
+  name: common
+spec:
+  templates:
+    audit: '"audit": {"user": "{{ user }}"}'
+    filters: '{% macro page(size=20) %}page=1&size={{ size }}{% endmacro page %}'
+"#,
+    )
+    .unwrap();
+    let mut tera = request_engine(false);
+    add_template_libraries(&mut tera, std::iter::once(library)).unwrap();
+    let mut context = Context::new();
+    context.insert("user", "jane");
+    let body = tera
+      .render_string("request.yaml#/body", "{ {% include \"common/audit\" %} }", &context)
+      .unwrap();
+    assert_eq!(body, "{ \"audit\": {\"user\": \"jane\"} }");
+    let url = tera
+      .render_string(
+        "request.yaml#/url",
+        "{% import \"common/filters\" as filters %}/users?{{ filters::page(size=50) }}",
+        &context,
+      )
+      .unwrap();
+    assert_eq!(url, "/users?page=1&size=50");
+  }
 }
@@ -0,0 +1,394 @@
+use super::manifests::ApixConfiguration;
+use crate::paths::{lock_file, restrict_dir, state_dir, write_atomic, FileLock, PRIVATE_FILE_MODE};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+// tokens expiring in less than this many seconds are refreshed before use
+static EXPIRY_MARGIN: i64 = 30;
+
+// what identifies a token: the issuer delivering it, for an audience and scope, to a client
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TokenRequest {
+  pub issuer: String,
+  pub audience: Option<String>,
+  pub scope: Option<String>,
+  pub client_id: Option<String>,
+  pub client_secret: Option<String>,
+}
+
+impl TokenRequest {
+  // client credentials default to `oidc-client-id` and `oidc-client-secret` configuration values
+  pub fn new(
+    issuer: &str,
+    audience: Option<&str>,
+    scope: Option<&str>,
+    client_id: Option<&str>,
+    client_secret: Option<&str>,
+  ) -> Self {
+    let config = |name: &str| ApixConfiguration::once().get(name).cloned();
+    Self {
+      issuer: issuer.trim_end_matches('/').to_string(),
+      audience: audience.map(str::to_string),
+      scope: scope.map(str::to_string),
+      client_id: client_id.map(str::to_string).or_else(|| config("oidc-client-id")),
+      client_secret: client_secret
+        .map(str::to_string)
+        .or_else(|| config("oidc-client-secret")),
+    }
+  }
+
+  fn key(&self) -> String {
+    let key = format!(
+      "{}|{}|{}|{}",
+      self.issuer,
+      self.audience.as_deref().unwrap_or_default(),
+      self.scope.as_deref().unwrap_or_default(),
+      self.client_id.as_deref().unwrap_or_default()
+    );
+    format!("{:x}", Sha256::digest(key.as_bytes()))
+  }
+
+  fn credentials(&self) -> Vec<(&'static str, String)> {
+    let mut form = Vec::new();
+    if let Some(client_id) = &self.client_id {
+      form.push(("client_id", client_id.clone()));
+    }
+    if let Some(client_secret) = &self.client_secret {
+      form.push(("client_secret", client_secret.clone()));
+    }
+    form
+  }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CachedToken {
+  pub issuer: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub audience: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub scope: Option<String>,
+  token_endpoint: String,
+  access_token: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  refresh_token: Option<String>,
+  // unix timestamp, tokens without expiration are valid until cleared
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub expires_at: Option<i64>,
+}
+
+impl CachedToken {
+  pub fn is_valid(&self, now: i64) -> bool {
+    self
+      .expires_at
+      .is_none_or(|expires_at| expires_at - EXPIRY_MARGIN > now)
+  }
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+  access_token: String,
+  refresh_token: Option<String>,
+  expires_in: Option<i64>,
+}
+
+// tokens cached per issuer, audience, scope and client under user state directory (`$XDG_STATE_HOME/apix/tokens` on linux)
+pub struct TokenStore {
+  dir: PathBuf,
+}
+
+impl TokenStore {
+  pub fn open() -> Result<Self> {
+    let dir = state_dir("tokens")?;
+    restrict_dir(&dir)?;
+    Ok(Self { dir })
+  }
+
+  fn path(&self, request: &TokenRequest) -> PathBuf {
+    self.dir.join(format!("{}.json", request.key()))
+  }
+
+  // refresh tokens may be rotated on use, so only one apix process at a time gets a token for a request
+  fn lock(&self, request: &TokenRequest) -> Result<FileLock> {
+    lock_file(&self.path(request))
+  }
+
+  fn load(&self, request: &TokenRequest) -> Option<CachedToken> {
+    let content = std::fs::read_to_string(self.path(request)).ok()?;
+    serde_json::from_str(&content).ok()
+  }
+
+  fn save(&self, request: &TokenRequest, token: &CachedToken) -> Result<()> {
+    let path = self.path(request);
+    // tokens are credentials, only the user should be able to read them
+    write_atomic(
+      &path,
+      serde_json::to_string(token)?.as_bytes(),
+      false,
+      Some(PRIVATE_FILE_MODE),
+    )
+    .map_err(|e| anyhow::anyhow!("Failed to save token {:?}\ncause: {}", &path, e))
+  }
+
+  pub fn list(&self) -> Result<Vec<CachedToken>> {
+    let mut tokens = std::fs::read_dir(&self.dir)?
+      .filter_map(|entry| entry.ok())
+      .filter_map(|entry| std::fs::read_to_string(entry.path()).ok())
+      .filter_map(|content| serde_json::from_str::<CachedToken>(&content).ok())
+      .collect::<Vec<_>>();
+    tokens.sort_by(|a, b| a.issuer.cmp(&b.issuer).then(a.audience.cmp(&b.audience)));
+    Ok(tokens)
+  }
+
+  pub fn clear(&self) -> Result<usize> {
+    let mut count = 0;
+    for entry in std::fs::read_dir(&self.dir)?
+      .filter_map(|entry| entry.ok())
+      .filter(|entry| entry.path().extension().is_some_and(|extension| extension == "json"))
+    {
+      std::fs::remove_file(entry.path())?;
+      count += 1;
+    }
+    Ok(count)
+  }
+}
+
+// find token endpoint of issuer with OpenID Connect discovery
+async fn discover_token_endpoint(client: &reqwest::Client, issuer: &str) -> Result<String> {
+  let url = format!("{}/.well-known/openid-configuration", issuer);
+  let configuration = client
+    .get(&url)
+    .send()
+    .await
+    .and_then(|response| response.error_for_status())
+    .map_err(|e| anyhow::anyhow!("OIDC discovery failed for {}\ncause: {}", issuer, e))?
+    .json::<Value>()
+    .await?;
+  configuration["token_endpoint"]
+    .as_str()
+    .map(str::to_string)
+    .ok_or_else(|| {
+      anyhow::anyhow!(
+        "OIDC discovery failed for {}\ncause: no token_endpoint in {}",
+        issuer,
+        url
+      )
+    })
+}
+
+async fn request_token(
+  client: &reqwest::Client,
+  endpoint: &str,
+  form: &[(&'static str, String)],
+) -> Result<TokenResponse> {
+  let response = client
+    .post(endpoint)
+    .form(form)
+    .send()
+    .await
+    .map_err(|e| anyhow::anyhow!("Token request to {} failed\ncause: {}", endpoint, e))?;
+  let status = response.status();
+  if !status.is_success() {
+    let body = response.text().await.unwrap_or_default();
+    return Err(anyhow::anyhow!(
+      "Token request to {} failed\ncause: {} {}",
+      endpoint,
+      status,
+      body
+    ));
+  }
+  Ok(response.json::<TokenResponse>().await?)
+}
+
+fn to_cached_token(request: &TokenRequest, endpoint: String, response: TokenResponse, now: i64) -> CachedToken {
+  CachedToken {
+    issuer: request.issuer.clone(),
+    audience: request.audience.clone(),
+    scope: request.scope.clone(),
+    token_endpoint: endpoint,
+    access_token: response.access_token,
+    refresh_token: response.refresh_token,
+    expires_at: response.expires_in.map(|expires_in| now + expires_in),
+  }
+}
+
+// cached token if still valid, else a refreshed one, else a new one from client credentials grant
+pub async fn get_token(request: &TokenRequest) -> Result<String> {
+  let store = TokenStore::open()?;
+  let _lock = store.lock(request)?;
+  let cached = store.load(request);
+  let now = chrono::Utc::now().timestamp();
+  if let Some(token) = cached.as_ref().filter(|token| token.is_valid(now)) {
+    return Ok(token.access_token.clone());
+  }
+  let client = reqwest::Client::new();
+  if let Some(CachedToken {
+    token_endpoint,
+    refresh_token: Some(refresh_token),
+    ..
+  }) = &cached
+  {
+    let mut form = vec![
+      ("grant_type", "refresh_token".to_string()),
+      ("refresh_token", refresh_token.clone()),
+    ];
+    form.extend(request.credentials());
+    // an expired or revoked refresh token falls back to a new client credentials grant
+    if let Ok(response) = request_token(&client, token_endpoint, &form).await {
+      let mut token = to_cached_token(request, token_endpoint.clone(), response, now);
+      token.refresh_token = token.refresh_token.or_else(|| Some(refresh_token.clone()));
+      store.save(request, &token)?;
+      return Ok(token.access_token);
+    }
+  }
+  let endpoint = match cached {
+    Some(token) => token.token_endpoint,
+    None => discover_token_endpoint(&client, &request.issuer).await?,
+  };
+  let mut form = vec![("grant_type", "client_credentials".to_string())];
+  form.extend(request.credentials());
+  if let Some(audience) = &request.audience {
+    form.push(("audience", audience.clone()));
+  }
+  if let Some(scope) = &request.scope {
+    form.push(("scope", scope.clone()));
+  }
+  let token = to_cached_token(
+    request,
+    endpoint.clone(),
+    request_token(&client, &endpoint, &form).await?,
+    now,
+  );
+  store.save(request, &token)?;
+  Ok(token.access_token)
+}
+
+// templates are rendered synchronously, so the token is fetched on its own runtime
+pub fn get_token_blocking(request: TokenRequest) -> Result<String> {
+  let issuer = request.issuer.clone();
+  std::thread::spawn(move || {
+    tokio::runtime::Builder::new_current_thread()
+      .enable_all()
+      .build()?
+      .block_on(get_token(&request))
+  })
+  .join()
+  .map_err(|_| anyhow::anyhow!("Failed to fetch token from {}", issuer))?
+}
+
+// `apix tokens` subcommands
+pub async fn handle_tokens(matches: &clap::ArgMatches) -> Result<()> {
+  match matches.subcommand() {
+    Some(("get", matches)) => {
+      if let Some(issuer) = matches.value_of("issuer") {
+        let request = TokenRequest::new(
+          issuer,
+          matches.value_of("audience"),
+          matches.value_of("scope"),
+          matches.value_of("client-id"),
+          matches.value_of("client-secret"),
+        );
+        println!("{}", get_token(&request).await?);
+      }
+    }
+    Some(("list", _)) => {
+      let now = chrono::Utc::now().timestamp();
+      for token in TokenStore::open()?.list()? {
+        let status = match token.expires_at {
+          Some(expires_at) if expires_at <= now => format!("expired since {}s", now - expires_at),
+          Some(expires_at) => format!("expires in {}s", expires_at - now),
+          None => "no expiration".to_string(),
+        };
+        println!(
+          "{} audience: {} scope: {} ({})",
+          token.issuer,
+          token.audience.as_deref().unwrap_or("-"),
+          token.scope.as_deref().unwrap_or("-"),
+          status
+        );
+      }
+    }
+    Some(("clear", _)) => {
+      let count = TokenStore::open()?.clear()?;
+      println!("{} cached token(s) removed", count);
+    }
+    _ => {}
+  }
+  Ok(())
+}
+
+// tera function `auth_token(issuer="https://...", audience="...", scope="...")` rendering an access token
+pub struct AuthTokenFunction;
+
+impl tera::Function for AuthTokenFunction {
+  fn call(&self, args: &HashMap<String, Value>) -> tera::Result<Value> {
+    let arg = |name: &str| args.get(name).and_then(Value::as_str);
+    let issuer = arg("issuer").ok_or_else(|| tera::Error::msg("auth_token requires an `issuer` argument"))?;
+    let request = TokenRequest::new(
+      issuer,
+      arg("audience"),
+      arg("scope"),
+      arg("client_id"),
+      arg("client_secret"),
+    );
+    let token = get_token_blocking(request).map_err(|e| tera::Error::msg(format!("{:#}", e)))?;
+    Ok(Value::String(token))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use test_case::test_case;
+
+  fn token(expires_at: Option<i64>) -> CachedToken {
+    CachedToken {
+      issuer: "https://issuer".to_string(),
+      audience: None,
+      scope: None,
+      token_endpoint: "https://issuer/token".to_string(),
+      access_token: "token".to_string(),
+      refresh_token: None,
+      expires_at,
+    }
+  }
+
+  #[test_case(None => true ; "no expiration")]
+  #[test_case(Some(2000) => true ; "expires later")]
+  #[test_case(Some(1020) => false ; "expires within margin")]
+  #[test_case(Some(900) => false ; "expired")]
+  fn test_token_is_valid(expires_at: Option<i64>) -> bool {
+    token(expires_at).is_valid(1000)
+  }
+
+  #[test]
+  fn test_token_request_key() {
+    let request = TokenRequest::new("https://issuer/", Some("api"), None, Some("cli"), Some("secret"));
+    assert_eq!(request.issuer, "https://issuer");
+    assert_eq!(
+      request.key(),
+      TokenRequest::new("https://issuer", Some("api"), None, Some("cli"), Some("other")).key()
+    );
+    assert_ne!(
+      request.key(),
+      TokenRequest::new("https://issuer", Some("admin"), None, Some("cli"), None).key()
+    );
+  }
+
+  #[test]
+  fn test_to_cached_token() {
+    let request = TokenRequest::new("https://issuer", Some("api"), None, Some("cli"), None);
+    let response = TokenResponse {
+      access_token: "access".to_string(),
+      refresh_token: Some("refresh".to_string()),
+      expires_in: Some(3600),
+    };
+    let token = to_cached_token(&request, "https://issuer/token".to_string(), response, 1000);
+    assert_eq!(token.expires_at, Some(4600));
+    assert_eq!(token.audience.as_deref(), Some("api"));
+  }
+}
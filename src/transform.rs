@@ -0,0 +1,68 @@
+use super::requests::CapturedResponse;
+use anyhow::Result;
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+use tera::{Context, Tera};
+
+// manifest `transform` template, rendered with the request context and the parsed `response`
+// example: `{{ response.body.items | map(attribute="name") | json_encode() }}`
+#[derive(Debug, Clone)]
+pub struct ResponseTransform {
+  engine: Tera,
+  context: Context,
+  name: String,
+}
+
+impl ResponseTransform {
+  pub fn new(mut engine: Tera, context: Context, name: String, template: &str) -> Result<Self> {
+    engine
+      .add_raw_template(&name, template)
+      .map_err(|e| anyhow::anyhow!("Invalid transform {}\ncause: {:#}", name, e))?;
+    Ok(Self { engine, context, name })
+  }
+
+  // transformed body, json outputs are pretty printed
+  pub fn apply(&self, status: StatusCode, headers: &HeaderMap, body: &str) -> Result<String> {
+    let response = CapturedResponse {
+      status,
+      headers: headers.clone(),
+      body: body.to_string(),
+    };
+    let mut context = self.context.clone();
+    context.insert("response", &response.to_json());
+    let transformed = self
+      .engine
+      .render(&self.name, &context)
+      .map_err(|e| anyhow::anyhow!("Failed to transform response with {}\ncause: {:#}", self.name, e))?;
+    Ok(match serde_json::from_str::<serde_json::Value>(&transformed) {
+      Ok(value) => serde_json::to_string_pretty(&value)?,
+      Err(_) => transformed,
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use test_case::test_case;
+
+  static BODY: &str = r#"{"items": [{"name": "Apix", "id": 1}, {"name": "Rust", "id": 2}], "total": 2}"#;
+
+  #[test_case(r#"{{ response.body.items | map(attribute="name") | json_encode() }}"# => "[\n  \"Apix\",\n  \"Rust\"\n]" ; "json")]
+  #[test_case("{{ response.status }}: {{ response.body.total }} items" => "200: 2 items" ; "text")]
+  #[test_case("{{ parameters.prefix }}{{ response.body.items.0.name }}" => "my Apix" ; "request context")]
+  fn test_transform(template: &str) -> String {
+    let mut context = Context::new();
+    context.insert("parameters", &serde_json::json!({ "prefix": "my " }));
+    let transform = ResponseTransform::new(Tera::default(), context, "test#/transform".to_string(), template).unwrap();
+    transform.apply(StatusCode::OK, &HeaderMap::new(), BODY).unwrap()
+  }
+
+  #[test]
+  fn test_transform_errors() {
+    assert!(ResponseTransform::new(Tera::default(), Context::new(), "test".to_string(), "{{ response").is_err());
+    let transform =
+      ResponseTransform::new(Tera::default(), Context::new(), "test".to_string(), "{{ missing }}").unwrap();
+    assert!(transform.apply(StatusCode::OK, &HeaderMap::new(), BODY).is_err());
+  }
+}
@@ -0,0 +1,221 @@
+use anyhow::Result;
+use reqwest::Client;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::io::Read;
+use std::path::Path;
+
+static RELEASES_URL: &str = "https://api.github.com/repos/ecyrbe/apix-rust/releases/latest";
+static APP_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"),);
+static BINARY_NAME: &str = if cfg!(windows) { "apix.exe" } else { "apix" };
+
+#[derive(Debug, Clone, Deserialize)]
+struct ReleaseAsset {
+  name: String,
+  browser_download_url: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Release {
+  tag_name: String,
+  assets: Vec<ReleaseAsset>,
+}
+
+impl Release {
+  fn asset(&self, name: &str) -> Option<&ReleaseAsset> {
+    self.assets.iter().find(|asset| asset.name == name)
+  }
+}
+
+// target triple used by release artifacts for the running platform
+fn release_target() -> Result<String> {
+  let platform = match std::env::consts::OS {
+    "linux" => "unknown-linux-gnu",
+    "macos" => "apple-darwin",
+    "windows" if cfg!(target_env = "gnu") => "pc-windows-gnu",
+    "windows" => "pc-windows-msvc",
+    os => return Err(anyhow::anyhow!("No apix release available for platform {}", os)),
+  };
+  Ok(format!("{}-{}", std::env::consts::ARCH, platform))
+}
+
+fn asset_name(tag: &str, target: &str) -> String {
+  let extension = if target.contains("windows") { "zip" } else { "tar.gz" };
+  format!("apix-{}-{}.{}", tag, target, extension)
+}
+
+fn parse_version(version: &str) -> Vec<u64> {
+  version
+    .trim_start_matches('v')
+    .split(['.', '-'])
+    .map_while(|part| part.parse().ok())
+    .collect()
+}
+
+// compare release versions numerically, `v` prefix is ignored
+fn is_newer(latest: &str, current: &str) -> bool {
+  parse_version(latest) > parse_version(current)
+}
+
+// checksum files use sha256sum format: `<hex digest>  <file name>`
+fn verify_checksum(content: &[u8], checksum_file: &str) -> Result<()> {
+  let expected = checksum_file
+    .split_whitespace()
+    .next()
+    .ok_or_else(|| anyhow::anyhow!("Empty checksum file"))?;
+  let actual = format!("{:x}", Sha256::digest(content));
+  if expected.eq_ignore_ascii_case(&actual) {
+    Ok(())
+  } else {
+    Err(anyhow::anyhow!(
+      "Checksum mismatch for downloaded release\nexpected: {}\nactual: {}",
+      expected,
+      actual
+    ))
+  }
+}
+
+fn extract_binary(archive_name: &str, content: &[u8]) -> Result<Vec<u8>> {
+  let mut binary = Vec::new();
+  if archive_name.ends_with(".zip") {
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(content))?;
+    archive.by_name(BINARY_NAME)?.read_to_end(&mut binary)?;
+    return Ok(binary);
+  }
+  let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(content));
+  for entry in archive.entries()? {
+    let mut entry = entry?;
+    if entry.path()?.file_name() == Some(std::ffi::OsStr::new(BINARY_NAME)) {
+      entry.read_to_end(&mut binary)?;
+      return Ok(binary);
+    }
+  }
+  Err(anyhow::anyhow!(
+    "Release archive {} does not contain {}",
+    archive_name,
+    BINARY_NAME
+  ))
+}
+
+// replace executable by renaming a sibling file over it, so a failure never leaves a partial binary
+fn replace_executable(executable: &Path, binary: &[u8]) -> Result<()> {
+  let new_path = executable.with_extension("new");
+  std::fs::write(&new_path, binary)
+    .map_err(|e| anyhow::anyhow!("Failed to write new executable {:?}\ncause: {}", &new_path, e))?;
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(&new_path, std::fs::Permissions::from_mode(0o755))?;
+  }
+  #[cfg(windows)]
+  {
+    // windows can't overwrite a running executable, but it can rename it
+    // the `.old` binary left by a previous update is removed first, as renaming can't replace it
+    let old_path = executable.with_extension("old");
+    match std::fs::remove_file(&old_path) {
+      Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+        return Err(anyhow::anyhow!(
+          "Failed to remove previous executable {:?}\ncause: {}",
+          &old_path,
+          e
+        ))
+      }
+      _ => {}
+    }
+    std::fs::rename(executable, &old_path)
+      .map_err(|e| anyhow::anyhow!("Failed to move running executable {:?}\ncause: {}", executable, e))?;
+  }
+  std::fs::rename(&new_path, executable)
+    .map_err(|e| anyhow::anyhow!("Failed to replace executable {:?}\ncause: {}", executable, e))
+}
+
+async fn download(client: &Client, url: &str) -> Result<Vec<u8>> {
+  let response = client.get(url).send().await?.error_for_status()?;
+  Ok(response.bytes().await?.to_vec())
+}
+
+pub async fn handle_self_update(check_only: bool) -> Result<()> {
+  let client = Client::builder().user_agent(APP_USER_AGENT).build()?;
+  let release: Release = client
+    .get(RELEASES_URL)
+    .header("accept", "application/vnd.github.v3+json")
+    .send()
+    .await?
+    .error_for_status()
+    .map_err(|e| anyhow::anyhow!("Failed to fetch latest apix release\ncause: {}", e))?
+    .json()
+    .await?;
+  let current = env!("CARGO_PKG_VERSION");
+  if !is_newer(&release.tag_name, current) {
+    println!("apix {} is up to date", current);
+    return Ok(());
+  }
+  let name = asset_name(&release.tag_name, &release_target()?);
+  let asset = release
+    .asset(&name)
+    .ok_or_else(|| anyhow::anyhow!("Release {} has no artifact {}", &release.tag_name, &name))?;
+  let checksum_name = format!("{}.sha256", name);
+  let checksum = release.asset(&checksum_name).ok_or_else(|| {
+    anyhow::anyhow!(
+      "Release {} has no checksum {}, refusing to update",
+      &release.tag_name,
+      &checksum_name
+    )
+  })?;
+  if check_only {
+    println!("apix {} is available (current {})", release.tag_name, current);
+    println!("download: {}", asset.browser_download_url);
+    return Ok(());
+  }
+  let content = download(&client, &asset.browser_download_url).await?;
+  let checksum = String::from_utf8(download(&client, &checksum.browser_download_url).await?)?;
+  verify_checksum(&content, &checksum)?;
+  let binary = extract_binary(&name, &content)?;
+  replace_executable(&std::env::current_exe()?, &binary)?;
+  println!("apix updated from {} to {}", current, release.tag_name);
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use test_case::test_case;
+
+  #[test_case("v0.7.0", "0.6.0" => true)]
+  #[test_case("v0.6.0", "0.6.0" => false)]
+  #[test_case("v0.5.9", "0.6.0" => false)]
+  #[test_case("v0.10.0", "0.9.1" => true)]
+  #[test_case("v1.0.0-beta", "0.9.1" => true)]
+  fn test_is_newer(latest: &str, current: &str) -> bool {
+    is_newer(latest, current)
+  }
+
+  #[test_case("v0.7.0", "x86_64-unknown-linux-gnu" => "apix-v0.7.0-x86_64-unknown-linux-gnu.tar.gz")]
+  #[test_case("v0.7.0", "x86_64-pc-windows-msvc" => "apix-v0.7.0-x86_64-pc-windows-msvc.zip")]
+  fn test_asset_name(tag: &str, target: &str) -> String {
+    asset_name(tag, target)
+  }
+
+  #[test]
+  fn test_verify_checksum() {
+    let checksum = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824  hello.tar.gz\n";
+    assert!(verify_checksum(b"hello", checksum).is_ok());
+    assert!(verify_checksum(b"hello!", checksum).is_err());
+  }
+
+  #[test]
+  fn test_extract_binary() {
+    let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+      Vec::new(),
+      flate2::Compression::default(),
+    ));
+    for (name, content) in [("README.md", "readme"), (BINARY_NAME, "binary")] {
+      let mut header = tar::Header::new_gnu();
+      header.set_size(content.len() as u64);
+      header.set_cksum();
+      builder.append_data(&mut header, name, content.as_bytes()).unwrap();
+    }
+    let archive = builder.into_inner().unwrap().finish().unwrap();
+    assert_eq!(extract_binary("apix.tar.gz", &archive).unwrap(), b"binary");
+  }
+}
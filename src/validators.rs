@@ -1,11 +1,19 @@
+use super::http_utils::{parse_date, range_header};
+use super::manifests::ApixManifest;
 use super::match_params::RequestParam;
+use super::template::register_shared_templates;
 use anyhow::Result;
+use jsonschema::{Draft, JSONSchema};
 use once_cell::sync::Lazy;
 use regex::Regex;
+use serde_json::Value;
+use std::error::Error;
+use tera::Tera;
 use url::Url;
 
+// internationalized hosts are converted to punycode and non-ASCII paths are percent encoded when parsing
 pub fn validate_url(str_url: &str) -> Result<Url> {
-  let url = Url::parse(str_url)?;
+  let url = Url::parse(str_url).map_err(|e| anyhow::anyhow!("Invalid url {}\ncause: {}", str_url, e))?;
   if !["https", "http"].contains(&url.scheme()) {
     Err(anyhow::anyhow!("Apix only supports http(s) protocols for now",))
   } else {
@@ -13,6 +21,22 @@ pub fn validate_url(str_url: &str) -> Result<Url> {
   }
 }
 
+// values holding 'Tera' expressions are rendered before being used
+pub fn is_template(value: &str) -> bool {
+  value.contains("{{") || value.contains("{%")
+}
+
+// templated urls can only be parsed once rendered, so only their template syntax is checked
+pub fn validate_url_template(str_url: &str) -> Result<()> {
+  if is_template(str_url) {
+    Tera::default()
+      .add_raw_template("url", str_url)
+      .map_err(|e| anyhow::anyhow!("Invalid url template: {}\ncause: {}", str_url, e.source().unwrap_or(&e)))
+  } else {
+    validate_url(str_url).map(|_| ())
+  }
+}
+
 pub fn validate_param(param: &str, request_type: RequestParam) -> Result<()> {
   static RE: Lazy<Regex> = Lazy::new(|| Regex::new("^([\\w-]+):(.*)$").unwrap());
   if RE.is_match(param) {
@@ -26,9 +50,91 @@ pub fn validate_param(param: &str, request_type: RequestParam) -> Result<()> {
   }
 }
 
+// request headers also accept `<name>;` to send an empty header
+pub fn validate_header(header: &str) -> Result<()> {
+  static RE: Lazy<Regex> = Lazy::new(|| Regex::new("^[\\w-]+;$").unwrap());
+  if RE.is_match(header) {
+    Ok(())
+  } else {
+    validate_param(header, RequestParam::Header)
+  }
+}
+
+pub fn validate_date(date: &str) -> Result<()> {
+  parse_date(date, chrono::Utc::now()).map(|_| ())
+}
+
+pub fn validate_range(range: &str) -> Result<()> {
+  range_header(range).map(|_| ())
+}
+
+pub fn validate_concurrency(concurrency: &str) -> Result<()> {
+  match concurrency.parse::<usize>() {
+    Ok(value) if value > 0 => Ok(()),
+    _ => Err(anyhow::anyhow!(
+      "Bad concurrency: \"{}\", should be a positive number",
+      concurrency
+    )),
+  }
+}
+
+pub fn validate_repeat(repeat: &str) -> Result<()> {
+  match repeat.parse::<usize>() {
+    Ok(value) if value > 0 => Ok(()),
+    _ => Err(anyhow::anyhow!(
+      "Bad repeat: \"{}\", should be a positive number",
+      repeat
+    )),
+  }
+}
+
+// validate a response body against a json schema, reporting every violation with its json pointer
+pub fn validate_response_schema(schema: &Value, body: &Value) -> Result<()> {
+  let schema = JSONSchema::options()
+    .with_draft(Draft::Draft7)
+    .compile(schema)
+    .map_err(|err| anyhow::anyhow!("Invalid responseSchema: {}", err))?;
+  if let Err(errors) = schema.validate(body) {
+    let violations = errors
+      .map(|error| match error.instance_path.to_string() {
+        path if path.is_empty() => format!("  /: {}", error),
+        path => format!("  {}: {}", path, error),
+      })
+      .collect::<Vec<_>>();
+    return Err(anyhow::anyhow!(
+      "Response body does not match responseSchema:\n{}",
+      violations.join("\n")
+    ));
+  }
+  Ok(())
+}
+
+// validate manifest content, reporting yaml parse errors and every invalid template, shared templates can be used
+pub fn validate_manifest(content: &str) -> Result<ApixManifest> {
+  let manifest: ApixManifest = serde_yaml::from_str(content).map_err(|e| anyhow::anyhow!("Invalid manifest: {}", e))?;
+  let mut tera = Tera::default();
+  register_shared_templates(&mut tera)?;
+  let errors = manifest
+    .templates()
+    .into_iter()
+    .filter_map(|(name, template)| {
+      tera.add_raw_template(&name, &template).err().map(|error| {
+        let cause = error.source().map(ToString::to_string).unwrap_or_default();
+        format!("  {}: {}", name, cause.trim())
+      })
+    })
+    .collect::<Vec<_>>();
+  if errors.is_empty() {
+    Ok(manifest)
+  } else {
+    Err(anyhow::anyhow!("Invalid templates in manifest:\n{}", errors.join("\n")))
+  }
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
+  use serde_json::json;
   use test_case::test_case;
   use url::Url;
 
@@ -40,10 +146,85 @@ mod tests {
     assert_eq!(validate_url(url).unwrap(), Url::parse(url).unwrap());
   }
 
+  #[test_case("https://www.google.com" => true ; "plain url")]
+  #[test_case("{{parameters.base}}/users" => true ; "templated origin")]
+  #[test_case("https://{% if env.PROD %}api{% else %}dev{% endif %}.example.com" => true ; "templated host")]
+  #[test_case("{{parameters.base/users" => false ; "unclosed expression")]
+  #[test_case("ftp://www.google.com" => false ; "unsupported scheme")]
+  fn test_validate_url_template(url: &str) -> bool {
+    validate_url_template(url).is_ok()
+  }
+
+  #[test_case("accept:application/json" => true ; "header with value")]
+  #[test_case("accept:" => true ; "unset header")]
+  #[test_case("content-type;" => true ; "empty header")]
+  #[test_case("content-type" => false ; "missing separator")]
+  fn test_validate_header(header: &str) -> bool {
+    validate_header(header).is_ok()
+  }
+
+  #[test_case("https://bücher.example/users" => "https://xn--bcher-kva.example/users" ; "idn host")]
+  #[test_case("https://example.com/bücher/ü?q=é" => "https://example.com/b%C3%BCcher/%C3%BC?q=%C3%A9" ; "non ascii path")]
+  #[test_case("https://xn--bcher-kva.example/" => "https://xn--bcher-kva.example/" ; "punycode host")]
+  fn test_validate_international_url(url: &str) -> String {
+    validate_url(url).unwrap().to_string()
+  }
+
   // test validate param with test_case
   #[test_case("name:value")]
   #[test_case("name-value" => panics)]
   fn test_validate_param(param: &str) {
     assert_eq!(validate_param(param, RequestParam::Header).unwrap(), ());
   }
+
+  // test validate concurrency with test_case
+  #[test_case("1")]
+  #[test_case("8")]
+  #[test_case("0" => panics)]
+  #[test_case("-1" => panics)]
+  #[test_case("many" => panics)]
+  fn test_validate_concurrency(concurrency: &str) {
+    validate_concurrency(concurrency).unwrap();
+  }
+
+  // test validate response schema reports each violation pointer
+  #[test]
+  fn test_validate_response_schema() {
+    let schema = json!({
+      "type": "object",
+      "required": ["id"],
+      "properties": {
+        "id": { "type": "integer" },
+        "tags": { "type": "array", "items": { "type": "string" } }
+      }
+    });
+    assert!(validate_response_schema(&schema, &json!({ "id": 1, "tags": ["a"] })).is_ok());
+    let message = validate_response_schema(&schema, &json!({ "tags": ["a", 2] }))
+      .unwrap_err()
+      .to_string();
+    assert!(message.contains("  /: \"id\" is a required property"));
+    assert!(message.contains("  /tags/1: 2 is not of type \"string\""));
+  }
+
+  // test validate manifest reports yaml and template errors
+  #[test]
+  fn test_validate_manifest() {
+    let manifest = r#"
+      apiVersion: apix.io/v1
+      metadata:
+        name: test
+      kind: Request
+      spec:
+        request:
+          method: get
+          url: "{{ context.url }}/todos"
+          headers:
+            accept: "{{ parameters.accept"
+    "#;
+    let error = validate_manifest(manifest).unwrap_err().to_string();
+    assert!(error.contains("request.headers.accept"));
+    assert!(!error.contains("request.url"));
+    assert!(validate_manifest(&manifest.replace("accept\"", "accept }}\"")).is_ok());
+    assert!(validate_manifest("kind: [").is_err());
+  }
 }